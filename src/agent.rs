@@ -0,0 +1,132 @@
+//! Running git-ws against a workspace on another machine, over SSH.
+//!
+//! `git-ws --host build01 status` runs `git-ws status` on build01 in agent
+//! mode, where operations write their results as JSON lines, and renders
+//! those here as if the workspace was local. One SSH connection serves the
+//! whole command, however many repositories it goes through. Other output,
+//! like the records of `status --json`, is passed through as is.
+
+use std::io::{BufRead, BufReader};
+use std::process::{Command, ExitCode, Stdio};
+
+use crate::interactive;
+use crate::output;
+use crate::{Error, Result};
+
+/// Hidden option putting git-ws in agent mode.
+pub const AGENT_OPTION: &str = "--agent";
+
+/// Program run on the host, git-ws unless `GIT_WS_REMOTE_PROGRAM` says
+/// otherwise.
+fn remote_program() -> String {
+    std::env::var("GIT_WS_REMOTE_PROGRAM").unwrap_or_else(|_| "git-ws".to_string())
+}
+
+/// The command running git-ws with `args` on `host`.
+fn ssh(host: &str, args: Vec<String>) -> Command {
+    let mut words = vec![
+        remote_program(),
+        AGENT_OPTION.to_string(),
+        // Nobody is there to answer prompts.
+        "--non-interactive".to_string(),
+    ];
+    words.extend(args);
+    let script: Vec<_> = words.iter().map(|word| quote(word)).collect();
+    let mut command = interactive::command("ssh");
+    command
+        .args(["-T", "--", host])
+        .arg(script.join(" "))
+        .stdin(Stdio::null());
+    command
+}
+
+/// Runs git-ws with `args` on `host`, and prints what it reports.
+pub fn run(host: &str, args: Vec<String>) -> Result<ExitCode> {
+    let mut child = ssh(host, args).stdout(Stdio::piped()).spawn()?;
+    let stdout = child.stdout.take().expect("piped stdout");
+    let mut results = Vec::new();
+    for line in BufReader::new(stdout).lines() {
+        let line = line?;
+        match output::parse_result_line(&line) {
+            Some(result) => results.push(result),
+            None => {
+                if !results.is_empty() {
+                    print!("{}", output::results_table(&results));
+                    results.clear();
+                }
+                println!("{}", line);
+            }
+        }
+    }
+    if !results.is_empty() {
+        print!("{}", output::results_table(&results));
+    }
+    let status = child.wait()?;
+    match status.code() {
+        // ssh exits with 255 when it cannot reach the host.
+        Some(255) => Err(Error::Operation(format!("cannot run git-ws on {}", host))),
+        Some(code) => Ok(ExitCode::from(code as u8)),
+        None => Err(Error::Operation(format!("git-ws on {} {}", host, status))),
+    }
+}
+
+/// What git-ws with `args` prints on `host`, failing when it does.
+pub fn capture(host: &str, args: Vec<String>) -> Result<String> {
+    let output = ssh(host, args).output()?;
+    if !output.status.success() {
+        return Err(Error::Operation(format!(
+            "git-ws on {}: {}",
+            host,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// `word` quoted for the POSIX shell running commands on the host. A
+/// leading `~` or `~/` is left out of the quotes, for the shell of the host
+/// to expand to its home, like in `-C '~/ws'` or `GIT_WS_REMOTE_PROGRAM`.
+fn quote(word: &str) -> String {
+    if word == "~" {
+        return word.to_string();
+    }
+    if let Some(rest) = word.strip_prefix("~/") {
+        return match rest {
+            "" => word.to_string(),
+            rest => format!("~/{}", quote(rest)),
+        };
+    }
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c));
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_words_for_the_shell_of_the_host() {
+        assert_eq!(quote("status"), "status");
+        assert_eq!(quote("my repo"), "'my repo'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("a~b"), "'a~b'");
+        assert_eq!(quote("~user/ws"), "'~user/ws'");
+    }
+
+    #[test]
+    fn leaves_a_leading_tilde_to_the_host() {
+        assert_eq!(quote("~"), "~");
+        assert_eq!(quote("~/"), "~/");
+        assert_eq!(quote("~/ws"), "~/ws");
+        assert_eq!(quote("~/my ws"), "~/'my ws'");
+        assert_eq!(quote("~/ws;rm"), "~/'ws;rm'");
+    }
+}
@@ -0,0 +1,94 @@
+//! Running as a git subcommand, `git ws status`, through the alias
+//! `ws = !git-ws`.
+//!
+//! git runs shell aliases from the top of the repository it was started in
+//! and tells where it was started in `GIT_PREFIX`. It also passes on the
+//! variables pinning it to that repository, like `GIT_DIR`, or telling it
+//! its output goes to a pager. git-ws starts from where the user was and
+//! keeps these variables from the commands it runs in every repository,
+//! where they would point git at the wrong repository or make it color
+//! output that is parsed.
+
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::interactive;
+use crate::{Error, Result};
+
+/// Variables that pin git to one repository, or describe the terminal of
+/// the git command that ran git-ws, which must not leak into the commands
+/// run in each repository.
+pub const INHERITED_GIT_VARS: &[&str] = &[
+    "GIT_DIR",
+    "GIT_WORK_TREE",
+    "GIT_INDEX_FILE",
+    "GIT_OBJECT_DIRECTORY",
+    "GIT_ALTERNATE_OBJECT_DIRECTORIES",
+    "GIT_COMMON_DIR",
+    "GIT_NAMESPACE",
+    "GIT_PREFIX",
+    "GIT_PAGER_IN_USE",
+];
+
+/// The directory the user ran git-ws from: the work tree git was pointed
+/// to with `GIT_WORK_TREE` or `GIT_DIR`, the directory git was started in
+/// when running as an alias, or the current directory.
+pub fn start_dir() -> io::Result<PathBuf> {
+    let cwd = env::current_dir()?;
+    if let Some(work_tree) = env::var_os("GIT_WORK_TREE") {
+        return Ok(cwd.join(work_tree));
+    }
+    if let Some(git_dir) = env::var_os("GIT_DIR") {
+        let git_dir = cwd.join(git_dir);
+        // A bare repository has no work tree, start from the repository.
+        return Ok(match git_dir.file_name() {
+            Some(name) if name == ".git" => git_dir.parent().unwrap_or(&git_dir).to_path_buf(),
+            _ => git_dir,
+        });
+    }
+    Ok(match env::var_os("GIT_PREFIX") {
+        Some(prefix) => cwd.join(prefix),
+        None => cwd,
+    })
+}
+
+/// Adds the git alias `name` running git-ws to the global git
+/// configuration.
+pub fn install(name: &str) -> Result<()> {
+    let status = interactive::command("git")
+        .args(["config", "--global", &format!("alias.{}", name), "!git-ws"])
+        .status()?;
+    if !status.success() {
+        return Err(Error::Operation(format!("could not set alias.{}", name)));
+    }
+    Ok(())
+}
+
+/// Bash completion of `git <name>`, for git's own completion script, which
+/// completes `git <alias>` with the function `_git_<alias>`. It hands over
+/// to the completion of git-ws, `_git-ws`.
+pub fn bash_git_completion(name: &str) -> String {
+    format!(
+        "_git_{name}() {{\n    \
+             local COMP_WORDS=(git-ws \"${{COMP_WORDS[@]:2}}\")\n    \
+             local COMP_CWORD=$((COMP_CWORD - 1))\n    \
+             _git-ws git-ws \"${{COMP_WORDS[COMP_CWORD]}}\" \"${{COMP_WORDS[COMP_CWORD - 1]}}\"\n\
+         }}\n",
+        name = name.replace('-', "_")
+    )
+}
+
+/// Where the completion of git-ws for `shell` is loaded from
+/// automatically, below the home directory.
+pub fn completion_path(shell: &str) -> Option<PathBuf> {
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+    let home = Path::new(&home);
+    let path = match shell {
+        "bash" => home.join(".local/share/bash-completion/completions/git-ws"),
+        "zsh" => home.join(".zfunc/_git-ws"),
+        "fish" => home.join(".config/fish/completions/git-ws.fish"),
+        _ => return None,
+    };
+    Some(path)
+}
@@ -0,0 +1,148 @@
+//! An on-disk cache of forge API responses, so commands asking about every
+//! repository, like `pr status`, stay fast and within the rate limit.
+//!
+//! Responses are kept per repository, in
+//! `.git-ws/api-cache/<api>/<project>/<hash of the URL>.json`, with their
+//! ETag. Each request sends it back in `If-None-Match`, and the forge
+//! answers `304 Not Modified` to a request whose response did not change,
+//! which GitHub does not count against the rate limit.
+//!
+//! The rate limit left, read from the headers of every response, is kept
+//! per API too. Once it is exhausted, cached responses are used as they are
+//! until it resets, and requests without one fail right away rather than
+//! being refused one after the other.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::recover;
+use crate::workspace::Workspace;
+use crate::Result;
+
+const CACHE_DIR: &str = "api-cache";
+const RATE_LIMIT_FILE: &str = "rate-limit.json";
+
+/// Writes so far, naming the temporary files apart.
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// The API response cache of a workspace.
+#[derive(Debug, Clone)]
+pub struct ApiCache {
+    dir: PathBuf,
+}
+
+impl ApiCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        ApiCache { dir: dir.into() }
+    }
+
+    pub fn for_workspace(workspace: &Workspace) -> Self {
+        ApiCache::new(workspace.state_dir().join(CACHE_DIR))
+    }
+
+    /// The responses of the API `api` about the project `path`.
+    pub fn project(&self, api: &str, path: &str) -> ProjectCache {
+        let api_dir = self.dir.join(dir_name(api));
+        ProjectCache {
+            dir: api_dir.join(path),
+            rate_limit: api_dir.join(RATE_LIMIT_FILE),
+        }
+    }
+
+    /// The last known rate limit of the API `api`.
+    pub fn rate_limit(&self, api: &str) -> Option<RateLimit> {
+        load(&self.dir.join(dir_name(api)).join(RATE_LIMIT_FILE))
+    }
+}
+
+/// `api`, like `https://api.github.com`, as a directory name.
+fn dir_name(api: &str) -> String {
+    let api = api.split_once("://").map_or(api, |(_, rest)| rest);
+    api.trim_end_matches('/').replace(['/', ':'], "_")
+}
+
+/// A response, with the ETag it was served with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub etag: String,
+    pub body: Value,
+}
+
+/// Requests left until the rate limit of an API resets.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub remaining: u64,
+    /// When it resets, in seconds since the epoch.
+    pub reset: i64,
+}
+
+impl RateLimit {
+    /// Whether no request is left before the reset.
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == 0 && self.reset > recover::now()
+    }
+
+    /// Minutes until the reset, at least one.
+    pub fn minutes_to_reset(&self) -> i64 {
+        ((self.reset - recover::now()) / 60).max(1)
+    }
+}
+
+/// The cached responses about one project.
+#[derive(Debug, Clone)]
+pub struct ProjectCache {
+    dir: PathBuf,
+    rate_limit: PathBuf,
+}
+
+impl ProjectCache {
+    pub fn get(&self, url: &str) -> Option<CachedResponse> {
+        load(&self.entry(url))
+    }
+
+    pub fn put(&self, url: &str, response: &CachedResponse) -> Result<()> {
+        store(&self.entry(url), response)
+    }
+
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        load(&self.rate_limit)
+    }
+
+    pub fn set_rate_limit(&self, rate_limit: RateLimit) -> Result<()> {
+        store(&self.rate_limit, &rate_limit)
+    }
+
+    fn entry(&self, url: &str) -> PathBuf {
+        let hash: String = Sha256::digest(url)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        self.dir.join(format!("{}.json", hash))
+    }
+}
+
+fn load<T: serde::de::DeserializeOwned>(path: &Path) -> Option<T> {
+    let contents = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// Writes `value` to `path` through a temporary file, the repositories
+/// being queried concurrently.
+fn store<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temporary = path.with_extension(format!(
+        "{}.{}.tmp",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&temporary, serde_json::to_string(value)?)?;
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
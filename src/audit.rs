@@ -0,0 +1,79 @@
+//! Auditing the effective git configuration of the repositories, to find
+//! those set differently from the others, like a stray `core.autocrlf`.
+
+use std::collections::BTreeMap;
+
+use crate::repository::GitRepository;
+use crate::{Error, Result};
+
+/// The value of `key` git uses in `repo`, whichever file sets it, `None`
+/// when it is not set.
+pub fn effective(repo: &GitRepository, key: &str) -> Result<Option<String>> {
+    match repo.git(["config", "--get", key]) {
+        Ok(value) => Ok(Some(value)),
+        // git exits with 1, saying nothing, for a key not set.
+        Err(Error::Operation(message)) if message.is_empty() => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The values of some keys across the repositories.
+#[derive(Debug, Clone, Default)]
+pub struct Audit {
+    pub keys: Vec<String>,
+    /// Values of the keys, in their order, by repository.
+    pub values: BTreeMap<String, Vec<Option<String>>>,
+}
+
+impl Audit {
+    pub fn new(keys: Vec<String>) -> Self {
+        Audit {
+            keys,
+            values: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, repo: &GitRepository) -> Result<()> {
+        let values = self
+            .keys
+            .iter()
+            .map(|key| effective(repo, key))
+            .collect::<Result<_>>()?;
+        self.values.insert(repo.name().to_string(), values);
+        Ok(())
+    }
+
+    /// The value most repositories have for the key at `index`, the first
+    /// in order on a tie.
+    pub fn usual(&self, index: usize) -> Option<&Option<String>> {
+        let mut counts: BTreeMap<&Option<String>, usize> = BTreeMap::new();
+        for values in self.values.values() {
+            *counts.entry(&values[index]).or_default() += 1;
+        }
+        let most = counts.values().copied().max()?;
+        counts
+            .into_iter()
+            .find(|(_, count)| *count == most)
+            .map(|(value, _)| value)
+    }
+
+    /// Whether the value of `repo` for the key at `index` differs from the
+    /// usual one.
+    pub fn is_outlier(&self, repo: &str, index: usize) -> bool {
+        match (self.values.get(repo), self.usual(index)) {
+            (Some(values), Some(usual)) => values[index] != *usual,
+            _ => false,
+        }
+    }
+
+    pub fn outliers(&self) -> usize {
+        self.values
+            .keys()
+            .map(|repo| {
+                (0..self.keys.len())
+                    .filter(|index| self.is_outlier(repo, *index))
+                    .count()
+            })
+            .sum()
+    }
+}
@@ -0,0 +1,159 @@
+//! SVG badges telling the health of every repository, for dashboards.
+//!
+//! `badges --output DIR` writes `DIR/<repo>/status.svg` (clean or dirty),
+//! `sync.svg` (ahead and behind the upstream) and `last-commit.svg` (age of
+//! the last commit on HEAD). Nothing refreshes them but running `badges`
+//! again, from cron or a script calling it after `sync`; files are only
+//! rewritten when their badge changed, so the dashboards are not touched
+//! needlessly.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::operations::GitOperation;
+use crate::output::RepoRecord;
+use crate::recover;
+use crate::repository::{GitRepository, ScanOptions};
+use crate::Result;
+
+const GREEN: &str = "#4c1";
+const YELLOW: &str = "#dfb317";
+const ORANGE: &str = "#fe7d37";
+const GREY: &str = "#9f9f9f";
+
+/// A flat badge, a grey label followed by a coloured value.
+#[derive(Debug, Clone)]
+pub struct Badge {
+    pub label: String,
+    pub value: String,
+    pub color: &'static str,
+}
+
+impl Badge {
+    fn new(label: &str, value: impl Into<String>, color: &'static str) -> Self {
+        Badge {
+            label: label.to_string(),
+            value: value.into(),
+            color,
+        }
+    }
+
+    pub fn to_svg(&self) -> String {
+        let label_width = text_width(&self.label);
+        let value_width = text_width(&self.value);
+        let width = label_width + value_width;
+        let label = escape(&self.label);
+        let value = escape(&self.value);
+        format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {value}">
+<title>{label}: {value}</title>
+<linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient>
+<clipPath id="r"><rect width="{width}" height="20" rx="3" fill="#fff"/></clipPath>
+<g clip-path="url(#r)"><rect width="{label_width}" height="20" fill="#555"/><rect x="{label_width}" width="{value_width}" height="20" fill="{color}"/><rect width="{width}" height="20" fill="url(#s)"/></g>
+<g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">
+<text x="{label_x}" y="14">{label}</text>
+<text x="{value_x}" y="14">{value}</text>
+</g>
+</svg>
+"##,
+            color = self.color,
+            label_x = label_width / 2,
+            value_x = label_width + value_width / 2,
+        )
+    }
+}
+
+/// Width of `text` in Verdana 11px, near enough, with the padding.
+fn text_width(text: &str) -> usize {
+    text.chars().count() * 7 + 10
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The badges of a repository, by file name.
+pub fn badges(record: &RepoRecord, last_commit: Option<i64>) -> Vec<(&'static str, Badge)> {
+    let status = if record.conflicted > 0 {
+        Badge::new("status", "conflicted", ORANGE)
+    } else if record.dirty {
+        Badge::new("status", "dirty", ORANGE)
+    } else {
+        Badge::new("status", "clean", GREEN)
+    };
+    let sync = match (&record.upstream, record.ahead, record.behind) {
+        (None, _, _) => Badge::new("sync", "no upstream", GREY),
+        (Some(_), 0, 0) => Badge::new("sync", "up to date", GREEN),
+        (Some(_), ahead, 0) => Badge::new("sync", format!("{} ahead", ahead), YELLOW),
+        (Some(_), 0, behind) => Badge::new("sync", format!("{} behind", behind), YELLOW),
+        (Some(_), ahead, behind) => Badge::new(
+            "sync",
+            format!("{} ahead, {} behind", ahead, behind),
+            ORANGE,
+        ),
+    };
+    let last_commit = match last_commit {
+        Some(time) => {
+            let days = (recover::now() - time) / 86400;
+            let color = match days {
+                ..=7 => GREEN,
+                8..=30 => YELLOW,
+                _ => GREY,
+            };
+            Badge::new("last commit", recover::ago(time), color)
+        }
+        None => Badge::new("last commit", "none", GREY),
+    };
+    vec![
+        ("status.svg", status),
+        ("sync.svg", sync),
+        ("last-commit.svg", last_commit),
+    ]
+}
+
+/// Writes the badges of every repository under a directory.
+pub struct BadgeOperation {
+    dir: PathBuf,
+}
+
+impl BadgeOperation {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        BadgeOperation { dir: dir.into() }
+    }
+}
+
+impl GitOperation for BadgeOperation {
+    fn name(&self) -> &str {
+        "badges"
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let record = RepoRecord::capture(repo, &[], ScanOptions::default())?;
+        let last_commit = repo
+            .open()?
+            .head()
+            .and_then(|head| head.peel_to_commit())
+            .map(|commit| commit.time().seconds())
+            .ok();
+        let dir = self.dir.join(repo.name());
+        fs::create_dir_all(&dir)?;
+        let badges = badges(&record, last_commit);
+        let mut written = 0;
+        for (file, badge) in &badges {
+            let path = dir.join(file);
+            let svg = badge.to_svg();
+            if fs::read_to_string(&path).ok().as_deref() != Some(svg.as_str()) {
+                fs::write(&path, svg)?;
+                written += 1;
+            }
+        }
+        let values: Vec<_> = badges
+            .iter()
+            .map(|(_, badge)| badge.value.as_str())
+            .collect();
+        Ok(format!("{} ({} updated)", values.join(", "), written))
+    }
+}
@@ -0,0 +1,251 @@
+//! Batch merges, rebases, cherry-picks and reverts, like `pull`, left
+//! conflicted in some repositories.
+//!
+//! The batch is recorded in the workspace state before it starts, with the
+//! HEAD of every repository, and kept while repositories are conflicted.
+//! `continue` resumes the repositories once their conflicts are resolved,
+//! `abort` stops the merges and rebases in progress and puts every
+//! repository back where the batch found it.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use git2::RepositoryState;
+use serde::{Deserialize, Serialize};
+
+use crate::conflicts;
+use crate::interactive;
+use crate::operations::GitOperation;
+use crate::repository::GitRepository;
+use crate::workspace::Workspace;
+use crate::{Error, Result};
+
+/// A batch merge or rebase, recorded in the workspace state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchMerge {
+    /// Command line of the batch, like `pull --rebase`.
+    pub command: String,
+    /// Commit HEAD pointed to in every repository before the batch.
+    pub heads: BTreeMap<String, String>,
+    /// Repositories left in the middle of a merge or rebase.
+    #[serde(default)]
+    pub conflicted: BTreeSet<String>,
+}
+
+/// Records the batch `command` about to run in `repos`. Fails while another
+/// batch is unfinished.
+pub fn begin(workspace: &Workspace, command: &str, repos: &[GitRepository]) -> Result<()> {
+    let mut state = workspace.load_state()?;
+    if let Some(batch) = &state.batch {
+        return Err(Error::Operation(format!(
+            "`{}` is unfinished in {}, run `git-ws continue` or `git-ws abort` first",
+            batch.command,
+            names(&batch.conflicted)
+        )));
+    }
+    let mut heads = BTreeMap::new();
+    for repo in repos {
+        if let Ok(head) = repo.head_sha() {
+            heads.insert(repo.name().to_string(), head);
+        }
+    }
+    state.batch = Some(BatchMerge {
+        command: command.to_string(),
+        heads,
+        conflicted: BTreeSet::new(),
+    });
+    workspace.save_state(&state)
+}
+
+/// Records which of `repos` the batch left in the middle of a merge or
+/// rebase, and forgets the batch when there is none left. Returns the
+/// conflicted repositories.
+pub fn settle(workspace: &Workspace, repos: &[GitRepository]) -> Result<BTreeSet<String>> {
+    let mut state = workspace.load_state()?;
+    let Some(batch) = &mut state.batch else {
+        return Ok(BTreeSet::new());
+    };
+    for repo in repos {
+        if in_progress(repo)?.is_some() {
+            batch.conflicted.insert(repo.name().to_string());
+        } else {
+            batch.conflicted.remove(repo.name());
+        }
+    }
+    let conflicted = batch.conflicted.clone();
+    if conflicted.is_empty() {
+        state.batch = None;
+    }
+    workspace.save_state(&state)?;
+    Ok(conflicted)
+}
+
+/// The unfinished batch of the workspace.
+pub fn current(workspace: &Workspace) -> Result<BatchMerge> {
+    workspace
+        .load_state()?
+        .batch
+        .ok_or_else(|| Error::Operation("no batch merge or rebase in progress".to_string()))
+}
+
+/// Forgets the batch of the workspace.
+pub fn forget(workspace: &Workspace) -> Result<()> {
+    let mut state = workspace.load_state()?;
+    state.batch = None;
+    workspace.save_state(&state)
+}
+
+/// Tells the user how to finish the batch, when repositories are left
+/// conflicted.
+pub fn hint(conflicted: &BTreeSet<String>) -> Option<String> {
+    if conflicted.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "conflicted: {}\nresolve the conflicts, see `git-ws conflicts`, then run \
+         `git-ws continue`, or run `git-ws abort` to undo the whole batch",
+        names(conflicted)
+    ))
+}
+
+fn names(repos: &BTreeSet<String>) -> String {
+    repos.iter().cloned().collect::<Vec<_>>().join(", ")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InProgress {
+    Merge,
+    Rebase,
+    CherryPick,
+    Revert,
+}
+
+/// The merge, rebase, cherry-pick or revert `repo` is in the middle of.
+fn in_progress(repo: &GitRepository) -> Result<Option<InProgress>> {
+    Ok(match repo.open()?.state() {
+        RepositoryState::Merge => Some(InProgress::Merge),
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence => {
+            Some(InProgress::CherryPick)
+        }
+        RepositoryState::Revert | RepositoryState::RevertSequence => Some(InProgress::Revert),
+        RepositoryState::Rebase
+        | RepositoryState::RebaseInteractive
+        | RepositoryState::RebaseMerge
+        | RepositoryState::ApplyMailboxOrRebase => Some(InProgress::Rebase),
+        _ => None,
+    })
+}
+
+/// Runs git in `repo` with an editor accepting the prepared commit message.
+fn git_without_editor(repo: &GitRepository, args: &[&str]) -> Result<()> {
+    let output = interactive::command("git")
+        .args(args)
+        .current_dir(repo.path())
+        .env("GIT_EDITOR", "true")
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::Operation(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Concludes the merge or rebase of a conflicted repository whose conflicts
+/// are resolved.
+pub struct ContinueOperation;
+
+impl GitOperation for ContinueOperation {
+    fn name(&self) -> &str {
+        "continue"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let Some(kind) = in_progress(repo)? else {
+            return Ok("nothing in progress".to_string());
+        };
+        let unresolved = conflicts::list(repo, &[])?;
+        if !unresolved.is_empty() {
+            let paths: Vec<_> = unresolved
+                .iter()
+                .map(|conflict| conflict.path.as_str())
+                .collect();
+            return Err(Error::Operation(format!(
+                "unresolved: {}",
+                paths.join(", ")
+            )));
+        }
+        let result = match kind {
+            InProgress::Merge => git_without_editor(repo, &["commit", "--no-edit"]),
+            InProgress::Rebase => git_without_editor(repo, &["rebase", "--continue"]),
+            InProgress::CherryPick => git_without_editor(repo, &["cherry-pick", "--continue"]),
+            InProgress::Revert => git_without_editor(repo, &["revert", "--continue"]),
+        };
+        if let Err(e) = result {
+            // The rebase stopped again, on a later commit.
+            if kind == InProgress::Rebase && !conflicts::list(repo, &[])?.is_empty() {
+                return Err(Error::Operation(
+                    "conflicted again, on a later commit".to_string(),
+                ));
+            }
+            return Err(e);
+        }
+        Ok(match kind {
+            InProgress::Merge => "merge concluded".to_string(),
+            InProgress::Rebase => "rebase concluded".to_string(),
+            InProgress::CherryPick => "cherry-pick concluded".to_string(),
+            InProgress::Revert => "revert concluded".to_string(),
+        })
+    }
+}
+
+/// Aborts the merge or rebase in progress, and moves HEAD back to where it
+/// was before the batch.
+pub struct AbortOperation {
+    heads: BTreeMap<String, String>,
+}
+
+impl AbortOperation {
+    pub fn new(batch: &BatchMerge) -> Self {
+        AbortOperation {
+            heads: batch.heads.clone(),
+        }
+    }
+}
+
+impl GitOperation for AbortOperation {
+    fn name(&self) -> &str {
+        "abort"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let Some(before) = self.heads.get(repo.name()) else {
+            return Err(Error::Skipped("not in the batch".to_string()));
+        };
+        let in_progress = in_progress(repo)?;
+        match in_progress {
+            Some(InProgress::Merge) => repo.git(["merge", "--abort"])?,
+            Some(InProgress::Rebase) => repo.git(["rebase", "--abort"])?,
+            Some(InProgress::CherryPick) => repo.git(["cherry-pick", "--abort"])?,
+            Some(InProgress::Revert) => repo.git(["revert", "--abort"])?,
+            None => String::new(),
+        };
+        if repo.head_sha()? != *before {
+            // Keeps local changes, and refuses to drop any it would lose.
+            repo.git(["reset", "--quiet", "--keep", before])?;
+            return Ok(format!("back to {}", &before[..7]));
+        }
+        Ok(if in_progress.is_some() {
+            "aborted".to_string()
+        } else {
+            "unchanged".to_string()
+        })
+    }
+}
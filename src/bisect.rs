@@ -0,0 +1,80 @@
+//! Bisecting one repository while the rest of the workspace stays put.
+//!
+//! The bisected repository is pinned for the duration of the session so batch
+//! operations started by the test command cannot move it, and the test
+//! command itself runs from the workspace root so it can build or exercise
+//! the whole workspace.
+
+use serde::{Deserialize, Serialize};
+
+use crate::interactive;
+use crate::repository::GitRepository;
+use crate::workspace::Workspace;
+use crate::{Error, Result};
+
+/// A bisect started by git-ws, recorded in the workspace state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BisectSession {
+    pub repo: String,
+    /// Whether the session pinned the repository, in which case it is
+    /// unpinned again on reset.
+    pub pinned: bool,
+}
+
+/// Starts bisecting `repo` between the `bad` and `good` revisions.
+pub fn start(workspace: &Workspace, repo: &GitRepository, bad: &str, good: &str) -> Result<()> {
+    let mut state = workspace.load_state()?;
+    if let Some(session) = &state.bisect {
+        return Err(Error::Operation(format!(
+            "a bisect is already in progress in {}, run `git-ws bisect reset` first",
+            session.repo
+        )));
+    }
+
+    repo.git(["bisect", "start", bad, good])?;
+    let pinned = state.pinned.insert(repo.name().to_string());
+    state.bisect = Some(BisectSession {
+        repo: repo.name().to_string(),
+        pinned,
+    });
+    workspace.save_state(&state)?;
+    Ok(())
+}
+
+/// Runs `test` from the workspace root for every commit git proposes until
+/// the first bad commit is found, and returns it.
+///
+/// The test follows the `git bisect run` conventions: exit code 0 marks the
+/// commit good, 125 skips it and anything else marks it bad.
+pub fn run(workspace: &Workspace, repo: &GitRepository, test: &str) -> Result<String> {
+    let script = format!("cd \"$GIT_WS_ROOT\" && {}", test);
+    let status = interactive::command("git")
+        .args(["bisect", "run", "sh", "-c", &script])
+        .current_dir(repo.path())
+        .env("GIT_WS_ROOT", workspace.root())
+        .status()?;
+    if !status.success() {
+        return Err(Error::Operation(format!(
+            "git bisect run failed ({})",
+            status
+        )));
+    }
+    repo.git(["show", "--no-patch", "--oneline", "refs/bisect/bad"])
+}
+
+/// Ends the bisect in progress, returning the repository to where it was
+/// before the session started.
+pub fn reset(workspace: &Workspace) -> Result<String> {
+    let mut state = workspace.load_state()?;
+    let session = state
+        .bisect
+        .take()
+        .ok_or_else(|| Error::Operation("no bisect in progress".to_string()))?;
+    let repo = workspace.find_repository(&session.repo)?;
+    repo.git(["bisect", "reset"])?;
+    if session.pinned {
+        state.pinned.remove(&session.repo);
+    }
+    workspace.save_state(&state)?;
+    Ok(session.repo)
+}
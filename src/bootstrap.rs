@@ -0,0 +1,167 @@
+//! Bringing repositories in line with team conventions when they join the
+//! workspace.
+//!
+//! Configured in the `[bootstrap]` section of the workspace configuration:
+//!
+//! ```toml
+//! [bootstrap]
+//! hooks = "tools/hooks"
+//! exclude = [".idea/", "*.local"]
+//! script = "make setup"
+//!
+//! [bootstrap.config]
+//! "pull.rebase" = "true"
+//! ```
+//!
+//! The steps are applied to repositories created by `ci-checkout` and, on
+//! demand, by `git-ws bootstrap`. Every step can be applied again safely.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::interactive;
+use crate::operations::GitOperation;
+use crate::repository::GitRepository;
+use crate::{Error, Result};
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Bootstrap {
+    /// Directory, relative to the workspace root, whose files are installed
+    /// as git hooks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hooks: Option<PathBuf>,
+    /// Configuration values set in the repository.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub config: BTreeMap<String, String>,
+    /// Patterns added to `.git/info/exclude`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Shell command run in the repository, with `GIT_WS_ROOT` and
+    /// `GIT_WS_REPO` set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+}
+
+impl Bootstrap {
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_none()
+            && self.config.is_empty()
+            && self.exclude.is_empty()
+            && self.script.is_none()
+    }
+
+    /// Applies every step to `repo` and describes what was done. Relative
+    /// paths are resolved against `root`, the workspace root.
+    pub fn apply(&self, root: &Path, repo: &GitRepository) -> Result<String> {
+        let git_dir = repo.open()?.path().to_path_buf();
+        let mut done = Vec::new();
+
+        if let Some(hooks) = &self.hooks {
+            let installed = install_hooks(&root.join(hooks), &git_dir.join("hooks"))?;
+            done.push(format!("{} hook(s)", installed));
+        }
+        for (key, value) in &self.config {
+            repo.git(["config", key, value])?;
+        }
+        if !self.config.is_empty() {
+            done.push(format!("{} config value(s)", self.config.len()));
+        }
+        if !self.exclude.is_empty() {
+            let added = add_excludes(&git_dir.join("info").join("exclude"), &self.exclude)?;
+            done.push(format!("{} exclude(s)", added));
+        }
+        if let Some(script) = &self.script {
+            let status = interactive::shell(script)
+                .current_dir(repo.path())
+                .env("GIT_WS_ROOT", root)
+                .env("GIT_WS_REPO", repo.name())
+                .status()?;
+            if !status.success() {
+                return Err(Error::Operation(format!(
+                    "bootstrap script `{}` failed ({})",
+                    script, status
+                )));
+            }
+            done.push("script".to_string());
+        }
+
+        if done.is_empty() {
+            Ok("nothing to do".to_string())
+        } else {
+            Ok(done.join(", "))
+        }
+    }
+}
+
+fn install_hooks(source: &Path, hooks: &Path) -> Result<usize> {
+    fs::create_dir_all(hooks)?;
+    let mut installed = 0;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            // Copying keeps the permissions, hooks stay executable.
+            fs::copy(entry.path(), hooks.join(entry.file_name()))?;
+            installed += 1;
+        }
+    }
+    Ok(installed)
+}
+
+/// Appends the patterns missing from `path`, returning how many were added.
+fn add_excludes(path: &Path, patterns: &[String]) -> Result<usize> {
+    let existing = fs::read_to_string(path).unwrap_or_default();
+    let missing: Vec<&String> = patterns
+        .iter()
+        .filter(|pattern| !existing.lines().any(|line| line == pattern.as_str()))
+        .collect();
+    if missing.is_empty() {
+        return Ok(0);
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    if !existing.is_empty() && !existing.ends_with('\n') {
+        writeln!(file)?;
+    }
+    for pattern in &missing {
+        writeln!(file, "{}", pattern)?;
+    }
+    Ok(missing.len())
+}
+
+/// Applies the bootstrap steps to existing repositories.
+pub struct BootstrapOperation {
+    bootstrap: Bootstrap,
+    root: PathBuf,
+}
+
+impl BootstrapOperation {
+    pub fn new(bootstrap: Bootstrap, root: impl Into<PathBuf>) -> Self {
+        BootstrapOperation {
+            bootstrap,
+            root: root.into(),
+        }
+    }
+}
+
+impl GitOperation for BootstrapOperation {
+    fn name(&self) -> &str {
+        "bootstrap"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        self.bootstrap.apply(&self.root, repo)
+    }
+}
@@ -0,0 +1,172 @@
+//! Local branches across the repositories of a workspace.
+
+use git2::BranchType;
+
+use crate::operations::GitOperation;
+use crate::repository::GitRepository;
+use crate::{Error, Result};
+
+/// The local branches of `repo`, sorted.
+pub fn list(repo: &GitRepository) -> Result<Vec<String>> {
+    let git = repo.open()?;
+    let mut names = Vec::new();
+    for branch in git.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        if let Some(name) = branch.name()? {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Creates a branch at HEAD, leaving the checked out branch alone.
+pub struct CreateBranchOperation {
+    name: String,
+}
+
+impl CreateBranchOperation {
+    pub fn new(name: impl Into<String>) -> Self {
+        CreateBranchOperation { name: name.into() }
+    }
+}
+
+impl GitOperation for CreateBranchOperation {
+    fn name(&self) -> &str {
+        "branch create"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let git = repo.open()?;
+        if git.find_branch(&self.name, BranchType::Local).is_ok() {
+            return Err(Error::Skipped(format!("{} exists already", self.name)));
+        }
+        let head = git
+            .head()
+            .and_then(|head| head.peel_to_commit())
+            .map_err(|_| Error::Operation("no commit to branch from".to_string()))?;
+        git.branch(&self.name, &head, false)?;
+        Ok(format!("created at {}", &head.id().to_string()[..7]))
+    }
+}
+
+/// Deletes a branch, unless it is checked out, or not merged and not
+/// `force`d.
+pub struct DeleteBranchOperation {
+    name: String,
+    force: bool,
+}
+
+impl DeleteBranchOperation {
+    pub fn new(name: impl Into<String>, force: bool) -> Self {
+        DeleteBranchOperation {
+            name: name.into(),
+            force,
+        }
+    }
+}
+
+impl GitOperation for DeleteBranchOperation {
+    fn name(&self) -> &str {
+        "branch delete"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let git = repo.open()?;
+        let Ok(branch) = git.find_branch(&self.name, BranchType::Local) else {
+            return Err(Error::Skipped(format!("no branch {}", self.name)));
+        };
+        if branch.is_head() {
+            return Err(Error::Operation(format!("{} is checked out", self.name)));
+        }
+        let tip = branch
+            .get()
+            .target()
+            .map(|id| id.to_string()[..7].to_string())
+            .unwrap_or_default();
+        // git tells unmerged branches apart, with the upstream in mind.
+        match repo.git(["branch", if self.force { "-D" } else { "-d" }, &self.name]) {
+            Err(Error::Operation(message)) if message.contains("not fully merged") => {
+                return Err(Error::Operation("not merged, use --force".to_string()));
+            }
+            result => result?,
+        };
+        Ok(format!("deleted, was {}", tip))
+    }
+}
+
+/// Checks a branch out, a local one, or one of `origin` tracked by a new
+/// local branch, or, when `create` is set, a new branch at HEAD.
+pub struct CheckoutOperation {
+    name: String,
+    create: bool,
+}
+
+impl CheckoutOperation {
+    pub fn new(name: impl Into<String>, create: bool) -> Self {
+        CheckoutOperation {
+            name: name.into(),
+            create,
+        }
+    }
+}
+
+/// Start of the message of repositories left on their branch because
+/// their local changes would be overwritten.
+pub const DIRTY: &str = "local changes would be overwritten";
+
+impl GitOperation for CheckoutOperation {
+    fn name(&self) -> &str {
+        "checkout"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let git = repo.open()?;
+        if repo.current_branch()?.as_deref() == Some(self.name.as_str()) {
+            return Ok(format!("already on {}", self.name));
+        }
+        let remote = format!("origin/{}", self.name);
+        let (args, message) = if git.find_branch(&self.name, BranchType::Local).is_ok() {
+            (vec!["checkout", "--quiet", &self.name], "switched")
+        } else if git.find_branch(&remote, BranchType::Remote).is_ok() {
+            (
+                vec!["checkout", "--quiet", "-b", &self.name, "--track", &remote],
+                "switched, tracking origin",
+            )
+        } else if self.create {
+            (
+                vec!["checkout", "--quiet", "-b", &self.name],
+                "created at HEAD",
+            )
+        } else {
+            return Err(Error::Skipped(format!(
+                "no branch {}, use -b to create it",
+                self.name
+            )));
+        };
+        match repo.git(args) {
+            Err(Error::Operation(message)) if message.contains("would be overwritten") => {
+                // The files are listed one per line, indented.
+                let files: Vec<_> = message
+                    .lines()
+                    .filter(|line| line.starts_with('\t'))
+                    .map(str::trim)
+                    .collect();
+                Err(Error::Operation(format!("{}: {}", DIRTY, files.join(", "))))
+            }
+            result => result.map(|_| message.to_string()),
+        }
+    }
+}
@@ -0,0 +1,344 @@
+//! Changesets: one logical change spread over several repositories.
+//!
+//! A changeset is named after its ticket, e.g. `PAY-123`, and is made of the
+//! local branches whose name contains that id, such as `feature/PAY-123` in
+//! one repository and `pay-123-client` in another. The match ignores case,
+//! and the id must stand on its own: `PAY-12` is not part of `PAY-123`.
+
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use git2::BranchType;
+use regex::RegexBuilder;
+
+use crate::api_cache::ApiCache;
+use crate::config::HostConfig;
+use crate::forge::{CiState, Project, PullRequest};
+use crate::manifest::Manifest;
+use crate::operations::GitOperation;
+use crate::repository::{self, ChangeCounts, GitRepository};
+use crate::{Error, Result};
+
+/// How often the checks of a pull request are looked at while waiting.
+const CI_POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Local branches of `repo` belonging to the changeset `id`.
+pub fn branches(repo: &GitRepository, id: &str) -> Result<Vec<String>> {
+    let id = RegexBuilder::new(&format!("(^|[^A-Za-z0-9]){}($|[^0-9])", regex::escape(id)))
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| Error::Operation(format!("invalid changeset id: {}", e)))?;
+    let git = repo.open()?;
+    let mut branches = Vec::new();
+    for branch in git.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        if let Some(name) = branch.name()? {
+            if id.is_match(name) {
+                branches.push(name.to_string());
+            }
+        }
+    }
+    branches.sort();
+    Ok(branches)
+}
+
+/// The branch changeset branches of `repo` are compared with:
+/// `origin/<default branch>`, or the local default branch without `origin`.
+pub fn base(repo: &GitRepository) -> Result<String> {
+    let git = repo.open()?;
+    let default = repository::default_branch(&git)?
+        .ok_or_else(|| Error::Operation("no default branch, pass a base".to_string()))?;
+    let remote = format!("origin/{}", default);
+    if git.revparse_single(&remote).is_ok() {
+        Ok(remote)
+    } else {
+        Ok(default)
+    }
+}
+
+/// Changes of `branch` since it forked from `base`, with paths prefixed by
+/// the repository name. A unified diff, or with `series` one patch per
+/// commit as `git format-patch` writes them.
+pub fn diff(repo: &GitRepository, branch: &str, base: &str, series: bool) -> Result<String> {
+    let src_prefix = format!("--src-prefix=a/{}/", repo.name());
+    let dst_prefix = format!("--dst-prefix=b/{}/", repo.name());
+    let mut args = vec![
+        "--no-color".to_string(),
+        "--no-ext-diff".to_string(),
+        src_prefix,
+        dst_prefix,
+    ];
+    if series {
+        args.insert(0, "format-patch".to_string());
+        args.push("--stdout".to_string());
+        args.push(format!("{}..{}", base, branch));
+    } else {
+        args.insert(0, "diff".to_string());
+        args.push(format!("{}...{}", base, branch));
+    }
+    let mut diff = repo.git(&args)?;
+    if !diff.is_empty() {
+        diff.push('\n');
+    }
+    Ok(diff)
+}
+
+/// A pull request of a changeset, waiting to be merged.
+pub struct PendingMerge {
+    pub repo: GitRepository,
+    pub branch: String,
+    pub project: Project,
+    pub pull_request: PullRequest,
+}
+
+impl PendingMerge {
+    /// Waits up to `ci_timeout` for the checks of the pull request to pass,
+    /// then merges it and returns the merge commit.
+    pub fn land(&self, ci_timeout: Duration) -> Result<String> {
+        let deadline = Instant::now() + ci_timeout;
+        loop {
+            match self.project.ci_state(&self.pull_request)? {
+                CiState::None | CiState::Success => break,
+                CiState::Failure => {
+                    return Err(Error::Operation(format!(
+                        "checks failed on {}",
+                        self.pull_request.url
+                    )))
+                }
+                CiState::Pending if Instant::now() >= deadline => {
+                    return Err(Error::Operation(format!(
+                        "checks still running on {} after {}s",
+                        self.pull_request.url,
+                        ci_timeout.as_secs()
+                    )))
+                }
+                CiState::Pending => thread::sleep(CI_POLL_INTERVAL),
+            }
+        }
+        self.project.merge(&self.pull_request)
+    }
+}
+
+/// The open pull requests of the changeset `id`, in the order of `repos`.
+/// Fails when a branch of the changeset has no open pull request.
+pub fn pending_merges(
+    repos: &[GitRepository],
+    id: &str,
+    hosts: &BTreeMap<String, HostConfig>,
+    cache: &ApiCache,
+) -> Result<Vec<PendingMerge>> {
+    let mut merges = Vec::new();
+    for repo in repos {
+        let branches = branches(repo, id)?;
+        if branches.is_empty() {
+            continue;
+        }
+        for branch in branches {
+            let project = Project::for_repository(repo, hosts, cache)
+                .map_err(|e| Error::Operation(format!("{}: {}", repo.name(), e)))?;
+            let pull_request = project.find_pull_request(&branch)?.ok_or_else(|| {
+                Error::Operation(format!(
+                    "{}: no open pull request for {}",
+                    repo.name(),
+                    branch
+                ))
+            })?;
+            merges.push(PendingMerge {
+                repo: repo.clone(),
+                branch,
+                project,
+                pull_request,
+            });
+        }
+    }
+    Ok(merges)
+}
+
+/// Orders `merges` so the repositories a repository depends on, according
+/// to `depends_on` in the manifest, are merged first. The order is kept
+/// otherwise.
+pub fn order_by_dependencies(
+    merges: Vec<PendingMerge>,
+    manifest: &Manifest,
+) -> Result<Vec<PendingMerge>> {
+    manifest.dependency_order(merges, |merge| merge.repo.name())
+}
+
+/// Rebases the branches of a changeset onto a new base and force-pushes them
+/// with lease.
+///
+/// Local changes are stashed first and restored afterwards, on the branch
+/// that was checked out. A rebase stopping on a conflict is aborted, leaving
+/// the branch as it was, and the conflicting files are reported.
+pub struct RebaseOperation {
+    changeset: String,
+    onto: String,
+    push: bool,
+}
+
+impl RebaseOperation {
+    pub fn new(changeset: impl Into<String>, onto: impl Into<String>) -> Self {
+        RebaseOperation {
+            changeset: changeset.into(),
+            onto: onto.into(),
+            push: true,
+        }
+    }
+
+    /// Whether rebased branches having an upstream are pushed.
+    pub fn push(mut self, push: bool) -> Self {
+        self.push = push;
+        self
+    }
+
+    /// Fetches the remote `onto` belongs to, when it is a remote branch.
+    fn fetch(&self, repo: &GitRepository) -> Result<()> {
+        let git = repo.open()?;
+        let remotes = git.remotes()?;
+        let remote = remotes
+            .iter()
+            .flatten()
+            .find(|remote| self.onto.starts_with(&format!("{}/", remote)));
+        if let Some(remote) = remote {
+            repo.git(["fetch", "--quiet", remote])?;
+        }
+        Ok(())
+    }
+
+    /// Rebases `branch`, returning the conflicting files when it stopped.
+    fn rebase(&self, repo: &GitRepository, branch: &str) -> Result<Option<Vec<String>>> {
+        let error = match repo.git(["rebase", "--quiet", &self.onto, branch]) {
+            Ok(_) => return Ok(None),
+            Err(e) => e,
+        };
+        let conflicts = repo.git(["diff", "--name-only", "--diff-filter=U"])?;
+        // Fails when the rebase did not even start.
+        let _ = repo.git(["rebase", "--abort"]);
+        if conflicts.is_empty() {
+            return Err(error);
+        }
+        Ok(Some(conflicts.lines().map(str::to_string).collect()))
+    }
+
+    /// Rebases and pushes `branches`, leaving whichever checked out.
+    fn rebase_all(&self, repo: &GitRepository, branches: &[String]) -> Result<String> {
+        let mut rebased = Vec::new();
+        let mut conflicts = Vec::new();
+        let mut failures = Vec::new();
+        for branch in branches {
+            match self.rebase(repo, branch) {
+                Ok(None) => rebased.push(branch.as_str()),
+                Ok(Some(files)) => {
+                    conflicts.push(format!("{} conflicts in {}", branch, files.join(", ")))
+                }
+                Err(e) => failures.push(format!("{}: {}", branch, e)),
+            }
+        }
+        if self.push {
+            for branch in &rebased {
+                let upstream = format!("{}@{{upstream}}", branch);
+                let Ok(upstream) = repo.git(["rev-parse", "--abbrev-ref", &upstream]) else {
+                    continue;
+                };
+                let Some((remote, target)) = upstream.split_once('/') else {
+                    continue;
+                };
+                let refspec = format!("{}:{}", branch, target);
+                if let Err(e) =
+                    repo.git(["push", "--quiet", "--force-with-lease", remote, &refspec])
+                {
+                    failures.push(format!("{}: push failed: {}", branch, e));
+                }
+            }
+        }
+
+        failures.extend(conflicts);
+        if failures.is_empty() {
+            Ok(format!("rebased {}", rebased.join(", ")))
+        } else {
+            Err(Error::Operation(failures.join("; ")))
+        }
+    }
+}
+
+impl GitOperation for RebaseOperation {
+    fn name(&self) -> &str {
+        "rebase"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn pushes(&self) -> bool {
+        self.push
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let branches = branches(repo, &self.changeset)?;
+        if branches.is_empty() {
+            return Err(Error::Skipped(format!("no branch for {}", self.changeset)));
+        }
+        self.fetch(repo)?;
+
+        let original = match repo.current_branch()? {
+            Some(branch) => branch,
+            None => repo.head_sha()?,
+        };
+        let stashed = !ChangeCounts::collect(&repo.open()?)?.is_clean();
+        if stashed {
+            repo.git([
+                "stash",
+                "push",
+                "--include-untracked",
+                "--message",
+                "git-ws rebase",
+            ])?;
+        }
+
+        let result = self.rebase_all(repo, &branches);
+        // Put the repository back as it was, whatever happened.
+        let mut restored = repo.git(["checkout", "--quiet", &original]).map(drop);
+        if stashed {
+            restored = restored.and(repo.git(["stash", "pop", "--quiet"]).map(drop));
+        }
+        match (result, restored) {
+            (Ok(message), Ok(())) => Ok(message),
+            (Err(e), Ok(())) | (Ok(_), Err(e)) => Err(e),
+            (Err(e), Err(restoring)) => Err(Error::Operation(format!("{}; {}", e, restoring))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{RepoState, TestWorkspace};
+
+    #[test]
+    fn finds_the_branches_naming_the_id() {
+        let test = TestWorkspace::builder()
+            .repo("api", RepoState::Clean)
+            .build()
+            .unwrap();
+        let repo = test.repository("api");
+        let git = repo.open().unwrap();
+        let head = git.head().unwrap().peel_to_commit().unwrap();
+        for name in [
+            "feature/PAY-12",
+            "pay-12-client",
+            "PAY-12",
+            "feature/PAY-123",
+            "XPAY-12",
+            "PAY-120-fix",
+        ] {
+            git.branch(name, &head, false).unwrap();
+        }
+        assert_eq!(
+            branches(&repo, "PAY-12").unwrap(),
+            ["PAY-12", "feature/PAY-12", "pay-12-client"]
+        );
+        assert_eq!(branches(&repo, "pay-123").unwrap(), ["feature/PAY-123"]);
+    }
+}
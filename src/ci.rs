@@ -0,0 +1,270 @@
+//! Reproducible workspace checkouts for CI.
+//!
+//! Repositories declared in the manifest are fetched shallowly at the commit
+//! recorded in the lockfile, retrying network failures, and verified once
+//! everything is checked out. Progress is reported as [`ProgressEvent`]s,
+//! which the command line prints as JSON lines.
+//!
+//! Shallow fetches go through the git command line as libgit2 does not
+//! support them.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::bootstrap::Bootstrap;
+use crate::lockfile::Lockfile;
+use crate::manifest::Manifest;
+use crate::operations::GitOperation;
+use crate::repository::{ChangeCounts, GitRepository};
+use crate::workspace::Workspace;
+use crate::{Error, Result};
+
+/// Delay before the first retry, doubled for every further attempt.
+const BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    Started {
+        repo: String,
+    },
+    Retrying {
+        repo: String,
+        attempt: u32,
+        error: String,
+    },
+    CheckedOut {
+        repo: String,
+        sha: String,
+    },
+    Failed {
+        repo: String,
+        error: String,
+    },
+    Verified {
+        repo: String,
+        ok: bool,
+        detail: String,
+    },
+    Summary {
+        checked_out: usize,
+        failed: usize,
+        verified: usize,
+    },
+}
+
+pub type ProgressSink = Arc<dyn Fn(ProgressEvent) + Send + Sync>;
+
+struct Source {
+    url: String,
+    branch: Option<String>,
+    sha: Option<String>,
+}
+
+/// Materializes the repositories of a manifest at their locked commits.
+pub struct CiCheckoutOperation {
+    sources: HashMap<String, Source>,
+    retries: u32,
+    depth: u32,
+    bootstrap: Option<(Bootstrap, PathBuf)>,
+    progress: ProgressSink,
+}
+
+impl CiCheckoutOperation {
+    pub fn new(manifest: &Manifest, lock: &Lockfile, progress: ProgressSink) -> Self {
+        let sources = manifest
+            .repositories
+            .iter()
+            .map(|repo| {
+                let source = Source {
+                    url: repo.url.clone(),
+                    branch: repo.branch.clone(),
+                    sha: lock.sha(&repo.path).map(str::to_string),
+                };
+                (repo.path.clone(), source)
+            })
+            .collect();
+        CiCheckoutOperation {
+            sources,
+            retries: 3,
+            depth: 1,
+            bootstrap: None,
+            progress,
+        }
+    }
+
+    /// How many times a failed fetch is retried.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// History depth of the shallow fetches.
+    pub fn depth(mut self, depth: u32) -> Self {
+        self.depth = depth.max(1);
+        self
+    }
+
+    /// Bootstrap steps applied to the repositories created by the checkout,
+    /// with the workspace root relative paths are resolved against.
+    pub fn bootstrap(mut self, bootstrap: Bootstrap, root: impl Into<PathBuf>) -> Self {
+        if !bootstrap.is_empty() {
+            self.bootstrap = Some((bootstrap, root.into()));
+        }
+        self
+    }
+
+    /// The repositories to check out, located in `workspace`, or those
+    /// `workspace` is limited to.
+    pub fn repositories(&self, workspace: &Workspace) -> Result<Vec<GitRepository>> {
+        let mut repos = Vec::new();
+        for path in self.sources.keys() {
+            if workspace.selects(path)? {
+                repos.push(GitRepository::new(
+                    path.as_str(),
+                    workspace.root().join(path),
+                ));
+            }
+        }
+        repos.sort_by(|a, b| a.name().cmp(b.name()));
+        Ok(repos)
+    }
+
+    fn checkout(&self, repo: &GitRepository) -> Result<String> {
+        let source = self
+            .sources
+            .get(repo.name())
+            .ok_or_else(|| Error::RepositoryNotFound(repo.name().to_string()))?;
+        (self.progress)(ProgressEvent::Started {
+            repo: repo.name().to_string(),
+        });
+
+        fs::create_dir_all(repo.path())?;
+        let created = !repo.path().join(".git").exists();
+        if created {
+            repo.git(["init", "--quiet"])?;
+            repo.git(["remote", "add", "origin", &source.url])?;
+        } else {
+            repo.git(["remote", "set-url", "origin", &source.url])?;
+        }
+
+        let depth = format!("--depth={}", self.depth);
+        let target = match &source.sha {
+            Some(sha) => {
+                let shallow = self.with_retries(repo, || {
+                    repo.git(["fetch", "--quiet", &depth, "origin", sha])
+                });
+                if let Err(e) = shallow {
+                    if !is_refused(&e) {
+                        return Err(e);
+                    }
+                    // The server does not serve arbitrary commits, fetch the
+                    // whole history instead.
+                    self.with_retries(repo, || repo.git(["fetch", "--quiet", "origin"]))?;
+                }
+                sha.as_str()
+            }
+            None => {
+                let branch = source.branch.as_deref().unwrap_or("HEAD");
+                self.with_retries(repo, || {
+                    repo.git(["fetch", "--quiet", &depth, "origin", branch])
+                })?;
+                "FETCH_HEAD"
+            }
+        };
+        repo.git(["checkout", "--quiet", "--force", "--detach", target])?;
+        if let (true, Some((bootstrap, root))) = (created, &self.bootstrap) {
+            bootstrap.apply(root, repo)?;
+        }
+
+        let sha = repo.head_sha()?;
+        (self.progress)(ProgressEvent::CheckedOut {
+            repo: repo.name().to_string(),
+            sha: sha.clone(),
+        });
+        Ok(sha)
+    }
+
+    fn with_retries<T>(
+        &self,
+        repo: &GitRepository,
+        mut attempt: impl FnMut() -> Result<T>,
+    ) -> Result<T> {
+        let mut tries = 0;
+        loop {
+            match attempt() {
+                Ok(value) => return Ok(value),
+                Err(e) if tries < self.retries && !is_refused(&e) => {
+                    tries += 1;
+                    (self.progress)(ProgressEvent::Retrying {
+                        repo: repo.name().to_string(),
+                        attempt: tries,
+                        error: e.to_string(),
+                    });
+                    thread::sleep(BACKOFF * 2u32.pow(tries - 1));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl GitOperation for CiCheckoutOperation {
+    fn name(&self) -> &str {
+        "ci-checkout"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let result = self.checkout(repo);
+        if let Err(e) = &result {
+            (self.progress)(ProgressEvent::Failed {
+                repo: repo.name().to_string(),
+                error: e.to_string(),
+            });
+        }
+        result
+    }
+}
+
+/// Whether the server refused to serve a commit, which retrying won't fix.
+fn is_refused(error: &Error) -> bool {
+    let message = error.to_string();
+    [
+        "not our ref",
+        "couldn't find remote ref",
+        "unadvertised object",
+    ]
+    .iter()
+    .any(|refusal| message.contains(refusal))
+}
+
+/// Checks that `repo` is checked out at `expected` with a clean working tree.
+pub fn verify(repo: &GitRepository, expected: Option<&str>) -> Result<()> {
+    let sha = repo.head_sha()?;
+    if let Some(expected) = expected {
+        if sha != expected {
+            return Err(Error::Operation(format!(
+                "HEAD is {} instead of {}",
+                sha, expected
+            )));
+        }
+    }
+    let changes = ChangeCounts::collect(&repo.open()?)?;
+    if !changes.is_clean() {
+        return Err(Error::Operation(format!(
+            "working tree not clean: {}",
+            changes
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,121 @@
+//! Removing the untracked files of the repositories, like `git clean`.
+//!
+//! `clean` only lists what it would remove unless given `-f`, and the state
+//! directory of the workspace is never removed, when the workspace root is
+//! a repository itself.
+
+use crate::operations::GitOperation;
+use crate::pathspec::Pathspecs;
+use crate::repository::GitRepository;
+use crate::trash::Trash;
+use crate::workspace::STATE_DIR;
+use crate::{Error, Result};
+
+/// What is removed besides the untracked files, like the flags of
+/// `git clean`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CleanOptions {
+    /// Untracked directories too, `-d`.
+    pub directories: bool,
+    /// Ignored files too, build outputs included, `-x`.
+    pub ignored: bool,
+}
+
+impl CleanOptions {
+    fn args(self, pathspecs: &[String]) -> Vec<String> {
+        let mut args = vec![format!("--exclude=/{}", STATE_DIR)];
+        if self.directories {
+            args.push("-d".to_string());
+        }
+        if self.ignored {
+            args.push("-x".to_string());
+        }
+        args.push("--".to_string());
+        args.extend(pathspecs.iter().cloned());
+        args
+    }
+}
+
+/// The paths of `repo` matching `pathspecs` a clean would remove, relative
+/// to it, directories ending with a slash.
+pub fn candidates(
+    repo: &GitRepository,
+    pathspecs: &[String],
+    options: CleanOptions,
+) -> Result<Vec<String>> {
+    let mut args = vec![
+        "-c".to_string(),
+        "core.quotePath=false".to_string(),
+        "clean".to_string(),
+        "--dry-run".to_string(),
+    ];
+    args.extend(options.args(pathspecs));
+    let output = repo.git(&args)?;
+    Ok(output
+        .lines()
+        .filter_map(|line| line.strip_prefix("Would remove "))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Removes the untracked files matching the pathspecs of each repository,
+/// or moves them to a [`Trash`].
+pub struct CleanOperation {
+    pathspecs: Pathspecs,
+    options: CleanOptions,
+    trash: Option<Trash>,
+}
+
+impl CleanOperation {
+    pub fn new(pathspecs: Pathspecs, options: CleanOptions) -> Self {
+        CleanOperation {
+            pathspecs,
+            options,
+            trash: None,
+        }
+    }
+
+    /// Moves the files to `trash` instead of deleting them.
+    pub fn trash(mut self, trash: Trash) -> Self {
+        self.trash = Some(trash);
+        self
+    }
+}
+
+impl GitOperation for CleanOperation {
+    fn name(&self) -> &str {
+        "clean"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let Some(matching) = self.pathspecs.for_repo(repo.name()) else {
+            return Err(Error::Skipped("no matching path".to_string()));
+        };
+        let files = candidates(repo, &matching, self.options)?;
+        if files.is_empty() {
+            return Err(Error::Skipped("nothing to clean".to_string()));
+        }
+        match &self.trash {
+            Some(trash) => {
+                for file in &files {
+                    trash.put(repo, file.trim_end_matches('/'))?;
+                }
+                Ok(format!("{} moved to trash {}", files.len(), trash.id()))
+            }
+            None => {
+                let mut args = vec![
+                    "clean".to_string(),
+                    "--force".to_string(),
+                    "--quiet".to_string(),
+                ];
+                args.extend(self.options.args(&matching));
+                repo.git(&args)?;
+                Ok(format!("{} removed", files.len()))
+            }
+        }
+    }
+}
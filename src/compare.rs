@@ -0,0 +1,171 @@
+//! Comparing the state of two workspaces, like a laptop and a build server.
+//!
+//! Either side is reduced to the branch, commit and dirtiness of every
+//! repository, as far as it is known: a manifest only tells branches, a
+//! lockfile only commits, while a workspace, or the `list --json` records
+//! of one, tells everything.
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::lockfile::Lockfile;
+use crate::manifest::Manifest;
+use crate::output::RepoRecord;
+use crate::Result;
+
+/// What is known of a repository of one side.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepoState {
+    /// Checked out branch, `None` when unknown or HEAD is detached.
+    pub branch: Option<String>,
+    pub head: Option<String>,
+    pub dirty: Option<bool>,
+}
+
+/// Repositories of one side, by name.
+pub type WorkspaceStates = BTreeMap<String, RepoState>;
+
+pub fn from_records(records: &[RepoRecord]) -> WorkspaceStates {
+    records
+        .iter()
+        .map(|record| {
+            let state = RepoState {
+                branch: record.branch.clone(),
+                head: record.head.clone(),
+                dirty: Some(record.dirty),
+            };
+            (record.repo.clone(), state)
+        })
+        .collect()
+}
+
+/// The repositories of the output of `list --json` or `status --json`.
+pub fn from_json(json: &str) -> Result<WorkspaceStates> {
+    let records: Vec<Value> = serde_json::from_str(json)?;
+    let field =
+        |record: &Value, key: &str| record.get(key).and_then(Value::as_str).map(str::to_string);
+    Ok(records
+        .iter()
+        .filter_map(|record| {
+            let state = RepoState {
+                branch: field(record, "branch"),
+                head: field(record, "head"),
+                dirty: record.get("dirty").and_then(Value::as_bool),
+            };
+            Some((field(record, "repo")?, state))
+        })
+        .collect())
+}
+
+/// The repositories of a manifest, on the branch it declares.
+pub fn from_manifest(manifest: &Manifest) -> WorkspaceStates {
+    manifest
+        .repositories
+        .iter()
+        .map(|repo| {
+            let state = RepoState {
+                branch: repo.branch.clone(),
+                ..RepoState::default()
+            };
+            (repo.path.trim_end_matches('/').to_string(), state)
+        })
+        .collect()
+}
+
+/// The repositories of a lockfile, at the commit it locks.
+pub fn from_lockfile(lock: &Lockfile) -> WorkspaceStates {
+    lock.repositories
+        .iter()
+        .map(|repo| {
+            let state = RepoState {
+                head: Some(repo.sha.clone()),
+                ..RepoState::default()
+            };
+            (repo.path.trim_end_matches('/').to_string(), state)
+        })
+        .collect()
+}
+
+/// How a repository differs between the two sides.
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    pub repo: String,
+    pub here: String,
+    pub there: String,
+    pub differences: Vec<&'static str>,
+}
+
+/// The repositories differing between `here` and `there`, comparing only
+/// what both sides know.
+pub fn diff(here: &WorkspaceStates, there: &WorkspaceStates) -> Vec<Divergence> {
+    let mut names: Vec<_> = here.keys().chain(there.keys()).collect();
+    names.sort();
+    names.dedup();
+    let mut divergences = Vec::new();
+    for name in names {
+        let (ours, theirs) = match (here.get(name), there.get(name)) {
+            (Some(ours), Some(theirs)) => (ours, theirs),
+            (Some(ours), None) => {
+                divergences.push(Divergence {
+                    repo: name.clone(),
+                    here: describe(ours),
+                    there: "(missing)".to_string(),
+                    differences: vec!["only here"],
+                });
+                continue;
+            }
+            (None, Some(theirs)) => {
+                divergences.push(Divergence {
+                    repo: name.clone(),
+                    here: "(missing)".to_string(),
+                    there: describe(theirs),
+                    differences: vec!["only there"],
+                });
+                continue;
+            }
+            (None, None) => continue,
+        };
+        let mut differences = Vec::new();
+        // A manifest knows no detached HEAD, only a missing branch.
+        if ours.branch.is_some() && theirs.branch.is_some() && ours.branch != theirs.branch {
+            differences.push("branch");
+        }
+        if let (Some(a), Some(b)) = (&ours.head, &theirs.head) {
+            // Either may be abbreviated.
+            if !a.starts_with(b.as_str()) && !b.starts_with(a.as_str()) {
+                differences.push("commit");
+            }
+        }
+        if let (Some(a), Some(b)) = (ours.dirty, theirs.dirty) {
+            if a != b {
+                differences.push(if a { "dirty here" } else { "dirty there" });
+            }
+        }
+        if !differences.is_empty() {
+            divergences.push(Divergence {
+                repo: name.clone(),
+                here: describe(ours),
+                there: describe(theirs),
+                differences,
+            });
+        }
+    }
+    divergences
+}
+
+/// Like `main@2f8a17b, dirty`, leaving out what is unknown.
+fn describe(state: &RepoState) -> String {
+    let mut described = state.branch.clone().unwrap_or_default();
+    if let Some(head) = &state.head {
+        described.push('@');
+        described.push_str(&head[..head.len().min(7)]);
+    }
+    if state.dirty == Some(true) {
+        described.push_str(", dirty");
+    }
+    if described.is_empty() {
+        described.push('-');
+    }
+    described
+}
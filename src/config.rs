@@ -0,0 +1,182 @@
+//! Workspace configuration, read from `config.toml` in the state directory.
+//!
+//! ```toml
+//! [hosts."gitlab.example.com"]
+//! username = "oauth2"
+//! token = { command = "pass show gitlab-token" }
+//!
+//! [hosts."github.com"]
+//! token = { env = "GITHUB_TOKEN" }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::bootstrap::Bootstrap;
+use crate::forge::ForgeKind;
+use crate::freeze::FreezeConfig;
+use crate::interactive;
+use crate::plan::PlanConfig;
+use crate::policy::PolicyConfig;
+use crate::ticket::TicketConfig;
+use crate::trash::TrashConfig;
+use crate::view::ViewConfig;
+use crate::watch::WatchConfig;
+use crate::{Error, Result};
+
+const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Config {
+    /// Repositories worked on at a time, as many as CPUs when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<usize>,
+
+    /// Repositories left out of the workspace, like `legacy-*`, see
+    /// [`crate::workspace::name_matches`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+
+    /// Named groups of repositories, by pattern, along with those of the
+    /// manifest, see [`crate::manifest`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<String, Vec<String>>,
+
+    /// Settings per remote host name.
+    #[serde(default)]
+    pub hosts: BTreeMap<String, HostConfig>,
+
+    /// Monorepo-like view over the workspace, see [`crate::view`].
+    #[serde(default)]
+    pub view: ViewConfig,
+
+    /// Steps applied to repositories joining the workspace, see
+    /// [`crate::bootstrap`].
+    #[serde(default)]
+    pub bootstrap: Bootstrap,
+
+    /// Signing of plans, see [`crate::plan`].
+    #[serde(default)]
+    pub plan: PlanConfig,
+
+    /// Watching the repositories, see [`crate::watch`].
+    #[serde(default)]
+    pub watch: WatchConfig,
+
+    /// Keeping deleted files, see [`crate::trash`].
+    #[serde(default)]
+    pub trash: TrashConfig,
+
+    /// Approval of mutating batches, see [`crate::policy`].
+    #[serde(default)]
+    pub policy: PolicyConfig,
+
+    /// Ticket references and their tracker, see [`crate::ticket`].
+    #[serde(default)]
+    pub tickets: TicketConfig,
+
+    /// Windows without pushes, see [`crate::freeze`].
+    #[serde(default)]
+    pub freeze: FreezeConfig,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct HostConfig {
+    /// User name sent with the token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Where to read the personal access token for HTTPS remotes from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<TokenSource>,
+    /// Forge serving the host, guessed from the host name when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forge: Option<ForgeKind>,
+    /// Base URL of the forge API, e.g. `https://git.example.com/api/v4`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api: Option<String>,
+}
+
+/// Where a token is read from. Tokens are read again on every run, so
+/// rotating them needs no change to the configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenSource {
+    /// An environment variable.
+    Env(String),
+    /// A file holding the token, `~/` expands to the home directory.
+    File(PathBuf),
+    /// A shell command printing the token, e.g. `pass show gitlab-token`.
+    Command(String),
+}
+
+impl TokenSource {
+    pub fn resolve(&self) -> Result<Zeroizing<String>> {
+        let token = match self {
+            TokenSource::Env(name) => std::env::var(name).map_err(|_| {
+                Error::Operation(format!("environment variable {} is not set", name))
+            })?,
+            TokenSource::File(path) => fs::read_to_string(expand_home(path))?,
+            TokenSource::Command(command) => {
+                let output = interactive::shell(command).output()?;
+                if !output.status.success() {
+                    return Err(Error::Operation(format!(
+                        "token command `{}` failed ({})",
+                        command, output.status
+                    )));
+                }
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            }
+        };
+        let token = Zeroizing::new(token.trim().to_string());
+        if token.is_empty() {
+            return Err(Error::Operation("token is empty".to_string()));
+        }
+        Ok(token)
+    }
+}
+
+impl Config {
+    /// Loads the configuration from `dir`, the default configuration when
+    /// there is no file.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(CONFIG_FILE);
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Writes the configuration to `dir`, leaving out the empty sections and
+    /// those left to their defaults.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf> {
+        let mut value = toml::Value::try_from(self)?;
+        let defaults = toml::Value::try_from(Config::default())?;
+        if let toml::Value::Table(table) = &mut value {
+            table.retain(|key, value| {
+                !matches!(value, toml::Value::Table(section) if section.is_empty())
+                    && defaults.get(key) != Some(value)
+            });
+        }
+        fs::create_dir_all(dir)?;
+        let path = dir.join(CONFIG_FILE);
+        fs::write(&path, toml::to_string_pretty(&value)?)?;
+        Ok(path)
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+            .unwrap_or_else(|| thread::available_parallelism().map_or(4, |cpus| cpus.get()))
+    }
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
@@ -0,0 +1,207 @@
+//! Conflicts left in the repositories by a batch merge, pull or rebase.
+
+use std::collections::HashSet;
+use std::fs;
+
+use crate::operations::GitOperation;
+use crate::pathspec::Pathspecs;
+use crate::repository::GitRepository;
+use crate::{Error, Result};
+
+/// A conflicted file.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    /// Path relative to the repository.
+    pub path: String,
+    /// Conflict markers, `<<<<<<<`, left in the file. Zero once they are
+    /// all resolved, or for conflicts without markers, like a file deleted
+    /// on one side.
+    pub markers: usize,
+}
+
+/// The conflicted files of `repo` matching `pathspecs`, every file when
+/// there is none.
+pub fn list(repo: &GitRepository, pathspecs: &[String]) -> Result<Vec<Conflict>> {
+    let mut args = vec![
+        "diff".to_string(),
+        "--name-only".to_string(),
+        "--diff-filter=U".to_string(),
+        "-z".to_string(),
+        "--".to_string(),
+    ];
+    args.extend(pathspecs.iter().cloned());
+    let paths = repo.git(&args)?;
+    let mut conflicts = Vec::new();
+    for path in paths.split('\0').filter(|path| !path.is_empty()) {
+        let markers = fs::read(repo.path().join(path))
+            .map(|contents| {
+                contents
+                    .split(|byte| *byte == b'\n')
+                    .filter(|line| line.starts_with(b"<<<<<<<"))
+                    .count()
+            })
+            .unwrap_or_default();
+        conflicts.push(Conflict {
+            path: path.to_string(),
+            markers,
+        });
+    }
+    Ok(conflicts)
+}
+
+/// Side kept when resolving conflicts in bulk. As for `git checkout`,
+/// during a rebase "ours" is the branch rebased onto.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+impl Side {
+    fn flag(self) -> &'static str {
+        match self {
+            Side::Ours => "--ours",
+            Side::Theirs => "--theirs",
+        }
+    }
+
+    /// Index stage holding the version of this side.
+    fn stage(self) -> &'static str {
+        match self {
+            Side::Ours => "2",
+            Side::Theirs => "3",
+        }
+    }
+}
+
+/// The paths among `paths` that have no version on `side`, deleted on that
+/// side and modified on the other.
+fn deleted_on(repo: &GitRepository, side: Side, paths: &[&str]) -> Result<HashSet<String>> {
+    let mut args = vec!["ls-files", "-u", "-z", "--"];
+    args.extend(paths);
+    let unmerged = repo.git(&args)?;
+    let mut present = HashSet::new();
+    for entry in unmerged.split('\0').filter(|entry| !entry.is_empty()) {
+        // <mode> <object> <stage>\t<path>
+        let Some((info, path)) = entry.split_once('\t') else {
+            continue;
+        };
+        if info.split(' ').nth(2) == Some(side.stage()) {
+            present.insert(path.to_string());
+        }
+    }
+    Ok(paths
+        .iter()
+        .filter(|path| !present.contains(**path))
+        .map(|path| path.to_string())
+        .collect())
+}
+
+/// Resolves the conflicted files matching the pathspecs by keeping one
+/// side, and marks them resolved.
+pub struct ResolveOperation {
+    side: Side,
+    pathspecs: Pathspecs,
+}
+
+impl ResolveOperation {
+    pub fn new(side: Side, pathspecs: Pathspecs) -> Self {
+        ResolveOperation { side, pathspecs }
+    }
+}
+
+impl GitOperation for ResolveOperation {
+    fn name(&self) -> &str {
+        "resolve"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let Some(pathspecs) = self.pathspecs.for_repo(repo.name()) else {
+            return Err(Error::Skipped("no matching path".to_string()));
+        };
+        let conflicts = list(repo, &pathspecs)?;
+        if conflicts.is_empty() {
+            return Err(Error::Skipped("no conflict".to_string()));
+        }
+        let paths: Vec<&str> = conflicts
+            .iter()
+            .map(|conflict| conflict.path.as_str())
+            .collect();
+        // The side deleting a file has nothing to check out, keeping it
+        // means removing the file.
+        let deleted = deleted_on(repo, self.side, &paths)?;
+        let (removed, kept): (Vec<&str>, Vec<&str>) =
+            paths.iter().partition(|path| deleted.contains(**path));
+        if !kept.is_empty() {
+            let mut checkout = vec!["checkout", self.side.flag(), "--"];
+            checkout.extend(&kept);
+            repo.git(&checkout)?;
+            let mut add = vec!["add", "--"];
+            add.extend(&kept);
+            repo.git(&add)?;
+        }
+        if !removed.is_empty() {
+            let mut rm = vec!["rm", "--quiet", "--"];
+            rm.extend(&removed);
+            repo.git(&rm)?;
+        }
+        Ok(format!("{} file(s) resolved", paths.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{RepoState, TestWorkspace};
+
+    const AUTHOR: [&str; 4] = ["-c", "user.name=Test", "-c", "user.email=test@example.com"];
+
+    fn commit(repo: &GitRepository, message: &str) {
+        repo.git(AUTHOR.iter().copied().chain(["commit", "-qam", message]))
+            .unwrap();
+    }
+
+    #[test]
+    fn keeps_the_chosen_side() {
+        let test = TestWorkspace::builder()
+            .repo("api", RepoState::Conflicted)
+            .build()
+            .unwrap();
+        let repo = test.repository("api");
+        ResolveOperation::new(Side::Theirs, Pathspecs::default())
+            .execute(&repo)
+            .unwrap();
+        assert!(list(&repo, &[]).unwrap().is_empty());
+        assert_eq!(
+            fs::read_to_string(repo.path().join("README")).unwrap(),
+            "theirs\n"
+        );
+    }
+
+    #[test]
+    fn removes_files_deleted_on_the_chosen_side() {
+        let test = TestWorkspace::builder()
+            .repo("api", RepoState::Clean)
+            .build()
+            .unwrap();
+        let repo = test.repository("api");
+        repo.git(["checkout", "-qb", "gone"]).unwrap();
+        repo.git(["rm", "-q", "README"]).unwrap();
+        commit(&repo, "Delete");
+        repo.git(["checkout", "-q", "main"]).unwrap();
+        fs::write(repo.path().join("README"), "changed\n").unwrap();
+        commit(&repo, "Change");
+        assert!(repo.git(["merge", "gone"]).is_err());
+        assert_eq!(list(&repo, &[]).unwrap().len(), 1);
+
+        ResolveOperation::new(Side::Theirs, Pathspecs::default())
+            .execute(&repo)
+            .unwrap();
+        assert!(list(&repo, &[]).unwrap().is_empty());
+        assert!(!repo.path().join("README").exists());
+    }
+}
@@ -0,0 +1,158 @@
+//! Merging several repositories into one, each below its own directory.
+//!
+//! The history of every source repository is rewritten so each commit has
+//! its tree moved below the destination directory, then merged into the
+//! target as an unrelated history. `git log -- <dir>/<file>` in the target
+//! therefore shows the full history of files that came from a source.
+//! Authors, committers, dates and messages are kept; signatures are not, as
+//! they would no longer match.
+
+use std::collections::HashMap;
+
+use git2::{Oid, Repository, Sort};
+
+use crate::repository::{self, ChangeCounts, GitRepository};
+use crate::subtree;
+use crate::workspace::Workspace;
+use crate::{Error, Result};
+
+/// Mode of tree entries that are directories.
+const TREE_MODE: i32 = 0o040000;
+
+/// What consolidating one source repository involves.
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub repo: GitRepository,
+    pub branch: String,
+    /// Commits of the branch that get rewritten.
+    pub commits: usize,
+    /// Directory of the target the repository ends up in.
+    pub destination: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub target: GitRepository,
+    pub steps: Vec<Step>,
+}
+
+/// Checks that `sources` can be consolidated into `target` and describes
+/// how. Every source goes to the directory of its workspace relative name.
+pub fn plan(
+    workspace: &Workspace,
+    target: &GitRepository,
+    sources: &[GitRepository],
+) -> Result<Plan> {
+    subtree::ensure_not_pinned(workspace, target)?;
+    let target_repo = target.open()?;
+    if !ChangeCounts::collect(&target_repo)?.is_clean() {
+        return Err(Error::Operation(format!(
+            "{} has uncommitted changes",
+            target.name()
+        )));
+    }
+
+    let mut steps = Vec::new();
+    for source in sources {
+        if source.name() == target.name() {
+            return Err(Error::Operation(format!(
+                "cannot consolidate {} into itself",
+                source.name()
+            )));
+        }
+        let destination = source.name().to_string();
+        if target.path().join(&destination).exists() {
+            return Err(Error::Operation(format!(
+                "{}/{} already exists",
+                target.name(),
+                destination
+            )));
+        }
+        let repo = source.open()?;
+        let mut walk = repo.revwalk()?;
+        walk.push_head()?;
+        steps.push(Step {
+            repo: source.clone(),
+            branch: repository::head_description(&repo)?,
+            commits: walk.count(),
+            destination,
+        });
+    }
+    Ok(Plan {
+        target: target.clone(),
+        steps,
+    })
+}
+
+/// Carries out `plan`, returning a line per consolidated repository.
+pub fn apply(plan: &Plan) -> Result<Vec<String>> {
+    let mut done = Vec::new();
+    for step in &plan.steps {
+        let rewritten = rewrite(&step.repo.open()?, &step.destination)?.to_string();
+        subtree::transfer(&step.repo, &rewritten, &plan.target)?;
+        plan.target.git([
+            "merge",
+            "--quiet",
+            "--allow-unrelated-histories",
+            "--no-edit",
+            "-m",
+            &format!(
+                "Consolidate {} into {}/",
+                step.repo.name(),
+                step.destination
+            ),
+            &rewritten,
+        ])?;
+        done.push(format!(
+            "{} ({} commits) merged into {}/{}",
+            step.repo.name(),
+            step.commits,
+            plan.target.name(),
+            step.destination
+        ));
+    }
+    Ok(done)
+}
+
+/// Rewrites the history of HEAD with every tree moved below `prefix` and
+/// returns the rewritten HEAD. The new commits are not referenced by any
+/// branch.
+fn rewrite(repo: &Repository, prefix: &str) -> Result<Oid> {
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+
+    let mut rewritten: HashMap<Oid, Oid> = HashMap::new();
+    let mut head = None;
+    for oid in walk {
+        let oid = oid?;
+        let commit = repo.find_commit(oid)?;
+        let tree = repo.find_tree(nest(repo, commit.tree_id(), prefix)?)?;
+        let parents = commit
+            .parent_ids()
+            .map(|parent| repo.find_commit(rewritten[&parent]))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let parents: Vec<_> = parents.iter().collect();
+        let new = repo.commit(
+            None,
+            &commit.author(),
+            &commit.committer(),
+            &String::from_utf8_lossy(commit.message_raw_bytes()),
+            &tree,
+            &parents,
+        )?;
+        rewritten.insert(oid, new);
+        head = Some(new);
+    }
+    head.ok_or_else(|| Error::Operation("nothing to consolidate".to_string()))
+}
+
+/// A tree holding `tree` at `prefix`.
+fn nest(repo: &Repository, mut tree: Oid, prefix: &str) -> Result<Oid> {
+    for component in prefix.rsplit('/').filter(|c| !c.is_empty()) {
+        let mut builder = repo.treebuilder(None)?;
+        builder.insert(component, tree, TREE_MODE)?;
+        tree = builder.write()?;
+    }
+    Ok(tree)
+}
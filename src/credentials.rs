@@ -0,0 +1,306 @@
+//! Authentication for operations talking to remotes.
+//!
+//! HTTPS credentials come from the user's git credential helpers through the
+//! `git credential fill/approve/reject` protocol, so whatever helper is
+//! configured (manager-core, osxkeychain, libsecret, ...) is reused and
+//! credentials typed in are stored where git would store them.
+//!
+//! Hosts with a personal access token configured use it instead; tokens are
+//! read once per run and never handed to the credential helpers.
+//!
+//! Credentials typed in by the user are also cached per host for the
+//! lifetime of the process, so a batch touching many repositories on the same
+//! host asks only once. Operations needing the same host while the user is
+//! being asked wait for the answer instead of prompting again.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, Write};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use git2::{Cred, CredentialType, RemoteCallbacks};
+use zeroize::Zeroizing;
+
+use crate::config::HostConfig;
+use crate::interactive;
+use crate::progress;
+use crate::redact;
+use crate::remote::RemoteUrl;
+
+/// How many times libgit2 may ask for credentials before giving up. It keeps
+/// asking as long as the callback returns something, even if the same
+/// credentials were already rejected.
+const MAX_ATTEMPTS: usize = 3;
+
+#[derive(Clone)]
+struct Secret {
+    username: String,
+    password: Zeroizing<String>,
+}
+
+type Slot = Arc<Mutex<Option<Secret>>>;
+
+/// Cached credentials, one slot per host.
+static CACHE: OnceLock<Mutex<HashMap<String, Slot>>> = OnceLock::new();
+
+/// Serializes prompts so questions for different hosts do not interleave.
+static PROMPT: Mutex<()> = Mutex::new(());
+
+/// Per-host configuration, see [`configure_hosts`].
+static HOSTS: OnceLock<BTreeMap<String, HostConfig>> = OnceLock::new();
+
+/// Tokens resolved so far in this run, per host.
+static TOKENS: OnceLock<Mutex<HashMap<String, Secret>>> = OnceLock::new();
+
+/// Where the credentials answered to libgit2 came from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Origin {
+    Token,
+    Helper,
+    Prompt,
+}
+
+/// Sets the per-host configuration, typically from the workspace
+/// [`Config`](crate::config::Config). Only the first call has an effect.
+pub fn configure_hosts(hosts: BTreeMap<String, HostConfig>) {
+    let _ = HOSTS.set(hosts);
+}
+
+/// Credentials for one remote operation.
+///
+/// Pass [`Credentials::callbacks`] to the operation and call
+/// [`Credentials::approve`] once it succeeded, so the credential helpers can
+/// store what was used. Rejected credentials are reported to the helpers
+/// automatically.
+#[derive(Default)]
+pub struct Credentials {
+    used: Arc<Mutex<Option<(String, Origin, Secret)>>>,
+}
+
+impl Credentials {
+    pub fn new() -> Self {
+        Credentials::default()
+    }
+
+    /// Callbacks resolving credentials the way git does: keys from the ssh
+    /// agent and the credential helpers for HTTPS, prompting once per host
+    /// when the helpers have nothing.
+    pub fn callbacks<'a>(&self) -> RemoteCallbacks<'a> {
+        let used = Arc::clone(&self.used);
+        let mut attempts = 0;
+        let mut callbacks = RemoteCallbacks::new();
+        callbacks.credentials(move |url, username, allowed| {
+            attempts += 1;
+            if attempts > MAX_ATTEMPTS {
+                return Err(git2::Error::from_str(&format!(
+                    "authentication failed for {}",
+                    url
+                )));
+            }
+            if allowed.contains(CredentialType::SSH_KEY) {
+                return Cred::ssh_key_from_agent(username.unwrap_or("git"));
+            }
+            if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+                let mut used = used.lock().unwrap_or_else(|e| e.into_inner());
+                // Being asked again means what we answered was rejected.
+                let (origin, secret) = match used.take() {
+                    Some((url, Origin::Token, _)) => {
+                        return Err(git2::Error::from_str(&format!(
+                            "the token configured for {} was rejected",
+                            url
+                        )));
+                    }
+                    Some((url, _, rejected)) => {
+                        reject(&url, &rejected);
+                        forget(&url);
+                        (Origin::Prompt, cached_or_prompt(&url, username)?)
+                    }
+                    None => match token(url)? {
+                        Some(secret) => (Origin::Token, secret),
+                        None => match fill(url, username) {
+                            Some(secret) => (Origin::Helper, secret),
+                            None => (Origin::Prompt, cached_or_prompt(url, username)?),
+                        },
+                    },
+                };
+                let cred = Cred::userpass_plaintext(&secret.username, &secret.password);
+                *used = Some((url.to_string(), origin, secret));
+                return cred;
+            }
+            if allowed.contains(CredentialType::USERNAME) {
+                return Cred::username(username.unwrap_or("git"));
+            }
+            Cred::default()
+        });
+        callbacks
+    }
+
+    /// Tells the credential helpers the credentials used were accepted.
+    pub fn approve(&self) {
+        let used = self.used.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((url, origin, secret)) = used.as_ref() {
+            if *origin != Origin::Token {
+                approve(url, secret);
+            }
+        }
+    }
+}
+
+/// The token configured for the host of `url`, resolved on first use.
+fn token(url: &str) -> Result<Option<Secret>, git2::Error> {
+    let host = match RemoteUrl::parse(url) {
+        Some(remote) => remote.host,
+        None => return Ok(None),
+    };
+    let config = match HOSTS.get().and_then(|hosts| hosts.get(&host)) {
+        Some(config) => config,
+        None => return Ok(None),
+    };
+    let source = match &config.token {
+        Some(source) => source,
+        None => return Ok(None),
+    };
+    let tokens = TOKENS.get_or_init(Default::default);
+    let mut tokens = tokens.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(secret) = tokens.get(&host) {
+        return Ok(Some(secret.clone()));
+    }
+    let password = source.resolve().map_err(|e| {
+        git2::Error::from_str(&format!("cannot read the token for {}: {}", host, e))
+    })?;
+    redact::register(&password);
+    let secret = Secret {
+        username: config
+            .username
+            .clone()
+            .unwrap_or_else(|| "x-access-token".to_string()),
+        password,
+    };
+    tokens.insert(host, secret.clone());
+    Ok(Some(secret))
+}
+
+/// Drops the cached credentials of the host of `url`.
+pub fn forget(url: &str) {
+    *slot(url).lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+fn slot(url: &str) -> Slot {
+    let key = match RemoteUrl::parse(url) {
+        Some(remote) => format!("{}://{}", remote.scheme, remote.host),
+        None => url.to_string(),
+    };
+    let cache = CACHE.get_or_init(Default::default);
+    let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+    Arc::clone(cache.entry(key).or_default())
+}
+
+fn cached_or_prompt(url: &str, username: Option<&str>) -> Result<Secret, git2::Error> {
+    let slot = slot(url);
+    // Held while prompting: other operations for the same host wait here and
+    // then reuse the answer.
+    let mut secret = slot.lock().unwrap_or_else(|e| e.into_inner());
+    if secret.is_none() {
+        interactive::require_input(format!("authenticating to {}", url))
+            .map_err(|e| git2::Error::from_str(&format!("{}, configure a credential helper", e)))?;
+        let prompted = prompt(url, username)
+            .map_err(|e| git2::Error::from_str(&format!("cannot read credentials: {}", e)))?;
+        redact::register(&prompted.password);
+        *secret = Some(prompted);
+    }
+    Ok(secret.clone().expect("credentials just stored"))
+}
+
+fn prompt(url: &str, username: Option<&str>) -> io::Result<Secret> {
+    let _prompt = PROMPT.lock().unwrap_or_else(|e| e.into_inner());
+    progress::suspend(|| ask(url, username))
+}
+
+fn ask(url: &str, username: Option<&str>) -> io::Result<Secret> {
+    let username = match username {
+        Some(username) => username.to_string(),
+        None => {
+            eprint!("Username for {}: ", url);
+            io::stderr().flush()?;
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            line.trim().to_string()
+        }
+    };
+    let password = rpassword::prompt_password(format!("Password for {}@{}: ", username, url))?;
+    Ok(Secret {
+        username,
+        password: Zeroizing::new(password),
+    })
+}
+
+/// Whether the credential helpers provide credentials for `url`.
+pub fn helpers_provide(url: &str) -> bool {
+    fill(url, None).is_some()
+}
+
+/// Asks the credential helpers, without letting git prompt itself.
+fn fill(url: &str, username: Option<&str>) -> Option<Secret> {
+    let mut input = format!("url={}\n", url);
+    if let Some(username) = username {
+        input.push_str(&format!("username={}\n", username));
+    }
+    let output = git_credential("fill", &input).ok()?;
+    let mut username = None;
+    let mut password = None;
+    for line in output.lines() {
+        if let Some(value) = line.strip_prefix("username=") {
+            username = Some(value.to_string());
+        } else if let Some(value) = line.strip_prefix("password=") {
+            password = Some(Zeroizing::new(value.to_string()));
+        }
+    }
+    let password = password?;
+    redact::register(&password);
+    Some(Secret {
+        username: username?,
+        password,
+    })
+}
+
+fn approve(url: &str, secret: &Secret) {
+    let _ = git_credential("approve", &describe(url, secret));
+}
+
+fn reject(url: &str, secret: &Secret) {
+    let _ = git_credential("reject", &describe(url, secret));
+}
+
+fn describe(url: &str, secret: &Secret) -> Zeroizing<String> {
+    Zeroizing::new(format!(
+        "url={}\nusername={}\npassword={}\n",
+        url,
+        secret.username,
+        secret.password.as_str()
+    ))
+}
+
+/// Runs `git credential <action>` with `input`, returning its output.
+fn git_credential(action: &str, input: &str) -> io::Result<Zeroizing<String>> {
+    let mut child = Command::new("git")
+        .args(["credential", action])
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes())?;
+        stdin.write_all(b"\n")?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "git credential {} failed",
+            action
+        )));
+    }
+    Ok(Zeroizing::new(
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+    ))
+}
@@ -0,0 +1,64 @@
+//! Diffs of the local changes of the repositories of a workspace.
+
+use crate::repository::GitRepository;
+use crate::Result;
+
+/// Counts of a diff, as `git diff --stat` sums them up.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DiffStat {
+    pub files: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl DiffStat {
+    pub fn add(&mut self, other: DiffStat) {
+        self.files += other.files;
+        self.insertions += other.insertions;
+        self.deletions += other.deletions;
+    }
+}
+
+fn args(staged: bool, pathspecs: &[String], options: &[&str]) -> Vec<String> {
+    let mut args = vec!["diff".to_string()];
+    if staged {
+        args.push("--cached".to_string());
+    }
+    args.extend(options.iter().map(|option| option.to_string()));
+    args.push("--".to_string());
+    args.extend(pathspecs.iter().cloned());
+    args
+}
+
+/// The changes of `repo` to paths matching `pathspecs`, every path when
+/// there is none: the staged ones when `staged` is set, those not staged
+/// yet otherwise. Untracked files are left out, as git does.
+pub fn diff(
+    repo: &GitRepository,
+    staged: bool,
+    pathspecs: &[String],
+    color: bool,
+) -> Result<String> {
+    let color = if color {
+        "--color=always"
+    } else {
+        "--color=never"
+    };
+    repo.git(args(staged, pathspecs, &[color]))
+}
+
+/// The counts of the changes [`diff`] shows.
+pub fn stat(repo: &GitRepository, staged: bool, pathspecs: &[String]) -> Result<DiffStat> {
+    let numstat = repo.git(args(staged, pathspecs, &["--numstat"]))?;
+    let mut stat = DiffStat::default();
+    for line in numstat.lines() {
+        let mut fields = line.split('\t');
+        // Binary files count `-` lines.
+        let insertions = fields.next().and_then(|count| count.parse().ok());
+        let deletions = fields.next().and_then(|count| count.parse().ok());
+        stat.files += 1;
+        stat.insertions += insertions.unwrap_or(0);
+        stat.deletions += deletions.unwrap_or(0);
+    }
+    Ok(stat)
+}
@@ -0,0 +1,186 @@
+//! Diagnostics for the workspace and the environment it runs in.
+
+use std::collections::BTreeSet;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use crate::credentials;
+use crate::remote::{RemoteUrl, Scheme};
+use crate::repository::{GitRepository, Snapshot};
+
+/// A repository with its snapshot, or the error taking it.
+pub type RepoSnapshot = (GitRepository, crate::Result<Snapshot>);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Result of a single diagnostic.
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub section: &'static str,
+    /// What was checked: a repository, a host or the environment.
+    pub subject: String,
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(section: &'static str, subject: impl Into<String>, name: &'static str) -> Self {
+        Check {
+            section,
+            subject: subject.into(),
+            name,
+            ok: false,
+            detail: String::new(),
+        }
+    }
+
+    fn result(mut self, ok: bool, detail: impl Into<String>) -> Self {
+        self.ok = ok;
+        self.detail = detail.into();
+        self
+    }
+}
+
+/// Checks the git installation and that every repository can be opened.
+pub fn local_checks(repos: &[RepoSnapshot]) -> Vec<Check> {
+    let mut checks = Vec::new();
+    let git = Check::new("local", "environment", "git executable");
+    checks.push(match Command::new("git").arg("--version").output() {
+        Ok(output) if output.status.success() => git.result(
+            true,
+            String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        ),
+        _ => git.result(false, "git not found on PATH"),
+    });
+    for (repo, snapshot) in repos {
+        let check = Check::new("local", repo.name(), "repository");
+        checks.push(match snapshot {
+            Ok(snapshot) => check.result(
+                true,
+                format!("{}: {}", snapshot.head_description(), snapshot.changes),
+            ),
+            Err(e) => check.result(false, e.to_string()),
+        });
+    }
+    checks
+}
+
+/// Checks every distinct remote host of the workspace: whether it is
+/// reachable, whether ssh hosts are trusted and whether HTTPS credentials
+/// resolve, plus whether the ssh agent can authenticate at all.
+pub fn network_checks(repos: &[RepoSnapshot]) -> Vec<Check> {
+    let hosts = remote_hosts(repos);
+    let mut checks = Vec::new();
+    if hosts.iter().any(|(scheme, _, _)| *scheme == Scheme::Ssh) {
+        checks.push(check_ssh_agent());
+    }
+    let per_host: Vec<Vec<Check>> = thread::scope(|scope| {
+        let handles: Vec<_> = hosts
+            .iter()
+            .map(|(scheme, host, port)| scope.spawn(move || check_host(*scheme, host, *port)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or_default())
+            .collect()
+    });
+    checks.extend(per_host.into_iter().flatten());
+    checks
+}
+
+fn remote_hosts(repos: &[RepoSnapshot]) -> BTreeSet<(Scheme, String, u16)> {
+    repos
+        .iter()
+        .filter_map(|(_, snapshot)| snapshot.as_ref().ok())
+        .flat_map(|snapshot| &snapshot.remotes)
+        .filter_map(|remote| remote.url.as_deref().and_then(RemoteUrl::parse))
+        .map(|url| (url.scheme, url.host.clone(), url.port_or_default()))
+        .collect()
+}
+
+fn check_host(scheme: Scheme, host: &str, port: u16) -> Vec<Check> {
+    let subject = format!("{}://{}:{}", scheme, host, port);
+    let reachable = Check::new("network", &subject, "reachable");
+    let reachable = match connect(host, port) {
+        Ok(()) => reachable.result(true, "connected"),
+        Err(e) => return vec![reachable.result(false, e)],
+    };
+    let mut checks = vec![reachable];
+    match scheme {
+        Scheme::Ssh => checks.push(check_host_key(&subject, host, port)),
+        Scheme::Https | Scheme::Http => checks.push(check_credentials(&subject, scheme, host)),
+        Scheme::Git => {}
+    }
+    checks
+}
+
+fn connect(host: &str, port: u16) -> Result<(), String> {
+    let addrs = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("cannot resolve {}: {}", host, e))?;
+    let mut last_error = format!("no address found for {}", host);
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(_) => return Ok(()),
+            Err(e) => last_error = format!("{}: {}", addr, e),
+        }
+    }
+    Err(last_error)
+}
+
+fn check_ssh_agent() -> Check {
+    let check = Check::new("network", "ssh-agent", "usable keys");
+    if std::env::var_os("SSH_AUTH_SOCK").is_none() {
+        return check.result(false, "SSH_AUTH_SOCK is not set, start ssh-agent");
+    }
+    match Command::new("ssh-add")
+        .arg("-l")
+        .stdin(Stdio::null())
+        .output()
+    {
+        Ok(output) if output.status.success() => {
+            let keys = String::from_utf8_lossy(&output.stdout).lines().count();
+            check.result(true, format!("{} key(s) loaded", keys))
+        }
+        Ok(output) if output.status.code() == Some(1) => {
+            check.result(false, "the agent has no keys, add one with ssh-add")
+        }
+        Ok(_) => check.result(false, "cannot connect to the agent"),
+        Err(e) => check.result(false, format!("cannot run ssh-add: {}", e)),
+    }
+}
+
+fn check_host_key(subject: &str, host: &str, port: u16) -> Check {
+    let check = Check::new("network", subject, "host key trusted");
+    let entry = if port == Scheme::Ssh.default_port() {
+        host.to_string()
+    } else {
+        format!("[{}]:{}", host, port)
+    };
+    match Command::new("ssh-keygen")
+        .args(["-F", &entry])
+        .stdin(Stdio::null())
+        .output()
+    {
+        Ok(output) if output.status.success() && !output.stdout.is_empty() => {
+            check.result(true, "found in known_hosts")
+        }
+        Ok(_) => check.result(
+            false,
+            format!("not in known_hosts, run `ssh-keyscan {}`", host),
+        ),
+        Err(e) => check.result(false, format!("cannot run ssh-keygen: {}", e)),
+    }
+}
+
+fn check_credentials(subject: &str, scheme: Scheme, host: &str) -> Check {
+    let check = Check::new("network", subject, "credentials");
+    if credentials::helpers_provide(&format!("{}://{}/", scheme, host)) {
+        check.result(true, "resolved by the credential helper")
+    } else {
+        check.result(false, "no credential helper provides credentials")
+    }
+}
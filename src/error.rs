@@ -0,0 +1,39 @@
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{}", .0.message())]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    TomlDe(#[from] toml::de::Error),
+
+    #[error(transparent)]
+    TomlSer(#[from] toml::ser::Error),
+
+    #[error("repository `{0}` is not part of the workspace")]
+    RepositoryNotFound(String),
+
+    #[error("{0} requires user input, which is disabled by --non-interactive")]
+    InputRequired(String),
+
+    #[error("{0}")]
+    Operation(String),
+
+    /// The repository was left alone on purpose, e.g. by a middleware.
+    #[error("{0}")]
+    Skipped(String),
+
+    /// The operation completed, with a problem worth reporting that is not a
+    /// failure.
+    #[error("{0}")]
+    Warning(String),
+}
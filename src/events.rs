@@ -0,0 +1,98 @@
+//! A stream of what happens in the workspace, for GUIs and other consumers
+//! that would otherwise have to poll.
+//!
+//! Create an [`EventBus`], attach it to the [`Workspace`](crate::workspace::Workspace)
+//! and to a [`WorkspaceWatcher`](crate::watch::WorkspaceWatcher), and add it
+//! to the [`BatchExecutor`](crate::executor::BatchExecutor) as a
+//! middleware, then [`subscribe`](EventBus::subscribe) from as many tasks as
+//! needed. Events are dropped when nobody listens; a subscriber falling more
+//! than the capacity behind misses the oldest events.
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::middleware::{Middleware, Next};
+use crate::operations::{GitOperation, OperationStatus};
+use crate::redact;
+use crate::repository::{ChangeCounts, GitRepository};
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WorkspaceEvent {
+    RepositoryDiscovered {
+        repo: String,
+    },
+    OperationStarted {
+        repo: String,
+        operation: String,
+    },
+    OperationFinished {
+        repo: String,
+        operation: String,
+        status: OperationStatus,
+        message: String,
+    },
+    /// A watched repository got changes it did not have before.
+    RepositoryDirty {
+        repo: String,
+        changes: ChangeCounts,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<WorkspaceEvent>,
+}
+
+impl EventBus {
+    /// A bus keeping at most `capacity` events for slow subscribers.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        EventBus { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<WorkspaceEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn emit(&self, event: WorkspaceEvent) {
+        // Failing only means there is no subscriber right now.
+        let _ = self.sender.send(event);
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        EventBus::new(256)
+    }
+}
+
+/// Reports the start and end of every operation.
+impl Middleware for EventBus {
+    fn call(
+        &self,
+        repo: &GitRepository,
+        operation: &dyn GitOperation,
+        next: Next<'_>,
+    ) -> Result<String> {
+        self.emit(WorkspaceEvent::OperationStarted {
+            repo: repo.name().to_string(),
+            operation: operation.name().to_string(),
+        });
+        let result = next.run(repo);
+        let (status, message) = match &result {
+            Ok(message) => (OperationStatus::Success, message.clone()),
+            Err(Error::Skipped(reason)) => (OperationStatus::Skipped, reason.clone()),
+            Err(Error::Warning(warning)) => (OperationStatus::Warning, warning.clone()),
+            Err(e) => (OperationStatus::Failed, e.to_string()),
+        };
+        self.emit(WorkspaceEvent::OperationFinished {
+            repo: repo.name().to_string(),
+            operation: operation.name().to_string(),
+            status,
+            message: redact::redact(&message),
+        });
+        result
+    }
+}
@@ -0,0 +1,490 @@
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+
+use crate::middleware::{Middleware, Next};
+use crate::operations::{GitOperation, OperationResult, OperationStatus};
+use crate::precondition::Preconditions;
+use crate::process::{self, Signal};
+use crate::progress::ProgressBars;
+use crate::redact;
+use crate::repository::GitRepository;
+use crate::schedule::{self, Limits};
+use crate::{Error, Result};
+
+/// How long the commands running get to exit when a batch fails fast.
+pub const FAIL_FAST_GRACE: Duration = Duration::from_secs(5);
+
+/// Runs an operation against many repositories, at most `concurrency` at a
+/// time.
+pub struct BatchExecutor {
+    concurrency: usize,
+    local_concurrency: usize,
+    pinned: BTreeSet<String>,
+    cancel: CancelToken,
+    deadline: Option<Instant>,
+    fail_fast: bool,
+    bars: Option<ProgressBars>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    preconditions: Arc<Preconditions>,
+}
+
+impl BatchExecutor {
+    pub fn new(concurrency: usize) -> Self {
+        BatchExecutor {
+            concurrency: concurrency.max(1),
+            local_concurrency: thread::available_parallelism().map_or(4, |cpus| cpus.get()),
+            pinned: BTreeSet::new(),
+            cancel: CancelToken::default(),
+            deadline: None,
+            fail_fast: false,
+            bars: None,
+            middleware: Vec::new(),
+            preconditions: Arc::new(Preconditions::default()),
+        }
+    }
+
+    /// How many local phases of pipelined operations run at a time, the
+    /// number of CPUs by default; `concurrency` limits their network
+    /// phases. See [`crate::schedule`].
+    pub fn with_local_concurrency(mut self, local_concurrency: usize) -> Self {
+        self.local_concurrency = local_concurrency.max(1);
+        self
+    }
+
+    /// Repositories that mutating operations must skip.
+    pub fn with_pinned(mut self, pinned: BTreeSet<String>) -> Self {
+        self.pinned = pinned;
+        self
+    }
+
+    /// Token that stops the executor from starting work on further
+    /// repositories once cancelled.
+    pub fn with_cancellation(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Stops starting repositories as `deadline` nears: a repository is
+    /// deferred, skipped, when the time left is shorter than the repositories
+    /// done so far took on average. Those started run to their end.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Cancels the batch as soon as a repository fails: those not started
+    /// are skipped, and the commands running are stopped, getting
+    /// [`FAIL_FAST_GRACE`] to exit. Git operations running run to their end.
+    pub fn with_fail_fast(mut self) -> Self {
+        self.fail_fast = true;
+        self
+    }
+
+    /// Draws the progress of the batches with `bars`: a spinner per
+    /// repository running and a bar counting those done.
+    pub fn with_progress_bars(mut self, bars: ProgressBars) -> Self {
+        self.bars = Some(bars);
+        self
+    }
+
+    /// Adds `middleware` around every operation, after the middleware added
+    /// before. Closures run by [`BatchExecutor::for_each`] are not operations
+    /// and bypass the middleware.
+    pub fn with_middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.middleware.push(middleware);
+        self
+    }
+
+    /// Preconditions repositories must meet for mutating operations to
+    /// change them, checked after the middleware, right before the
+    /// operation runs. Repositories that do not are skipped. Closures do not
+    /// check them.
+    pub fn with_preconditions(mut self, preconditions: Preconditions) -> Self {
+        self.preconditions = Arc::new(preconditions);
+        self
+    }
+
+    /// Runs `operation` against every repository, through the middleware,
+    /// and returns the results in the order of `repos`. The phases of a
+    /// pipelined operation run under their own limits.
+    pub async fn execute_operation(
+        &self,
+        repos: &[GitRepository],
+        operation: Arc<dyn GitOperation>,
+    ) -> Vec<OperationResult> {
+        self.execute_operation_streaming(repos, operation, |_| {})
+            .await
+    }
+
+    /// Like [`BatchExecutor::execute_operation`], also calling `on_result`
+    /// with the result of each repository as soon as it is done, in the
+    /// order they finish.
+    pub async fn execute_operation_streaming(
+        &self,
+        repos: &[GitRepository],
+        operation: Arc<dyn GitOperation>,
+        on_result: impl Fn(&OperationResult) + Send + Sync + 'static,
+    ) -> Vec<OperationResult> {
+        let mutating = operation.is_mutating();
+        let chain: Arc<[Arc<dyn Middleware>]> = self.middleware.clone().into();
+        let preconditions = Arc::clone(&self.preconditions);
+        let limits = operation
+            .is_pipelined()
+            .then(|| Arc::new(Limits::new(self.concurrency, self.local_concurrency)));
+        let concurrency = limits
+            .as_ref()
+            .map_or(self.concurrency, |limits| limits.total());
+        let on_done = move |outcome: &RepoOutcome<String>| on_result(&outcome.to_result());
+        let outcomes = self
+            .spawn_each(repos, mutating, concurrency, on_done, move |handle| {
+                let operation = Arc::clone(&operation);
+                let chain = Arc::clone(&chain);
+                let preconditions = Arc::clone(&preconditions);
+                let limits = limits.clone();
+                async move {
+                    handle
+                        .run_blocking(move |repo| {
+                            let run =
+                                || Next::new(operation.as_ref(), &chain, &preconditions).run(repo);
+                            match limits {
+                                Some(limits) => schedule::with_limits(limits, run),
+                                None => run(),
+                            }
+                        })
+                        .await
+                }
+            })
+            .await;
+        outcomes.iter().map(RepoOutcome::to_result).collect()
+    }
+
+    /// Runs `f` against every repository, at most `concurrency` at a time, and
+    /// returns the outcomes in the order of `repos`.
+    ///
+    /// Use [`BatchExecutor::for_each_mutating`] for closures changing the
+    /// repositories, so pinned repositories are skipped.
+    pub async fn for_each<F, Fut, T>(&self, repos: &[GitRepository], f: F) -> Vec<RepoOutcome<T>>
+    where
+        F: Fn(RepoHandle) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawn_each(repos, false, self.concurrency, |_| {}, f)
+            .await
+    }
+
+    /// Like [`BatchExecutor::for_each`], skipping pinned repositories.
+    pub async fn for_each_mutating<F, Fut, T>(
+        &self,
+        repos: &[GitRepository],
+        f: F,
+    ) -> Vec<RepoOutcome<T>>
+    where
+        F: Fn(RepoHandle) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.spawn_each(repos, true, self.concurrency, |_| {}, f)
+            .await
+    }
+
+    /// Runs `f` against every repository, calling `on_done` with each
+    /// outcome as soon as it is known.
+    async fn spawn_each<D, F, Fut, T>(
+        &self,
+        repos: &[GitRepository],
+        mutating: bool,
+        concurrency: usize,
+        on_done: D,
+        f: F,
+    ) -> Vec<RepoOutcome<T>>
+    where
+        D: Fn(&RepoOutcome<T>) + Send + Sync + 'static,
+        F: Fn(RepoHandle) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let bars = self
+            .bars
+            .as_ref()
+            .and_then(|bars| bars.batch(repos.len()))
+            .map(Arc::new);
+        let on_done: OnDone<T> = match bars.clone() {
+            // Results printed as they come must not garble the bars.
+            Some(bars) => Arc::new(move |outcome: &RepoOutcome<T>| {
+                bars.finished(&outcome.repo, matches!(outcome.outcome, Outcome::Failed(_)));
+                bars.suspend(|| on_done(outcome));
+            }),
+            None => Arc::new(on_done),
+        };
+        let f = Arc::new(f);
+        let pace = Arc::new(Mutex::new(Pace::default()));
+        let semaphore = Arc::new(Semaphore::new(concurrency));
+        let mut handles = Vec::with_capacity(repos.len());
+
+        for repo in repos {
+            let name = repo.name().to_string();
+            if mutating && self.pinned.contains(&name) {
+                let outcome = RepoOutcome {
+                    repo: name,
+                    outcome: Outcome::Skipped("pinned".to_string()),
+                    duration: Duration::ZERO,
+                };
+                on_done(&outcome);
+                handles.push(Pending::Done(outcome));
+                continue;
+            }
+            let handle = RepoHandle {
+                repo: repo.clone(),
+                cancel: self.cancel.clone(),
+            };
+            let f = Arc::clone(&f);
+            let on_done = Arc::clone(&on_done);
+            let pace = Arc::clone(&pace);
+            let deadline = self.deadline;
+            let semaphore = Arc::clone(&semaphore);
+            let bars = bars.clone();
+            let fail_fast = self.fail_fast.then(|| self.cancel.clone());
+            let task = tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await.expect("semaphore closed");
+                let near = |deadline: Instant| {
+                    let average = pace.lock().unwrap_or_else(|e| e.into_inner()).average();
+                    Instant::now() + average >= deadline
+                };
+                let (outcome, duration) = if handle.is_cancelled() {
+                    (Outcome::Skipped("cancelled".to_string()), Duration::ZERO)
+                } else if deadline.is_some_and(near) {
+                    (
+                        Outcome::Skipped("deferred, the deadline is near".to_string()),
+                        Duration::ZERO,
+                    )
+                } else {
+                    if let Some(bars) = &bars {
+                        bars.started(&name);
+                    }
+                    let start = Instant::now();
+                    let outcome = match f(handle).await {
+                        Ok(value) => Outcome::Success(value),
+                        Err(Error::Skipped(reason)) => Outcome::Skipped(reason),
+                        Err(Error::Warning(warning)) => Outcome::Warning(warning),
+                        Err(e) => Outcome::Failed(e),
+                    };
+                    let duration = start.elapsed();
+                    pace.lock().unwrap_or_else(|e| e.into_inner()).add(duration);
+                    (outcome, duration)
+                };
+                let outcome = RepoOutcome {
+                    repo: name,
+                    outcome,
+                    duration,
+                };
+                let failed = matches!(outcome.outcome, Outcome::Failed(_));
+                if let Some(cancel) = fail_fast.filter(|cancel| failed && !cancel.is_cancelled()) {
+                    cancel.cancel();
+                    tokio::task::spawn_blocking(|| {
+                        process::stop_children(Signal::Terminate, FAIL_FAST_GRACE)
+                    });
+                }
+                on_done(&outcome);
+                outcome
+            });
+            handles.push(Pending::Running(repo.name().to_string(), task));
+        }
+
+        let mut outcomes = Vec::with_capacity(handles.len());
+        for handle in handles {
+            let outcome = match handle {
+                Pending::Running(repo, task) => match task.await {
+                    Ok(outcome) => outcome,
+                    Err(e) => {
+                        let outcome = RepoOutcome {
+                            repo,
+                            outcome: Outcome::Failed(Error::Operation(format!(
+                                "task failed: {}",
+                                e
+                            ))),
+                            duration: Duration::ZERO,
+                        };
+                        on_done(&outcome);
+                        outcome
+                    }
+                },
+                Pending::Done(outcome) => outcome,
+            };
+            outcomes.push(outcome);
+        }
+        outcomes
+    }
+}
+
+/// Called with the outcome of each repository as it is done.
+type OnDone<T> = Arc<dyn Fn(&RepoOutcome<T>) + Send + Sync>;
+
+/// How long the repositories done took, for the deadline.
+#[derive(Debug, Default)]
+struct Pace {
+    total: Duration,
+    done: u32,
+}
+
+impl Pace {
+    fn add(&mut self, duration: Duration) {
+        self.total += duration;
+        self.done += 1;
+    }
+
+    fn average(&self) -> Duration {
+        self.total.checked_div(self.done).unwrap_or_default()
+    }
+}
+
+/// A repository of [`BatchExecutor::spawn_each`], its task or its outcome
+/// when it was not run.
+enum Pending<T> {
+    Running(String, JoinHandle<RepoOutcome<T>>),
+    Done(RepoOutcome<T>),
+}
+
+/// Cancels a batch: repositories not started yet are skipped, and closures
+/// can check [`RepoHandle::is_cancelled`] to stop early.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// The repository a closure run by [`BatchExecutor::for_each`] works on.
+#[derive(Debug, Clone)]
+pub struct RepoHandle {
+    repo: GitRepository,
+    cancel: CancelToken,
+}
+
+impl RepoHandle {
+    pub fn repo(&self) -> &GitRepository {
+        &self.repo
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// Runs blocking work, such as git2 calls or git commands, on the
+    /// blocking thread pool.
+    pub async fn run_blocking<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&GitRepository) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let repo = self.repo.clone();
+        tokio::task::spawn_blocking(move || f(&repo))
+            .await
+            .map_err(|e| Error::Operation(format!("task failed: {}", e)))?
+    }
+}
+
+impl Deref for RepoHandle {
+    type Target = GitRepository;
+
+    fn deref(&self) -> &GitRepository {
+        &self.repo
+    }
+}
+
+/// What happened in one repository.
+#[derive(Debug)]
+pub enum Outcome<T> {
+    Success(T),
+    /// Completed, with the warning.
+    Warning(String),
+    Skipped(String),
+    Failed(Error),
+}
+
+/// Outcome of running a closure against one repository.
+#[derive(Debug)]
+pub struct RepoOutcome<T> {
+    pub repo: String,
+    pub outcome: Outcome<T>,
+    pub duration: Duration,
+}
+
+impl<T: ToString> RepoOutcome<T> {
+    /// The outcome as an [`OperationResult`], for the usual reporting.
+    /// Messages are redacted.
+    pub fn to_result(&self) -> OperationResult {
+        let (status, message) = match &self.outcome {
+            Outcome::Success(value) => (OperationStatus::Success, value.to_string()),
+            Outcome::Warning(warning) => (OperationStatus::Warning, warning.clone()),
+            Outcome::Skipped(reason) => (OperationStatus::Skipped, reason.clone()),
+            Outcome::Failed(e) => (OperationStatus::Failed, e.to_string()),
+        };
+        OperationResult {
+            repo: self.repo.clone(),
+            status,
+            message: redact::redact(&message),
+            duration: self.duration,
+        }
+    }
+}
+
+/// Parses a duration such as `90s`, `2m` or `1h`, in seconds without a
+/// unit.
+pub fn parse_duration(duration: &str) -> std::result::Result<Duration, String> {
+    let duration = duration.trim();
+    let (digits, unit) = match duration.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => duration.split_at(i),
+        None => (duration, ""),
+    };
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => return Err(format!("unknown duration unit `{}`", unit)),
+    };
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid duration `{}`", duration))?;
+    value
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration `{}` is too long", duration))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration(" 1h "), Ok(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        assert_eq!(
+            parse_duration("2d"),
+            Err("unknown duration unit `d`".to_string())
+        );
+        assert_eq!(parse_duration("m"), Err("invalid duration `m`".to_string()));
+        assert!(parse_duration(&format!("{}h", u64::MAX)).is_err());
+    }
+}
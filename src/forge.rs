@@ -0,0 +1,573 @@
+//! Talking to the forge hosting a repository: GitHub, GitLab, Gitea,
+//! Bitbucket Cloud or Server, or Azure DevOps.
+//!
+//! The forge of a remote host is configured next to its token, and guessed
+//! for github.com, bitbucket.org, dev.azure.com and hosts whose name
+//! contains `gitlab`, `gitea` or `bitbucket`:
+//!
+//! ```toml
+//! [hosts."git.example.com"]
+//! forge = "gitlab"
+//! api = "https://git.example.com/api/v4"
+//! token = { env = "GITLAB_TOKEN" }
+//! ```
+//!
+//! `forge` is one of `github`, `gitlab`, `gitea`, `bitbucket` (Bitbucket
+//! Cloud), `bitbucket-server` and `azure`. Bitbucket Cloud takes an app
+//! password along with the `username` of the host, or an access token
+//! without one; Azure DevOps a personal access token.
+//!
+//! Responses are cached with their ETag and the rate limit is watched, see
+//! [`crate::api_cache`].
+
+mod azure;
+mod bitbucket;
+mod gitea;
+mod github;
+mod gitlab;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use zeroize::Zeroizing;
+
+use crate::api_cache::{ApiCache, CachedResponse, ProjectCache, RateLimit};
+use crate::config::HostConfig;
+use crate::operations::GitOperation;
+use crate::recover;
+use crate::remote::RemoteUrl;
+use crate::repository::{self, GitRepository};
+use crate::{Error, Result};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForgeKind {
+    GitHub,
+    GitLab,
+    Gitea,
+    #[serde(rename = "bitbucket")]
+    BitbucketCloud,
+    #[serde(rename = "bitbucket-server")]
+    BitbucketServer,
+    #[serde(rename = "azure")]
+    AzureDevOps,
+}
+
+impl ForgeKind {
+    /// The forge `host` most likely is, from its name.
+    fn guess(host: &str) -> Option<Self> {
+        match host {
+            "github.com" => Some(ForgeKind::GitHub),
+            "bitbucket.org" => Some(ForgeKind::BitbucketCloud),
+            "dev.azure.com" | "ssh.dev.azure.com" => Some(ForgeKind::AzureDevOps),
+            _ if host.ends_with(".visualstudio.com") => Some(ForgeKind::AzureDevOps),
+            _ if host.contains("gitlab") => Some(ForgeKind::GitLab),
+            _ if host.contains("gitea") => Some(ForgeKind::Gitea),
+            _ if host.contains("bitbucket") => Some(ForgeKind::BitbucketServer),
+            _ => None,
+        }
+    }
+
+    /// Base URL of the API of the forge on `host`, when not configured.
+    fn default_api(self, host: &str) -> String {
+        match self {
+            ForgeKind::GitHub if host == "github.com" => "https://api.github.com".to_string(),
+            ForgeKind::GitHub => format!("https://{}/api/v3", host),
+            ForgeKind::GitLab => format!("https://{}/api/v4", host),
+            ForgeKind::Gitea => format!("https://{}/api/v1", host),
+            ForgeKind::BitbucketCloud => "https://api.bitbucket.org/2.0".to_string(),
+            ForgeKind::BitbucketServer => format!("https://{}/rest/api/1.0", host),
+            ForgeKind::AzureDevOps if host == "ssh.dev.azure.com" => {
+                "https://dev.azure.com".to_string()
+            }
+            ForgeKind::AzureDevOps => format!("https://{}", host),
+        }
+    }
+
+    /// Path of the project on the forge from the path of a remote URL,
+    /// without the parts the URL adds: `scm/` on Bitbucket Server, `_git/`
+    /// and the `v3/` of ssh on Azure DevOps.
+    fn project_path(self, path: &str) -> String {
+        let path = path
+            .trim_start_matches('/')
+            .trim_end_matches('/')
+            .trim_end_matches(".git");
+        match self {
+            ForgeKind::BitbucketServer => path.strip_prefix("scm/").unwrap_or(path).to_string(),
+            ForgeKind::AzureDevOps => path
+                .strip_prefix("v3/")
+                .unwrap_or(path)
+                .replace("/_git/", "/"),
+            _ => path.to_string(),
+        }
+    }
+}
+
+/// An open pull request (merge request on GitLab).
+#[derive(Debug, Clone)]
+pub struct PullRequest {
+    /// Number, the iid on GitLab.
+    pub number: u64,
+    pub url: String,
+    /// Commit the source branch points to.
+    pub head: String,
+}
+
+/// Combined state of the checks of a commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CiState {
+    /// No check is configured.
+    None,
+    Pending,
+    Success,
+    Failure,
+}
+
+impl fmt::Display for CiState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CiState::None => "none",
+            CiState::Pending => "pending",
+            CiState::Success => "success",
+            CiState::Failure => "failure",
+        })
+    }
+}
+
+/// What git-ws does with the pull requests of a project, one implementation
+/// per kind of forge.
+pub trait Forge: Send + Sync {
+    /// The open pull request from `branch`, if any.
+    fn find_pull_request(&self, branch: &str) -> Result<Option<PullRequest>>;
+
+    fn ci_state(&self, pull_request: &PullRequest) -> Result<CiState>;
+
+    /// Merges `pull_request`, provided its source branch did not move, and
+    /// returns the merge commit.
+    fn merge(&self, pull_request: &PullRequest) -> Result<String>;
+
+    /// Opens a pull request from `branch` into `base`.
+    fn create_pull_request(&self, branch: &str, base: &str, title: &str) -> Result<PullRequest>;
+}
+
+/// The project a repository's `origin` points to on its forge.
+pub struct Project {
+    pub kind: ForgeKind,
+    /// Base URL of the API, e.g. `https://api.github.com`.
+    pub api: String,
+    /// Path of the project on the forge, e.g. `team/api`.
+    pub path: String,
+    forge: Box<dyn Forge>,
+}
+
+impl Project {
+    pub fn for_repository(
+        repo: &GitRepository,
+        hosts: &BTreeMap<String, HostConfig>,
+        cache: &ApiCache,
+    ) -> Result<Self> {
+        let git = repo.open()?;
+        let remote = git.find_remote("origin")?;
+        let url = remote
+            .url()
+            .and_then(RemoteUrl::parse)
+            .ok_or_else(|| Error::Operation("origin is not a network remote".to_string()))?;
+        let host = hosts.get(&url.host).cloned().unwrap_or_default();
+        let kind = host
+            .forge
+            .or_else(|| ForgeKind::guess(&url.host))
+            .ok_or_else(|| {
+                Error::Operation(format!(
+                    "unknown forge for {}, set hosts.\"{}\".forge",
+                    url.host, url.host
+                ))
+            })?;
+        let api = host
+            .api
+            .clone()
+            .unwrap_or_else(|| kind.default_api(&url.host))
+            .trim_end_matches('/')
+            .to_string();
+        let token = host
+            .token
+            .as_ref()
+            .ok_or_else(|| Error::Operation(format!("no token configured for {}", url.host)))?
+            .resolve()?;
+        let path = kind.project_path(&url.path);
+        let auth = match kind {
+            ForgeKind::GitHub | ForgeKind::BitbucketServer => Auth::Bearer(token),
+            ForgeKind::GitLab => Auth::Header("PRIVATE-TOKEN", token),
+            ForgeKind::Gitea => Auth::Header("Authorization", format!("token {}", *token).into()),
+            ForgeKind::BitbucketCloud => match &host.username {
+                Some(username) => Auth::Basic(username.clone(), token),
+                None => Auth::Bearer(token),
+            },
+            // A personal access token goes with an empty user name.
+            ForgeKind::AzureDevOps => Auth::Basic(String::new(), token),
+        };
+        let client = Client {
+            kind,
+            api: api.clone(),
+            auth,
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            cache: cache.project(&api, &path),
+        };
+        let forge: Box<dyn Forge> = match kind {
+            ForgeKind::GitHub => Box::new(github::GitHub::new(client, &path)),
+            ForgeKind::GitLab => Box::new(gitlab::GitLab::new(client, &path)),
+            ForgeKind::Gitea => Box::new(gitea::Gitea::new(client, &path)),
+            ForgeKind::BitbucketCloud => Box::new(bitbucket::Cloud::new(client, &path)?),
+            ForgeKind::BitbucketServer => Box::new(bitbucket::Server::new(client, &path)?),
+            ForgeKind::AzureDevOps => Box::new(azure::AzureDevOps::new(client, &path)?),
+        };
+        Ok(Project {
+            kind,
+            api,
+            path,
+            forge,
+        })
+    }
+
+    pub fn find_pull_request(&self, branch: &str) -> Result<Option<PullRequest>> {
+        self.forge.find_pull_request(branch)
+    }
+
+    pub fn ci_state(&self, pull_request: &PullRequest) -> Result<CiState> {
+        self.forge.ci_state(pull_request)
+    }
+
+    pub fn merge(&self, pull_request: &PullRequest) -> Result<String> {
+        self.forge.merge(pull_request)
+    }
+
+    pub fn create_pull_request(
+        &self,
+        branch: &str,
+        base: &str,
+        title: &str,
+    ) -> Result<PullRequest> {
+        self.forge.create_pull_request(branch, base, title)
+    }
+}
+
+/// The pull request of the checked out branch of a repository.
+#[derive(Debug, Clone)]
+pub struct BranchStatus {
+    pub branch: String,
+    /// Base URL of the API asked.
+    pub api: String,
+    /// The open pull request, with the state of its checks.
+    pub pull_request: Option<(PullRequest, CiState)>,
+}
+
+/// The open pull request from the checked out branch of `repo`, if any.
+pub fn branch_status(
+    repo: &GitRepository,
+    hosts: &BTreeMap<String, HostConfig>,
+    cache: &ApiCache,
+) -> Result<BranchStatus> {
+    let Some(branch) = repo.current_branch()? else {
+        return Err(Error::Skipped("HEAD is detached".to_string()));
+    };
+    let project = Project::for_repository(repo, hosts, cache)?;
+    let pull_request = match project.find_pull_request(&branch)? {
+        Some(pull_request) => {
+            let ci = project.ci_state(&pull_request)?;
+            Some((pull_request, ci))
+        }
+        None => None,
+    };
+    Ok(BranchStatus {
+        branch,
+        api: project.api,
+        pull_request,
+    })
+}
+
+/// Opens a pull request from the checked out branch, once pushed, into the
+/// default branch or the given base.
+pub struct CreatePullRequestOperation {
+    hosts: BTreeMap<String, HostConfig>,
+    cache: ApiCache,
+    title: String,
+    base: Option<String>,
+}
+
+impl CreatePullRequestOperation {
+    pub fn new(
+        hosts: BTreeMap<String, HostConfig>,
+        cache: ApiCache,
+        title: impl Into<String>,
+        base: Option<String>,
+    ) -> Self {
+        CreatePullRequestOperation {
+            hosts,
+            cache,
+            title: title.into(),
+            base,
+        }
+    }
+}
+
+impl GitOperation for CreatePullRequestOperation {
+    fn name(&self) -> &str {
+        "pr create"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let Some(branch) = repo.current_branch()? else {
+            return Err(Error::Skipped("HEAD is detached".to_string()));
+        };
+        let base = match &self.base {
+            Some(base) => base.clone(),
+            None => repository::default_branch(&repo.open()?)?
+                .ok_or_else(|| Error::Operation("no default branch, pass --base".to_string()))?,
+        };
+        if branch == base {
+            return Err(Error::Skipped(format!("on {}", base)));
+        }
+        let remote_branch = format!("refs/remotes/origin/{}", branch);
+        if repo
+            .git(["rev-parse", "--verify", "--quiet", &remote_branch])
+            .is_err()
+        {
+            return Err(Error::Skipped(format!("{} is not pushed", branch)));
+        }
+        let project = Project::for_repository(repo, &self.hosts, &self.cache)?;
+        if let Some(open) = project.find_pull_request(&branch)? {
+            return Err(Error::Skipped(format!("already open, {}", open.url)));
+        }
+        let created = project.create_pull_request(&branch, &base, &self.title)?;
+        Ok(format!("opened {}", created.url))
+    }
+}
+
+/// How requests authenticate.
+enum Auth {
+    /// `Authorization: Bearer <token>`.
+    Bearer(Zeroizing<String>),
+    /// The token as it is, in a header of its own.
+    Header(&'static str, Zeroizing<String>),
+    /// HTTP basic authentication, with the token as password.
+    Basic(String, Zeroizing<String>),
+}
+
+/// An authenticated client for the API of one forge, caching the responses
+/// about one project.
+struct Client {
+    kind: ForgeKind,
+    api: String,
+    auth: Auth,
+    agent: ureq::Agent,
+    cache: ProjectCache,
+}
+
+impl Client {
+    /// Gets `url`, revalidating the cached response when there is one, and
+    /// using it as it is while the rate limit is exhausted.
+    fn get<T: serde::de::DeserializeOwned>(&self, url: &str, query: &[(&str, &str)]) -> Result<T> {
+        let key = query
+            .iter()
+            .enumerate()
+            .fold(url.to_string(), |key, (i, (name, value))| {
+                format!(
+                    "{}{}{}={}",
+                    key,
+                    if i == 0 { '?' } else { '&' },
+                    name,
+                    value
+                )
+            });
+        let cached = self.cache.get(&key);
+        if let Some(rate_limit) = self.cache.rate_limit().filter(RateLimit::is_exhausted) {
+            return self.stale(cached, rate_limit);
+        }
+        let mut request = self.authorize(self.agent.get(url));
+        for (name, value) in query {
+            request = request.query(name, value);
+        }
+        if let Some(cached) = &cached {
+            request = request.set("If-None-Match", &cached.etag);
+        }
+        let response = match request.call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(code @ (403 | 429), response)) => {
+                match rate_limit(&response).filter(RateLimit::is_exhausted) {
+                    Some(rate_limit) => {
+                        self.record(rate_limit);
+                        return self.stale(cached, rate_limit);
+                    }
+                    None => return Err(api_error(ureq::Error::Status(code, response))),
+                }
+            }
+            Err(e) => return Err(api_error(e)),
+        };
+        if let Some(rate_limit) = rate_limit(&response) {
+            self.record(rate_limit);
+        }
+        if let (304, Some(cached)) = (response.status(), cached) {
+            return Ok(serde_json::from_value(cached.body)?);
+        }
+        let etag = response.header("ETag").map(str::to_string);
+        let body: Value = response.into_json()?;
+        if let Some(etag) = etag {
+            // The cache only saves requests, a response it failed to keep
+            // is fetched again next time.
+            let _ = self.cache.put(
+                &key,
+                &CachedResponse {
+                    etag,
+                    body: body.clone(),
+                },
+            );
+        }
+        Ok(serde_json::from_value(body)?)
+    }
+
+    /// The cached response, while the rate limit is exhausted.
+    fn stale<T: serde::de::DeserializeOwned>(
+        &self,
+        cached: Option<CachedResponse>,
+        rate_limit: RateLimit,
+    ) -> Result<T> {
+        match cached {
+            Some(cached) => Ok(serde_json::from_value(cached.body)?),
+            None => Err(Error::Operation(format!(
+                "forge API rate limit exhausted, resets in {} min",
+                rate_limit.minutes_to_reset()
+            ))),
+        }
+    }
+
+    fn record(&self, rate_limit: RateLimit) {
+        let _ = self.cache.set_rate_limit(rate_limit);
+    }
+
+    /// Sends `body` to `url` with `method`, returning the response, `null`
+    /// when it has no body.
+    fn send(&self, method: &str, url: &str, body: Value) -> Result<Value> {
+        let mut request = self.authorize(self.agent.request(method, url));
+        if self.kind == ForgeKind::BitbucketServer {
+            // Bitbucket Server refuses API writes without it, as a guard
+            // against cross-site requests.
+            request = request.set("X-Atlassian-Token", "no-check");
+        }
+        let response = request.send_json(body).map_err(api_error)?;
+        if let Some(rate_limit) = rate_limit(&response) {
+            self.record(rate_limit);
+        }
+        let text = response.into_string()?;
+        if text.trim().is_empty() {
+            return Ok(Value::Null);
+        }
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    fn authorize(&self, request: ureq::Request) -> ureq::Request {
+        let request = request.set("User-Agent", "git-ws");
+        let request = match self.kind {
+            ForgeKind::GitHub => request.set("Accept", "application/vnd.github+json"),
+            _ => request.set("Accept", "application/json"),
+        };
+        match &self.auth {
+            Auth::Bearer(token) => {
+                request.set("Authorization", &format!("Bearer {}", token.as_str()))
+            }
+            Auth::Header(name, token) => request.set(name, token),
+            Auth::Basic(user, token) => {
+                let credentials = Zeroizing::new(BASE64.encode(format!("{}:{}", user, **token)));
+                request.set("Authorization", &format!("Basic {}", credentials.as_str()))
+            }
+        }
+    }
+}
+
+/// The worst of `states`: any failure fails, anything pending is pending.
+fn combine(states: &[CiState]) -> CiState {
+    if states.is_empty() {
+        CiState::None
+    } else if states.contains(&CiState::Failure) {
+        CiState::Failure
+    } else if states.contains(&CiState::Pending) {
+        CiState::Pending
+    } else {
+        CiState::Success
+    }
+}
+
+/// Splits the path of a project into its last component, the repository,
+/// and what comes before, failing when there is nothing before.
+fn split_path<'a>(path: &'a str, layout: &str) -> Result<(&'a str, &'a str)> {
+    path.rsplit_once('/')
+        .filter(|(owner, repo)| !owner.is_empty() && !repo.is_empty())
+        .ok_or_else(|| {
+            Error::Operation(format!(
+                "unexpected project path {}, expected {}",
+                path, layout
+            ))
+        })
+}
+
+/// The rate limit the headers of `response` tell, GitHub's or GitLab's, or
+/// the wait a `Retry-After` asks for.
+fn rate_limit(response: &ureq::Response) -> Option<RateLimit> {
+    if let Some(after) = response
+        .header("Retry-After")
+        .and_then(|after| after.trim().parse::<i64>().ok())
+    {
+        return Some(RateLimit {
+            remaining: 0,
+            reset: recover::now() + after,
+        });
+    }
+    let header = |names: [&str; 2]| {
+        names
+            .iter()
+            .find_map(|name| response.header(name))
+            .map(str::trim)
+    };
+    Some(RateLimit {
+        remaining: header(["X-RateLimit-Remaining", "RateLimit-Remaining"])?
+            .parse()
+            .ok()?,
+        reset: header(["X-RateLimit-Reset", "RateLimit-Reset"])?
+            .parse()
+            .ok()?,
+    })
+}
+
+fn string(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+fn api_error(error: ureq::Error) -> Error {
+    match error {
+        ureq::Error::Status(code, response) => {
+            let body: Value = response.into_json().unwrap_or(Value::Null);
+            // Where GitHub, Bitbucket Cloud and Bitbucket Server put it.
+            let message = [
+                &body["message"],
+                &body["error"]["message"],
+                &body["errors"][0]["message"],
+            ]
+            .into_iter()
+            .find_map(Value::as_str)
+            .unwrap_or("request failed");
+            Error::Operation(format!("forge API error {}: {}", code, message))
+        }
+        ureq::Error::Transport(transport) => {
+            Error::Operation(format!("forge API unreachable: {}", transport))
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Azure DevOps, on dev.azure.com or an older `*.visualstudio.com` host.
+
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use super::{combine, split_path, string, CiState, Client, Forge, PullRequest};
+use crate::Result;
+
+const API_VERSION: &str = "7.0";
+
+pub(super) struct AzureDevOps {
+    client: Client,
+    /// `organization/project`, or just `project` on `*.visualstudio.com`.
+    project: String,
+    repo: String,
+}
+
+impl AzureDevOps {
+    pub(super) fn new(client: Client, path: &str) -> Result<Self> {
+        let (project, repo) = split_path(path, "organization/project/_git/repository")?;
+        Ok(AzureDevOps {
+            client,
+            project: project.to_string(),
+            repo: repo.to_string(),
+        })
+    }
+
+    fn url(&self, rest: &str) -> String {
+        format!(
+            "{}/{}/_apis/git/repositories/{}{}",
+            self.client.api, self.project, self.repo, rest
+        )
+    }
+
+    fn pull_request(&self, found: &Value) -> PullRequest {
+        let number = found["pullRequestId"].as_u64().unwrap_or_default();
+        PullRequest {
+            number,
+            url: format!(
+                "{}/{}/_git/{}/pullrequest/{}",
+                self.client.api, self.project, self.repo, number
+            ),
+            head: string(&found["lastMergeSourceCommit"]["commitId"]),
+        }
+    }
+}
+
+impl Forge for AzureDevOps {
+    fn find_pull_request(&self, branch: &str) -> Result<Option<PullRequest>> {
+        let found: Value = self.client.get(
+            &self.url("/pullrequests"),
+            &[
+                (
+                    "searchCriteria.sourceRefName",
+                    &format!("refs/heads/{}", branch),
+                ),
+                ("searchCriteria.status", "active"),
+                ("api-version", API_VERSION),
+            ],
+        )?;
+        Ok(found["value"].get(0).map(|found| self.pull_request(found)))
+    }
+
+    fn ci_state(&self, pull_request: &PullRequest) -> Result<CiState> {
+        let url = self.url(&format!("/pullRequests/{}/statuses", pull_request.number));
+        let statuses: Value = self.client.get(&url, &[("api-version", API_VERSION)])?;
+        // Every update of a status is kept, the last of each one counts.
+        let mut latest = BTreeMap::new();
+        for status in statuses["value"].as_array().into_iter().flatten() {
+            let context = format!(
+                "{}/{}",
+                string(&status["context"]["genre"]),
+                string(&status["context"]["name"])
+            );
+            let id = status["id"].as_u64().unwrap_or_default();
+            let state = match status["state"].as_str() {
+                Some("succeeded" | "notApplicable") => CiState::Success,
+                Some("pending" | "notSet") => CiState::Pending,
+                _ => CiState::Failure,
+            };
+            if latest.get(&context).is_none_or(|(seen, _)| *seen < id) {
+                latest.insert(context, (id, state));
+            }
+        }
+        let states: Vec<_> = latest.into_values().map(|(_, state)| state).collect();
+        Ok(combine(&states))
+    }
+
+    fn merge(&self, pull_request: &PullRequest) -> Result<String> {
+        // Completing fails when the source moved past the commit given.
+        let url = format!(
+            "{}?api-version={}",
+            self.url(&format!("/pullrequests/{}", pull_request.number)),
+            API_VERSION
+        );
+        let merged = self.client.send(
+            "PATCH",
+            &url,
+            json!({
+                "status": "completed",
+                "lastMergeSourceCommit": { "commitId": pull_request.head },
+            }),
+        )?;
+        Ok(string(&merged["lastMergeCommit"]["commitId"]))
+    }
+
+    fn create_pull_request(&self, branch: &str, base: &str, title: &str) -> Result<PullRequest> {
+        let url = format!("{}?api-version={}", self.url("/pullrequests"), API_VERSION);
+        let created = self.client.send(
+            "POST",
+            &url,
+            json!({
+                "sourceRefName": format!("refs/heads/{}", branch),
+                "targetRefName": format!("refs/heads/{}", base),
+                "title": title,
+            }),
+        )?;
+        Ok(self.pull_request(&created))
+    }
+}
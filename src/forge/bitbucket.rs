@@ -0,0 +1,194 @@
+//! Bitbucket Cloud, and Bitbucket Server or Data Center.
+//!
+//! Neither merges only a given commit, so the source branch is checked not
+//! to have moved right before merging.
+
+use serde_json::{json, Value};
+
+use super::{combine, split_path, string, CiState, Client, Forge, PullRequest};
+use crate::{Error, Result};
+
+/// State of a build status, alike on both.
+fn build_state(state: &Value) -> CiState {
+    match state.as_str() {
+        Some("SUCCESSFUL") => CiState::Success,
+        Some("INPROGRESS") => CiState::Pending,
+        _ => CiState::Failure,
+    }
+}
+
+fn moved(pull_request: &PullRequest, head: &str) -> Error {
+    Error::Operation(format!(
+        "the source branch of #{} moved to {}",
+        pull_request.number, head
+    ))
+}
+
+/// Bitbucket Cloud, on bitbucket.org.
+pub(super) struct Cloud {
+    client: Client,
+    /// `workspace/repo`.
+    path: String,
+}
+
+impl Cloud {
+    pub(super) fn new(client: Client, path: &str) -> Result<Self> {
+        split_path(path, "workspace/repository")?;
+        Ok(Cloud {
+            client,
+            path: path.to_string(),
+        })
+    }
+
+    fn url(&self, rest: &str) -> String {
+        format!("{}/repositories/{}{}", self.client.api, self.path, rest)
+    }
+}
+
+fn cloud_pull_request(found: &Value) -> PullRequest {
+    PullRequest {
+        number: found["id"].as_u64().unwrap_or_default(),
+        url: string(&found["links"]["html"]["href"]),
+        head: string(&found["source"]["commit"]["hash"]),
+    }
+}
+
+impl Forge for Cloud {
+    fn find_pull_request(&self, branch: &str) -> Result<Option<PullRequest>> {
+        let found: Value = self.client.get(
+            &self.url("/pullrequests"),
+            &[
+                ("state", "OPEN"),
+                ("q", &format!("source.branch.name=\"{}\"", branch)),
+            ],
+        )?;
+        Ok(found["values"].get(0).map(cloud_pull_request))
+    }
+
+    fn ci_state(&self, pull_request: &PullRequest) -> Result<CiState> {
+        let url = self.url(&format!("/commit/{}/statuses", pull_request.head));
+        let statuses: Value = self.client.get(&url, &[])?;
+        let states: Vec<_> = statuses["values"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|status| build_state(&status["state"]))
+            .collect();
+        Ok(combine(&states))
+    }
+
+    fn merge(&self, pull_request: &PullRequest) -> Result<String> {
+        let url = self.url(&format!("/pullrequests/{}", pull_request.number));
+        let current: Value = self.client.get(&url, &[])?;
+        let head = string(&current["source"]["commit"]["hash"]);
+        if head != pull_request.head {
+            return Err(moved(pull_request, &head));
+        }
+        let merged = self
+            .client
+            .send("POST", &format!("{}/merge", url), json!({}))?;
+        Ok(string(&merged["merge_commit"]["hash"]))
+    }
+
+    fn create_pull_request(&self, branch: &str, base: &str, title: &str) -> Result<PullRequest> {
+        let created = self.client.send(
+            "POST",
+            &self.url("/pullrequests"),
+            json!({
+                "title": title,
+                "source": { "branch": { "name": branch } },
+                "destination": { "branch": { "name": base } },
+            }),
+        )?;
+        Ok(cloud_pull_request(&created))
+    }
+}
+
+/// Bitbucket Server, or Data Center, self-hosted.
+pub(super) struct Server {
+    client: Client,
+    /// `projects/KEY/repos/repo`, or `users/name/repos/repo` for a personal
+    /// repository, `~name/repo` in its URL.
+    path: String,
+}
+
+impl Server {
+    pub(super) fn new(client: Client, path: &str) -> Result<Self> {
+        let (owner, repo) = split_path(path, "PROJECT/repository")?;
+        let path = match owner.strip_prefix('~') {
+            Some(user) => format!("users/{}/repos/{}", user, repo),
+            None => format!("projects/{}/repos/{}", owner, repo),
+        };
+        Ok(Server { client, path })
+    }
+
+    fn url(&self, rest: &str) -> String {
+        format!("{}/{}{}", self.client.api, self.path, rest)
+    }
+}
+
+fn server_pull_request(found: &Value) -> PullRequest {
+    PullRequest {
+        number: found["id"].as_u64().unwrap_or_default(),
+        url: string(&found["links"]["self"][0]["href"]),
+        head: string(&found["fromRef"]["latestCommit"]),
+    }
+}
+
+impl Forge for Server {
+    fn find_pull_request(&self, branch: &str) -> Result<Option<PullRequest>> {
+        let found: Value = self.client.get(
+            &self.url("/pull-requests"),
+            &[
+                ("state", "OPEN"),
+                ("direction", "OUTGOING"),
+                ("at", &format!("refs/heads/{}", branch)),
+            ],
+        )?;
+        Ok(found["values"].get(0).map(server_pull_request))
+    }
+
+    fn ci_state(&self, pull_request: &PullRequest) -> Result<CiState> {
+        // Build statuses have an API of their own, next to the core one.
+        let api = self.client.api.trim_end_matches("/api/1.0");
+        let url = format!("{}/build-status/1.0/commits/{}", api, pull_request.head);
+        let statuses: Value = self.client.get(&url, &[])?;
+        let states: Vec<_> = statuses["values"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|status| build_state(&status["state"]))
+            .collect();
+        Ok(combine(&states))
+    }
+
+    fn merge(&self, pull_request: &PullRequest) -> Result<String> {
+        let url = self.url(&format!("/pull-requests/{}", pull_request.number));
+        let current: Value = self.client.get(&url, &[])?;
+        let head = string(&current["fromRef"]["latestCommit"]);
+        if head != pull_request.head {
+            return Err(moved(pull_request, &head));
+        }
+        // The version guards against any other change since it was read.
+        let version = current["version"].as_u64().unwrap_or_default();
+        let merged = self.client.send(
+            "POST",
+            &format!("{}/merge?version={}", url, version),
+            json!({}),
+        )?;
+        Ok(string(&merged["properties"]["mergeCommit"]["id"]))
+    }
+
+    fn create_pull_request(&self, branch: &str, base: &str, title: &str) -> Result<PullRequest> {
+        let created = self.client.send(
+            "POST",
+            &self.url("/pull-requests"),
+            json!({
+                "title": title,
+                "fromRef": { "id": format!("refs/heads/{}", branch) },
+                "toRef": { "id": format!("refs/heads/{}", base) },
+            }),
+        )?;
+        Ok(server_pull_request(&created))
+    }
+}
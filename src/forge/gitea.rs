@@ -0,0 +1,94 @@
+//! Gitea, and Forgejo, whose API follows GitHub's without its filters.
+
+use serde_json::{json, Value};
+
+use super::{string, CiState, Client, Forge, PullRequest};
+use crate::Result;
+
+/// Pull requests asked for at once.
+const PAGE_SIZE: usize = 50;
+
+pub(super) struct Gitea {
+    client: Client,
+    /// `owner/repo`.
+    path: String,
+}
+
+impl Gitea {
+    pub(super) fn new(client: Client, path: &str) -> Self {
+        Gitea {
+            client,
+            path: path.to_string(),
+        }
+    }
+
+    fn url(&self, rest: &str) -> String {
+        format!("{}/repos/{}{}", self.client.api, self.path, rest)
+    }
+}
+
+fn pull_request(found: &Value) -> PullRequest {
+    PullRequest {
+        number: found["number"].as_u64().unwrap_or_default(),
+        url: string(&found["html_url"]),
+        head: string(&found["head"]["sha"]),
+    }
+}
+
+impl Forge for Gitea {
+    fn find_pull_request(&self, branch: &str) -> Result<Option<PullRequest>> {
+        // No filter on the source branch, the open ones are gone through.
+        let limit = PAGE_SIZE.to_string();
+        for page in 1.. {
+            let page = page.to_string();
+            let found: Vec<Value> = self.client.get(
+                &self.url("/pulls"),
+                &[("state", "open"), ("limit", &limit), ("page", &page)],
+            )?;
+            if let Some(found) = found
+                .iter()
+                .find(|found| found["head"]["ref"].as_str() == Some(branch))
+            {
+                return Ok(Some(pull_request(found)));
+            }
+            if found.len() < PAGE_SIZE {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    fn ci_state(&self, pull_request: &PullRequest) -> Result<CiState> {
+        let url = self.url(&format!("/commits/{}/status", pull_request.head));
+        let status: Value = self.client.get(&url, &[])?;
+        if status["total_count"].as_u64().unwrap_or_default() == 0 {
+            return Ok(CiState::None);
+        }
+        Ok(match status["state"].as_str() {
+            Some("success" | "warning") => CiState::Success,
+            Some("pending") => CiState::Pending,
+            _ => CiState::Failure,
+        })
+    }
+
+    fn merge(&self, pull_request: &PullRequest) -> Result<String> {
+        let url = self.url(&format!("/pulls/{}", pull_request.number));
+        self.client.send(
+            "POST",
+            &format!("{}/merge", url),
+            json!({ "Do": "merge", "head_commit_id": pull_request.head }),
+        )?;
+        // The merge answers nothing, the pull request tells the commit.
+        let merged: Value = self.client.get(&url, &[])?;
+        Ok(string(&merged["merge_commit_sha"]))
+    }
+
+    fn create_pull_request(&self, branch: &str, base: &str, title: &str) -> Result<PullRequest> {
+        let created = self.client.send(
+            "POST",
+            &self.url("/pulls"),
+            json!({ "title": title, "head": branch, "base": base }),
+        )?;
+        Ok(pull_request(&created))
+    }
+}
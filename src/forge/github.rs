@@ -0,0 +1,86 @@
+//! GitHub, and GitHub Enterprise Server.
+
+use serde_json::{json, Value};
+
+use super::{combine, string, CiState, Client, Forge, PullRequest};
+use crate::Result;
+
+pub(super) struct GitHub {
+    client: Client,
+    /// `owner/repo`.
+    path: String,
+}
+
+impl GitHub {
+    pub(super) fn new(client: Client, path: &str) -> Self {
+        GitHub {
+            client,
+            path: path.to_string(),
+        }
+    }
+
+    fn url(&self, rest: &str) -> String {
+        format!("{}/repos/{}{}", self.client.api, self.path, rest)
+    }
+}
+
+fn pull_request(found: &Value) -> PullRequest {
+    PullRequest {
+        number: found["number"].as_u64().unwrap_or_default(),
+        url: string(&found["html_url"]),
+        head: string(&found["head"]["sha"]),
+    }
+}
+
+impl Forge for GitHub {
+    fn find_pull_request(&self, branch: &str) -> Result<Option<PullRequest>> {
+        let owner = self.path.split('/').next().unwrap_or_default();
+        let found: Vec<Value> = self.client.get(
+            &self.url("/pulls"),
+            &[
+                ("state", "open"),
+                ("head", &format!("{}:{}", owner, branch)),
+            ],
+        )?;
+        Ok(found.first().map(pull_request))
+    }
+
+    fn ci_state(&self, pull_request: &PullRequest) -> Result<CiState> {
+        let commit = self.url(&format!("/commits/{}", pull_request.head));
+        let status: Value = self.client.get(&format!("{}/status", commit), &[])?;
+        let checks: Value = self.client.get(&format!("{}/check-runs", commit), &[])?;
+        let mut states = Vec::new();
+        if status["total_count"].as_u64().unwrap_or_default() > 0 {
+            states.push(match status["state"].as_str() {
+                Some("success") => CiState::Success,
+                Some("pending") => CiState::Pending,
+                _ => CiState::Failure,
+            });
+        }
+        for run in checks["check_runs"].as_array().into_iter().flatten() {
+            states.push(match (run["status"].as_str(), run["conclusion"].as_str()) {
+                (Some("completed"), Some("success" | "neutral" | "skipped")) => CiState::Success,
+                (Some("completed"), _) => CiState::Failure,
+                _ => CiState::Pending,
+            });
+        }
+        Ok(combine(&states))
+    }
+
+    fn merge(&self, pull_request: &PullRequest) -> Result<String> {
+        let url = self.url(&format!("/pulls/{}/merge", pull_request.number));
+        let merged = self
+            .client
+            .send("PUT", &url, json!({ "sha": pull_request.head }))?;
+        Ok(string(&merged["sha"]))
+    }
+
+    fn create_pull_request(&self, branch: &str, base: &str, title: &str) -> Result<PullRequest> {
+        let created = self.client.send(
+            "POST",
+            &self.url("/pulls"),
+            json!({ "title": title, "head": branch, "base": base }),
+        )?;
+        Ok(pull_request(&created))
+    }
+}
@@ -0,0 +1,76 @@
+//! GitLab, where pull requests are merge requests.
+
+use serde_json::{json, Value};
+
+use super::{string, CiState, Client, Forge, PullRequest};
+use crate::Result;
+
+pub(super) struct GitLab {
+    client: Client,
+    /// `group/project`, subgroups included.
+    path: String,
+}
+
+impl GitLab {
+    pub(super) fn new(client: Client, path: &str) -> Self {
+        GitLab {
+            client,
+            path: path.to_string(),
+        }
+    }
+
+    fn url(&self, rest: &str) -> String {
+        format!(
+            "{}/projects/{}{}",
+            self.client.api,
+            self.path.replace('/', "%2F"),
+            rest
+        )
+    }
+}
+
+fn merge_request(found: &Value) -> PullRequest {
+    PullRequest {
+        number: found["iid"].as_u64().unwrap_or_default(),
+        url: string(&found["web_url"]),
+        head: string(&found["sha"]),
+    }
+}
+
+impl Forge for GitLab {
+    fn find_pull_request(&self, branch: &str) -> Result<Option<PullRequest>> {
+        let found: Vec<Value> = self.client.get(
+            &self.url("/merge_requests"),
+            &[("state", "opened"), ("source_branch", branch)],
+        )?;
+        Ok(found.first().map(merge_request))
+    }
+
+    fn ci_state(&self, pull_request: &PullRequest) -> Result<CiState> {
+        let url = self.url(&format!("/merge_requests/{}", pull_request.number));
+        let request: Value = self.client.get(&url, &[])?;
+        Ok(match request["head_pipeline"]["status"].as_str() {
+            None => CiState::None,
+            Some("success" | "skipped") => CiState::Success,
+            Some("failed" | "canceled") => CiState::Failure,
+            Some(_) => CiState::Pending,
+        })
+    }
+
+    fn merge(&self, pull_request: &PullRequest) -> Result<String> {
+        let url = self.url(&format!("/merge_requests/{}/merge", pull_request.number));
+        let merged = self
+            .client
+            .send("PUT", &url, json!({ "sha": pull_request.head }))?;
+        Ok(string(&merged["merge_commit_sha"]))
+    }
+
+    fn create_pull_request(&self, branch: &str, base: &str, title: &str) -> Result<PullRequest> {
+        let created = self.client.send(
+            "POST",
+            &self.url("/merge_requests"),
+            json!({ "source_branch": branch, "target_branch": base, "title": title }),
+        )?;
+        Ok(merge_request(&created))
+    }
+}
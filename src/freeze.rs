@@ -0,0 +1,447 @@
+//! Freeze windows, during which git-ws refuses to push.
+//!
+//! Weekly windows are set in the configuration, and single dates or ranges
+//! of dates in a calendar file, one per line:
+//!
+//! ```toml
+//! [freeze]
+//! windows = [{ from = "Fri 18:00", to = "Mon 08:00" }]
+//! # Relative to the workspace root.
+//! calendar = "freeze-calendar.txt"
+//! # Times are local unless an offset is given.
+//! utc_offset = "+01:00"
+//! ```
+//!
+//! ```text
+//! # Dates are inclusive, what follows them is the reason.
+//! 2026-12-21..2027-01-04 year-end freeze
+//! 2026-11-26 Thanksgiving
+//! ```
+//!
+//! Every operation that pushes is refused, see [`FreezeGuard`]. Pushing
+//! anyway takes `--override-freeze REASON`, the reason being written to the
+//! journal along with the batch, see [`crate::recover`].
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Once, OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::middleware::{Middleware, Next};
+use crate::operations::GitOperation;
+use crate::repository::GitRepository;
+use crate::{Error, Result};
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const MINUTES_PER_WEEK: i64 = 7 * 1440;
+
+/// The `[freeze]` section of the configuration.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct FreezeConfig {
+    /// Weekly windows.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub windows: Vec<FreezeWindow>,
+    /// File listing frozen dates, relative to the workspace root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calendar: Option<PathBuf>,
+    /// Offset from UTC of the times of the windows and the dates of the
+    /// calendar, like `+01:00`, the local one when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utc_offset: Option<String>,
+}
+
+/// A window repeating every week, like from `Fri 18:00` to `Mon 08:00`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FreezeWindow {
+    pub from: String,
+    pub to: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// The freeze in force.
+#[derive(Debug, Clone)]
+pub struct Freeze {
+    /// The window or dates, like `Fri 18:00 - Mon 08:00`.
+    pub when: String,
+    pub reason: Option<String>,
+}
+
+impl fmt::Display for Freeze {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            Some(reason) => write!(f, "{}, {}", self.when, reason),
+            None => write!(f, "{}", self.when),
+        }
+    }
+}
+
+impl FreezeConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.windows.is_empty() || self.calendar.is_some()
+    }
+
+    /// The freeze in force at `time`, in seconds since the epoch, `root`
+    /// being the workspace root the calendar is relative to.
+    pub fn active(&self, root: &Path, time: i64) -> Result<Option<Freeze>> {
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+        let offset = match &self.utc_offset {
+            Some(offset) => parse_offset(offset)?,
+            None => local_offset(time),
+        };
+        let local = time + offset;
+        let minute =
+            ((local.div_euclid(86400) + 3).rem_euclid(7)) * 1440 + local.rem_euclid(86400) / 60;
+        for window in &self.windows {
+            let from = parse_weekly(&window.from)?;
+            let to = parse_weekly(&window.to)?;
+            let inside = if from <= to {
+                (from..to).contains(&minute)
+            } else {
+                minute >= from || minute < to
+            };
+            if inside {
+                return Ok(Some(Freeze {
+                    when: format!("{} - {}", window.from, window.to),
+                    reason: window.reason.clone(),
+                }));
+            }
+        }
+        let Some(calendar) = &self.calendar else {
+            return Ok(None);
+        };
+        let path = root.join(calendar);
+        let contents = fs::read_to_string(&path).map_err(|e| {
+            Error::Operation(format!(
+                "cannot read the freeze calendar {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let today = local.div_euclid(86400);
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (dates, reason) = match line.split_once(char::is_whitespace) {
+                Some((dates, reason)) => (dates, Some(reason.trim().to_string())),
+                None => (line, None),
+            };
+            let (first, last) = dates.split_once("..").unwrap_or((dates, dates));
+            let invalid = || {
+                Error::Operation(format!(
+                    "invalid line in the freeze calendar {}: {}",
+                    path.display(),
+                    line
+                ))
+            };
+            let first = parse_date(first).ok_or_else(invalid)?;
+            let last = parse_date(last).ok_or_else(invalid)?;
+            if (first..=last).contains(&today) {
+                return Ok(Some(Freeze {
+                    when: dates.to_string(),
+                    reason,
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Refuses to push during a freeze unless `override_reason` is given,
+/// returning the freeze overridden.
+pub fn check(
+    config: &FreezeConfig,
+    root: &Path,
+    time: i64,
+    override_reason: Option<&str>,
+) -> Result<Option<Freeze>> {
+    let Some(freeze) = config.active(root, time)? else {
+        return Ok(None);
+    };
+    overridable(&freeze, override_reason)?;
+    Ok(Some(freeze))
+}
+
+fn overridable(freeze: &Freeze, override_reason: Option<&str>) -> Result<()> {
+    match override_reason {
+        Some(reason) if !reason.trim().is_empty() => Ok(()),
+        Some(_) => Err(Error::Operation(
+            "--override-freeze needs a reason".to_string(),
+        )),
+        None => Err(Error::Operation(format!(
+            "pushing is frozen ({}), pass --override-freeze REASON to push anyway",
+            freeze
+        ))),
+    }
+}
+
+/// Refuses the operations that push, see [`GitOperation::pushes`], during
+/// the freeze in force when the batch started, unless it is overridden.
+/// The calendar is only read once an operation pushes, so a broken one
+/// fails those alone.
+pub struct FreezeGuard {
+    config: FreezeConfig,
+    root: PathBuf,
+    time: i64,
+    override_reason: Option<String>,
+    freeze: OnceLock<std::result::Result<Option<Freeze>, String>>,
+    warned: Once,
+}
+
+impl FreezeGuard {
+    /// The guard of a batch starting at `time`, `root` being the workspace
+    /// root the calendar is relative to.
+    pub fn new(
+        config: &FreezeConfig,
+        root: &Path,
+        time: i64,
+        override_reason: Option<&str>,
+    ) -> Self {
+        FreezeGuard {
+            config: config.clone(),
+            root: root.to_path_buf(),
+            time,
+            override_reason: override_reason.map(str::to_string),
+            freeze: OnceLock::new(),
+            warned: Once::new(),
+        }
+    }
+
+    fn freeze(&self) -> Result<Option<&Freeze>> {
+        let freeze = self.freeze.get_or_init(|| {
+            self.config
+                .active(&self.root, self.time)
+                .map_err(|e| e.to_string())
+        });
+        match freeze {
+            Ok(freeze) => Ok(freeze.as_ref()),
+            Err(message) => Err(Error::Operation(message.clone())),
+        }
+    }
+
+    /// The reason the freeze in force is overridden with, for the journal.
+    pub fn overridden(&self) -> Option<&str> {
+        self.freeze().ok()??;
+        self.override_reason
+            .as_deref()
+            .filter(|reason| !reason.trim().is_empty())
+    }
+}
+
+impl Middleware for FreezeGuard {
+    fn call(
+        &self,
+        repo: &GitRepository,
+        operation: &dyn GitOperation,
+        next: Next<'_>,
+    ) -> Result<String> {
+        if !operation.pushes() {
+            return next.run(repo);
+        }
+        if let Some(freeze) = self.freeze()? {
+            overridable(freeze, self.override_reason.as_deref())?;
+            self.warned.call_once(|| {
+                eprintln!(
+                    "warning: pushing during the freeze {}: {}",
+                    freeze,
+                    self.override_reason.as_deref().unwrap_or_default()
+                );
+            });
+        }
+        next.run(repo)
+    }
+}
+
+/// `Fri 18:00` as minutes since Monday midnight.
+fn parse_weekly(time: &str) -> Result<i64> {
+    let invalid = || {
+        Error::Operation(format!(
+            "invalid freeze time {}, expected like Fri 18:00",
+            time
+        ))
+    };
+    let (day, clock) = time.trim().split_once(' ').ok_or_else(invalid)?;
+    let day = day.to_lowercase();
+    let day = DAYS
+        .iter()
+        .position(|name| day.starts_with(name))
+        .ok_or_else(invalid)? as i64;
+    let minutes = parse_clock(clock.trim()).ok_or_else(invalid)?;
+    Ok((day * 1440 + minutes) % MINUTES_PER_WEEK)
+}
+
+/// `18:00` as minutes since midnight.
+fn parse_clock(clock: &str) -> Option<i64> {
+    let (hours, minutes) = clock.split_once(':')?;
+    let hours: i64 = hours.parse().ok()?;
+    let minutes: i64 = minutes.parse().ok()?;
+    (hours <= 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// `+01:00` or `-0530` as seconds.
+fn parse_offset(offset: &str) -> Result<i64> {
+    let invalid = || Error::Operation(format!("invalid UTC offset {}", offset));
+    let (sign, rest) = match offset.trim().split_at_checked(1) {
+        Some(("+", rest)) => (1, rest),
+        Some(("-", rest)) => (-1, rest),
+        _ => return Err(invalid()),
+    };
+    let rest = rest.replace(':', "");
+    if rest.len() != 4 || !rest.is_ascii() {
+        return Err(invalid());
+    }
+    let hours: i64 = rest[..2].parse().map_err(|_| invalid())?;
+    let minutes: i64 = rest[2..].parse().map_err(|_| invalid())?;
+    Ok(sign * (hours * 3600 + minutes * 60))
+}
+
+/// `2026-12-21` as days since the epoch, after Howard Hinnant.
+fn parse_date(date: &str) -> Option<i64> {
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146_097 + doe - 719_468)
+}
+
+/// Offset from UTC of the local time at `time`, in seconds.
+#[cfg(unix)]
+fn local_offset(time: i64) -> i64 {
+    let time = time as libc::time_t;
+    // SAFETY: localtime_r only writes to the tm it is given.
+    unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            return 0;
+        }
+        tm.tm_gmtoff as i64
+    }
+}
+
+/// Elsewhere there is no local offset here, set `utc_offset`.
+#[cfg(not(unix))]
+fn local_offset(_time: i64) -> i64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2000-03-01"), Some(11_017));
+        assert_eq!(parse_date("2026-12-21"), Some(20_808));
+        assert_eq!(parse_date("2026-13-01"), None);
+        assert_eq!(parse_date("2026-12"), None);
+    }
+
+    #[test]
+    fn parses_weekly_times_and_offsets() {
+        assert_eq!(parse_weekly("Mon 00:00").unwrap(), 0);
+        assert_eq!(parse_weekly("fri 18:00").unwrap(), 4 * 1440 + 18 * 60);
+        assert!(parse_weekly("Fri").is_err());
+        assert!(parse_weekly("Fri 18:60").is_err());
+        assert_eq!(parse_offset("+01:00").unwrap(), 3600);
+        assert_eq!(parse_offset("-0530").unwrap(), -(5 * 3600 + 30 * 60));
+        assert!(parse_offset("01:00").is_err());
+    }
+
+    #[test]
+    fn finds_the_window_in_force() {
+        let config = FreezeConfig {
+            windows: vec![FreezeWindow {
+                from: "Fri 18:00".to_string(),
+                to: "Mon 08:00".to_string(),
+                reason: None,
+            }],
+            calendar: None,
+            utc_offset: Some("+00:00".to_string()),
+        };
+        // Saturday 2026-12-19 and Tuesday 2026-12-22, at noon.
+        let saturday = 20_806 * 86400 + 12 * 3600;
+        let tuesday = 20_809 * 86400 + 12 * 3600;
+        assert!(config.active(Path::new("."), saturday).unwrap().is_some());
+        assert!(config.active(Path::new("."), tuesday).unwrap().is_none());
+    }
+
+    struct Push;
+
+    impl GitOperation for Push {
+        fn name(&self) -> &str {
+            "push"
+        }
+
+        fn pushes(&self) -> bool {
+            true
+        }
+
+        fn execute(&self, _repo: &GitRepository) -> Result<String> {
+            Ok("pushed".to_string())
+        }
+    }
+
+    struct Fetch;
+
+    impl GitOperation for Fetch {
+        fn name(&self) -> &str {
+            "fetch"
+        }
+
+        fn execute(&self, _repo: &GitRepository) -> Result<String> {
+            Ok("fetched".to_string())
+        }
+    }
+
+    #[test]
+    fn guards_the_operations_that_push() {
+        let config = FreezeConfig {
+            calendar: Some(PathBuf::from("calendar.txt")),
+            utc_offset: Some("+00:00".to_string()),
+            ..FreezeConfig::default()
+        };
+        let root = std::env::temp_dir().join(format!("git-ws-freeze-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("calendar.txt"), "1970-01-01 epoch\n").unwrap();
+        let repo = GitRepository::new("api", &root);
+        let preconditions = crate::precondition::Preconditions::default();
+        let run = |guard: &FreezeGuard, operation: &dyn GitOperation| {
+            guard.call(&repo, operation, Next::new(operation, &[], &preconditions))
+        };
+
+        let frozen = FreezeGuard::new(&config, &root, 3600, None);
+        assert_eq!(run(&frozen, &Fetch).unwrap(), "fetched");
+        let error = run(&frozen, &Push).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "pushing is frozen (1970-01-01, epoch), pass --override-freeze REASON to push anyway"
+        );
+        assert_eq!(frozen.overridden(), None);
+        let overridden = FreezeGuard::new(&config, &root, 3600, Some("hotfix"));
+        assert_eq!(run(&overridden, &Push).unwrap(), "pushed");
+        assert_eq!(overridden.overridden(), Some("hotfix"));
+        let thawed = FreezeGuard::new(&config, &root, 86400, None);
+        assert_eq!(run(&thawed, &Push).unwrap(), "pushed");
+
+        // Only pushing reads the calendar.
+        fs::remove_dir_all(&root).unwrap();
+        let broken = FreezeGuard::new(&config, &root, 3600, None);
+        assert_eq!(run(&broken, &Fetch).unwrap(), "fetched");
+        assert!(run(&broken, &Push).is_err());
+    }
+}
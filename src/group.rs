@@ -0,0 +1,226 @@
+//! Grouping repositories by their first-level directory, like the team
+//! directories `payments/` and `infra/` of a workspace.
+//!
+//! `list --by-dir` and `status --by-dir` print a table per group under a line
+//! summing it up; `--collapse` prints only that line, for every group or for
+//! the ones named.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::executor::{Outcome, RepoOutcome};
+use crate::operations::RepoStatus;
+
+/// Group of the repositories right in the workspace root.
+pub const TOP_LEVEL: &str = ".";
+
+/// The group of the repository `repo`: its first-level directory.
+pub fn group_of(repo: &str) -> &str {
+    match repo.split_once('/') {
+        Some((dir, _)) => dir,
+        None => TOP_LEVEL,
+    }
+}
+
+/// `items` by group, the group of each named by `repo`.
+pub fn group<T>(
+    items: impl IntoIterator<Item = T>,
+    repo: impl Fn(&T) -> &str,
+) -> BTreeMap<String, Vec<T>> {
+    let mut groups: BTreeMap<String, Vec<T>> = BTreeMap::new();
+    for item in items {
+        let name = group_of(repo(&item)).to_string();
+        groups.entry(name).or_default().push(item);
+    }
+    groups
+}
+
+/// Counts of the repositories of a group.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Subtotal {
+    pub repos: usize,
+    pub clean: usize,
+    pub changed: usize,
+    pub ahead: usize,
+    pub behind: usize,
+    pub failed: usize,
+}
+
+impl Subtotal {
+    /// Just the number of repositories, for the commands knowing nothing of
+    /// their changes.
+    pub fn count(repos: usize) -> Self {
+        Subtotal {
+            repos,
+            ..Subtotal::default()
+        }
+    }
+
+    /// Counts the statuses of the repositories of a group.
+    pub fn of_status(outcomes: &[RepoOutcome<RepoStatus>]) -> Self {
+        let mut subtotal = Subtotal::count(outcomes.len());
+        for outcome in outcomes {
+            let status = match &outcome.outcome {
+                Outcome::Success(status) => status,
+                Outcome::Failed(_) => {
+                    subtotal.failed += 1;
+                    continue;
+                }
+                Outcome::Warning(_) | Outcome::Skipped(_) => continue,
+            };
+            let snapshot = &status.snapshot;
+            if snapshot.changes.is_clean() {
+                subtotal.clean += 1;
+            } else {
+                subtotal.changed += 1;
+            }
+            if let Some(upstream) = &snapshot.upstream {
+                if upstream.ahead > 0 {
+                    subtotal.ahead += 1;
+                }
+                if upstream.behind > 0 {
+                    subtotal.behind += 1;
+                }
+            }
+        }
+        subtotal
+    }
+
+    pub fn add(&mut self, other: Subtotal) {
+        self.repos += other.repos;
+        self.clean += other.clean;
+        self.changed += other.changed;
+        self.ahead += other.ahead;
+        self.behind += other.behind;
+        self.failed += other.failed;
+    }
+}
+
+impl fmt::Display for Subtotal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.repos,
+            if self.repos == 1 {
+                "repository"
+            } else {
+                "repositories"
+            }
+        )?;
+        let parts: Vec<String> = [
+            (self.clean, "clean"),
+            (self.changed, "changed"),
+            (self.ahead, "ahead"),
+            (self.behind, "behind"),
+            (self.failed, "failed"),
+        ]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{} {}", count, label))
+        .collect();
+        if !parts.is_empty() {
+            write!(f, ": {}", parts.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// How groups are shown.
+#[derive(Debug, Clone, Default)]
+pub struct Grouping {
+    /// Groups shown as their summary line only, every one when empty.
+    collapsed: Option<Vec<String>>,
+}
+
+impl Grouping {
+    /// Every group expanded, but those of `collapsed`: none when it is
+    /// `None`, every one when it is empty.
+    pub fn new(collapsed: Option<Vec<String>>) -> Self {
+        let collapsed = collapsed.map(|names| {
+            names
+                .iter()
+                .map(|name| name.trim_end_matches('/').to_string())
+                .collect()
+        });
+        Grouping { collapsed }
+    }
+
+    pub fn is_collapsed(&self, group: &str) -> bool {
+        match &self.collapsed {
+            Some(names) => names.is_empty() || names.iter().any(|name| name == group),
+            None => false,
+        }
+    }
+
+    /// Renders the groups, each with the table `render` makes of its items
+    /// unless it is collapsed, and the total of their subtotals.
+    pub fn render<T>(
+        &self,
+        groups: BTreeMap<String, (Subtotal, Vec<T>)>,
+        render: impl Fn(Vec<T>) -> String,
+    ) -> String {
+        let mut rendered = String::new();
+        let mut total = Subtotal::default();
+        let count = groups.len();
+        for (name, (subtotal, items)) in groups {
+            total.add(subtotal);
+            let label = if name == TOP_LEVEL {
+                name.clone()
+            } else {
+                format!("{}/", name)
+            };
+            if self.is_collapsed(&name) {
+                rendered.push_str(&format!("▸ {}  {}\n", label, subtotal));
+            } else {
+                rendered.push_str(&format!("▾ {}  {}\n", label, subtotal));
+                rendered.push_str(&render(items));
+            }
+        }
+        if count > 1 {
+            rendered.push_str(&format!("total  {}\n", total));
+        }
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::operations::StatusOperation;
+    use crate::testing::{RepoState, TestWorkspace};
+
+    #[test]
+    fn counts_the_statuses() {
+        let test = TestWorkspace::builder()
+            .repo("team/clean", RepoState::Clean)
+            .repo("team/dirty", RepoState::Dirty)
+            .repo("team/ahead", RepoState::Ahead(1))
+            .repo("team/behind", RepoState::Behind(2))
+            .build()
+            .unwrap();
+        let status = StatusOperation::default();
+        let mut outcomes: Vec<_> = ["clean", "dirty", "ahead", "behind"]
+            .iter()
+            .map(|name| {
+                let repo = test.repository(&format!("team/{}", name));
+                RepoOutcome {
+                    repo: repo.name().to_string(),
+                    outcome: Outcome::Success(status.status(&repo).unwrap()),
+                    duration: Duration::ZERO,
+                }
+            })
+            .collect();
+        outcomes.push(RepoOutcome {
+            repo: "team/gone".to_string(),
+            outcome: Outcome::Failed(crate::Error::Operation("gone".to_string())),
+            duration: Duration::ZERO,
+        });
+        assert_eq!(
+            Subtotal::of_status(&outcomes).to_string(),
+            "5 repositories: 3 clean, 1 changed, 1 ahead, 1 behind, 1 failed"
+        );
+    }
+}
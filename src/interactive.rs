@@ -0,0 +1,173 @@
+//! Whether git-ws may ask the user for input.
+//!
+//! In non-interactive mode nothing prompts, opens an editor or waits on the
+//! terminal: child processes get their stdin closed and git is configured not
+//! to prompt, and anything that would need input fails with
+//! [`Error::InputRequired`] instead.
+
+use std::ffi::OsStr;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use inquire::InquireError;
+
+use crate::alias;
+use crate::progress;
+use crate::{Error, Result};
+
+static NON_INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_non_interactive(non_interactive: bool) {
+    NON_INTERACTIVE.store(non_interactive, Ordering::Relaxed);
+}
+
+/// Whether input may be requested: not disabled and attached to a terminal.
+pub fn is_interactive() -> bool {
+    !NON_INTERACTIVE.load(Ordering::Relaxed) && std::io::stdin().is_terminal()
+}
+
+/// Fails with an actionable error when `what` would need user input.
+pub fn require_input(what: impl Into<String>) -> Result<()> {
+    if is_interactive() {
+        Ok(())
+    } else {
+        Err(Error::InputRequired(what.into()))
+    }
+}
+
+/// Asks `question` on stderr and returns the answer, trimmed.
+pub fn ask(question: &str) -> Result<String> {
+    require_input(format!("answering \"{}\"", question))?;
+    progress::suspend(|| {
+        eprint!("{} ", question);
+        io::stderr().flush()?;
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        Ok(answer.trim().to_string())
+    })
+}
+
+/// Asks to pick some of `options`, typing to narrow them down by fuzzy
+/// matching and space to pick, returning those picked in their order.
+pub fn choose(question: &str, options: Vec<String>) -> Result<Vec<String>> {
+    require_input(format!("answering \"{}\"", question))?;
+    progress::suspend(|| {
+        inquire::MultiSelect::new(question, options)
+            .with_help_message("type to filter, space to pick, right to pick all, enter to go on")
+            .prompt()
+    })
+    .map_err(|e| match e {
+        InquireError::IO(e) => Error::Io(e),
+        InquireError::OperationCanceled | InquireError::OperationInterrupted => {
+            Error::Operation("cancelled".to_string())
+        }
+        e => Error::Operation(e.to_string()),
+    })
+}
+
+/// A command for `program` that cannot block on user input when running
+/// non-interactively. It does not inherit the variables git passes to
+/// git-ws run as an alias, see [`alias`](crate::alias).
+pub fn command(program: impl AsRef<OsStr>) -> Command {
+    let mut command = Command::new(program);
+    for var in alias::INHERITED_GIT_VARS {
+        command.env_remove(var);
+    }
+    if NON_INTERACTIVE.load(Ordering::Relaxed) {
+        command
+            .stdin(Stdio::null())
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .env("GIT_EDITOR", ":")
+            .env("GIT_SEQUENCE_EDITOR", ":")
+            .env("GIT_MERGE_AUTOEDIT", "no");
+        if std::env::var_os("GIT_SSH_COMMAND").is_none() && std::env::var_os("GIT_SSH").is_none() {
+            command.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes");
+        }
+    }
+    command
+}
+
+/// A command running `script` through the platform shell: `sh -c` on Unix,
+/// `cmd /C` on Windows.
+pub fn shell(script: &str) -> Command {
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = command("cmd");
+        cmd.arg("/C");
+        cmd
+    } else {
+        let mut cmd = command("sh");
+        cmd.arg("-c");
+        cmd
+    };
+    cmd.arg(script);
+    cmd
+}
+
+/// `word` quoted for [`shell`], to reach the command as one argument, as
+/// is. On Windows, cmd still expands `%VARIABLES%` in quotes.
+pub fn shell_quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        return word.to_string();
+    }
+    if cfg!(windows) {
+        format!("\"{}\"", word.replace('"', "\"\""))
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Runs git with `args` in `dir`, on the terminal, for commands talking
+/// to the user, like `add --patch`.
+pub fn git_on_terminal<I, S>(dir: &Path, args: I) -> Result<()>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    require_input("talking to git on the terminal")?;
+    let status = progress::suspend(|| command("git").args(args).current_dir(dir).status())?;
+    if !status.success() {
+        return Err(Error::Operation(format!("git exited with {}", status)));
+    }
+    Ok(())
+}
+
+/// Opens `path` in the editor git uses, and waits until it is closed.
+pub fn edit(path: &Path) -> Result<()> {
+    require_input(format!("editing {}", path.display()))?;
+    let output = command("git").args(["var", "GIT_EDITOR"]).output()?;
+    let editor = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || editor.is_empty() {
+        return Err(Error::Operation("no editor configured".to_string()));
+    }
+    let status = progress::suspend(|| {
+        shell(&format!("{} \"$@\"", editor))
+            .arg(&editor)
+            .arg(path)
+            .status()
+    })?;
+    if !status.success() {
+        return Err(Error::Operation(format!(
+            "{} exited with {}",
+            editor, status
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn quotes_words_for_the_shell() {
+        assert_eq!(shell_quote("src/main.rs"), "src/main.rs");
+        assert_eq!(shell_quote("a  b"), "'a  b'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote("x;rm -rf ~"), "'x;rm -rf ~'");
+        assert_eq!(shell_quote(""), "''");
+    }
+}
@@ -0,0 +1,94 @@
+//! git-ws is a git workspace manage tool.
+//!
+//! A workspace is a directory containing several git repositories. The
+//! library discovers those repositories, runs [`operations::GitOperation`]s
+//! against them concurrently through the [`executor::BatchExecutor`] and
+//! keeps a small amount of workspace state under `.git-ws/`.
+//!
+//! Custom per-repository logic does not need an operation type:
+//!
+//! ```no_run
+//! # async fn example() -> git_ws::Result<()> {
+//! use git_ws::executor::BatchExecutor;
+//! use git_ws::workspace::Workspace;
+//!
+//! let workspace = Workspace::discover(&std::env::current_dir()?);
+//! let outcomes = workspace
+//!     .for_each_repo(&BatchExecutor::new(4), |repo| async move {
+//!         repo.run_blocking(|repo| repo.head_sha()).await
+//!     })
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod agent;
+pub mod alias;
+pub mod api_cache;
+pub mod audit;
+pub mod badge;
+pub mod batch;
+pub mod bisect;
+pub mod bootstrap;
+pub mod branch;
+pub mod changeset;
+pub mod ci;
+pub mod clean;
+pub mod compare;
+pub mod config;
+pub mod conflicts;
+pub mod consolidate;
+pub mod credentials;
+pub mod diff;
+pub mod doctor;
+pub mod error;
+pub mod events;
+pub mod executor;
+pub mod forge;
+pub mod freeze;
+pub mod group;
+pub mod interactive;
+pub mod lockfile;
+pub mod log;
+pub mod mailmap;
+pub mod manifest;
+pub mod middleware;
+pub mod operations;
+pub mod output;
+pub mod patch;
+pub mod pathspec;
+pub mod plan;
+pub mod policy;
+pub mod portable;
+pub mod precondition;
+pub mod process;
+pub mod progress;
+pub mod query;
+pub mod recover;
+pub mod redact;
+pub mod remote;
+pub mod report;
+pub mod repository;
+pub mod resume;
+pub mod schedule;
+pub mod session;
+pub mod setup;
+pub mod shard;
+pub mod shell;
+pub mod stash;
+pub mod state;
+pub mod subtree;
+pub mod sync;
+pub mod tag;
+pub mod template;
+pub mod testing;
+pub mod ticket;
+pub mod trailer;
+pub mod trash;
+pub mod update;
+pub mod usage;
+pub mod view;
+pub mod watch;
+pub mod workspace;
+
+pub use error::{Error, Result};
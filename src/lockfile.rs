@@ -0,0 +1,57 @@
+//! Lockfile recording the exact commit of every repository, for reproducible
+//! checkouts.
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::repository::GitRepository;
+use crate::Result;
+
+/// Default file name of the lockfile.
+pub const LOCK_FILE: &str = "git-ws.lock";
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Lockfile {
+    #[serde(default, rename = "repository")]
+    pub repositories: Vec<LockedRepository>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedRepository {
+    pub path: String,
+    pub sha: String,
+}
+
+impl Lockfile {
+    /// Records the current HEAD of every repository.
+    pub fn capture(repos: &[GitRepository]) -> Result<Self> {
+        let repositories = repos
+            .iter()
+            .map(|repo| {
+                Ok(LockedRepository {
+                    path: repo.name().to_string(),
+                    sha: repo.head_sha()?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Lockfile { repositories })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn sha(&self, path: &str) -> Option<&str> {
+        self.repositories
+            .iter()
+            .find(|repo| repo.path == path)
+            .map(|repo| repo.sha.as_str())
+    }
+}
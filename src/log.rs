@@ -0,0 +1,49 @@
+//! Recent commits of the repositories of a workspace.
+
+use crate::mailmap::{self, Mailmap};
+use crate::repository::GitRepository;
+use crate::Result;
+
+/// A commit as `log` shows it.
+#[derive(Debug, Clone)]
+pub struct Commit {
+    /// Abbreviated hash.
+    pub hash: String,
+    pub author: String,
+    /// Author date, like `2024-03-18`.
+    pub date: String,
+    pub summary: String,
+}
+
+/// The latest `count` commits of HEAD in `repo`, the latest first, none for
+/// a repository without commits. Authors are mapped by the mailmap of the
+/// workspace too, when there is one.
+pub fn recent(
+    repo: &GitRepository,
+    count: usize,
+    mailmap: Option<&Mailmap>,
+) -> Result<Vec<Commit>> {
+    if repo.head_sha().is_err() {
+        return Ok(Vec::new());
+    }
+    let mut args = mailmap::git_args(repo, mailmap)?;
+    args.extend([
+        "log".to_string(),
+        format!("--max-count={}", count),
+        "--date=short".to_string(),
+        "--format=%h%x1f%aN%x1f%ad%x1f%s".to_string(),
+    ]);
+    let output = repo.git(&args)?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, '\x1f');
+            Some(Commit {
+                hash: fields.next()?.to_string(),
+                author: fields.next()?.to_string(),
+                date: fields.next()?.to_string(),
+                summary: fields.next().unwrap_or_default().to_string(),
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,282 @@
+//! A mailmap for the whole workspace, merging the identities of a person
+//! across repositories.
+//!
+//! `.git-ws/mailmap` follows the format of `.mailmap`, see gitmailmap(5),
+//! and applies on top of the mailmap of each repository wherever git-ws
+//! shows or counts authors: `log`, `authors`, the shortlog of the cover
+//! letter of `format-patch` and the statistics of `report html`.
+//!
+//! ```text
+//! Jane Doe <jane@example.com> <jdoe@old-company.com>
+//! Jane Doe <jane@example.com> <jane@laptop.local>
+//! ```
+//!
+//! `authors --unmapped` lists the identities it does not mention, pointing
+//! at those looking like another one.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::repository::GitRepository;
+use crate::workspace::Workspace;
+use crate::Result;
+
+pub const MAILMAP_FILE: &str = "mailmap";
+/// Directory of the state directory the mailmap is merged with those the
+/// repositories configure, by repository.
+const MERGED_DIR: &str = "mailmaps";
+
+/// The mailmap of the workspace.
+#[derive(Debug, Clone)]
+pub struct Mailmap {
+    path: PathBuf,
+    /// Every email it mentions, lowercase.
+    emails: BTreeSet<String>,
+}
+
+impl Mailmap {
+    /// The mailmap of `workspace`, `None` when it has none.
+    pub fn for_workspace(workspace: &Workspace) -> Result<Option<Self>> {
+        let path = workspace.state_dir().join(MAILMAP_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&path)?;
+        let emails = contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .flat_map(|line| {
+                line.split('<')
+                    .skip(1)
+                    .filter_map(|rest| rest.split_once('>'))
+                    .map(|(email, _)| email.trim().to_lowercase())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        Ok(Some(Mailmap { path, emails }))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether it mentions `email`, to map it or as the one mapped to.
+    pub fn covers(&self, email: &str) -> bool {
+        self.emails.contains(&email.to_lowercase())
+    }
+}
+
+/// Arguments of git, before the command, applying `mailmap` along with the
+/// mailmaps of `repo`. Its `.mailmap` applies anyway, but the file its
+/// `mailmap.file` names would be replaced: the two are merged in a file of
+/// the state directory then, the entries of `mailmap` last to win.
+pub fn git_args(repo: &GitRepository, mailmap: Option<&Mailmap>) -> Result<Vec<String>> {
+    let Some(mailmap) = mailmap else {
+        return Ok(Vec::new());
+    };
+    let own = repo
+        .open()?
+        .config()?
+        .get_path("mailmap.file")
+        .ok()
+        .map(|own| repo.path().join(own));
+    let file = match own {
+        Some(own) if own.is_file() => {
+            let mut merged = fs::read_to_string(&own)?;
+            if !merged.is_empty() && !merged.ends_with('\n') {
+                merged.push('\n');
+            }
+            merged.push_str(&fs::read_to_string(&mailmap.path)?);
+            let dir = mailmap.path.with_file_name(MERGED_DIR);
+            fs::create_dir_all(&dir)?;
+            let file = dir.join(repo.name().replace('/', "%"));
+            fs::write(&file, merged)?;
+            file
+        }
+        _ => mailmap.path.clone(),
+    };
+    Ok(vec![
+        "-c".to_string(),
+        format!("mailmap.file={}", file.display()),
+    ])
+}
+
+/// A name and an email, as commits record them.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Identity {
+    pub name: String,
+    pub email: String,
+}
+
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} <{}>", self.name, self.email)
+    }
+}
+
+/// Number of commits by author identity, as recorded and as mapped.
+pub type Identities = BTreeMap<(Identity, Identity), usize>;
+
+/// The author identities of the commits of HEAD in `repo`, as recorded and
+/// as mapped, with their number of commits. `since` limits the commits to
+/// the recent ones, like `3 months ago`.
+pub fn identities(
+    repo: &GitRepository,
+    mailmap: Option<&Mailmap>,
+    since: Option<&str>,
+) -> Result<Identities> {
+    let mut found = BTreeMap::new();
+    if repo.head_sha().is_err() {
+        return Ok(found);
+    }
+    let mut args = git_args(repo, mailmap)?;
+    args.extend([
+        "log".to_string(),
+        "--format=%an%x1f%ae%x1f%aN%x1f%aE".to_string(),
+    ]);
+    if let Some(since) = since {
+        args.push(format!("--since={}", since));
+    }
+    let output = repo.git(&args)?;
+    for line in output.lines() {
+        let fields: Vec<_> = line.split('\x1f').collect();
+        let [name, email, mapped_name, mapped_email] = fields[..] else {
+            continue;
+        };
+        let recorded = Identity {
+            name: name.to_string(),
+            email: email.to_string(),
+        };
+        let mapped = Identity {
+            name: mapped_name.to_string(),
+            email: mapped_email.to_string(),
+        };
+        *found.entry((recorded, mapped)).or_insert(0) += 1;
+    }
+    Ok(found)
+}
+
+/// An identity, with its commits across the workspace.
+#[derive(Debug, Clone)]
+pub struct Author {
+    pub identity: Identity,
+    pub commits: usize,
+    pub repos: BTreeSet<String>,
+}
+
+/// The mapped identities of every repository, each once, the most commits
+/// first.
+pub fn authors(found: &[(String, Identities)]) -> Vec<Author> {
+    tally(found, |(_, mapped)| mapped)
+}
+
+/// An identity the mailmap does not mention.
+#[derive(Debug, Clone)]
+pub struct Unmapped {
+    pub author: Author,
+    /// Another identity with the same name or email, likely the same
+    /// person's.
+    pub resembles: Option<Identity>,
+}
+
+/// The recorded identities of every repository `mailmap` does not mention,
+/// the most commits first.
+pub fn unmapped(found: &[(String, Identities)], mailmap: Option<&Mailmap>) -> Vec<Unmapped> {
+    let recorded = tally(found, |(recorded, _)| recorded);
+    recorded
+        .iter()
+        .filter(|author| mailmap.is_none_or(|mailmap| !mailmap.covers(&author.identity.email)))
+        .map(|author| {
+            let identity = &author.identity;
+            let resembles = recorded
+                .iter()
+                .map(|other| &other.identity)
+                .find(|other| {
+                    *other != identity
+                        && (other.name.eq_ignore_ascii_case(&identity.name)
+                            || other.email.eq_ignore_ascii_case(&identity.email))
+                })
+                .cloned();
+            Unmapped {
+                author: author.clone(),
+                resembles,
+            }
+        })
+        .collect()
+}
+
+fn tally(
+    found: &[(String, Identities)],
+    identity: impl Fn(&(Identity, Identity)) -> &Identity,
+) -> Vec<Author> {
+    let mut authors: BTreeMap<Identity, Author> = BTreeMap::new();
+    for (repo, identities) in found {
+        for (pair, commits) in identities {
+            let identity = identity(pair);
+            let author = authors.entry(identity.clone()).or_insert_with(|| Author {
+                identity: identity.clone(),
+                commits: 0,
+                repos: BTreeSet::new(),
+            });
+            author.commits += commits;
+            author.repos.insert(repo.clone());
+        }
+    }
+    let mut authors: Vec<_> = authors.into_values().collect();
+    authors.sort_by_key(|author| Reverse(author.commits));
+    authors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{RepoState, TestWorkspace};
+
+    #[test]
+    fn merges_with_the_mailmap_of_the_repository() {
+        let test = TestWorkspace::builder()
+            .repo("api", RepoState::Clean)
+            .build()
+            .unwrap();
+        let repo = test.repository("api");
+        repo.git([
+            "-c",
+            "user.name=Jane",
+            "-c",
+            "user.email=jane@laptop.local",
+            "commit",
+            "--quiet",
+            "--allow-empty",
+            "--message=Second",
+        ])
+        .unwrap();
+        // The repository maps the test author, the workspace Jane.
+        let own = repo.path().join("authors.map");
+        fs::write(&own, "Tester <tester@example.com> <test@git-ws.invalid>\n").unwrap();
+        repo.git(["config", "mailmap.file", "authors.map"]).unwrap();
+        let dir = test.workspace().state_dir();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(MAILMAP_FILE),
+            "Jane Doe <jane@example.com> <jane@laptop.local>\n",
+        )
+        .unwrap();
+        let mailmap = Mailmap::for_workspace(test.workspace()).unwrap().unwrap();
+        assert!(mailmap.covers("JANE@laptop.local"));
+
+        let found = identities(&repo, Some(&mailmap), None).unwrap();
+        let mapped: BTreeSet<_> = found.keys().map(|(_, mapped)| mapped.to_string()).collect();
+        assert_eq!(
+            mapped,
+            BTreeSet::from([
+                "Jane Doe <jane@example.com>".to_string(),
+                "Tester <tester@example.com>".to_string(),
+            ])
+        );
+    }
+}
@@ -1,3 +1,4746 @@
-fn main() {
-    println!("Hello, world!");
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::IsTerminal;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use clap::builder::FalseyValueParser;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use tabled::Tabled;
+
+use git_ws::agent;
+use git_ws::alias;
+use git_ws::api_cache::ApiCache;
+use git_ws::audit::Audit;
+use git_ws::badge::BadgeOperation;
+use git_ws::batch::{self, AbortOperation, BatchMerge, ContinueOperation};
+use git_ws::bisect;
+use git_ws::bootstrap::BootstrapOperation;
+use git_ws::branch::{self, CheckoutOperation, CreateBranchOperation, DeleteBranchOperation};
+use git_ws::changeset::{self, RebaseOperation};
+use git_ws::ci::{self, CiCheckoutOperation, ProgressEvent, ProgressSink};
+use git_ws::clean::{self, CleanOperation, CleanOptions};
+use git_ws::compare;
+use git_ws::conflicts::{self, ResolveOperation, Side};
+use git_ws::consolidate;
+use git_ws::credentials;
+use git_ws::diff::{self, DiffStat};
+use git_ws::doctor::{self, Check};
+use git_ws::executor::{self, BatchExecutor, CancelToken, Outcome, RepoOutcome};
+use git_ws::forge::{self, CreatePullRequestOperation};
+use git_ws::freeze::{self, FreezeGuard};
+use git_ws::group::{self, Grouping, Subtotal};
+use git_ws::interactive;
+use git_ws::lockfile::{Lockfile, LOCK_FILE};
+use git_ws::log;
+use git_ws::mailmap::{self, Mailmap, MAILMAP_FILE};
+use git_ws::manifest::{Manifest, MANIFEST_FILE};
+use git_ws::middleware::{DryRun, Logging};
+use git_ws::operations::{
+    AddOperation, AttachOperation, CherryPickOperation, CommitOperation, CommitSource,
+    DescribeOperation, ExecOperation, ExitCodes, FetchOperation, GitOperation, MergeMode,
+    MergeOperation, OperationResult, OperationStatus, PullMode, PullOperation, PushOperation,
+    RebaseBranchOperation, RefreshOperation, ResetMode, ResetOperation, RevertOperation,
+    RmOperation, StatusOperation, TrackOperation, FAST_FORWARD, MERGED,
+};
+use git_ws::output::{self, RepoRecord, ResultStream};
+use git_ws::patch::{self, AmOperation, FormatPatchOperation};
+use git_ws::pathspec::Pathspecs;
+use git_ws::plan::{Plan, PlanGuard, Planner};
+use git_ws::policy;
+use git_ws::portable::{self, RestoreOperation};
+use git_ws::precondition::{Precondition, Preconditions};
+use git_ws::process;
+use git_ws::progress::ProgressBars;
+use git_ws::query::Query;
+use git_ws::recover::{self, Journal, RecoverOperation};
+use git_ws::redact;
+use git_ws::remote::{self, AddRemoteOperation, RemoveRemoteOperation, SetRemoteUrlOperation};
+use git_ws::report;
+use git_ws::repository::{self, GitRepository, IgnoredFiles, RenameDetection, ScanOptions};
+use git_ws::resume::{self, Progress};
+use git_ws::session::{self, Session};
+use git_ws::setup;
+use git_ws::shard::{self, Shard};
+use git_ws::shell::{self, Shell};
+use git_ws::stash::{self, StashApplyOperation, StashDropOperation, StashPushOperation};
+use git_ws::state::WorkspaceState;
+use git_ws::subtree;
+use git_ws::sync::SyncOperation;
+use git_ws::tag::{self, CreateTagOperation, DeleteTagOperation};
+use git_ws::template::Template;
+use git_ws::ticket;
+use git_ws::trailer;
+use git_ws::trash::{self, Trash};
+use git_ws::update::{self, Channel};
+use git_ws::usage::{self, Usage};
+use git_ws::view::{self, ViewCommitOperation};
+use git_ws::watch::WorkspaceWatcher;
+use git_ws::workspace::Workspace;
+use git_ws::Result;
+
+#[derive(Parser)]
+#[command(
+    name = "git-ws",
+    version,
+    about = "git-ws is a git workspace manage tool"
+)]
+struct Cli {
+    /// Workspace root, defaults to the current directory
+    #[arg(short = 'C', long, global = true)]
+    workspace: Option<PathBuf>,
+
+    /// Never prompt, open an editor or wait on the terminal; fail instead
+    #[arg(
+        long,
+        global = true,
+        env = "GIT_WS_NON_INTERACTIVE",
+        value_parser = FalseyValueParser::new()
+    )]
+    non_interactive: bool,
+
+    /// Show what mutating commands would do without changing anything
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Log every operation as it starts and finishes
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Repositories to work on at a time, the `concurrency` of the
+    /// configuration or as many as CPUs by default
+    #[arg(short, long, global = true, env = "GIT_WS_JOBS", value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// Stop starting repositories as this deadline nears, like 30s or 2m,
+    /// reporting those left as deferred
+    #[arg(long, global = true, value_name = "DURATION", value_parser = executor::parse_duration)]
+    deadline: Option<Duration>,
+
+    /// Cancel the batch as soon as a repository fails, skipping those left
+    /// and stopping the commands running
+    #[arg(long, global = true, env = "GIT_WS_FAIL_FAST")]
+    fail_fast: bool,
+
+    /// Do not draw the progress of batches on stderr, drawn on a terminal
+    /// otherwise, unless --non-interactive is given
+    #[arg(long, global = true, env = "GIT_WS_NO_PROGRESS")]
+    no_progress: bool,
+
+    /// Write the changes a mutating command would make to this plan file,
+    /// for `git-ws apply`, instead of making them
+    #[arg(long, global = true, value_name = "FILE")]
+    plan: Option<PathBuf>,
+
+    /// Only change REPO when it is on BRANCH at SHA, either may be left out
+    #[arg(long, global = true, value_name = "REPO=BRANCH@SHA")]
+    expect: Vec<String>,
+
+    /// Only change repositories whose working tree is clean
+    #[arg(long, global = true)]
+    expect_clean: bool,
+
+    /// Only work on the repositories of this shard of the workspace, like
+    /// 2/5, to split the workspace across parallel jobs
+    #[arg(long, global = true, env = "GIT_WS_SHARD", value_name = "INDEX/COUNT")]
+    shard: Option<Shard>,
+
+    /// Only work on the repositories with this name, `*` matching anything,
+    /// like 'service-*'; may be repeated
+    #[arg(short = 'r', long = "repo", global = true, value_name = "NAME")]
+    only_repos: Vec<String>,
+
+    /// Leave out the repositories with this name, like --repo, on top of
+    /// those excluded by the configuration; may be repeated
+    #[arg(long = "exclude", global = true, value_name = "NAME")]
+    excluded_repos: Vec<String>,
+
+    /// Pick the repositories to work on from a list before running, typing
+    /// to narrow it down
+    #[arg(short, long, global = true, conflicts_with = "non_interactive")]
+    interactive: bool,
+
+    /// Only work on the repositories of this group of the manifest
+    #[arg(long, global = true, env = "GIT_WS_GROUP", value_name = "NAME")]
+    group: Option<String>,
+
+    /// Run the command against the workspace of this host, over SSH, with
+    /// the git-ws installed there; -C is a path on the host
+    #[arg(long, global = true, value_name = "HOST")]
+    host: Option<String>,
+
+    /// Write the results of operations as JSON lines, for `--host`
+    #[arg(long, global = true, hide = true)]
+    agent: bool,
+
+    /// Push during a freeze window, the reason being written to the
+    /// journal
+    #[arg(long, global = true, value_name = "REASON")]
+    override_freeze: Option<String>,
+
+    /// Resume this interrupted batch, see `git-ws resume`
+    #[arg(long, global = true, hide = true, value_name = "ID")]
+    resume_batch: Option<String>,
+
+    /// Only change the repositories of this plan, used by `git-ws apply`
+    #[arg(long, global = true, value_name = "FILE", hide = true)]
+    apply_plan: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Commands,
+}
+
+/// Machine readable output of list and status.
+#[derive(Args)]
+struct RecordArgs {
+    /// Print each repository with this template, like
+    /// '{repo}\t{branch}\t{ahead}/{behind}'
+    #[arg(long, conflicts_with_all = ["json", "query"])]
+    template: Option<String>,
+
+    /// Print the repositories as JSON
+    #[arg(long)]
+    json: bool,
+
+    /// Print what this jq-style query gives for the JSON, like
+    /// '.[] | select(.dirty) | .repo', strings without quotes
+    #[arg(long)]
+    query: Option<String>,
+}
+
+impl RecordArgs {
+    fn format(&self) -> Result<Option<RecordFormat>> {
+        Ok(if let Some(template) = &self.template {
+            Some(RecordFormat::Template(Template::parse(template)?))
+        } else if let Some(query) = &self.query {
+            Some(RecordFormat::Json(Some(Query::parse(query)?)))
+        } else if self.json {
+            Some(RecordFormat::Json(None))
+        } else {
+            None
+        })
+    }
+}
+
+enum RecordFormat {
+    Template(Template),
+    Json(Option<Query>),
+}
+
+/// Grouping of the repositories by their first-level directory.
+#[derive(Args)]
+struct GroupArgs {
+    /// Group the repositories by their first-level directory, with a
+    /// summary line for each group
+    #[arg(long, conflicts_with_all = ["template", "json", "query"])]
+    by_dir: bool,
+
+    /// Show only the summary line of these groups, of every group when
+    /// none is given; implies --by-dir
+    #[arg(long, value_name = "GROUP", num_args = 0.., value_delimiter = ',',
+          conflicts_with_all = ["template", "json", "query"])]
+    collapse: Option<Vec<String>>,
+}
+
+impl GroupArgs {
+    fn grouping(&self) -> Option<Grouping> {
+        if self.by_dir || self.collapse.is_some() {
+            Some(Grouping::new(self.collapse.clone()))
+        } else {
+            None
+        }
+    }
+}
+
+/// A page of the repositories, for workspaces too large to look at whole.
+#[derive(Args)]
+struct PageArgs {
+    /// Only this many repositories
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Skip this many repositories first
+    #[arg(long, default_value_t = 0)]
+    offset: usize,
+}
+
+impl PageArgs {
+    /// The repositories of the page, telling on stderr when it is not all
+    /// of them.
+    fn apply(&self, repos: Vec<GitRepository>) -> Vec<GitRepository> {
+        let total = repos.len();
+        let page: Vec<_> = repos
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect();
+        if page.len() < total && !page.is_empty() {
+            eprintln!(
+                "note: repositories {} to {} of {}",
+                self.offset + 1,
+                self.offset + page.len(),
+                total
+            );
+        }
+        page
+    }
+}
+
+/// Repositories whose metadata is computed, and printed, at once. Larger
+/// workspaces are printed a chunk at a time, so memory stays bounded and
+/// the first rows show up early.
+const CHUNK: usize = 64;
+
+#[derive(Subcommand)]
+enum Commands {
+    /// List the repositories of the workspace
+    List {
+        #[command(flatten)]
+        records: RecordArgs,
+        #[command(flatten)]
+        groups: GroupArgs,
+        #[command(flatten)]
+        page: PageArgs,
+    },
+    /// Show branch and pending changes of every repository
+    Status {
+        /// Only consider paths matching these pathspecs
+        pathspec: Vec<String>,
+        /// Files at least this similar, in percent, are renamed
+        #[arg(long, value_name = "PERCENT", default_value_t = 50,
+              value_parser = clap::value_parser!(u16).range(0..=100))]
+        find_renames: u16,
+        /// Show renamed files as deleted and untracked ones
+        #[arg(long)]
+        no_renames: bool,
+        /// Show ignored files too, directories holding only ignored files
+        /// as one unless MODE is matching
+        #[arg(long, value_name = "MODE", num_args = 0..=1,
+              default_missing_value = "traditional")]
+        ignored: Option<IgnoredMode>,
+        #[command(flatten)]
+        records: RecordArgs,
+        #[command(flatten)]
+        groups: GroupArgs,
+        #[command(flatten)]
+        page: PageArgs,
+    },
+    /// Show the changes not staged yet of every repository having some
+    Diff {
+        /// Show the staged changes instead
+        #[arg(long, visible_alias = "cached")]
+        staged: bool,
+        /// Show a table of the changed files and lines instead
+        #[arg(long)]
+        stat: bool,
+        /// Only consider paths matching these pathspecs
+        pathspec: Vec<String>,
+    },
+    /// Stage changes matching the pathspecs in every repository
+    Add {
+        /// Pick the hunks to stage, one repository after the other
+        #[arg(short, long)]
+        patch: bool,
+        /// Paths to stage, everything when omitted
+        pathspec: Vec<String>,
+    },
+    /// Remove tracked files from the working tree and the index
+    Rm {
+        /// Paths to remove, directories with what they hold
+        #[arg(required = true)]
+        pathspec: Vec<String>,
+        /// Move the files to the trash of the workspace instead of deleting
+        /// them, see `git-ws trash`
+        #[arg(long)]
+        trash: bool,
+    },
+    /// Remove the untracked files of every repository
+    ///
+    /// Only lists what would be removed, unless given -f.
+    Clean {
+        /// Remove the files, rather than listing them
+        #[arg(short, long)]
+        force: bool,
+        /// Remove untracked directories too
+        #[arg(short = 'd')]
+        directories: bool,
+        /// Remove ignored files too, like build outputs
+        #[arg(short = 'x')]
+        ignored: bool,
+        /// Move the files to the trash of the workspace instead of deleting
+        /// them, see `git-ws trash`
+        #[arg(long, requires = "force")]
+        trash: bool,
+        /// Paths to clean, everything when omitted
+        pathspec: Vec<String>,
+    },
+    /// Stash the local changes of every repository, and bring them back
+    Stash {
+        #[command(subcommand)]
+        action: StashAction,
+    },
+    /// Look at and restore the files moved to the trash by --trash
+    Trash {
+        #[command(subcommand)]
+        action: TrashAction,
+    },
+    /// Print the changes of the repositories as they happen, until
+    /// interrupted
+    ///
+    /// Repositories on network filesystems are polled, see the [watch]
+    /// section of the configuration.
+    Watch,
+    /// List, create and delete branches across the repositories
+    Branch {
+        #[command(subcommand)]
+        action: BranchAction,
+    },
+    /// Tag HEAD in every repository, or list and delete tags
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Tag {
+        #[command(subcommand)]
+        action: Option<TagAction>,
+        /// Tag to create
+        #[arg(required = true)]
+        name: Option<String>,
+        /// Create an annotated tag with this message, a lightweight one
+        /// otherwise
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+    /// List the remotes of every repository, or add, remove and change
+    /// one in all of them
+    Remote {
+        #[command(subcommand)]
+        action: RemoteAction,
+    },
+    /// Set the workspace up, asking for its root, the repositories left
+    /// out, groups, concurrency and credentials, and write its
+    /// configuration
+    Setup,
+    /// Replace git-ws with its latest release, checking its checksum and
+    /// signature first
+    SelfUpdate {
+        #[arg(long, value_enum, default_value = "stable")]
+        channel: ChannelKind,
+        /// Only tell whether a newer release is out
+        #[arg(long)]
+        check: bool,
+    },
+    /// Move the workspace to another machine as one file
+    State {
+        #[command(subcommand)]
+        action: StateAction,
+    },
+    /// Look at the commits created together by git-ws
+    Batch {
+        #[command(subcommand)]
+        action: BatchAction,
+    },
+    /// Compare the branches, commits and local changes of the workspace with
+    /// another workspace, and list the repositories differing
+    ///
+    /// OTHER is a workspace directory, a manifest, a lockfile, the output of
+    /// `git-ws list --json`, or a HOST, or HOST:PATH, whose workspace is
+    /// read over SSH.
+    DiffWorkspace { other: String },
+    /// Write SVG badges of the health of every repository, for dashboards
+    ///
+    /// Each repository gets status.svg, sync.svg and last-commit.svg in a
+    /// directory of its own. Badges are not refreshed on their own: run it
+    /// again, from cron or after `sync`. Only changed badges are rewritten.
+    Badges {
+        /// Directory to write the badges in
+        #[arg(short, long, default_value = "badges")]
+        output: PathBuf,
+    },
+    /// Look at the git configuration across the repositories
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Write reports of the workspace
+    Report {
+        #[command(subcommand)]
+        action: ReportAction,
+    },
+    /// Show the latest commits of every repository
+    Log {
+        /// Number of commits shown per repository
+        #[arg(short = 'n', long = "max-count", default_value_t = 10)]
+        count: usize,
+    },
+    /// List the tickets the commits since a ref mention, in every
+    /// repository, each once
+    ///
+    /// Ticket ids match tickets.pattern of the configuration, Jira-like ids
+    /// by default. Repositories without the ref are skipped.
+    Tickets {
+        /// Ref the commits follow, like the tag of the last release
+        #[arg(long)]
+        since: String,
+        /// Ask the tracker of the configuration for the titles
+        #[arg(long)]
+        titles: bool,
+    },
+    /// List the authors of the commits of every repository, each person
+    /// once
+    ///
+    /// Identities are merged by the mailmap of each repository and by the
+    /// one of the workspace, .git-ws/mailmap.
+    Authors {
+        /// Only count the commits since this date, like "3 months ago"
+        #[arg(long)]
+        since: Option<String>,
+        /// List the identities the mailmap of the workspace does not
+        /// mention instead, with another looking like the same person's
+        #[arg(long)]
+        unmapped: bool,
+    },
+    /// Show the operations run most, how long their batches take and the
+    /// repositories failing most, from what this machine recorded
+    Usage {
+        /// Only count the operations since this date, like "1 month ago"
+        #[arg(long)]
+        since: Option<String>,
+        /// Repositories listed at most
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+    /// List the recent movements of the branches of every repository,
+    /// telling those of git-ws
+    Reflog {
+        /// Only movements since this date, like `2 days ago` or `yesterday`
+        #[arg(long, default_value = "1 hour ago")]
+        since: String,
+    },
+    /// Run the batch interrupted by a crash or a reboot again, skipping
+    /// what it did on each repository already
+    ///
+    /// Resumes the only interrupted batch, unless --batch is given.
+    Resume {
+        /// Id, or start of the id, of the batch to resume
+        #[arg(long)]
+        batch: Option<String>,
+    },
+    /// Undo a batch: reset the branches it moved back to where they were
+    ///
+    /// Lists the latest batches to choose from, unless --batch is given,
+    /// and asks before resetting anything, unless --yes is given.
+    Recover {
+        /// Id, or start of the id, of the batch to undo
+        #[arg(long)]
+        batch: Option<String>,
+        /// Reset the branches moved again since the batch too
+        #[arg(long)]
+        force: bool,
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Fetch and integrate the upstream branch in every repository
+    ///
+    /// Merges by default. Repositories left conflicted are reported and
+    /// stay conflicted, for `conflicts` to list.
+    Pull {
+        /// Rebase the branch onto its upstream instead of merging
+        #[arg(long, conflicts_with = "ff_only")]
+        rebase: bool,
+        /// Only fast-forward, failing where the branch has diverged
+        #[arg(long)]
+        ff_only: bool,
+    },
+    /// Merge a branch into the checked out branch of every repository
+    ///
+    /// The branch is taken from origin where there is no local one.
+    /// Repositories left conflicted are listed at the end, for `conflicts`,
+    /// `continue` and `abort`.
+    Merge {
+        branch: String,
+        /// Create a merge commit even when the branch fast-forwards
+        #[arg(long, conflicts_with = "ff_only")]
+        no_ff: bool,
+        /// Only fast-forward, failing where the branches have diverged
+        #[arg(long)]
+        ff_only: bool,
+    },
+    /// Cherry-pick a commit onto the checked out branch of every repository,
+    /// to backport a fix to forks of the same codebase
+    ///
+    /// Repositories without the commit, or already having its change, are
+    /// skipped. With --by-message, each repository picks the commit, on any
+    /// of its branches, whose message matches PATTERN. Repositories left
+    /// conflicted are listed at the end, for `conflicts`, `continue` and
+    /// `abort`.
+    CherryPick {
+        /// Commit to pick
+        #[arg(required_unless_present = "by_message", conflicts_with = "by_message")]
+        commit: Option<String>,
+        /// Extended regular expression matching the message of the commit
+        #[arg(long, value_name = "PATTERN")]
+        by_message: Option<String>,
+    },
+    /// Revert a commit of the checked out branch of every repository, with
+    /// a commit undoing it
+    ///
+    /// Repositories whose branch lacks the commit, or reverted it already,
+    /// are skipped. With --by-message, each repository reverts the commit of
+    /// its branch whose message matches PATTERN. Repositories left
+    /// conflicted are listed at the end, for `conflicts`, `continue` and
+    /// `abort`.
+    Revert {
+        /// Commit to revert
+        #[arg(required_unless_present = "by_message", conflicts_with = "by_message")]
+        commit: Option<String>,
+        /// Extended regular expression matching the message of the commit
+        #[arg(long, value_name = "PATTERN")]
+        by_message: Option<String>,
+    },
+    /// Reset the checked out branch of every repository to TARGET, like a
+    /// release tag, the index too by default
+    ///
+    /// --hard refuses repositories with uncommitted changes unless --force
+    /// is given. Repositories without TARGET are skipped, and `recover`
+    /// undoes the reset.
+    Reset {
+        /// Ref to reset to
+        target: String,
+        /// Keep the index and the working tree, the changes staged
+        #[arg(long, conflicts_with_all = ["mixed", "hard"])]
+        soft: bool,
+        /// Keep the working tree, the default
+        #[arg(long, conflicts_with = "hard")]
+        mixed: bool,
+        /// Reset the working tree too
+        #[arg(long)]
+        hard: bool,
+        /// Discard the uncommitted changes a hard reset loses
+        #[arg(long, requires = "hard")]
+        force: bool,
+    },
+    /// Clone the repositories of the manifest missing on disk, fetch the
+    /// others, and put every repository on its default branch
+    ///
+    /// Repositories with local changes stay on their branch, and branches
+    /// diverged from the remote are left alone.
+    Sync,
+    /// Combine the JSON reports of the shards of a workspace, like those of
+    /// `git-ws --shard 2/5 status --json`, into one
+    MergeReports {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Fetch the remote of every repository, in parallel
+    ///
+    /// Fetches the remote of the checked out branch, or origin.
+    Fetch {
+        /// Fetch every remote
+        #[arg(long)]
+        all: bool,
+        /// Delete the remote-tracking branches deleted on the remote
+        #[arg(short, long)]
+        prune: bool,
+    },
+    /// Fetch every remote of every repository, then show its status, in
+    /// one pass
+    Refresh {
+        /// Delete the remote-tracking branches deleted on the remote
+        #[arg(short, long)]
+        prune: bool,
+    },
+    /// Push the checked out branch of every repository to its upstream
+    Push {
+        /// Push branches without upstream to the branch of the same name on
+        /// --remote, and track it
+        #[arg(short = 'u', long)]
+        set_upstream: bool,
+        /// Remote for --set-upstream
+        #[arg(long, default_value = "origin")]
+        remote: String,
+    },
+    /// Resume the batch merge or rebase left conflicted, once the
+    /// conflicts are resolved
+    ///
+    /// Only the repositories left conflicted are resumed.
+    Continue,
+    /// Abort the batch merge or rebase left conflicted, putting every
+    /// repository back where the batch found it
+    Abort,
+    /// List the conflicted files of every repository, to edit or resolve
+    /// them
+    ///
+    /// Without --edit, --ours or --theirs, prints each conflicted file with
+    /// the number of conflict markers left in it.
+    Conflicts {
+        /// Only the conflicted files matching these paths
+        pathspec: Vec<String>,
+        /// Open the conflicted files in the editor, one after the other
+        #[arg(long, conflicts_with_all = ["ours", "theirs"])]
+        edit: bool,
+        /// Resolve the conflicted files by keeping our side
+        #[arg(long, conflicts_with = "theirs")]
+        ours: bool,
+        /// Resolve the conflicted files by keeping their side
+        #[arg(long)]
+        theirs: bool,
+    },
+    /// Commit the index of every repository
+    Commit {
+        /// Commit message
+        #[arg(short, long)]
+        message: String,
+        /// Stage modified and deleted tracked files first
+        #[arg(short, long)]
+        all: bool,
+        /// Add a Git-Ws-Batch trailer with an id shared by the commits of
+        /// every repository, for `batch show`
+        #[arg(long)]
+        trailers: bool,
+        /// Add a Git-Ws-Changeset trailer, implies --trailers
+        #[arg(long, value_name = "ID")]
+        changeset: Option<String>,
+    },
+    /// Run a command in every repository
+    ///
+    /// Everything after the options of exec is the command, its own options
+    /// included: `git-ws exec cargo build --release`. Put `--` before a
+    /// command starting with a dash.
+    ///
+    /// {repo}, {path} and {branch} in the command are replaced with the
+    /// name, path and branch of each repository, which the command also
+    /// finds in GIT_WS_REPO_NAME, GIT_WS_REPO_PATH and GIT_WS_BRANCH. In the
+    /// script of --shell they are quoted for where they stand, in quotes or
+    /// not, so that each stays one word.
+    Exec {
+        /// Run the command through the shell (sh, cmd on Windows), its first
+        /// word as a script like 'make | tee build.log', the words after it
+        /// as arguments quoted as given
+        #[arg(long)]
+        shell: bool,
+        /// Seconds commands get to exit after an interrupt before being killed
+        #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+        grace: u64,
+        /// Kill the command when it runs longer than this, per repository
+        #[arg(long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+        /// Limit the memory of the command, e.g. 512M or 2G
+        #[arg(long, value_name = "SIZE", value_parser = process::parse_size)]
+        max_memory: Option<u64>,
+        /// Limit the CPU time of the command
+        #[arg(long, value_name = "SECONDS")]
+        max_cpu: Option<u64>,
+        /// Exit codes of flaky failures, after which the command runs again
+        #[arg(
+            long,
+            value_name = "CODES",
+            value_delimiter = ',',
+            allow_negative_numbers = true
+        )]
+        retry_on: Vec<i32>,
+        /// How many times to run the command again after a retryable failure
+        #[arg(long, default_value_t = 2)]
+        retries: u32,
+        /// Exit codes reported as warnings instead of failures
+        #[arg(
+            long,
+            value_name = "CODES",
+            value_delimiter = ',',
+            allow_negative_numbers = true
+        )]
+        warn_on: Vec<i32>,
+        /// Print the exit code, output and duration of the command in every
+        /// repository as JSON
+        #[arg(long)]
+        json: bool,
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Set missing upstreams to the branch of the same name on a remote
+    Track {
+        /// Remote holding the upstream branches
+        #[arg(long, default_value = "origin")]
+        remote: String,
+        /// Push branches the remote does not have yet
+        #[arg(long)]
+        push: bool,
+    },
+    /// Check a branch out in every repository
+    ///
+    /// A branch only on origin is checked out as a new local branch
+    /// tracking it. Repositories whose local changes the checkout would
+    /// overwrite stay on their branch.
+    #[command(visible_alias = "switch")]
+    Checkout {
+        branch: String,
+        /// Create the branch at HEAD where it does not exist
+        #[arg(short = 'b', long)]
+        create: bool,
+        /// Repositories to check the branch out in, all of them when omitted
+        repos: Vec<String>,
+    },
+    /// Put repositories with a detached HEAD back on a branch
+    Attach {
+        /// Create or check out this branch at the current commit instead of
+        /// returning to the default branch
+        #[arg(long)]
+        branch: Option<String>,
+        /// Repositories to attach, all of them when omitted
+        repos: Vec<String>,
+    },
+    /// Describe every repository like `git describe --tags --dirty`
+    Describe {
+        #[arg(long, value_enum, default_value_t = DescribeFormat::Table)]
+        format: DescribeFormat,
+    },
+    /// Write the commit and branch of every repository as environment variables
+    Env {
+        /// File to write instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Record the commit of every repository in a lockfile
+    Lock {
+        #[arg(short, long, default_value = LOCK_FILE)]
+        output: PathBuf,
+    },
+    /// Check out the manifest repositories at their locked commits, for CI
+    ///
+    /// Progress is printed as JSON lines.
+    CiCheckout {
+        #[arg(long, default_value = MANIFEST_FILE)]
+        manifest: PathBuf,
+        #[arg(long, default_value = LOCK_FILE)]
+        lock: PathBuf,
+        /// How many times a failed fetch is retried
+        #[arg(long, default_value_t = 3)]
+        retries: u32,
+        /// History depth of the shallow fetches
+        #[arg(long, default_value_t = 1)]
+        depth: u32,
+    },
+    /// Apply the configured bootstrap steps to repositories
+    Bootstrap {
+        /// Repositories to bootstrap, all when none is given
+        repos: Vec<String>,
+    },
+    /// Diagnose the workspace, the git installation and remote access
+    Doctor,
+    /// Work with the monorepo-like view configured over the repositories
+    View {
+        #[command(subcommand)]
+        action: ViewAction,
+    },
+    /// Move directories between repositories with their history
+    Subtree {
+        #[command(subcommand)]
+        action: SubtreeAction,
+    },
+    /// Merge repositories into one, each below its own directory, with history
+    Consolidate {
+        /// Repository receiving the others
+        #[arg(long)]
+        into: String,
+        #[arg(required = true)]
+        repos: Vec<String>,
+    },
+    /// Rebase the checked out branch of every repository onto UPSTREAM, or
+    /// the branches of a changeset, force-pushing them with lease
+    ///
+    /// Repositories left conflicted are remembered: `rebase --continue`
+    /// concludes their rebases once resolved, `rebase --abort` stops them.
+    /// The branches of changeset PAY-123 are the local branches whose name
+    /// contains PAY-123, ignoring case.
+    Rebase {
+        /// Branch to rebase onto, like origin/main
+        #[arg(required_unless_present_any = ["changeset", "continue_", "abort"],
+              conflicts_with_all = ["changeset", "continue_", "abort"])]
+        upstream: Option<String>,
+        /// Changeset id, e.g. PAY-123
+        #[arg(long, conflicts_with_all = ["continue_", "abort"])]
+        changeset: Option<String>,
+        /// New base of the branches of the changeset
+        #[arg(long, default_value = "origin/main")]
+        onto: String,
+        /// Keep the rebased branches of the changeset local
+        #[arg(long)]
+        no_push: bool,
+        /// Conclude the rebases left conflicted, once resolved
+        #[arg(long = "continue", conflicts_with = "abort")]
+        continue_: bool,
+        /// Stop the rebases left conflicted, putting their branch back
+        #[arg(long)]
+        abort: bool,
+    },
+    /// Export the commits made since a ref as patches, one directory per
+    /// repository
+    FormatPatch {
+        /// Ref the exported commits follow, e.g. origin/main
+        #[arg(long)]
+        since: String,
+        /// Directory receiving the patches
+        #[arg(short, long, default_value = "patches")]
+        output: PathBuf,
+        /// Add a cover letter summarizing the series, for mailing lists
+        #[arg(long)]
+        cover_letter: bool,
+    },
+    /// Apply patches exported by format-patch, from another workspace
+    Am {
+        /// Directory written by format-patch
+        dir: PathBuf,
+    },
+    /// Look at the branches of a changeset across repositories
+    Changeset {
+        #[command(subcommand)]
+        action: ChangesetAction,
+    },
+    /// Work with pull requests
+    Pr {
+        #[command(subcommand)]
+        action: PrAction,
+    },
+    /// Run the command of a plan written with --plan
+    ///
+    /// Only the planned repositories are changed, and nothing is when any of
+    /// them changed since the plan was written.
+    Apply {
+        #[arg(value_name = "PLAN")]
+        file: PathBuf,
+    },
+    /// Record the git-ws commands run in this workspace to a session file
+    Record {
+        #[command(subcommand)]
+        action: RecordAction,
+    },
+    /// Run the commands of a recorded session, confirming each one
+    ///
+    /// Pass -C/--workspace to replay them against another workspace.
+    Replay {
+        /// Session file written by record
+        session: PathBuf,
+        /// Run every step without asking
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Print the path of the repository best matching a fuzzy name, for the
+    /// cd of the shell integration
+    Cd {
+        /// Part of the repository name, the workspace root when omitted
+        query: Option<String>,
+        /// List the matching repositories, best first, instead
+        #[arg(long)]
+        list: bool,
+    },
+    /// Print the completion script of git-ws for a shell
+    Completions {
+        shell: ShellKind,
+        /// Name of the git alias running git-ws, to complete as well
+        #[arg(long, default_value = "ws")]
+        alias: String,
+    },
+    /// Add the git alias running git-ws, to run it as `git ws`
+    InstallAlias {
+        /// Name of the alias
+        #[arg(long, default_value = "ws")]
+        name: String,
+        /// Also install the completion of git-ws, and of the alias, for
+        /// this shell
+        #[arg(long, value_name = "SHELL")]
+        completions: Option<ShellKind>,
+    },
+    /// Print the shell integration, to evaluate from the shell startup file
+    ///
+    /// It defines a function running git-ws, whose `cd` subcommand jumps to
+    /// a repository of the workspace: eval "$(git-ws shell-init bash)"
+    ShellInit {
+        shell: ShellKind,
+        /// Name of the function
+        #[arg(long, default_value = "gws")]
+        name: String,
+    },
+    /// Exclude a repository from every mutating operation
+    Pin { repo: String },
+    /// Allow mutating operations on a pinned repository again
+    Unpin { repo: String },
+    /// Bisect one repository, using the rest of the workspace as test bed
+    Bisect {
+        #[command(subcommand)]
+        action: BisectAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum BranchAction {
+    /// List the branches, with the repositories having each, `*` marking
+    /// those having it checked out
+    List,
+    /// Create a branch at HEAD in every repository
+    Create { name: String },
+    /// Delete a branch from every repository having it
+    Delete {
+        name: String,
+        /// Delete the branch even when it is not merged
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum StashAction {
+    /// Stash the local changes of every repository having some
+    Push {
+        /// Describe the stashed changes
+        #[arg(short, long)]
+        message: Option<String>,
+        /// Stash the untracked files too
+        #[arg(short = 'u', long)]
+        include_untracked: bool,
+    },
+    /// Apply the entries of the latest `stash push` of git-ws, and drop them
+    Pop {
+        /// Entry to apply instead, like `stash@{1}` or `1`
+        #[arg(value_parser = stash::parse_index)]
+        stash: Option<usize>,
+    },
+    /// Apply the entries of the latest `stash push` of git-ws, keeping them
+    Apply {
+        /// Entry to apply instead, like `stash@{1}` or `1`
+        #[arg(value_parser = stash::parse_index)]
+        stash: Option<usize>,
+    },
+    /// List the stash entries of every repository
+    List,
+    /// Drop the entries of the latest `stash push` of git-ws
+    Drop {
+        /// Entry to drop instead, like `stash@{1}` or `1`
+        #[arg(value_parser = stash::parse_index)]
+        stash: Option<usize>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Show the effective value of some keys in every repository, marking
+    /// the values differing from the one most repositories have
+    ///
+    /// Exits with 1 when a value differs.
+    Audit {
+        /// Keys to compare, like user.email,core.autocrlf,pull.rebase
+        #[arg(long, required = true, value_delimiter = ',')]
+        keys: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReportAction {
+    /// Write a static HTML dashboard: status, statistics, stale
+    /// repositories and compliance with the manifest
+    ///
+    /// Made from the state `refresh` cached, without network. Repositories
+    /// missing from the cache are collected on the way.
+    Html {
+        /// Directory to write index.html in
+        #[arg(short, long, default_value = "site")]
+        output: PathBuf,
+        /// Days without commit after which a repository is stale
+        #[arg(long, default_value_t = 90)]
+        stale_days: u64,
+        /// Collect the state of every repository now, rather than reading
+        /// what `refresh` cached
+        #[arg(long)]
+        refresh: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagAction {
+    /// List the tags, with the repositories having each
+    List,
+    /// Delete a tag from every repository having it
+    Delete { name: String },
+}
+
+#[derive(Subcommand)]
+enum RemoteAction {
+    /// List the remotes of every repository, with their URLs
+    List,
+    /// Add a remote to every repository not having it
+    ///
+    /// {repo} in the URL is replaced with the name of the repository in the
+    /// workspace, like payments/api, and {name} with its last component,
+    /// like api.
+    Add { name: String, url: String },
+    /// Remove a remote, and its remote-tracking branches, from every
+    /// repository having it
+    #[command(visible_alias = "rm")]
+    Remove { name: String },
+    /// Change the URL of a remote in every repository having it, {repo}
+    /// and {name} replaced like for add
+    SetUrl {
+        name: String,
+        url: String,
+        /// Only change the URL pushed to
+        #[arg(long)]
+        push: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TrashAction {
+    /// List the runs whose files are in the trash, the latest first
+    List,
+    /// Move the files of a run back to their repositories
+    ///
+    /// Their removal stays staged, `git-ws add` them to keep them.
+    Restore {
+        /// Run id, printed by the command trashing the files and by
+        /// `trash list`
+        id: String,
+        /// Replace the files the repositories have again
+        #[arg(long)]
+        force: bool,
+    },
+    /// Delete every file in the trash for good
+    Empty,
+}
+
+#[derive(Subcommand)]
+enum StateAction {
+    /// Write the manifest, lockfile, configuration and journal of the
+    /// workspace, with the branch and commit of every repository, to FILE
+    Export {
+        #[arg(default_value = "state.tar.zst")]
+        file: PathBuf,
+        /// Add the local changes of the repositories, untracked files
+        /// included
+        #[arg(long)]
+        changes: bool,
+    },
+    /// Recreate the workspace exported to FILE: clone the repositories and
+    /// put them on their branch and commit
+    Import {
+        #[arg(default_value = "state.tar.zst")]
+        file: PathBuf,
+        /// Replace the manifest, lockfile and configuration of the workspace
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum BatchAction {
+    /// List the commits of a batch, created by `commit --trailers`
+    Show {
+        /// Batch id, printed by `commit --trailers`
+        id: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ChangesetAction {
+    /// Print the changes of a changeset as one diff, paths prefixed with
+    /// the repository
+    Diff {
+        /// Changeset id, e.g. PAY-123
+        id: String,
+        /// Branch to compare with, `origin/<default branch>` when omitted
+        #[arg(long)]
+        base: Option<String>,
+        /// One patch per commit, as git format-patch writes them
+        #[arg(long)]
+        series: bool,
+        /// File to write instead of standard output
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum RecordAction {
+    /// Start recording, appending to the session file if it exists
+    Start {
+        #[arg(default_value = "session.json")]
+        session: PathBuf,
+    },
+    /// Stop recording
+    Stop,
+}
+
+#[derive(Subcommand)]
+enum PrAction {
+    /// Show the open pull request of the checked out branch of every
+    /// repository, and the state of its checks
+    ///
+    /// Responses of the forge are cached in the workspace and revalidated,
+    /// so running it again is quick and spares the rate limit.
+    Status,
+    /// Open a pull request from the checked out branch of every repository,
+    /// once pushed to origin
+    ///
+    /// Repositories on the base branch, or with a pull request open
+    /// already, are skipped.
+    Create {
+        #[arg(long)]
+        title: String,
+        /// Branch to merge into, the default branch when omitted
+        #[arg(long)]
+        base: Option<String>,
+    },
+    /// Merge the pull requests of a changeset one after the other
+    ///
+    /// Before each merge, waits for the checks of the pull request to pass.
+    /// Stops at the first failure, listing what to revert.
+    Merge {
+        /// Changeset id, e.g. PAY-123
+        #[arg(long)]
+        changeset: String,
+        #[arg(long, value_enum, default_value_t = MergeOrder::Workspace)]
+        order: MergeOrder,
+        /// Seconds to wait for the checks of each pull request
+        #[arg(long, value_name = "SECONDS", default_value_t = 1800)]
+        ci_timeout: u64,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum MergeOrder {
+    /// The order of the repositories in the workspace
+    Workspace,
+    /// Dependencies first, following `depends_on` in the manifest
+    Deps,
+}
+
+#[derive(Subcommand)]
+enum BisectAction {
+    /// Start bisecting a repository and pin it until the bisect is reset
+    Start {
+        repo: String,
+        /// A revision known to be bad
+        bad: String,
+        /// A revision known to be good
+        good: String,
+        /// Command run from the workspace root to test each commit
+        #[arg(long)]
+        test: Option<String>,
+    },
+    /// End the bisect and restore the repository
+    Reset,
+}
+
+#[derive(Subcommand)]
+enum SubtreeAction {
+    /// Extract a directory into another repository, created if missing
+    Split {
+        repo: String,
+        path: String,
+        /// Repository receiving the directory
+        #[arg(long)]
+        to: String,
+        /// Directory in an existing target, the same path by default
+        #[arg(long)]
+        prefix: Option<String>,
+        /// Remove the directory from the source repository afterwards
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Add or update a directory of a repository from another repository
+    Merge {
+        repo: String,
+        prefix: String,
+        /// Repository to take the history from
+        #[arg(long)]
+        from: String,
+        /// Only take the history of this directory of the source
+        #[arg(long)]
+        path: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ViewAction {
+    /// Create the view directory with a link per mapping
+    Materialize,
+    /// Show pending changes per view path
+    Status,
+    /// Commit the changes below the mapped directories
+    Commit {
+        #[arg(short, long)]
+        message: String,
+    },
+}
+
+/// Which ignored files status shows, as for `git status --ignored`.
+#[derive(Clone, Copy, ValueEnum)]
+enum IgnoredMode {
+    /// Directories holding only ignored files as one entry
+    Traditional,
+    /// Every ignored file
+    Matching,
+    No,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ShellKind {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl From<ShellKind> for Shell {
+    fn from(shell: ShellKind) -> Self {
+        match shell {
+            ShellKind::Bash => Shell::Bash,
+            ShellKind::Zsh => Shell::Zsh,
+            ShellKind::Fish => Shell::Fish,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ChannelKind {
+    Stable,
+    /// The prereleases, built from the main branch
+    Nightly,
+}
+
+impl From<ChannelKind> for Channel {
+    fn from(channel: ChannelKind) -> Self {
+        match channel {
+            ChannelKind::Stable => Channel::Stable,
+            ChannelKind::Nightly => Channel::Nightly,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DescribeFormat {
+    Table,
+    Json,
+    /// `REPO_<NAME>_VERSION=...` lines
+    Env,
+}
+
+#[derive(Tabled)]
+struct CheckRow {
+    #[tabled(rename = "Section")]
+    section: &'static str,
+    #[tabled(rename = "Subject")]
+    subject: String,
+    #[tabled(rename = "Check")]
+    name: &'static str,
+    #[tabled(rename = "Result")]
+    result: &'static str,
+    #[tabled(rename = "Detail")]
+    detail: String,
+}
+
+impl From<Check> for CheckRow {
+    fn from(check: Check) -> Self {
+        CheckRow {
+            section: check.section,
+            subject: check.subject,
+            name: check.name,
+            result: if check.ok { "ok" } else { "failed" },
+            detail: redact::redact(&check.detail),
+        }
+    }
+}
+
+#[derive(Tabled)]
+struct PlanRow {
+    #[tabled(rename = "Repository")]
+    repo: String,
+    #[tabled(rename = "Branch")]
+    branch: String,
+    #[tabled(rename = "Commits")]
+    commits: usize,
+    #[tabled(rename = "Destination")]
+    destination: String,
+}
+
+#[derive(Tabled)]
+struct PrStatusRow {
+    #[tabled(rename = "Repository")]
+    repo: String,
+    #[tabled(rename = "Branch")]
+    branch: String,
+    #[tabled(rename = "Pull request")]
+    url: String,
+    #[tabled(rename = "Checks")]
+    checks: String,
+}
+
+#[derive(Tabled)]
+struct MergeRow {
+    #[tabled(rename = "#")]
+    position: usize,
+    #[tabled(rename = "Repository")]
+    repo: String,
+    #[tabled(rename = "Branch")]
+    branch: String,
+    #[tabled(rename = "Pull request")]
+    url: String,
+}
+
+#[derive(Tabled)]
+struct ViewRow {
+    #[tabled(rename = "View")]
+    view: String,
+    #[tabled(rename = "Repository")]
+    repo: String,
+    #[tabled(rename = "Path")]
+    path: String,
+    #[tabled(rename = "Changes")]
+    changes: String,
+}
+
+#[derive(Tabled)]
+struct TrashRow {
+    #[tabled(rename = "Run")]
+    id: String,
+    #[tabled(rename = "When")]
+    when: String,
+    #[tabled(rename = "Repository")]
+    repo: String,
+    #[tabled(rename = "Files")]
+    files: String,
+}
+
+#[derive(Tabled)]
+struct StashRow {
+    #[tabled(rename = "Repository")]
+    repo: String,
+    #[tabled(rename = "Stash")]
+    name: String,
+    #[tabled(rename = "When")]
+    when: String,
+    #[tabled(rename = "Message")]
+    message: String,
+}
+
+#[derive(Tabled)]
+struct BranchRow {
+    #[tabled(rename = "Branch")]
+    branch: String,
+    #[tabled(rename = "Repositories")]
+    repos: String,
+}
+
+#[derive(Tabled)]
+struct DiffStatRow {
+    #[tabled(rename = "Repository")]
+    repo: String,
+    #[tabled(rename = "Files")]
+    files: usize,
+    #[tabled(rename = "Insertions")]
+    insertions: String,
+    #[tabled(rename = "Deletions")]
+    deletions: String,
+}
+
+impl DiffStatRow {
+    fn new(repo: impl Into<String>, stat: DiffStat) -> Self {
+        DiffStatRow {
+            repo: repo.into(),
+            files: stat.files,
+            insertions: format!("+{}", stat.insertions),
+            deletions: format!("-{}", stat.deletions),
+        }
+    }
+}
+
+#[derive(Tabled)]
+struct TicketRow {
+    #[tabled(rename = "Ticket")]
+    id: String,
+    #[tabled(rename = "Repositories")]
+    repos: String,
+    #[tabled(rename = "Commits")]
+    commits: usize,
+}
+
+#[derive(Tabled)]
+struct TitledTicketRow {
+    #[tabled(rename = "Ticket")]
+    id: String,
+    #[tabled(rename = "Title")]
+    title: String,
+    #[tabled(rename = "Repositories")]
+    repos: String,
+    #[tabled(rename = "Commits")]
+    commits: usize,
+}
+
+#[derive(Tabled)]
+struct AuthorRow {
+    #[tabled(rename = "Author")]
+    author: String,
+    #[tabled(rename = "Commits")]
+    commits: usize,
+    #[tabled(rename = "Repositories")]
+    repos: String,
+}
+
+#[derive(Tabled)]
+struct UnmappedRow {
+    #[tabled(rename = "Identity")]
+    identity: String,
+    #[tabled(rename = "Commits")]
+    commits: usize,
+    #[tabled(rename = "Repositories")]
+    repos: String,
+    #[tabled(rename = "Looks like")]
+    resembles: String,
+}
+
+#[derive(Tabled)]
+struct LogRow {
+    #[tabled(rename = "Commit")]
+    hash: String,
+    #[tabled(rename = "Author")]
+    author: String,
+    #[tabled(rename = "Date")]
+    date: String,
+    #[tabled(rename = "Summary")]
+    summary: String,
+}
+
+#[derive(Tabled)]
+struct RemoteRow {
+    #[tabled(rename = "Repository")]
+    repo: String,
+    #[tabled(rename = "Remote")]
+    name: String,
+    #[tabled(rename = "URL")]
+    url: String,
+    #[tabled(rename = "Push URL")]
+    push_url: String,
+}
+
+#[derive(Tabled)]
+struct TagRow {
+    #[tabled(rename = "Tag")]
+    tag: String,
+    #[tabled(rename = "Kind")]
+    kind: &'static str,
+    #[tabled(rename = "Repositories")]
+    repos: String,
+}
+
+#[derive(Tabled)]
+struct DivergenceRow {
+    #[tabled(rename = "Repository")]
+    repo: String,
+    #[tabled(rename = "Here")]
+    here: String,
+    #[tabled(rename = "There")]
+    there: String,
+    #[tabled(rename = "Differs")]
+    differences: String,
+}
+
+#[derive(Tabled)]
+struct ReflogRow {
+    #[tabled(rename = "Repository")]
+    repo: String,
+    #[tabled(rename = "Branch")]
+    branch: String,
+    #[tabled(rename = "When")]
+    when: String,
+    #[tabled(rename = "Change")]
+    change: String,
+    #[tabled(rename = "Reflog")]
+    message: String,
+    #[tabled(rename = "git-ws")]
+    batch: String,
+}
+
+#[derive(Tabled)]
+struct OperationUsageRow {
+    #[tabled(rename = "Operation")]
+    name: String,
+    #[tabled(rename = "Batches")]
+    batches: usize,
+    #[tabled(rename = "Repositories")]
+    runs: usize,
+    #[tabled(rename = "Failed")]
+    failed: usize,
+    #[tabled(rename = "Average batch")]
+    average_batch: String,
+    #[tabled(rename = "At once")]
+    parallelism: String,
+}
+
+#[derive(Tabled)]
+struct RepoUsageRow {
+    #[tabled(rename = "Repository")]
+    name: String,
+    #[tabled(rename = "Runs")]
+    runs: usize,
+    #[tabled(rename = "Failed")]
+    failed: usize,
+    #[tabled(rename = "Warnings")]
+    warnings: usize,
+    #[tabled(rename = "Average")]
+    average: String,
+    #[tabled(rename = "Failing")]
+    failing: String,
+}
+
+#[derive(Tabled)]
+struct BatchRow {
+    #[tabled(rename = "#")]
+    position: usize,
+    #[tabled(rename = "Batch")]
+    id: String,
+    #[tabled(rename = "When")]
+    when: String,
+    #[tabled(rename = "Operations")]
+    operations: String,
+    #[tabled(rename = "Repositories")]
+    repos: String,
+}
+
+#[derive(Tabled)]
+struct ResetRow {
+    #[tabled(rename = "Repository")]
+    repo: String,
+    #[tabled(rename = "Branch")]
+    branch: String,
+    #[tabled(rename = "From")]
+    from: String,
+    #[tabled(rename = "To")]
+    to: String,
+}
+
+#[derive(Tabled)]
+struct TrailedRow {
+    #[tabled(rename = "Repository")]
+    repo: String,
+    #[tabled(rename = "Commit")]
+    id: String,
+    #[tabled(rename = "Subject")]
+    subject: String,
+}
+
+#[derive(Tabled)]
+struct ConflictRow {
+    #[tabled(rename = "Repository")]
+    repo: String,
+    #[tabled(rename = "File")]
+    path: String,
+    #[tabled(rename = "Markers")]
+    markers: usize,
+}
+
+#[derive(Tabled)]
+struct RepoRow {
+    #[tabled(rename = "Repository")]
+    name: String,
+    #[tabled(rename = "Branch")]
+    branch: String,
+    #[tabled(rename = "Path")]
+    path: String,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", redact::redact(&e.to_string()));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<ExitCode> {
+    interactive::set_non_interactive(cli.non_interactive);
+    output::set_agent_mode(cli.agent);
+    if let Some(host) = &cli.host {
+        if matches!(
+            cli.command,
+            Commands::Cd { .. }
+                | Commands::Completions { .. }
+                | Commands::InstallAlias { .. }
+                | Commands::ShellInit { .. }
+        ) {
+            eprintln!("error: --host is not supported by this command");
+            return Ok(ExitCode::FAILURE);
+        }
+        let args = session::without_options(command_args(), &["--host"], false);
+        return agent::run(host, args);
+    }
+    let root = match &cli.workspace {
+        Some(root) => root.clone(),
+        None => alias::start_dir()?,
+    };
+    let mut workspace = Workspace::discover(&root);
+    if let Some(shard) = cli.shard {
+        workspace = workspace.with_shard(shard);
+    }
+    if let Some(group) = &cli.group {
+        workspace = workspace.with_group(group);
+    }
+    if !cli.only_repos.is_empty() {
+        workspace = workspace.with_repos(cli.only_repos.clone());
+    }
+    let config = workspace.load_config()?;
+    workspace = workspace
+        .without_repos(config.exclude.clone())
+        .without_repos(cli.excluded_repos.clone());
+    if cli.interactive {
+        let names = workspace
+            .discover_repositories()?
+            .iter()
+            .map(|repo| repo.name().to_string())
+            .collect();
+        let picked = interactive::choose("Repositories to work on:", names)?;
+        if picked.is_empty() {
+            eprintln!("error: no repository picked");
+            return Ok(ExitCode::FAILURE);
+        }
+        workspace = workspace.with_repos(picked);
+    }
+    credentials::configure_hosts(config.hosts.clone());
+    let state = workspace.load_state()?;
+    let concurrency = cli
+        .jobs
+        .map_or_else(|| config.concurrency(), NonZeroUsize::get);
+    let mut executor = BatchExecutor::new(concurrency).with_pinned(state.pinned.clone());
+    if let Some(deadline) = cli.deadline {
+        executor = executor.with_deadline(Instant::now() + deadline);
+    }
+    if cli.fail_fast {
+        executor = executor.with_fail_fast();
+    }
+    if draws_progress(&cli, std::io::stderr().is_terminal()) {
+        executor = executor.with_progress_bars(ProgressBars::new());
+    }
+    if cli.verbose {
+        executor = executor.with_middleware(Arc::new(Logging));
+    }
+    if cli.dry_run {
+        if !effect(&cli.command).honours_dry_run() {
+            eprintln!("error: --dry-run is not supported by {}", command_name());
+            return Ok(ExitCode::FAILURE);
+        }
+        executor = executor.with_middleware(Arc::new(DryRun));
+    }
+    if let Some(path) = &cli.plan {
+        if !plannable(&cli.command) {
+            eprintln!("error: --plan is not supported by this command");
+            return Ok(ExitCode::FAILURE);
+        }
+        // Commands that can be planned take no free-form arguments, so the
+        // options are removed wherever they are.
+        let args =
+            session::without_options(command_args(), &["-C", "--workspace", "--plan"], false);
+        let path = std::env::current_dir()?.join(path);
+        let planner = Planner::new(&path, args, config.plan.key()?)?;
+        executor = executor.with_middleware(Arc::new(planner));
+        eprintln!("writing the plan to {}", path.display());
+    }
+    let mut preconditions = Preconditions::default();
+    preconditions.all.clean = cli.expect_clean;
+    for spec in &cli.expect {
+        let (repo, precondition) = Precondition::parse(spec)?;
+        preconditions.repos.insert(repo, precondition);
+    }
+    let mut planned = None;
+    if let Some(path) = &cli.apply_plan {
+        let plan = Plan::load(path)?;
+        plan.verify(&config.plan)?;
+        executor = executor.with_middleware(Arc::new(PlanGuard::new(&plan)));
+        preconditions.repos.extend(plan.preconditions().repos);
+        planned = Some(plan.repositories);
+    }
+    if config.policy.is_enabled()
+        && effect(&cli.command).changes_repositories()
+        && !cli.dry_run
+        && cli.plan.is_none()
+    {
+        // The repositories the batch may change: those of the plan it
+        // applies, or every one but the pinned.
+        let repos: Vec<_> = workspace
+            .discover_repositories()?
+            .into_iter()
+            .filter(|repo| match &planned {
+                Some(planned) => planned.iter().any(|planned| planned.repo == repo.name()),
+                None => !state.is_pinned(repo.name()),
+            })
+            .collect();
+        let args = session::without_options(command_args(), &["-C", "--workspace"], false);
+        policy::check(&config.policy, args, &repos, target_ref(&cli.command))?;
+    }
+    executor = executor.with_preconditions(preconditions);
+    let state_dir = workspace.state_dir();
+    let (batch_id, progress) = match &cli.resume_batch {
+        Some(id) => match resume::pending(&state_dir)?
+            .into_iter()
+            .find(|(batch, _)| batch.id == *id)
+        {
+            Some((batch, done)) => (
+                batch.id.clone(),
+                Progress::resuming(&state_dir, batch, done),
+            ),
+            _ => {
+                eprintln!("error: batch {} was not interrupted", id);
+                return Ok(ExitCode::FAILURE);
+            }
+        },
+        None => {
+            let batch_id = trailer::new_batch_id();
+            let args = session::without_options(
+                command_args(),
+                &["-C", "--workspace", "--resume-batch"],
+                true,
+            );
+            let progress = Progress::new(&state_dir, &batch_id, args);
+            (batch_id, progress)
+        }
+    };
+    let freeze_guard = Arc::new(FreezeGuard::new(
+        &config.freeze,
+        workspace.root(),
+        recover::now(),
+        cli.override_freeze.as_deref(),
+    ));
+    let journal =
+        Arc::new(Journal::new(&workspace.state_dir(), &batch_id).with_freeze(freeze_guard.clone()));
+    executor = executor
+        .with_middleware(freeze_guard)
+        .with_middleware(journal.clone());
+    if !cli.dry_run && cli.plan.is_none() {
+        executor = executor
+            .with_middleware(Arc::new(Usage::new(&state_dir, &batch_id)))
+            .with_middleware(Arc::new(progress));
+    }
+    if let Some(session) = &state.recording {
+        if !matches!(
+            cli.command,
+            Commands::Record { .. }
+                | Commands::Replay { .. }
+                | Commands::Cd { .. }
+                | Commands::Completions { .. }
+                | Commands::InstallAlias { .. }
+                | Commands::ShellInit { .. }
+        ) {
+            Session::append(session, command_args())?;
+        }
+    }
+
+    match cli.command {
+        Commands::List {
+            records: args,
+            groups,
+            page,
+        } => {
+            let repos = page.apply(workspace.discover_repositories()?);
+            if let Some(format) = args.format()? {
+                let options = ScanOptions::default();
+                let mut printer = RecordPrinter::new(&format);
+                for chunk in repos.chunks(CHUNK) {
+                    let pathspecs = Pathspecs::default();
+                    printer.print(records(chunk, &executor, &state, &pathspecs, options).await?)?;
+                }
+                return printer.finish();
+            }
+            let row = |repo: &GitRepository| RepoRow {
+                branch: branch_column(repo),
+                name: pin_marker(repo.name(), state.is_pinned(repo.name())),
+                path: repo.path().display().to_string(),
+            };
+            if let Some(grouping) = groups.grouping() {
+                let groups = group::group(repos, |repo| repo.name())
+                    .into_iter()
+                    .map(|(name, repos)| (name, (Subtotal::count(repos.len()), repos)))
+                    .collect();
+                print!(
+                    "{}",
+                    grouping.render(groups, |repos| output::render(repos.iter().map(row)))
+                );
+                return Ok(ExitCode::SUCCESS);
+            }
+            for chunk in repos.chunks(CHUNK) {
+                print!("{}", output::render(chunk.iter().map(row)));
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Status {
+            pathspec,
+            find_renames,
+            no_renames,
+            ignored,
+            records: args,
+            groups,
+            page,
+        } => {
+            let options = ScanOptions {
+                renames: if no_renames {
+                    RenameDetection::Off
+                } else {
+                    RenameDetection::Similarity(find_renames)
+                },
+                ignored: match ignored {
+                    None | Some(IgnoredMode::No) => IgnoredFiles::Hidden,
+                    Some(IgnoredMode::Traditional) => IgnoredFiles::Collapsed,
+                    Some(IgnoredMode::Matching) => IgnoredFiles::Matching,
+                },
+            };
+            let repos = workspace.discover_repositories()?;
+            let pathspecs = Pathspecs::resolve(pathspec, workspace.root(), &repos);
+            let repos = page.apply(narrow(repos, &pathspecs)?);
+            if let Some(format) = args.format()? {
+                let mut printer = RecordPrinter::new(&format);
+                for chunk in repos.chunks(CHUNK) {
+                    printer.print(records(chunk, &executor, &state, &pathspecs, options).await?)?;
+                }
+                return printer.finish();
+            }
+            let status = Arc::new(
+                StatusOperation::resolved(pathspecs)
+                    .renames(options.renames)
+                    .ignored(options.ignored),
+            );
+            let mut failed = false;
+            let grouping = groups.grouping();
+            if let Some(grouping) = grouping {
+                // Subtotals are counted from the statuses, not their messages.
+                let mut outcomes = executor
+                    .for_each(&repos, move |repo| {
+                        let status = Arc::clone(&status);
+                        async move { repo.run_blocking(move |repo| status.status(repo)).await }
+                    })
+                    .await;
+                for outcome in &mut outcomes {
+                    failed |= matches!(outcome.outcome, Outcome::Failed(_));
+                    outcome.repo = pin_marker(&outcome.repo, state.is_pinned(&outcome.repo));
+                }
+                let groups = group::group(outcomes, |outcome| &outcome.repo)
+                    .into_iter()
+                    .map(|(name, outcomes)| (name, (Subtotal::of_status(&outcomes), outcomes)))
+                    .collect();
+                print!(
+                    "{}",
+                    grouping.render(groups, |outcomes| {
+                        let results: Vec<_> = outcomes.iter().map(RepoOutcome::to_result).collect();
+                        output::results_table(&results)
+                    })
+                );
+            } else {
+                let operation: Arc<dyn GitOperation> = status;
+                for chunk in repos.chunks(CHUNK) {
+                    let mut results = executor
+                        .execute_operation(chunk, Arc::clone(&operation))
+                        .await;
+                    for result in &mut results {
+                        result.repo = pin_marker(&result.repo, state.is_pinned(&result.repo));
+                    }
+                    failed |= results.iter().any(OperationResult::is_failure);
+                    print!("{}", output::results_table(&results));
+                }
+            }
+            Ok(if failed {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            })
+        }
+        Commands::Diff {
+            staged,
+            stat,
+            pathspec,
+        } => {
+            let repos = workspace.discover_repositories()?;
+            let pathspecs = Pathspecs::resolve(pathspec, workspace.root(), &repos);
+            let repos = narrow(repos, &pathspecs)?;
+            let color = std::io::stdout().is_terminal();
+            let mut rows = Vec::new();
+            let mut total = DiffStat::default();
+            for repo in &repos {
+                let Some(matching) = pathspecs.for_repo(repo.name()) else {
+                    continue;
+                };
+                if stat {
+                    let repo_stat = diff::stat(repo, staged, &matching)?;
+                    if repo_stat.files > 0 {
+                        total.add(repo_stat);
+                        rows.push(DiffStatRow::new(repo.name(), repo_stat));
+                    }
+                    continue;
+                }
+                let diff = diff::diff(repo, staged, &matching, color)?;
+                if diff.is_empty() {
+                    continue;
+                }
+                println!("== {} ==", repo.name());
+                println!("{}\n", diff);
+            }
+            if stat && !rows.is_empty() {
+                if rows.len() > 1 {
+                    rows.push(DiffStatRow::new("total", total));
+                }
+                print!("{}", output::render(rows));
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Add { patch, pathspec } => {
+            let repos = workspace.discover_repositories()?;
+            let pathspecs = Pathspecs::resolve(pathspec, workspace.root(), &repos);
+            let repos = narrow(repos, &pathspecs)?;
+            if patch {
+                interactive::require_input("add --patch")?;
+                let mut results = Vec::new();
+                for repo in &repos {
+                    let Some(matching) = pathspecs.for_repo(repo.name()) else {
+                        continue;
+                    };
+                    let mut diff = vec!["diff".to_string(), "--quiet".to_string()];
+                    diff.push("--".to_string());
+                    diff.extend(matching.iter().cloned());
+                    // Exits with 1 when there are changes.
+                    if repo.git(&diff).is_ok() {
+                        continue;
+                    }
+                    eprintln!("\n== {} ==", repo.name());
+                    let mut args = vec!["add".to_string(), "--patch".to_string()];
+                    args.push("--".to_string());
+                    args.extend(matching.iter().cloned());
+                    let started = std::time::Instant::now();
+                    let outcome = interactive::git_on_terminal(repo.path(), &args)
+                        .and_then(|_| repo.git(["diff", "--cached", "--name-only"]));
+                    let (status, message) = match outcome {
+                        Ok(staged) => (
+                            OperationStatus::Success,
+                            format!("{} file(s) staged", staged.lines().count()),
+                        ),
+                        Err(e) => (OperationStatus::Failed, e.to_string()),
+                    };
+                    results.push(OperationResult {
+                        repo: repo.name().to_string(),
+                        status,
+                        message,
+                        duration: started.elapsed(),
+                    });
+                }
+                if results.is_empty() {
+                    println!("nothing to stage");
+                    return Ok(ExitCode::SUCCESS);
+                }
+                return report(&results);
+            }
+            let results = executor
+                .execute_operation(&repos, Arc::new(AddOperation::resolved(pathspecs)))
+                .await;
+            report(&results)
+        }
+        Commands::Rm { pathspec, trash } => {
+            let repos = workspace.discover_repositories()?;
+            let pathspecs = Pathspecs::resolve(pathspec, workspace.root(), &repos);
+            let repos = narrow(repos, &pathspecs)?;
+            let mut operation = RmOperation::resolved(pathspecs);
+            if trash {
+                trash::expire(&workspace, config.trash.max_age())?;
+                operation = operation.trash(Trash::new(&workspace.state_dir()));
+            }
+            let results = executor
+                .execute_operation(&repos, Arc::new(operation))
+                .await;
+            report(&results)
+        }
+        Commands::Clean {
+            force,
+            directories,
+            ignored,
+            trash,
+            pathspec,
+        } => {
+            let repos = workspace.discover_repositories()?;
+            let pathspecs = Pathspecs::resolve(pathspec, workspace.root(), &repos);
+            let repos = narrow(repos, &pathspecs)?;
+            let options = CleanOptions {
+                directories,
+                ignored,
+            };
+            if force {
+                let mut operation = CleanOperation::new(pathspecs, options);
+                if trash {
+                    trash::expire(&workspace, config.trash.max_age())?;
+                    operation = operation.trash(Trash::new(&workspace.state_dir()));
+                }
+                let results = executor
+                    .execute_operation(&repos, Arc::new(operation))
+                    .await;
+                return report(&results);
+            }
+            let pathspecs = Arc::new(pathspecs);
+            let outcomes = executor
+                .for_each(&repos, move |repo| {
+                    let pathspecs = Arc::clone(&pathspecs);
+                    async move {
+                        repo.run_blocking(move |repo| match pathspecs.for_repo(repo.name()) {
+                            Some(matching) => clean::candidates(repo, &matching, options),
+                            None => Ok(Vec::new()),
+                        })
+                        .await
+                    }
+                })
+                .await;
+            let mut count = 0;
+            let mut code = ExitCode::SUCCESS;
+            for outcome in outcomes {
+                match outcome.outcome {
+                    Outcome::Success(files) => {
+                        for file in &files {
+                            println!("would remove {}/{}", outcome.repo, file);
+                        }
+                        count += files.len();
+                    }
+                    Outcome::Failed(e) => {
+                        eprintln!("error: {}: {}", outcome.repo, e);
+                        code = ExitCode::FAILURE;
+                    }
+                    Outcome::Warning(message) | Outcome::Skipped(message) => {
+                        eprintln!("warning: {}: {}", outcome.repo, message);
+                    }
+                }
+            }
+            if count == 0 {
+                println!("nothing to clean");
+            } else {
+                println!("{} path(s) would be removed, pass -f to remove them", count);
+            }
+            Ok(code)
+        }
+        Commands::Stash { action } => match action {
+            StashAction::Push {
+                message,
+                include_untracked,
+            } => {
+                let operation = StashPushOperation::new(&batch_id, message, include_untracked);
+                execute_and_report(&workspace, &executor, operation).await
+            }
+            StashAction::Pop { stash } => {
+                let Some(selector) = stash_selector(&workspace, stash)? else {
+                    eprintln!("error: nothing stashed by git-ws, name the entry to pop");
+                    return Ok(ExitCode::FAILURE);
+                };
+                let operation = StashApplyOperation::new(selector, true);
+                execute_and_report(&workspace, &executor, operation).await
+            }
+            StashAction::Apply { stash } => {
+                let Some(selector) = stash_selector(&workspace, stash)? else {
+                    eprintln!("error: nothing stashed by git-ws, name the entry to apply");
+                    return Ok(ExitCode::FAILURE);
+                };
+                let operation = StashApplyOperation::new(selector, false);
+                execute_and_report(&workspace, &executor, operation).await
+            }
+            StashAction::Drop { stash } => {
+                let Some(selector) = stash_selector(&workspace, stash)? else {
+                    eprintln!("error: nothing stashed by git-ws, name the entry to drop");
+                    return Ok(ExitCode::FAILURE);
+                };
+                let operation = StashDropOperation::new(selector);
+                execute_and_report(&workspace, &executor, operation).await
+            }
+            StashAction::List => {
+                let mut rows = Vec::new();
+                for repo in workspace.discover_repositories()? {
+                    for entry in stash::list(&repo)? {
+                        rows.push(StashRow {
+                            repo: repo.name().to_string(),
+                            name: entry.name,
+                            when: recover::ago(entry.time),
+                            message: entry.message,
+                        });
+                    }
+                }
+                if rows.is_empty() {
+                    println!("no stash entries");
+                } else {
+                    print!("{}", output::render(rows));
+                }
+                Ok(ExitCode::SUCCESS)
+            }
+        },
+        Commands::Trash { action } => match action {
+            TrashAction::List => {
+                let mut rows = Vec::new();
+                for run in trash::list(&workspace)? {
+                    for (repo, files) in run.files {
+                        rows.push(TrashRow {
+                            id: run.id.clone(),
+                            when: recover::ago(run.time),
+                            repo,
+                            files: files.join("\n"),
+                        });
+                    }
+                }
+                if rows.is_empty() {
+                    println!("the trash is empty");
+                } else {
+                    print!("{}", output::render(rows));
+                }
+                Ok(ExitCode::SUCCESS)
+            }
+            TrashAction::Restore { id, force } => {
+                let mut code = ExitCode::SUCCESS;
+                for (repo, restored) in trash::restore(&workspace, &id, force)? {
+                    println!("{}: {} restored", repo, restored.restored);
+                    if !restored.existing.is_empty() {
+                        eprintln!(
+                            "error: {}: left in the trash, the repository has them again: {}",
+                            repo,
+                            restored.existing.join(", ")
+                        );
+                        code = ExitCode::FAILURE;
+                    }
+                }
+                Ok(code)
+            }
+            TrashAction::Empty => {
+                trash::empty(&workspace)?;
+                Ok(ExitCode::SUCCESS)
+            }
+        },
+        Commands::Watch => {
+            let repos = workspace.discover_repositories()?;
+            let mut watcher = WorkspaceWatcher::new(&repos, &config.watch)?;
+            let polled: Vec<_> = watcher
+                .repositories()
+                .filter(|(_, polled)| *polled)
+                .map(|(name, _)| name)
+                .collect();
+            eprintln!(
+                "watching {} repositories{}",
+                repos.len(),
+                if polled.is_empty() {
+                    String::new()
+                } else {
+                    format!(", polling {}", polled.join(", "))
+                }
+            );
+            tokio::task::spawn_blocking(move || -> Result<ExitCode> {
+                loop {
+                    for (name, changes) in watcher.changes()? {
+                        println!("{}: {}", name, changes);
+                    }
+                }
+            })
+            .await
+            .expect("watcher panicked")
+        }
+        Commands::DiffWorkspace { other } => {
+            let mut failed = false;
+            let mut here = Vec::new();
+            let repos = workspace.discover_repositories()?;
+            let scanned = records(
+                &repos,
+                &executor,
+                &state,
+                &Pathspecs::default(),
+                ScanOptions::default(),
+            )
+            .await?;
+            for record in scanned {
+                match record.outcome {
+                    Outcome::Success(record) => here.push(record),
+                    Outcome::Failed(e) => {
+                        failed = true;
+                        eprintln!("error: {}: {}", record.repo, e);
+                    }
+                    Outcome::Warning(message) | Outcome::Skipped(message) => {
+                        failed = true;
+                        eprintln!("error: {}: {}", record.repo, message);
+                    }
+                }
+            }
+            let here = compare::from_records(&here);
+            let path = PathBuf::from(&other);
+            let there = if path.is_dir() {
+                let other_workspace = Workspace::discover(&path);
+                let repos = other_workspace.discover_repositories()?;
+                let state = other_workspace.load_state()?;
+                let mut there = Vec::new();
+                let options = ScanOptions::default();
+                for record in
+                    records(&repos, &executor, &state, &Pathspecs::default(), options).await?
+                {
+                    if let Outcome::Success(record) = record.outcome {
+                        there.push(record);
+                    }
+                }
+                compare::from_records(&there)
+            } else if path.is_file() {
+                // Lockfiles lack the urls of manifests, manifests the shas of
+                // lockfiles.
+                match compare::from_json(&std::fs::read_to_string(&path)?) {
+                    Ok(there) => there,
+                    Err(_) => match Lockfile::load(&path) {
+                        Ok(lock) => compare::from_lockfile(&lock),
+                        Err(_) => compare::from_manifest(&Manifest::load(&path)?),
+                    },
+                }
+            } else {
+                let (host, root) = match other.split_once(':') {
+                    Some((host, root)) => (host, Some(root)),
+                    None => (other.as_str(), None),
+                };
+                let mut args = Vec::new();
+                if let Some(root) = root {
+                    args.extend(["-C".to_string(), root.to_string()]);
+                }
+                args.extend(["list".to_string(), "--json".to_string()]);
+                compare::from_json(&agent::capture(host, args)?)?
+            };
+            let divergences = compare::diff(&here, &there);
+            if divergences.is_empty() {
+                println!("no divergence with {}", other);
+                return Ok(if failed {
+                    ExitCode::FAILURE
+                } else {
+                    ExitCode::SUCCESS
+                });
+            }
+            print!(
+                "{}",
+                output::render(divergences.into_iter().map(|divergence| DivergenceRow {
+                    repo: divergence.repo,
+                    here: divergence.here,
+                    there: divergence.there,
+                    differences: divergence.differences.join(", "),
+                }))
+            );
+            Ok(ExitCode::FAILURE)
+        }
+        Commands::Badges { output } => {
+            execute_and_report(&workspace, &executor, BadgeOperation::new(output)).await
+        }
+        Commands::Config { action } => match action {
+            ConfigAction::Audit { keys } => {
+                let mut audit = Audit::new(keys);
+                for repo in workspace.discover_repositories()? {
+                    audit.add(&repo)?;
+                }
+                let mut columns = vec!["Repository".to_string()];
+                columns.extend(audit.keys.iter().cloned());
+                let rows = audit
+                    .values
+                    .iter()
+                    .map(|(repo, values)| {
+                        let mut row = vec![repo.clone()];
+                        for (index, value) in values.iter().enumerate() {
+                            let value = value.clone().unwrap_or_else(|| "(unset)".to_string());
+                            if audit.is_outlier(repo, index) {
+                                row.push(format!("{} *", value));
+                            } else {
+                                row.push(value);
+                            }
+                        }
+                        row
+                    })
+                    .collect();
+                print!("{}", output::render_matrix(columns, rows));
+                let outliers = audit.outliers();
+                if outliers == 0 {
+                    return Ok(ExitCode::SUCCESS);
+                }
+                println!(
+                    "* {} value(s) differ from the one most repositories have",
+                    outliers
+                );
+                Ok(ExitCode::FAILURE)
+            }
+        },
+        Commands::Report { action } => match action {
+            ReportAction::Html {
+                output,
+                stale_days,
+                refresh,
+            } => {
+                let repos = workspace.discover_repositories()?;
+                let mut cache = if refresh {
+                    BTreeMap::new()
+                } else {
+                    report::load_cache(&state_dir)?
+                };
+                let missing: Vec<_> = repos
+                    .iter()
+                    .filter(|repo| !cache.contains_key(repo.name()))
+                    .cloned()
+                    .collect();
+                let mut code = ExitCode::SUCCESS;
+                if !missing.is_empty() {
+                    let collected = collect_reports(&workspace, &executor, &missing).await?;
+                    if let Err(e) = report::save_cache(&state_dir, &collected) {
+                        eprintln!("warning: cannot cache the reports: {}", e);
+                    }
+                    if collected.len() < missing.len() {
+                        code = ExitCode::FAILURE;
+                    }
+                    cache.extend(
+                        collected
+                            .into_iter()
+                            .map(|report| (report.record.repo.clone(), report)),
+                    );
+                }
+                let reports: Vec<_> = repos
+                    .iter()
+                    .filter_map(|repo| cache.remove(repo.name()))
+                    .map(|mut report| {
+                        report.record.pinned = state.is_pinned(&report.record.repo);
+                        report
+                    })
+                    .collect();
+                let title = workspace
+                    .root()
+                    .file_name()
+                    .map(|name| format!("{} workspace", name.to_string_lossy()))
+                    .unwrap_or_else(|| "Workspace".to_string());
+                let path = report::write_html(&output, &title, &reports, stale_days)?;
+                println!(
+                    "wrote {} for {} repositories",
+                    path.display(),
+                    reports.len()
+                );
+                Ok(code)
+            }
+        },
+        Commands::Tickets { since, titles } => {
+            let pattern = Arc::new(config.tickets.pattern()?);
+            let outcomes = executor
+                .for_each(&workspace.discover_repositories()?, move |repo| {
+                    let pattern = Arc::clone(&pattern);
+                    let since = since.clone();
+                    async move {
+                        repo.run_blocking(move |repo| ticket::scan(repo, &since, &pattern))
+                            .await
+                    }
+                })
+                .await;
+            let mut found = Vec::new();
+            let mut code = ExitCode::SUCCESS;
+            for outcome in outcomes {
+                match outcome.outcome {
+                    Outcome::Success(ids) => found.push((outcome.repo, ids)),
+                    Outcome::Failed(e) => {
+                        eprintln!("error: {}: {}", outcome.repo, e);
+                        code = ExitCode::FAILURE;
+                    }
+                    Outcome::Warning(message) | Outcome::Skipped(message) => {
+                        eprintln!("warning: {}: {}", outcome.repo, message);
+                    }
+                }
+            }
+            let mut tickets = ticket::merge(found);
+            if tickets.is_empty() {
+                println!("no ticket mentioned");
+                return Ok(code);
+            }
+            let repos = |ticket: &ticket::Ticket| {
+                ticket.repos.iter().cloned().collect::<Vec<_>>().join(", ")
+            };
+            if titles {
+                for (id, e) in ticket::fetch_titles(&config.tickets, &mut tickets)? {
+                    eprintln!("warning: {}: {}", id, e);
+                }
+                let rows = tickets.iter().map(|ticket| TitledTicketRow {
+                    id: ticket.id.clone(),
+                    title: ticket.title.clone().unwrap_or_default(),
+                    repos: repos(ticket),
+                    commits: ticket.commits,
+                });
+                print!("{}", output::render(rows));
+            } else {
+                let rows = tickets.iter().map(|ticket| TicketRow {
+                    id: ticket.id.clone(),
+                    repos: repos(ticket),
+                    commits: ticket.commits,
+                });
+                print!("{}", output::render(rows));
+            }
+            Ok(code)
+        }
+        Commands::Log { count } => {
+            let mailmap = Mailmap::for_workspace(&workspace)?;
+            for repo in workspace.discover_repositories()? {
+                let commits = log::recent(&repo, count, mailmap.as_ref())?;
+                if commits.is_empty() {
+                    continue;
+                }
+                println!("{}", repo.name());
+                print!(
+                    "{}",
+                    output::render(commits.into_iter().map(|commit| LogRow {
+                        hash: commit.hash,
+                        author: commit.author,
+                        date: commit.date,
+                        summary: commit.summary,
+                    }))
+                );
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Authors { since, unmapped } => {
+            let mailmap = Arc::new(Mailmap::for_workspace(&workspace)?);
+            let outcomes = executor
+                .for_each(&workspace.discover_repositories()?, {
+                    let mailmap = Arc::clone(&mailmap);
+                    move |repo| {
+                        let mailmap = Arc::clone(&mailmap);
+                        let since = since.clone();
+                        async move {
+                            repo.run_blocking(move |repo| {
+                                mailmap::identities(
+                                    repo,
+                                    mailmap.as_ref().as_ref(),
+                                    since.as_deref(),
+                                )
+                            })
+                            .await
+                        }
+                    }
+                })
+                .await;
+            let mut found = Vec::new();
+            let mut code = ExitCode::SUCCESS;
+            for outcome in outcomes {
+                match outcome.outcome {
+                    Outcome::Success(identities) => found.push((outcome.repo, identities)),
+                    Outcome::Failed(e) => {
+                        eprintln!("error: {}: {}", outcome.repo, e);
+                        code = ExitCode::FAILURE;
+                    }
+                    Outcome::Warning(message) | Outcome::Skipped(message) => {
+                        eprintln!("warning: {}: {}", outcome.repo, message);
+                    }
+                }
+            }
+            let repos =
+                |repos: &BTreeSet<String>| repos.iter().cloned().collect::<Vec<_>>().join(", ");
+            if unmapped {
+                let unmapped = mailmap::unmapped(&found, mailmap.as_ref().as_ref());
+                let rows = unmapped.iter().map(|unmapped| UnmappedRow {
+                    identity: unmapped.author.identity.to_string(),
+                    commits: unmapped.author.commits,
+                    repos: repos(&unmapped.author.repos),
+                    resembles: unmapped
+                        .resembles
+                        .as_ref()
+                        .map(ToString::to_string)
+                        .unwrap_or_default(),
+                });
+                print!("{}", output::render(rows));
+                match mailmap.as_ref() {
+                    Some(mailmap) => println!(
+                        "{} identities not in {}",
+                        unmapped.len(),
+                        mailmap.path().display()
+                    ),
+                    None => println!(
+                        "no mailmap, write one to {}",
+                        workspace.state_dir().join(MAILMAP_FILE).display()
+                    ),
+                }
+            } else {
+                let rows = mailmap::authors(&found)
+                    .into_iter()
+                    .map(|author| AuthorRow {
+                        author: author.identity.to_string(),
+                        commits: author.commits,
+                        repos: repos(&author.repos),
+                    });
+                print!("{}", output::render(rows));
+            }
+            Ok(code)
+        }
+        Commands::Usage { since, top } => {
+            let mut entries = usage::load(&workspace.state_dir())?;
+            if let Some(since) = since {
+                let repos = workspace.discover_repositories()?;
+                let Some(first) = repos.first() else {
+                    return Ok(ExitCode::SUCCESS);
+                };
+                let since = recover::parse_since(first, &since)?.max(0) as u64 * 1000;
+                entries.retain(|entry| entry.started >= since);
+            }
+            let mut selected = BTreeMap::new();
+            for entry in &entries {
+                if !selected.contains_key(&entry.repo) {
+                    selected.insert(entry.repo.clone(), workspace.selects(&entry.repo)?);
+                }
+            }
+            entries.retain(|entry| selected[&entry.repo]);
+            if entries.is_empty() {
+                println!("nothing recorded yet");
+                return Ok(ExitCode::SUCCESS);
+            }
+            let (operations, repos) = usage::summarize(&entries);
+            let rows = operations.iter().map(|usage| OperationUsageRow {
+                name: usage.name.clone(),
+                batches: usage.batches,
+                runs: usage.runs,
+                failed: usage.failed,
+                average_batch: format!("{:.2}s", usage.average_batch.as_secs_f64()),
+                parallelism: format!("{:.1}", usage.parallelism),
+            });
+            print!("{}", output::render(rows));
+            let rows = repos.iter().take(top).map(|usage| RepoUsageRow {
+                name: usage.name.clone(),
+                runs: usage.runs,
+                failed: usage.failed,
+                warnings: usage.warnings,
+                average: format!("{:.2}s", usage.average.as_secs_f64()),
+                failing: usage.failing.iter().cloned().collect::<Vec<_>>().join(", "),
+            });
+            print!("{}", output::render(rows));
+            println!("{}", concurrency_note(concurrency));
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Reflog { since } => {
+            let repos = workspace.discover_repositories()?;
+            let Some(first) = repos.first() else {
+                return Ok(ExitCode::SUCCESS);
+            };
+            let since = recover::parse_since(first, &since)?;
+            let journal = recover::load(&workspace.state_dir())?;
+            let mut rows = Vec::new();
+            for repo in &repos {
+                for entry in recover::reflog(repo, since, &journal)? {
+                    rows.push(ReflogRow {
+                        repo: repo.name().to_string(),
+                        branch: entry.branch,
+                        when: recover::ago(entry.time),
+                        change: format!("{}..{}", &entry.old[..7], &entry.new[..7]),
+                        message: entry.message,
+                        batch: entry
+                            .batch
+                            .map(|batch| format!("{} {}", batch.operation, &batch.batch[..8]))
+                            .unwrap_or_default(),
+                    });
+                }
+            }
+            print!("{}", output::render(rows));
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Resume { batch } => {
+            let mut pending = resume::pending(&workspace.state_dir())?;
+            if let Some(id) = &batch {
+                pending.retain(|(pending, _)| pending.id.starts_with(id.as_str()));
+            }
+            let (batch, done) = match pending.len() {
+                0 => {
+                    println!("no interrupted batch");
+                    return Ok(ExitCode::SUCCESS);
+                }
+                1 => pending.remove(0),
+                _ => {
+                    for (batch, _) in &pending {
+                        eprintln!(
+                            "{} git-ws {}, interrupted {}",
+                            &batch.id[..8.min(batch.id.len())],
+                            batch.args.join(" "),
+                            recover::ago(batch.time)
+                        );
+                    }
+                    eprintln!("error: several batches were interrupted, name the one to resume with --batch");
+                    return Ok(ExitCode::FAILURE);
+                }
+            };
+            println!(
+                "resuming git-ws {}, interrupted {}, {} operations done already",
+                batch.args.join(" "),
+                recover::ago(batch.time),
+                done.len()
+            );
+            let status = interactive::command(std::env::current_exe()?)
+                .arg("-C")
+                .arg(workspace.root())
+                .arg("--resume-batch")
+                .arg(&batch.id)
+                .args(&batch.args)
+                .status()?;
+            Ok(if status.success() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            })
+        }
+        Commands::Recover { batch, force, yes } => {
+            let batches = recover::batches(recover::load(&workspace.state_dir())?);
+            if batches.is_empty() {
+                eprintln!("error: no batch moved any branch");
+                return Ok(ExitCode::FAILURE);
+            }
+            let chosen = match batch {
+                Some(id) => batches.iter().find(|batch| batch.id.starts_with(&id)),
+                None => {
+                    let rows = batches
+                        .iter()
+                        .take(10)
+                        .enumerate()
+                        .map(|(i, batch)| BatchRow {
+                            position: i + 1,
+                            id: batch.id[..8].to_string(),
+                            when: recover::ago(batch.time),
+                            operations: batch.operations.join(", "),
+                            repos: recover::resets(batch)
+                                .into_keys()
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                        });
+                    print!("{}", output::render(rows));
+                    let answer = interactive::ask("Batch to undo [1]:")?;
+                    let position = if answer.is_empty() {
+                        Some(1)
+                    } else {
+                        answer.parse::<usize>().ok()
+                    };
+                    position.and_then(|position| batches.get(position.wrapping_sub(1)))
+                }
+            };
+            let Some(chosen) = chosen else {
+                eprintln!("error: no such batch");
+                return Ok(ExitCode::FAILURE);
+            };
+            let resets = recover::resets(chosen);
+            let rows = resets.iter().flat_map(|(repo, resets)| {
+                resets.iter().map(move |reset| ResetRow {
+                    repo: repo.clone(),
+                    branch: reset.branch.clone(),
+                    from: short(reset.from.as_deref()),
+                    to: short(reset.to.as_deref()),
+                })
+            });
+            print!("{}", output::render(rows));
+            if !yes {
+                let answer = interactive::ask("Reset these branches? [y/N]")?;
+                if !answer.eq_ignore_ascii_case("y") {
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+            let repos: Vec<_> = workspace
+                .discover_repositories()?
+                .into_iter()
+                .filter(|repo| resets.contains_key(repo.name()))
+                .collect();
+            let results = executor
+                .execute_operation(&repos, Arc::new(RecoverOperation::new(resets, force)))
+                .await;
+            report(&results)
+        }
+        Commands::Pull { rebase, ff_only } => {
+            let mode = if rebase {
+                PullMode::Rebase
+            } else if ff_only {
+                PullMode::FastForwardOnly
+            } else {
+                PullMode::Merge
+            };
+            let repos = workspace.discover_repositories()?;
+            let command = match mode {
+                PullMode::Merge => "pull",
+                PullMode::Rebase => "pull --rebase",
+                PullMode::FastForwardOnly => "pull --ff-only",
+            };
+            batch::begin(&workspace, command, &repos)?;
+            let results = executor
+                .execute_operation(&repos, Arc::new(PullOperation::new(mode)))
+                .await;
+            let code = report(&results)?;
+            if let Some(hint) = batch::hint(&batch::settle(&workspace, &repos)?) {
+                eprintln!("{}", hint);
+            }
+            Ok(code)
+        }
+        Commands::Merge {
+            branch,
+            no_ff,
+            ff_only,
+        } => {
+            let mode = if no_ff {
+                Some(MergeMode::NoFastForward)
+            } else if ff_only {
+                Some(MergeMode::FastForwardOnly)
+            } else {
+                None
+            };
+            let repos = workspace.discover_repositories()?;
+            batch::begin(&workspace, &format!("merge {}", branch), &repos)?;
+            let results = executor
+                .execute_operation(&repos, Arc::new(MergeOperation::new(branch, mode)))
+                .await;
+            let code = report(&results)?;
+            for (label, prefix) in [("fast-forwarded", FAST_FORWARD), ("merged", MERGED)] {
+                let repos: Vec<_> = results
+                    .iter()
+                    .filter(|result| result.is_success() && result.message.starts_with(prefix))
+                    .map(|result| result.repo.as_str())
+                    .collect();
+                if !repos.is_empty() {
+                    println!("{}: {}", label, repos.join(", "));
+                }
+            }
+            if let Some(hint) = batch::hint(&batch::settle(&workspace, &repos)?) {
+                eprintln!("{}", hint);
+            }
+            Ok(code)
+        }
+        Commands::CherryPick { commit, by_message } => {
+            let (command, source) = match by_message {
+                Some(pattern) => (
+                    format!("cherry-pick --by-message {}", pattern),
+                    CommitSource::Message(pattern),
+                ),
+                None => {
+                    let commit = commit.expect("required by clap");
+                    (
+                        format!("cherry-pick {}", commit),
+                        CommitSource::Commit(commit),
+                    )
+                }
+            };
+            let repos = workspace.discover_repositories()?;
+            batch::begin(&workspace, &command, &repos)?;
+            let results = executor
+                .execute_operation(&repos, Arc::new(CherryPickOperation::new(source)))
+                .await;
+            let code = report(&results)?;
+            if let Some(hint) = batch::hint(&batch::settle(&workspace, &repos)?) {
+                eprintln!("{}", hint);
+            }
+            Ok(code)
+        }
+        Commands::Revert { commit, by_message } => {
+            let (command, source) = match by_message {
+                Some(pattern) => (
+                    format!("revert --by-message {}", pattern),
+                    CommitSource::Message(pattern),
+                ),
+                None => {
+                    let commit = commit.expect("required by clap");
+                    (format!("revert {}", commit), CommitSource::Commit(commit))
+                }
+            };
+            let repos = workspace.discover_repositories()?;
+            batch::begin(&workspace, &command, &repos)?;
+            let results = executor
+                .execute_operation(&repos, Arc::new(RevertOperation::new(source)))
+                .await;
+            let code = report(&results)?;
+            if let Some(hint) = batch::hint(&batch::settle(&workspace, &repos)?) {
+                eprintln!("{}", hint);
+            }
+            Ok(code)
+        }
+        Commands::Reset {
+            target,
+            soft,
+            mixed: _,
+            hard,
+            force,
+        } => {
+            let mode = if soft {
+                ResetMode::Soft
+            } else if hard {
+                ResetMode::Hard
+            } else {
+                ResetMode::Mixed
+            };
+            let operation = ResetOperation::new(target, mode, force);
+            execute_and_report(&workspace, &executor, operation).await
+        }
+        Commands::Sync => {
+            if !workspace.has_manifest() {
+                eprintln!("error: sync needs a manifest, {}", MANIFEST_FILE);
+                return Ok(ExitCode::FAILURE);
+            }
+            let manifest = Manifest::for_workspace(workspace.root())?;
+            let operation =
+                SyncOperation::new(&manifest).bootstrap(config.bootstrap.clone(), workspace.root());
+            let repos = operation.repositories(&workspace)?;
+            let results = executor
+                .execute_operation(&repos, Arc::new(operation))
+                .await;
+            report(&results)
+        }
+        Commands::MergeReports { files } => {
+            let mut reports = Vec::new();
+            for file in &files {
+                reports.push(serde_json::from_str(&std::fs::read_to_string(file)?)?);
+            }
+            println!("{}", serde_json::to_string_pretty(&shard::merge(reports)?)?);
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Fetch { all, prune } => {
+            execute_and_report(&workspace, &executor, FetchOperation::new(all, prune)).await
+        }
+        Commands::Refresh { prune } => {
+            let mut results = execute(&workspace, &executor, RefreshOperation::new(prune)).await?;
+            // What was fetched is cached for `report html`.
+            let fetched: Vec<_> = workspace
+                .discover_repositories()?
+                .into_iter()
+                .filter(|repo| {
+                    results
+                        .iter()
+                        .any(|result| result.repo == repo.name() && result.is_success())
+                })
+                .collect();
+            let reports = collect_reports(&workspace, &executor, &fetched).await?;
+            if let Err(e) = report::save_cache(&state_dir, &reports) {
+                eprintln!("warning: cannot cache the reports: {}", e);
+            }
+            for result in &mut results {
+                result.repo = pin_marker(&result.repo, state.is_pinned(&result.repo));
+            }
+            report(&results)
+        }
+        Commands::Push {
+            set_upstream,
+            remote,
+        } => {
+            let operation = PushOperation::new(remote, set_upstream);
+            execute_and_report(&workspace, &executor, operation).await
+        }
+        Commands::Continue => continue_batch(&workspace, &executor).await,
+        Commands::Abort => {
+            let batch = batch::current(&workspace)?;
+            abort_batch(&workspace, &executor, batch, false).await
+        }
+        Commands::Conflicts {
+            pathspec,
+            edit,
+            ours,
+            theirs,
+        } => {
+            let repos = workspace.discover_repositories()?;
+            let pathspecs = Pathspecs::resolve(pathspec, workspace.root(), &repos);
+            let repos = narrow(repos, &pathspecs)?;
+            if ours || theirs {
+                let side = if ours { Side::Ours } else { Side::Theirs };
+                let results = executor
+                    .execute_operation(&repos, Arc::new(ResolveOperation::new(side, pathspecs)))
+                    .await;
+                return report(&results);
+            }
+            let mut found = Vec::new();
+            for repo in &repos {
+                let Some(pathspecs) = pathspecs.for_repo(repo.name()) else {
+                    continue;
+                };
+                for conflict in conflicts::list(repo, &pathspecs)? {
+                    found.push((repo, conflict));
+                }
+            }
+            if found.is_empty() {
+                println!("no conflict");
+                return Ok(ExitCode::SUCCESS);
+            }
+            if edit {
+                for (repo, conflict) in &found {
+                    interactive::edit(&repo.path().join(&conflict.path))?;
+                }
+                let mut left = Vec::new();
+                for (repo, conflict) in found {
+                    let path = std::slice::from_ref(&conflict.path);
+                    left.extend(
+                        conflicts::list(repo, path)?
+                            .into_iter()
+                            .filter(|conflict| conflict.markers > 0)
+                            .map(|conflict| (repo, conflict)),
+                    );
+                }
+                found = left;
+                if found.is_empty() {
+                    println!("no conflict marker left, stage the files to mark them resolved");
+                    return Ok(ExitCode::SUCCESS);
+                }
+                eprintln!("conflict markers are left in:");
+            }
+            let rows = found.iter().map(|(repo, conflict)| ConflictRow {
+                repo: repo.name().to_string(),
+                path: conflict.path.clone(),
+                markers: conflict.markers,
+            });
+            print!("{}", output::render(rows));
+            Ok(if edit {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            })
+        }
+        Commands::Commit {
+            message,
+            all,
+            trailers,
+            changeset,
+        } => {
+            let mut added = Vec::new();
+            if trailers || changeset.is_some() {
+                eprintln!("batch {}", batch_id);
+                added.push((trailer::BATCH.to_string(), batch_id));
+            }
+            if let Some(changeset) = changeset {
+                added.push((trailer::CHANGESET.to_string(), changeset));
+            }
+            let operation = CommitOperation::new(message, all).trailers(added);
+            execute_and_report(&workspace, &executor, operation).await
+        }
+        Commands::Branch { action } => match action {
+            BranchAction::List => {
+                let mut branches: BTreeMap<String, Vec<String>> = BTreeMap::new();
+                for repo in workspace.discover_repositories()? {
+                    let current = repo.current_branch()?;
+                    for name in branch::list(&repo)? {
+                        let marker = if current.as_ref() == Some(&name) {
+                            "*"
+                        } else {
+                            ""
+                        };
+                        branches.entry(name).or_default().push(format!(
+                            "{}{}",
+                            repo.name(),
+                            marker
+                        ));
+                    }
+                }
+                print!(
+                    "{}",
+                    output::render(branches.into_iter().map(|(branch, repos)| BranchRow {
+                        branch,
+                        repos: repos.join(", "),
+                    }))
+                );
+                Ok(ExitCode::SUCCESS)
+            }
+            BranchAction::Create { name } => {
+                execute_and_report(&workspace, &executor, CreateBranchOperation::new(name)).await
+            }
+            BranchAction::Delete { name, force } => {
+                let operation = DeleteBranchOperation::new(name, force);
+                execute_and_report(&workspace, &executor, operation).await
+            }
+        },
+        Commands::Tag {
+            action,
+            name,
+            message,
+        } => match action {
+            Some(TagAction::List) => {
+                let mut tags: BTreeMap<(String, bool), Vec<String>> = BTreeMap::new();
+                for repo in workspace.discover_repositories()? {
+                    for entry in tag::list(&repo)? {
+                        tags.entry((entry.name, entry.annotated))
+                            .or_default()
+                            .push(repo.name().to_string());
+                    }
+                }
+                if tags.is_empty() {
+                    println!("no tags");
+                    return Ok(ExitCode::SUCCESS);
+                }
+                print!(
+                    "{}",
+                    output::render(tags.into_iter().map(|((tag, annotated), repos)| TagRow {
+                        tag,
+                        kind: if annotated {
+                            "annotated"
+                        } else {
+                            "lightweight"
+                        },
+                        repos: repos.join(", "),
+                    }))
+                );
+                Ok(ExitCode::SUCCESS)
+            }
+            Some(TagAction::Delete { name }) => {
+                execute_and_report(&workspace, &executor, DeleteTagOperation::new(name)).await
+            }
+            None => {
+                let name = name.expect("required by clap");
+                let operation = CreateTagOperation::new(name, message);
+                execute_and_report(&workspace, &executor, operation).await
+            }
+        },
+        Commands::Remote { action } => match action {
+            RemoteAction::List => {
+                let mut rows = Vec::new();
+                for repo in workspace.discover_repositories()? {
+                    for entry in remote::list(&repo)? {
+                        rows.push(RemoteRow {
+                            repo: repo.name().to_string(),
+                            name: entry.name,
+                            url: redact::redact(&entry.url.unwrap_or_default()),
+                            push_url: redact::redact(&entry.push_url.unwrap_or_default()),
+                        });
+                    }
+                }
+                if rows.is_empty() {
+                    println!("no remotes");
+                    return Ok(ExitCode::SUCCESS);
+                }
+                print!("{}", output::render(rows));
+                Ok(ExitCode::SUCCESS)
+            }
+            RemoteAction::Add { name, url } => {
+                let operation = AddRemoteOperation::new(name, url);
+                execute_and_report(&workspace, &executor, operation).await
+            }
+            RemoteAction::Remove { name } => {
+                let operation = RemoveRemoteOperation::new(name);
+                execute_and_report(&workspace, &executor, operation).await
+            }
+            RemoteAction::SetUrl { name, url, push } => {
+                let operation = SetRemoteUrlOperation::new(name, url, push);
+                execute_and_report(&workspace, &executor, operation).await
+            }
+        },
+        Commands::SelfUpdate { channel, check } => {
+            let channel = Channel::from(channel);
+            match update::run(channel, check)? {
+                update::Outcome::UpToDate { tag } => {
+                    println!(
+                        "git-ws {} is up to date, the latest {} release is {}",
+                        env!("CARGO_PKG_VERSION"),
+                        channel,
+                        tag
+                    );
+                }
+                update::Outcome::Available { tag } => {
+                    println!("{} is out, run self-update to install it", tag);
+                }
+                update::Outcome::Updated { tag, path } => {
+                    println!("updated {} to {}", path.display(), tag);
+                }
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Setup => {
+            let path = setup::run(workspace.root())?;
+            println!("wrote {}", path.display());
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::State { action } => match action {
+            StateAction::Export { file, changes } => {
+                let repos = workspace.discover_repositories()?;
+                let exported = portable::export(&workspace, &repos, &file, changes)?;
+                if !exported.left_out.is_empty() {
+                    eprintln!(
+                        "warning: no origin, left out of the manifest: {}",
+                        exported.left_out.join(", ")
+                    );
+                }
+                println!(
+                    "exported {} repositories to {}{}",
+                    exported.repos,
+                    file.display(),
+                    if exported.changes.is_empty() {
+                        String::new()
+                    } else {
+                        format!(", with the changes of {}", exported.changes.join(", "))
+                    }
+                );
+                Ok(ExitCode::SUCCESS)
+            }
+            StateAction::Import { file, force } => {
+                let imported = portable::import(workspace.root(), &file, force)?;
+                for name in &imported.written {
+                    eprintln!("wrote {}", name);
+                }
+                let manifest = Manifest::for_workspace(workspace.root())?;
+                let sync = SyncOperation::new(&manifest)
+                    .bootstrap(workspace.load_config()?.bootstrap, workspace.root());
+                let missing: Vec<_> = sync
+                    .repositories(&workspace)?
+                    .into_iter()
+                    .filter(|repo| !repo.path().exists())
+                    .collect();
+                let mut results = executor.execute_operation(&missing, Arc::new(sync)).await;
+                results.retain(OperationResult::is_failure);
+                let repos = workspace.discover_repositories()?;
+                let restore = RestoreOperation::new(&imported);
+                results.extend(executor.execute_operation(&repos, Arc::new(restore)).await);
+                results.sort_by(|a, b| a.repo.cmp(&b.repo));
+                report(&results)
+            }
+        },
+        Commands::Batch {
+            action: BatchAction::Show { id },
+        } => {
+            let mut rows = Vec::new();
+            for repo in workspace.discover_repositories()? {
+                for commit in trailer::find(&repo, trailer::BATCH, &id)? {
+                    rows.push(TrailedRow {
+                        repo: repo.name().to_string(),
+                        id: commit.id,
+                        subject: commit.subject,
+                    });
+                }
+            }
+            if rows.is_empty() {
+                eprintln!("error: no commit of batch {}", id);
+                return Ok(ExitCode::FAILURE);
+            }
+            print!("{}", output::render(rows));
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Exec {
+            shell,
+            grace,
+            timeout,
+            max_memory,
+            max_cpu,
+            retry_on,
+            retries,
+            warn_on,
+            json,
+            command,
+        } => {
+            let cancel = CancelToken::default();
+            let executor = executor.with_cancellation(cancel.clone());
+            // --fail-fast cancels too, without a signal to wait for.
+            let interrupted = CancelToken::default();
+            let (stop, signalled) = (cancel.clone(), interrupted.clone());
+            let interrupt = tokio::spawn(async move {
+                let signal = process::interrupted().await.ok()?;
+                eprintln!("interrupted, stopping commands");
+                signalled.cancel();
+                stop.cancel();
+                let grace = Duration::from_secs(grace);
+                tokio::task::spawn_blocking(move || process::shutdown(signal, grace))
+                    .await
+                    .ok()?;
+                Some(signal)
+            });
+            let limits = process::Limits {
+                timeout: timeout.map(Duration::from_secs),
+                memory: max_memory,
+                cpu: max_cpu.map(Duration::from_secs),
+            };
+            let exit_codes = ExitCodes {
+                retry: retry_on,
+                warn: warn_on,
+                retries,
+            };
+            let outputs = Arc::new(Mutex::new(BTreeMap::new()));
+            let sink = Arc::clone(&outputs);
+            let mut operation = ExecOperation::new(command)
+                .shell(shell)
+                .limits(limits)
+                .exit_codes(exit_codes);
+            if json {
+                operation = operation.on_output(Arc::new(move |repo: &str, output| {
+                    sink.lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .insert(repo.to_string(), output);
+                }));
+            }
+            let results = execute(&workspace, &executor, operation).await?;
+            let code = if json {
+                let mut outputs = outputs.lock().unwrap_or_else(|e| e.into_inner());
+                let records = output::exec_records(&results, &mut outputs);
+                println!("{}", serde_json::to_string_pretty(&records)?);
+                report_failures(&results)?
+            } else {
+                report(&results)?
+            };
+            if !interrupted.is_cancelled() {
+                interrupt.abort();
+                return Ok(code);
+            }
+            match interrupt.await {
+                Ok(Some(signal)) => Ok(ExitCode::from(signal.exit_code())),
+                _ => Ok(code),
+            }
+        }
+        Commands::Track { remote, push } => {
+            execute_and_report(&workspace, &executor, TrackOperation::new(remote, push)).await
+        }
+        Commands::Checkout {
+            branch,
+            create,
+            repos,
+        } => {
+            let repos = select(&workspace, &repos)?;
+            let results = executor
+                .execute_operation(&repos, Arc::new(CheckoutOperation::new(branch, create)))
+                .await;
+            let code = report(&results)?;
+            let dirty: Vec<_> = results
+                .iter()
+                .filter(|result| result.is_failure() && result.message.starts_with(branch::DIRTY))
+                .map(|result| result.repo.as_str())
+                .collect();
+            if !dirty.is_empty() {
+                eprintln!(
+                    "not switched because of local changes: {}\ncommit or stash them, then run \
+                     the checkout again",
+                    dirty.join(", ")
+                );
+            }
+            Ok(code)
+        }
+        Commands::Attach { branch, repos } => {
+            let repos = select(&workspace, &repos)?;
+            let results = executor
+                .execute_operation(&repos, Arc::new(AttachOperation::new(branch)))
+                .await;
+            report(&results)
+        }
+        Commands::Describe { format } => {
+            let results = execute(&workspace, &executor, DescribeOperation).await?;
+            match format {
+                DescribeFormat::Table => return report(&results),
+                DescribeFormat::Json => {
+                    let versions = output::results_map(&results);
+                    println!("{}", serde_json::to_string_pretty(&versions)?);
+                }
+                DescribeFormat::Env => {
+                    for (repo, version) in output::results_map(&results) {
+                        println!("{}={}", output::env_var_name(&repo, "VERSION"), version);
+                    }
+                }
+            }
+            report_failures(&results)
+        }
+        Commands::Env { output } => {
+            let mut env = String::new();
+            for repo in workspace.discover_repositories()? {
+                let branch = repo.current_branch()?.unwrap_or_default();
+                env.push_str(&format!(
+                    "{}={}\n{}={}\n",
+                    output::env_var_name(repo.name(), "SHA"),
+                    repo.head_sha()?,
+                    output::env_var_name(repo.name(), "BRANCH"),
+                    branch
+                ));
+            }
+            match output {
+                Some(path) => std::fs::write(path, env)?,
+                None => print!("{}", env),
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Lock { output } => {
+            let lock = Lockfile::capture(&workspace.discover_repositories()?)?;
+            lock.save(&output)?;
+            println!(
+                "locked {} repositories in {}",
+                lock.repositories.len(),
+                output.display()
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::CiCheckout {
+            manifest,
+            lock,
+            retries,
+            depth,
+        } => {
+            let manifest = Manifest::load(&manifest)?;
+            let lock = Lockfile::load(&lock)?;
+            let progress: ProgressSink = Arc::new(|event: ProgressEvent| {
+                if let Ok(line) = serde_json::to_string(&event) {
+                    println!("{}", redact::redact(&line));
+                }
+            });
+            let operation = CiCheckoutOperation::new(&manifest, &lock, Arc::clone(&progress))
+                .retries(retries)
+                .depth(depth)
+                .bootstrap(config.bootstrap.clone(), workspace.root());
+            let repos = operation.repositories(&workspace)?;
+            let results = executor
+                .execute_operation(&repos, Arc::new(operation))
+                .await;
+
+            let mut verified = 0;
+            for (repo, result) in repos.iter().zip(&results) {
+                if !result.is_success() {
+                    continue;
+                }
+                let (ok, detail) = match ci::verify(repo, lock.sha(repo.name())) {
+                    Ok(()) => (true, result.message.clone()),
+                    Err(e) => (false, e.to_string()),
+                };
+                verified += ok as usize;
+                progress(ProgressEvent::Verified {
+                    repo: repo.name().to_string(),
+                    ok,
+                    detail,
+                });
+            }
+            let failed = results.iter().filter(|r| r.is_failure()).count();
+            progress(ProgressEvent::Summary {
+                checked_out: results.len() - failed,
+                failed,
+                verified,
+            });
+            if verified == repos.len() {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                Ok(ExitCode::FAILURE)
+            }
+        }
+        Commands::Doctor => {
+            let repos = workspace.discover_repositories()?;
+            let checks = tokio::task::spawn_blocking(move || {
+                let snapshots: Vec<_> = repos
+                    .into_iter()
+                    .map(|repo| {
+                        let snapshot = repo.snapshot();
+                        (repo, snapshot)
+                    })
+                    .collect();
+                let mut checks = doctor::local_checks(&snapshots);
+                checks.extend(doctor::network_checks(&snapshots));
+                checks
+            })
+            .await
+            .expect("doctor panicked");
+            let healthy = checks.iter().all(|check| check.ok);
+            print!("{}", output::render(checks.into_iter().map(CheckRow::from)));
+            if healthy {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                Ok(ExitCode::FAILURE)
+            }
+        }
+        Commands::View { action } => {
+            let mappings = view::resolve(&config.view, &workspace.discover_repositories()?)?;
+            match action {
+                ViewAction::Materialize => {
+                    let created = view::materialize(&workspace, &config.view, &mappings)?;
+                    println!(
+                        "materialized {} path(s) in {}",
+                        created.len(),
+                        config.view.dir.display()
+                    );
+                    Ok(ExitCode::SUCCESS)
+                }
+                ViewAction::Status => {
+                    let mut rows = Vec::new();
+                    for mapping in &mappings {
+                        rows.push(ViewRow {
+                            view: mapping.to.clone(),
+                            repo: mapping.repo.name().to_string(),
+                            path: mapping.subpath.clone(),
+                            changes: view::status(mapping)?.to_string(),
+                        });
+                    }
+                    print!("{}", output::render(rows));
+                    Ok(ExitCode::SUCCESS)
+                }
+                ViewAction::Commit { message } => {
+                    let operation = ViewCommitOperation::new(&mappings, message);
+                    let repos = view::repositories(&mappings);
+                    let results = executor
+                        .execute_operation(&repos, Arc::new(operation))
+                        .await;
+                    report(&results)
+                }
+            }
+        }
+        Commands::Subtree { action } => {
+            let message = match action {
+                SubtreeAction::Split {
+                    repo,
+                    path,
+                    to,
+                    prefix,
+                    remove,
+                } => {
+                    let source = workspace.find_repository(&repo)?;
+                    subtree::split(&workspace, &source, &path, &to, prefix.as_deref(), remove)?
+                }
+                SubtreeAction::Merge {
+                    repo,
+                    prefix,
+                    from,
+                    path,
+                } => {
+                    let target = workspace.find_repository(&repo)?;
+                    let source = workspace.find_repository(&from)?;
+                    subtree::merge(&workspace, &target, &prefix, &source, path.as_deref())?
+                }
+            };
+            println!("{}", message);
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Bootstrap { repos } => {
+            if config.bootstrap.is_empty() {
+                println!("no bootstrap steps configured");
+                return Ok(ExitCode::SUCCESS);
+            }
+            let operation = BootstrapOperation::new(config.bootstrap.clone(), workspace.root());
+            let results = executor
+                .execute_operation(&select(&workspace, &repos)?, Arc::new(operation))
+                .await;
+            report(&results)
+        }
+        Commands::Rebase {
+            upstream,
+            changeset,
+            onto,
+            no_push,
+            continue_,
+            abort,
+        } => {
+            if continue_ || abort {
+                let batch = batch::current(&workspace)?;
+                if !batch.command.starts_with("rebase") {
+                    eprintln!(
+                        "error: `{}` is unfinished, run `git-ws continue` or `git-ws abort`",
+                        batch.command
+                    );
+                    return Ok(ExitCode::FAILURE);
+                }
+                return if continue_ {
+                    continue_batch(&workspace, &executor).await
+                } else {
+                    abort_batch(&workspace, &executor, batch, true).await
+                };
+            }
+            if let Some(changeset) = changeset {
+                let operation = RebaseOperation::new(changeset, onto).push(!no_push);
+                return execute_and_report(&workspace, &executor, operation).await;
+            }
+            let upstream = upstream.expect("required by clap");
+            let repos = workspace.discover_repositories()?;
+            batch::begin(&workspace, &format!("rebase {}", upstream), &repos)?;
+            let results = executor
+                .execute_operation(&repos, Arc::new(RebaseBranchOperation::new(upstream)))
+                .await;
+            let code = report(&results)?;
+            if let Some(hint) = batch::hint(&batch::settle(&workspace, &repos)?) {
+                eprintln!("{}", hint);
+            }
+            Ok(code)
+        }
+        Commands::Consolidate { into, repos } => {
+            let target = workspace.find_repository(&into)?;
+            let plan = consolidate::plan(&workspace, &target, &select(&workspace, &repos)?)?;
+            let rows = plan.steps.iter().map(|step| PlanRow {
+                repo: step.repo.name().to_string(),
+                branch: step.branch.clone(),
+                commits: step.commits,
+                destination: format!("{}/{}", target.name(), step.destination),
+            });
+            print!("{}", output::render(rows));
+            if cli.dry_run {
+                println!("dry run, {} left unchanged", target.name());
+            } else {
+                for line in consolidate::apply(&plan)? {
+                    println!("{}", line);
+                }
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::FormatPatch {
+            since,
+            output,
+            cover_letter,
+        } => {
+            let output = std::env::current_dir()?.join(output);
+            let operation = FormatPatchOperation::new(&since, &output).cover_letter(cover_letter);
+            let repos = workspace.discover_repositories()?;
+            let results = executor
+                .execute_operation(&repos, Arc::new(operation))
+                .await;
+            if cover_letter {
+                let exported: Vec<GitRepository> = repos
+                    .into_iter()
+                    .zip(&results)
+                    .filter(|(_, result)| result.is_success())
+                    .map(|(repo, _)| repo)
+                    .collect();
+                if !exported.is_empty() {
+                    let manifest = Manifest::for_workspace(workspace.root())?;
+                    let mailmap = Mailmap::for_workspace(&workspace)?;
+                    let letter =
+                        patch::cover_letter(&exported, &since, &manifest, mailmap.as_ref())?;
+                    std::fs::write(output.join(patch::COVER_LETTER), letter)?;
+                }
+            }
+            println!("patches written to {}", output.display());
+            report(&results)
+        }
+        Commands::Am { dir } => {
+            let series = patch::series(&std::env::current_dir()?.join(dir))?;
+            let repos = workspace.discover_repositories()?;
+            let mut results = Vec::new();
+            for name in series.keys() {
+                if !repos.iter().any(|repo| repo.name() == name) {
+                    results.push(OperationResult {
+                        repo: name.clone(),
+                        status: OperationStatus::Failed,
+                        message: "not in this workspace".to_string(),
+                        duration: Duration::ZERO,
+                    });
+                }
+            }
+            let repos: Vec<_> = repos
+                .into_iter()
+                .filter(|repo| series.contains_key(repo.name()))
+                .collect();
+            let operation = Arc::new(AmOperation::new(series));
+            results.extend(executor.execute_operation(&repos, operation).await);
+            report(&results)
+        }
+        Commands::Changeset { action } => match action {
+            ChangesetAction::Diff {
+                id,
+                base,
+                series,
+                output,
+            } => {
+                let mut combined = String::new();
+                let mut found = false;
+                for repo in workspace.discover_repositories()? {
+                    let branches = changeset::branches(&repo, &id)?;
+                    if branches.is_empty() {
+                        continue;
+                    }
+                    found = true;
+                    let base = match &base {
+                        Some(base) => base.clone(),
+                        None => changeset::base(&repo)?,
+                    };
+                    for branch in branches {
+                        combined.push_str(&changeset::diff(&repo, &branch, &base, series)?);
+                    }
+                }
+                if !found {
+                    eprintln!("error: no branch for {}", id);
+                    return Ok(ExitCode::FAILURE);
+                }
+                match output {
+                    Some(path) => std::fs::write(path, combined)?,
+                    None => print!("{}", combined),
+                }
+                Ok(ExitCode::SUCCESS)
+            }
+        },
+        Commands::Pr { action } => match action {
+            PrAction::Create { title, base } => {
+                let operation = CreatePullRequestOperation::new(
+                    config.hosts.clone(),
+                    ApiCache::for_workspace(&workspace),
+                    title,
+                    base,
+                );
+                execute_and_report(&workspace, &executor, operation).await
+            }
+            PrAction::Status => {
+                let repos = workspace.discover_repositories()?;
+                let cache = Arc::new(ApiCache::for_workspace(&workspace));
+                let hosts = Arc::new(config.hosts.clone());
+                let outcomes = executor
+                    .for_each(&repos, {
+                        let cache = Arc::clone(&cache);
+                        move |repo| {
+                            let cache = Arc::clone(&cache);
+                            let hosts = Arc::clone(&hosts);
+                            async move {
+                                repo.run_blocking(move |repo| {
+                                    forge::branch_status(repo, &hosts, &cache)
+                                })
+                                .await
+                            }
+                        }
+                    })
+                    .await;
+                let mut rows = Vec::new();
+                let mut apis = BTreeSet::new();
+                let mut code = ExitCode::SUCCESS;
+                for outcome in outcomes {
+                    match outcome.outcome {
+                        Outcome::Success(status) => {
+                            let (url, checks) = match &status.pull_request {
+                                Some((pull_request, ci)) => {
+                                    (pull_request.url.clone(), ci.to_string())
+                                }
+                                None => ("none".to_string(), String::new()),
+                            };
+                            apis.insert(status.api);
+                            rows.push(PrStatusRow {
+                                repo: outcome.repo,
+                                branch: status.branch,
+                                url,
+                                checks,
+                            });
+                        }
+                        Outcome::Failed(e) => {
+                            eprintln!("error: {}: {}", outcome.repo, e);
+                            code = ExitCode::FAILURE;
+                        }
+                        Outcome::Warning(message) | Outcome::Skipped(message) => {
+                            eprintln!("warning: {}: {}", outcome.repo, message);
+                        }
+                    }
+                }
+                print!("{}", output::render(rows));
+                for api in apis {
+                    if let Some(rate_limit) = cache.rate_limit(&api) {
+                        println!(
+                            "{}: {} requests left, resetting in {} min",
+                            api,
+                            rate_limit.remaining,
+                            rate_limit.minutes_to_reset()
+                        );
+                    }
+                }
+                Ok(code)
+            }
+            PrAction::Merge {
+                changeset,
+                order,
+                ci_timeout,
+            } => {
+                let mut repos = workspace.discover_repositories()?;
+                repos.retain(|repo| {
+                    let pinned = state.is_pinned(repo.name());
+                    if pinned {
+                        eprintln!("skipping pinned {}", repo.name());
+                    }
+                    !pinned
+                });
+                let mut merges = changeset::pending_merges(
+                    &repos,
+                    &changeset,
+                    &config.hosts,
+                    &ApiCache::for_workspace(&workspace),
+                )?;
+                if merges.is_empty() {
+                    eprintln!("error: no branch for {}", changeset);
+                    return Ok(ExitCode::FAILURE);
+                }
+                if order == MergeOrder::Deps {
+                    let manifest = Manifest::for_workspace(workspace.root())?;
+                    merges = changeset::order_by_dependencies(merges, &manifest)?;
+                }
+                let rows = merges.iter().enumerate().map(|(i, merge)| MergeRow {
+                    position: i + 1,
+                    repo: merge.repo.name().to_string(),
+                    branch: merge.branch.clone(),
+                    url: merge.pull_request.url.clone(),
+                });
+                print!("{}", output::render(rows));
+                if cli.dry_run {
+                    println!("dry run, nothing merged");
+                    return Ok(ExitCode::SUCCESS);
+                }
+                // Merging on the forge runs no operation, the freeze guard
+                // of the executor never sees it.
+                let reason = cli.override_freeze.as_deref();
+                if let Some(freeze) =
+                    freeze::check(&config.freeze, workspace.root(), recover::now(), reason)?
+                {
+                    eprintln!(
+                        "warning: merging during the freeze {}: {}",
+                        freeze,
+                        reason.unwrap_or_default()
+                    );
+                }
+
+                let ci_timeout = Duration::from_secs(ci_timeout);
+                let mut merged = Vec::new();
+                for (i, merge) in merges.iter().enumerate() {
+                    println!("merging {} ({})", merge.repo.name(), merge.pull_request.url);
+                    match merge.land(ci_timeout) {
+                        Ok(commit) => {
+                            if let Err(e) =
+                                journal.record_override(&merge.repo, "pr merge", &merge.branch)
+                            {
+                                eprintln!("warning: {}: not journaled: {}", merge.repo.name(), e);
+                            }
+                            merged.push((merge, commit));
+                        }
+                        Err(e) => {
+                            eprintln!(
+                                "error: {}: {}",
+                                merge.repo.name(),
+                                redact::redact(&e.to_string())
+                            );
+                            if !merged.is_empty() {
+                                eprintln!("already merged, revert in this order to back out:");
+                                for (merge, commit) in merged.iter().rev() {
+                                    eprintln!(
+                                        "  {}: git revert -m 1 {}",
+                                        merge.repo.name(),
+                                        commit
+                                    );
+                                }
+                            }
+                            eprintln!("not merged:");
+                            for merge in &merges[i..] {
+                                eprintln!("  {}: {}", merge.repo.name(), merge.pull_request.url);
+                            }
+                            return Ok(ExitCode::FAILURE);
+                        }
+                    }
+                }
+                println!("merged {} pull request(s)", merged.len());
+                Ok(ExitCode::SUCCESS)
+            }
+        },
+        Commands::Apply { file: path } => {
+            let plan = Plan::load(&path)?;
+            plan.verify(&config.plan)?;
+            let drift = plan.drift(&workspace)?;
+            if !drift.is_empty() {
+                for (repo, reason) in drift {
+                    eprintln!("error: {}: {}", repo, reason);
+                }
+                eprintln!("error: the workspace changed since the plan, nothing applied");
+                return Ok(ExitCode::FAILURE);
+            }
+            if plan.repositories.is_empty() {
+                println!("nothing planned");
+                return Ok(ExitCode::SUCCESS);
+            }
+            let status = interactive::command(std::env::current_exe()?)
+                .arg("-C")
+                .arg(workspace.root())
+                .arg("--apply-plan")
+                .arg(std::env::current_dir()?.join(&path))
+                .args(&plan.args)
+                .status()?;
+            if status.success() {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                Ok(ExitCode::FAILURE)
+            }
+        }
+        Commands::Record { action } => {
+            let mut state = state;
+            match action {
+                RecordAction::Start { session } => {
+                    if let Some(current) = &state.recording {
+                        eprintln!("error: already recording to {}", current.display());
+                        return Ok(ExitCode::FAILURE);
+                    }
+                    let session = std::env::current_dir()?.join(session);
+                    if !session.exists() {
+                        Session::default().save(&session)?;
+                    }
+                    println!("recording to {}", session.display());
+                    state.recording = Some(session);
+                }
+                RecordAction::Stop => match state.recording.take() {
+                    Some(session) => println!("recorded to {}", session.display()),
+                    None => println!("not recording"),
+                },
+            }
+            workspace.save_state(&state)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Cd { query, list } => {
+            let names = workspace.cached_repository_names()?;
+            let query = query.unwrap_or_default();
+            if list {
+                for name in shell::fuzzy_matches(&names, &query) {
+                    println!("{}", name);
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+            if query.is_empty() {
+                println!("{}", workspace.root().display());
+                return Ok(ExitCode::SUCCESS);
+            }
+            match shell::fuzzy_matches(&names, &query).first() {
+                Some(name) => {
+                    println!("{}", workspace.root().join(name).display());
+                    Ok(ExitCode::SUCCESS)
+                }
+                None => {
+                    eprintln!("error: no repository matches '{}'", query);
+                    Ok(ExitCode::FAILURE)
+                }
+            }
+        }
+        Commands::Completions { shell, alias } => {
+            print!("{}", completion_script(shell, &alias));
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::InstallAlias { name, completions } => {
+            alias::install(&name)?;
+            println!("git {} now runs git-ws", name);
+            if let Some(shell) = completions {
+                let shell_name = shell
+                    .to_possible_value()
+                    .map(|value| value.get_name().to_string())
+                    .unwrap_or_default();
+                let Some(path) = alias::completion_path(&shell_name) else {
+                    eprintln!("error: no home directory to install completions to");
+                    return Ok(ExitCode::FAILURE);
+                };
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                std::fs::write(&path, completion_script(shell, &name))?;
+                println!("completions written to {}", path.display());
+                if let ShellKind::Bash = shell {
+                    println!(
+                        "source it from ~/.bashrc to complete `git {}` as well",
+                        name
+                    );
+                }
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::ShellInit { shell, name } => {
+            print!("{}", shell::init(shell.into(), &name));
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Replay { session, yes } => {
+            let session = Session::load(&session)?;
+            let exe = std::env::current_exe()?;
+            let total = session.steps.len();
+            for (i, step) in session.steps.iter().enumerate() {
+                println!("[{}/{}] git-ws {}", i + 1, total, step.args.join(" "));
+                if !yes {
+                    match interactive::ask("run it? [y]es, [s]kip, [q]uit")?.as_str() {
+                        "y" | "yes" => {}
+                        "s" | "skip" => continue,
+                        _ => {
+                            println!("stopped before step {}", i + 1);
+                            return Ok(ExitCode::FAILURE);
+                        }
+                    }
+                }
+                let mut command = interactive::command(&exe);
+                command.arg("-C").arg(workspace.root());
+                for (flag, set) in [
+                    ("--non-interactive", cli.non_interactive),
+                    ("--dry-run", cli.dry_run),
+                    ("--verbose", cli.verbose),
+                ] {
+                    if set {
+                        command.arg(flag);
+                    }
+                }
+                if let Some(jobs) = cli.jobs {
+                    command.arg("--jobs").arg(jobs.to_string());
+                }
+                let status = command.args(&step.args).status()?;
+                if !status.success() {
+                    eprintln!("error: step {} failed ({}), stopping", i + 1, status);
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Pin { repo } => {
+            let repo = workspace.find_repository(&repo)?;
+            let mut state = state;
+            if state.pinned.insert(repo.name().to_string()) {
+                workspace.save_state(&state)?;
+                println!("pinned {}", repo.name());
+            } else {
+                println!("{} is already pinned", repo.name());
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Unpin { repo } => {
+            let repo = repo.trim_end_matches('/');
+            let mut state = state;
+            if state.pinned.remove(repo) {
+                workspace.save_state(&state)?;
+                println!("unpinned {}", repo);
+            } else {
+                println!("{} is not pinned", repo);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Commands::Bisect { action } => match action {
+            BisectAction::Start {
+                repo,
+                bad,
+                good,
+                test,
+            } => {
+                let repo = workspace.find_repository(&repo)?;
+                bisect::start(&workspace, &repo, &bad, &good)?;
+                println!(
+                    "bisecting {}, it stays pinned until `git-ws bisect reset`",
+                    repo.name()
+                );
+                if let Some(test) = test {
+                    let first_bad = bisect::run(&workspace, &repo, &test)?;
+                    println!("first bad commit in {}: {}", repo.name(), first_bad);
+                }
+                Ok(ExitCode::SUCCESS)
+            }
+            BisectAction::Reset => {
+                let repo = bisect::reset(&workspace)?;
+                println!("bisect in {} reset", repo);
+                Ok(ExitCode::SUCCESS)
+            }
+        },
+    }
+}
+
+async fn execute(
+    workspace: &Workspace,
+    executor: &BatchExecutor,
+    operation: impl GitOperation + 'static,
+) -> Result<Vec<OperationResult>> {
+    let repos = workspace.discover_repositories()?;
+    Ok(executor
+        .execute_operation(&repos, Arc::new(operation))
+        .await)
+}
+
+/// What the HTML report shows of `repos`, reporting to stderr the
+/// repositories it cannot collect.
+async fn collect_reports(
+    workspace: &Workspace,
+    executor: &BatchExecutor,
+    repos: &[GitRepository],
+) -> Result<Vec<report::RepoReport>> {
+    let manifest = Arc::new(Manifest::for_workspace(workspace.root())?);
+    let mailmap = Arc::new(Mailmap::for_workspace(workspace)?);
+    let outcomes = executor
+        .for_each(repos, move |repo| {
+            let manifest = Arc::clone(&manifest);
+            let mailmap = Arc::clone(&mailmap);
+            async move {
+                repo.run_blocking(move |repo| {
+                    report::collect(repo, &manifest, mailmap.as_ref().as_ref(), false)
+                })
+                .await
+            }
+        })
+        .await;
+    let mut reports = Vec::new();
+    for outcome in outcomes {
+        match outcome.outcome {
+            Outcome::Success(report) => reports.push(report),
+            Outcome::Failed(e) => eprintln!("error: {}: {}", outcome.repo, e),
+            Outcome::Warning(message) | Outcome::Skipped(message) => {
+                eprintln!("warning: {}: {}", outcome.repo, message);
+            }
+        }
+    }
+    Ok(reports)
+}
+
+/// The stash entries `stash pop`, `apply` or `drop` take: the one at
+/// `index` in every repository, or those of the latest `stash push`, `None`
+/// when git-ws stashed nothing.
+fn stash_selector(workspace: &Workspace, index: Option<usize>) -> Result<Option<stash::Selector>> {
+    if let Some(index) = index {
+        return Ok(Some(stash::Selector::Index(index)));
+    }
+    let repos = workspace.discover_repositories()?;
+    Ok(stash::latest_batch(&repos)?.map(stash::Selector::Batch))
+}
+
+/// Runs `operation` against every repository and reports the results,
+/// each as soon as its repository is done when [`output::streams_results`].
+async fn execute_and_report(
+    workspace: &Workspace,
+    executor: &BatchExecutor,
+    operation: impl GitOperation + 'static,
+) -> Result<ExitCode> {
+    let repos = workspace.discover_repositories()?;
+    if !output::streams_results() {
+        return report(
+            &executor
+                .execute_operation(&repos, Arc::new(operation))
+                .await,
+        );
+    }
+    let stream = ResultStream::new(repos.iter().map(GitRepository::name));
+    let results = executor
+        .execute_operation_streaming(&repos, Arc::new(operation), move |result| {
+            stream.print(result)
+        })
+        .await;
+    if results.iter().any(OperationResult::is_failure) {
+        Ok(ExitCode::FAILURE)
+    } else {
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+/// The line of `usage` telling how many repositories batches work on at
+/// once.
+fn concurrency_note(concurrency: usize) -> String {
+    format!(
+        "{} {} at a time at most, see --jobs or concurrency in the configuration",
+        concurrency,
+        if concurrency == 1 {
+            "repository"
+        } else {
+            "repositories"
+        }
+    )
+}
+
+/// Whether batches draw progress bars on stderr, `terminal` telling whether
+/// it is one. The log of --verbose and the lines of agent mode would fight
+/// the bars, and --non-interactive turns off what needs a terminal.
+fn draws_progress(cli: &Cli, terminal: bool) -> bool {
+    terminal && !cli.non_interactive && !cli.no_progress && !cli.verbose && !cli.agent
+}
+
+/// The completion script of git-ws for `shell`, completing the git alias
+/// `alias` too where git's completion needs help.
+fn completion_script(shell: ShellKind, alias_name: &str) -> String {
+    let generator = match shell {
+        ShellKind::Bash => clap_complete::Shell::Bash,
+        ShellKind::Zsh => clap_complete::Shell::Zsh,
+        ShellKind::Fish => clap_complete::Shell::Fish,
+    };
+    let mut script = Vec::new();
+    clap_complete::generate(generator, &mut Cli::command(), "git-ws", &mut script);
+    let mut script = String::from_utf8_lossy(&script).into_owned();
+    if let ShellKind::Bash = shell {
+        script.push_str(&alias::bash_git_completion(alias_name));
+    }
+    script
+}
+
+/// The repositories of `repos` that `pathspecs` touch, with a note when
+/// that leaves some out.
+fn narrow(repos: Vec<GitRepository>, pathspecs: &Pathspecs) -> Result<Vec<GitRepository>> {
+    let total = repos.len();
+    let selected = pathspecs.select(repos)?;
+    if selected.len() < total {
+        let names: Vec<_> = selected.iter().map(|repo| repo.name()).collect();
+        eprintln!(
+            "note: the paths touch {} of {} repositories{}{}",
+            selected.len(),
+            total,
+            if names.is_empty() { "" } else { ": " },
+            names.join(", ")
+        );
+    }
+    Ok(selected)
+}
+
+/// A record of every repository of `repos`, counting only the changes to
+/// paths matching its pathspecs.
+async fn records(
+    repos: &[GitRepository],
+    executor: &BatchExecutor,
+    state: &WorkspaceState,
+    pathspecs: &Pathspecs,
+    options: ScanOptions,
+) -> Result<Vec<RepoOutcome<RepoRecord>>> {
+    let pathspecs = Arc::new(pathspecs.clone());
+    let mut records = executor
+        .for_each(repos, move |repo| {
+            let pathspecs = Arc::clone(&pathspecs);
+            async move {
+                repo.run_blocking(move |repo| {
+                    let pathspecs = pathspecs.for_repo(repo.name()).unwrap_or_default();
+                    RepoRecord::capture(repo, &pathspecs, options)
+                })
+                .await
+            }
+        })
+        .await;
+    for record in &mut records {
+        if let Outcome::Success(record) = &mut record.outcome {
+            record.pinned = state.is_pinned(&record.repo);
+        }
+    }
+    Ok(records)
+}
+
+/// Prints records in a format as they are captured, a chunk at a time, and
+/// errors to stderr. Queries look at every record at once, so they are only
+/// run at the end.
+struct RecordPrinter<'a> {
+    format: &'a RecordFormat,
+    failed: bool,
+    printed: usize,
+    /// The records a query runs on.
+    kept: Vec<RepoRecord>,
+}
+
+impl<'a> RecordPrinter<'a> {
+    fn new(format: &'a RecordFormat) -> Self {
+        RecordPrinter {
+            format,
+            failed: false,
+            printed: 0,
+            kept: Vec::new(),
+        }
+    }
+
+    fn print(&mut self, records: Vec<RepoOutcome<RepoRecord>>) -> Result<()> {
+        for record in records {
+            let message = match record.outcome {
+                Outcome::Success(captured) => {
+                    self.print_one(captured)?;
+                    continue;
+                }
+                Outcome::Failed(e) => e.to_string(),
+                Outcome::Warning(message) | Outcome::Skipped(message) => message,
+            };
+            self.failed = true;
+            eprintln!("error: {}: {}", record.repo, redact::redact(&message));
+        }
+        Ok(())
+    }
+
+    fn print_one(&mut self, record: RepoRecord) -> Result<()> {
+        match self.format {
+            RecordFormat::Template(template) => println!("{}", template.render(&record)?),
+            // The elements of a pretty printed array, written one by one.
+            RecordFormat::Json(None) => {
+                let element = serde_json::to_string_pretty(&record)?.replace('\n', "\n  ");
+                let separator = if self.printed == 0 { "[" } else { "," };
+                print!("{}\n  {}", separator, element);
+            }
+            RecordFormat::Json(Some(_)) => self.kept.push(record),
+        }
+        self.printed += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<ExitCode> {
+        match self.format {
+            RecordFormat::Template(_) => {}
+            RecordFormat::Json(None) if self.printed == 0 => println!("[]"),
+            RecordFormat::Json(None) => println!("\n]"),
+            RecordFormat::Json(Some(query)) => {
+                for value in query.run(&serde_json::to_value(&self.kept)?)? {
+                    match value {
+                        serde_json::Value::String(string) => println!("{}", string),
+                        value => println!("{}", value),
+                    }
+                }
+            }
+        }
+        Ok(if self.failed {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        })
+    }
+}
+
+/// The named repositories, or every repository when no name is given.
+fn select(workspace: &Workspace, names: &[String]) -> Result<Vec<GitRepository>> {
+    if names.is_empty() {
+        return workspace.discover_repositories();
+    }
+    names
+        .iter()
+        .map(|name| workspace.find_repository(name))
+        .collect()
+}
+
+/// Arguments following `git-ws` on the command line.
+fn command_args() -> Vec<String> {
+    std::env::args_os()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Whether every change `command` makes goes through mutating operations,
+/// which a plan can hold.
+fn plannable(command: &Commands) -> bool {
+    matches!(
+        command,
+        Commands::Add { patch: false, .. }
+            | Commands::Rm { .. }
+            | Commands::Clean { force: true, .. }
+            | Commands::Pull { .. }
+            | Commands::Merge { .. }
+            | Commands::CherryPick { .. }
+            | Commands::Revert { .. }
+            | Commands::Reset { .. }
+            | Commands::Sync
+            | Commands::Branch {
+                action: BranchAction::Create { .. } | BranchAction::Delete { .. }
+            }
+            | Commands::Tag {
+                action: None | Some(TagAction::Delete { .. }),
+                ..
+            }
+            | Commands::Stash {
+                action: StashAction::Push { .. }
+                    | StashAction::Pop { .. }
+                    | StashAction::Apply { .. }
+                    | StashAction::Drop { .. }
+            }
+            | Commands::Push { .. }
+            | Commands::Remote {
+                action: RemoteAction::Add { .. }
+                    | RemoteAction::Remove { .. }
+                    | RemoteAction::SetUrl { .. }
+            }
+            | Commands::Continue
+            | Commands::Abort
+            | Commands::Conflicts { ours: true, .. }
+            | Commands::Conflicts { theirs: true, .. }
+            | Commands::Commit { .. }
+            | Commands::Track { .. }
+            | Commands::Checkout { .. }
+            | Commands::Attach { .. }
+            | Commands::Bootstrap { .. }
+            | Commands::Rebase { .. }
+            | Commands::Am { .. }
+            | Commands::View {
+                action: ViewAction::Commit { .. }
+            }
+    )
+}
+
+/// What a command changes, for --dry-run and the policy service. Every
+/// command says, so that a new one cannot run under --dry-run without being
+/// stopped by it, nor change repositories without the service approving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Effect {
+    /// Changes no repository, at most writing the files asked for, like
+    /// reports.
+    Reads,
+    /// Changes git-ws itself, its state or installation, or only the
+    /// remote-tracking branches of the repositories.
+    Local,
+    /// Changes repositories or their remotes through mutating operations,
+    /// which the `DryRun` middleware stops.
+    Operations,
+    /// Changes repositories or their remotes itself, stopping at --dry-run
+    /// when `dry_run` is set.
+    Changes { dry_run: bool },
+    /// Runs git-ws again, passing --dry-run on when `dry_run` is set.
+    Delegates { dry_run: bool },
+}
+
+impl Effect {
+    fn honours_dry_run(self) -> bool {
+        match self {
+            Effect::Reads | Effect::Operations => true,
+            Effect::Changes { dry_run } | Effect::Delegates { dry_run } => dry_run,
+            Effect::Local => false,
+        }
+    }
+
+    /// Whether the policy service is asked about it; the git-ws run again
+    /// asks for itself.
+    fn changes_repositories(self) -> bool {
+        matches!(self, Effect::Operations | Effect::Changes { .. })
+    }
+}
+
+fn effect(command: &Commands) -> Effect {
+    match command {
+        Commands::List { .. }
+        | Commands::Status { .. }
+        | Commands::Diff { .. }
+        | Commands::Watch
+        | Commands::Batch { .. }
+        | Commands::DiffWorkspace { .. }
+        | Commands::Badges { .. }
+        | Commands::Config { .. }
+        | Commands::Report { .. }
+        | Commands::Log { .. }
+        | Commands::Tickets { .. }
+        | Commands::Authors { .. }
+        | Commands::Usage { .. }
+        | Commands::Reflog { .. }
+        | Commands::MergeReports { .. }
+        | Commands::Describe { .. }
+        | Commands::Env { .. }
+        | Commands::Lock { .. }
+        | Commands::Doctor
+        | Commands::FormatPatch { .. }
+        | Commands::Changeset { .. }
+        | Commands::Cd { .. }
+        | Commands::Completions { .. }
+        | Commands::ShellInit { .. }
+        | Commands::SelfUpdate { check: true, .. }
+        | Commands::Clean { force: false, .. }
+        | Commands::Stash {
+            action: StashAction::List,
+        }
+        | Commands::Trash {
+            action: TrashAction::List,
+        }
+        | Commands::Branch {
+            action: BranchAction::List,
+        }
+        | Commands::Tag {
+            action: Some(TagAction::List),
+            ..
+        }
+        | Commands::Remote {
+            action: RemoteAction::List,
+        }
+        | Commands::State {
+            action: StateAction::Export { .. },
+        }
+        | Commands::View {
+            action: ViewAction::Status,
+        }
+        | Commands::Pr {
+            action: PrAction::Status,
+        } => Effect::Reads,
+        // Opening the conflicted files for the user to edit.
+        Commands::Conflicts {
+            ours: false,
+            theirs: false,
+            ..
+        } => Effect::Reads,
+        Commands::Setup
+        | Commands::SelfUpdate { check: false, .. }
+        | Commands::Fetch { .. }
+        | Commands::Refresh { .. }
+        | Commands::Record { .. }
+        | Commands::InstallAlias { .. }
+        | Commands::Pin { .. }
+        | Commands::Unpin { .. }
+        | Commands::Trash {
+            action: TrashAction::Empty,
+        } => Effect::Local,
+        Commands::Add { patch: false, .. }
+        | Commands::Rm { .. }
+        | Commands::Clean { force: true, .. }
+        | Commands::Stash { .. }
+        | Commands::Branch { .. }
+        | Commands::Tag { .. }
+        | Commands::Remote { .. }
+        | Commands::Recover { .. }
+        | Commands::Pull { .. }
+        | Commands::Merge { .. }
+        | Commands::CherryPick { .. }
+        | Commands::Revert { .. }
+        | Commands::Reset { .. }
+        | Commands::Sync
+        | Commands::Push { .. }
+        | Commands::Continue
+        | Commands::Abort
+        | Commands::Conflicts { .. }
+        | Commands::Commit { .. }
+        | Commands::Track { .. }
+        | Commands::Checkout { .. }
+        | Commands::Attach { .. }
+        | Commands::CiCheckout { .. }
+        | Commands::Bootstrap { .. }
+        | Commands::Rebase { .. }
+        | Commands::Am { .. }
+        | Commands::View {
+            action: ViewAction::Commit { .. },
+        }
+        | Commands::Pr {
+            action: PrAction::Create { .. },
+        } => Effect::Operations,
+        Commands::Consolidate { .. }
+        | Commands::Pr {
+            action: PrAction::Merge { .. },
+        } => Effect::Changes { dry_run: true },
+        // Exec runs in the executor, but its commands are not operations
+        // the middleware knows to stop.
+        Commands::Add { patch: true, .. }
+        | Commands::Exec { .. }
+        | Commands::Trash {
+            action: TrashAction::Restore { .. },
+        }
+        | Commands::State {
+            action: StateAction::Import { .. },
+        }
+        | Commands::View {
+            action: ViewAction::Materialize,
+        }
+        | Commands::Subtree { .. }
+        | Commands::Bisect { .. } => Effect::Changes { dry_run: false },
+        Commands::Replay { .. } => Effect::Delegates { dry_run: true },
+        Commands::Resume { .. } | Commands::Apply { .. } => Effect::Delegates { dry_run: false },
+    }
+}
+
+/// The ref `command` changes or changes the repositories to, for the policy
+/// service.
+fn target_ref(command: &Commands) -> Option<&str> {
+    match command {
+        Commands::Merge { branch, .. } | Commands::Checkout { branch, .. } => Some(branch),
+        Commands::Reset { target, .. } => Some(target),
+        Commands::CherryPick { commit, .. } | Commands::Revert { commit, .. } => commit.as_deref(),
+        Commands::Rebase {
+            upstream: Some(upstream),
+            ..
+        } => Some(upstream),
+        Commands::Rebase {
+            changeset: Some(_),
+            onto,
+            ..
+        } => Some(onto),
+        Commands::Tag { name, .. } => name.as_deref(),
+        Commands::Branch {
+            action: BranchAction::Create { name } | BranchAction::Delete { name, .. },
+        } => Some(name),
+        Commands::Pr {
+            action: PrAction::Create { base, .. },
+        } => base.as_deref(),
+        _ => None,
+    }
+}
+
+/// The command git-ws runs, like `trash restore`.
+fn command_name() -> String {
+    let mut names = Vec::new();
+    if let Ok(matches) = Cli::command().try_get_matches_from(std::env::args_os()) {
+        let mut matches = &matches;
+        while let Some((name, sub)) = matches.subcommand() {
+            names.push(name.to_string());
+            matches = sub;
+        }
+    }
+    names.join(" ")
+}
+
+/// Concludes the merges and rebases the unfinished batch left conflicted.
+async fn continue_batch(workspace: &Workspace, executor: &BatchExecutor) -> Result<ExitCode> {
+    let batch = batch::current(workspace)?;
+    let repos: Vec<_> = workspace
+        .discover_repositories()?
+        .into_iter()
+        .filter(|repo| batch.conflicted.contains(repo.name()))
+        .collect();
+    let results = executor
+        .execute_operation(&repos, Arc::new(ContinueOperation))
+        .await;
+    let code = report(&results)?;
+    match batch::hint(&batch::settle(workspace, &repos)?) {
+        Some(hint) => eprintln!("{}", hint),
+        None => println!("`{}` is finished", batch.command),
+    }
+    Ok(code)
+}
+
+/// Undoes the unfinished `batch` in every repository it ran in, or only
+/// stops its merges and rebases in progress when `conflicted_only`.
+async fn abort_batch(
+    workspace: &Workspace,
+    executor: &BatchExecutor,
+    batch: BatchMerge,
+    conflicted_only: bool,
+) -> Result<ExitCode> {
+    let repos: Vec<_> = workspace
+        .discover_repositories()?
+        .into_iter()
+        .filter(|repo| {
+            if conflicted_only {
+                batch.conflicted.contains(repo.name())
+            } else {
+                batch.heads.contains_key(repo.name())
+            }
+        })
+        .collect();
+    let results = executor
+        .execute_operation(&repos, Arc::new(AbortOperation::new(&batch)))
+        .await;
+    let code = report(&results)?;
+    if !results.iter().any(OperationResult::is_failure) {
+        batch::forget(workspace)?;
+    }
+    Ok(code)
+}
+
+fn report(results: &[OperationResult]) -> Result<ExitCode> {
+    print!("{}", output::results_table(results));
+    if results.iter().any(OperationResult::is_failure) {
+        Ok(ExitCode::FAILURE)
+    } else {
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+/// Reports failures on stderr, for commands whose stdout is machine readable.
+fn report_failures(results: &[OperationResult]) -> Result<ExitCode> {
+    let mut code = ExitCode::SUCCESS;
+    for result in results.iter().filter(|result| result.is_failure()) {
+        eprintln!(
+            "error: {}: {}",
+            result.repo,
+            redact::redact(&result.message)
+        );
+        code = ExitCode::FAILURE;
+    }
+    Ok(code)
+}
+
+/// An abbreviated commit id, `(none)` for a branch that did not exist.
+fn short(id: Option<&str>) -> String {
+    match id {
+        Some(id) => id[..7].to_string(),
+        None => "(none)".to_string(),
+    }
+}
+
+/// The branch column of list, read from HEAD alone: scanning the working
+/// tree of every repository would make listing as slow as status.
+fn branch_column(repo: &GitRepository) -> String {
+    repo.open()
+        .and_then(|git| repository::head_description(&git))
+        .unwrap_or_else(|e| format!("(error: {})", e))
+}
+
+fn pin_marker(name: &str, pinned: bool) -> String {
+    if pinned {
+        format!("{} (pinned)", name)
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_progress_on_an_interactive_terminal_only() {
+        let cli = |args: &[&str]| Cli::parse_from(["git-ws"].iter().chain(args));
+        assert!(draws_progress(&cli(&["status"]), true));
+        assert!(!draws_progress(&cli(&["status"]), false));
+        assert!(!draws_progress(
+            &cli(&["--non-interactive", "status"]),
+            true
+        ));
+        assert!(!draws_progress(&cli(&["--no-progress", "status"]), true));
+        assert!(!draws_progress(&cli(&["--verbose", "status"]), true));
+    }
+
+    /// The arguments of every leaf command, its required arguments filled.
+    fn every_command(command: &clap::Command, path: Vec<String>, out: &mut Vec<Vec<String>>) {
+        let subcommands: Vec<_> = command
+            .get_subcommands()
+            .filter(|sub| sub.get_name() != "help")
+            .collect();
+        if subcommands.is_empty() || !command.is_subcommand_required_set() && !path.is_empty() {
+            out.push(filled(command, &path, |arg| arg.is_required_set()));
+            // Arguments required unless another is given, like the commit of
+            // cherry-pick.
+            out.push(filled(command, &path, |arg| {
+                arg.is_required_set() || arg.is_positional()
+            }));
+        }
+        for sub in subcommands {
+            let mut path = path.clone();
+            path.push(sub.get_name().to_string());
+            every_command(sub, path, out);
+        }
+    }
+
+    fn filled(
+        command: &clap::Command,
+        path: &[String],
+        fill: impl Fn(&clap::Arg) -> bool,
+    ) -> Vec<String> {
+        let mut args = path.to_vec();
+        for arg in command.get_arguments().filter(|arg| fill(arg)) {
+            let value = arg
+                .get_possible_values()
+                .first()
+                .map(|value| value.get_name().to_string())
+                .unwrap_or_else(|| "1".to_string());
+            match arg.get_long() {
+                Some(long) => args.extend([format!("--{}", long), value]),
+                None => args.push(value),
+            }
+        }
+        args
+    }
+
+    #[test]
+    fn every_command_says_whether_it_honours_dry_run() {
+        let mut commands = Vec::new();
+        every_command(&Cli::command(), Vec::new(), &mut commands);
+        assert!(commands.len() > 50);
+        let mut rejected = Vec::new();
+        for pair in commands.chunks(2) {
+            let parse = |args: &Vec<String>| {
+                Cli::try_parse_from(std::iter::once("git-ws".to_string()).chain(args.clone()))
+            };
+            let (args, cli) = match parse(&pair[0]) {
+                Ok(cli) => (&pair[0], cli),
+                Err(_) => (
+                    &pair[1],
+                    parse(&pair[1]).unwrap_or_else(|err| panic!("{:?}: {}", pair[1], err)),
+                ),
+            };
+            if !effect(&cli.command).honours_dry_run() {
+                rejected.push(args[0].clone());
+            }
+        }
+        for name in [
+            "exec", "fetch", "trash", "subtree", "bisect", "setup", "resume", "apply",
+        ] {
+            assert!(
+                rejected.iter().any(|r| r == name),
+                "{} honours --dry-run",
+                name
+            );
+        }
+        let honours = |args: &[&str]| {
+            effect(&Cli::parse_from(["git-ws"].iter().chain(args)).command).honours_dry_run()
+        };
+        assert!(honours(&["status"]));
+        assert!(honours(&["pull"]));
+        assert!(honours(&["consolidate", "--into", "1", "1"]));
+        assert!(honours(&["replay", "1"]));
+        assert!(!honours(&["add", "--patch"]));
+        assert!(!honours(&["trash", "restore", "1"]));
+    }
+
+    #[test]
+    fn asks_the_policy_service_about_every_command_changing_repositories() {
+        let asks = |args: &[&str]| {
+            effect(&Cli::parse_from(["git-ws"].iter().chain(args)).command).changes_repositories()
+        };
+        assert!(asks(&["push"]));
+        assert!(asks(&["pr", "merge", "--changeset", "1"]));
+        assert!(asks(&["pr", "create", "--title", "1"]));
+        assert!(asks(&["recover"]));
+        assert!(asks(&["consolidate", "--into", "1", "1"]));
+        assert!(asks(&["ci-checkout"]));
+        assert!(asks(&["trash", "restore", "1"]));
+        assert!(asks(&["subtree", "split", "1", "1", "--to", "1"]));
+        assert!(!asks(&["status"]));
+        assert!(!asks(&["fetch"]));
+        assert!(!asks(&["resume"]));
+    }
+
+    #[test]
+    fn tells_the_concurrency_in_words() {
+        assert!(concurrency_note(1).starts_with("1 repository at a time"));
+        assert!(concurrency_note(8).starts_with("8 repositories at a time"));
+    }
+
+    #[test]
+    fn lists_the_branch_without_reading_the_working_tree() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("git-ws-list-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let git = git2::Repository::init(&dir)?;
+        let signature = git2::Signature::now("git-ws test", "test@git-ws.invalid")?;
+        let tree = git.find_tree(git.index()?.write_tree()?)?;
+        git.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Initial commit",
+            &tree,
+            &[],
+        )?;
+        git.branch("topic", &git.head()?.peel_to_commit()?, false)?;
+        git.set_head("refs/heads/topic")?;
+        // An index git cannot read fails any scan of the working tree.
+        std::fs::write(dir.join(".git/index"), "broken")?;
+        assert_eq!(branch_column(&GitRepository::new("api", &dir)), "topic");
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 }
@@ -0,0 +1,164 @@
+//! The workspace manifest, declaring the repositories of a workspace.
+//!
+//! With a manifest, the workspace is made of the repositories it declares
+//! instead of those found walking the workspace, see
+//! [`Workspace::discover_repositories`](crate::workspace::Workspace::discover_repositories).
+//!
+//! ```toml
+//! [[repository]]
+//! path = "services/api"
+//! url = "git@example.com:team/api.git"
+//! branch = "main"
+//!
+//! [groups]
+//! # Repository paths, `*` matching anything, see
+//! # `workspace::name_matches`.
+//! backend = ["services/*"]
+//! ```
+//!
+//! `--group backend` runs a command on the repositories of a group only.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Result};
+
+/// Default file name of the manifest, at the workspace root.
+pub const MANIFEST_FILE: &str = ".git-ws.toml";
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default, rename = "repository")]
+    pub repositories: Vec<ManifestRepository>,
+    /// Named groups of repositories, by path.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestRepository {
+    /// Path relative to the workspace root, which is also the repository
+    /// name.
+    pub path: String,
+    pub url: String,
+    /// Default branch, the remote HEAD when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Repositories whose changes must land before the changes of this one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// The manifest at the root of the workspace at `root`, an empty one
+    /// when there is none.
+    pub fn for_workspace(root: &Path) -> Result<Self> {
+        let path = root.join(MANIFEST_FILE);
+        if path.exists() {
+            Manifest::load(&path)
+        } else {
+            Ok(Manifest::default())
+        }
+    }
+
+    /// Orders `items`, each belonging to the repository `name` returns, so
+    /// the repositories a repository depends on come first. Dependencies on
+    /// repositories without items are ignored, and the order is kept
+    /// otherwise.
+    pub fn dependency_order<T>(&self, items: Vec<T>, name: impl Fn(&T) -> &str) -> Result<Vec<T>> {
+        let names: BTreeSet<String> = items.iter().map(|item| name(item).to_string()).collect();
+        let ready = |item: &T, done: &BTreeSet<String>| {
+            self.find(name(item)).is_none_or(|repo| {
+                repo.depends_on
+                    .iter()
+                    .filter(|dependency| names.contains(*dependency))
+                    .all(|dependency| done.contains(dependency))
+            })
+        };
+
+        let mut remaining = items;
+        let mut ordered = Vec::with_capacity(remaining.len());
+        let mut done = BTreeSet::new();
+        while !remaining.is_empty() {
+            let (next, blocked): (Vec<_>, Vec<_>) =
+                remaining.into_iter().partition(|item| ready(item, &done));
+            if next.is_empty() {
+                let cycle: BTreeSet<&str> = blocked.iter().map(&name).collect();
+                return Err(Error::Operation(format!(
+                    "dependency cycle between {}",
+                    cycle.into_iter().collect::<Vec<_>>().join(", ")
+                )));
+            }
+            done.extend(next.iter().map(|item| name(item).to_string()));
+            ordered.extend(next);
+            remaining = blocked;
+        }
+        Ok(ordered)
+    }
+
+    pub fn find(&self, path: &str) -> Option<&ManifestRepository> {
+        self.repositories.iter().find(|repo| repo.path == path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> Manifest {
+        toml::from_str(
+            r#"
+            [[repository]]
+            path = "app"
+            url = "https://example.com/app.git"
+            depends_on = ["lib", "proto"]
+
+            [[repository]]
+            path = "lib"
+            url = "https://example.com/lib.git"
+            depends_on = ["proto"]
+
+            [[repository]]
+            path = "proto"
+            url = "https://example.com/proto.git"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn orders_dependencies_first() {
+        let ordered = manifest()
+            .dependency_order(vec!["app", "docs", "lib", "proto"], |name| name)
+            .unwrap();
+        assert_eq!(ordered, ["docs", "proto", "lib", "app"]);
+    }
+
+    #[test]
+    fn ignores_dependencies_without_items() {
+        let ordered = manifest()
+            .dependency_order(vec!["app", "lib"], |name| name)
+            .unwrap();
+        assert_eq!(ordered, ["lib", "app"]);
+    }
+
+    #[test]
+    fn reports_cycles() {
+        let mut manifest = manifest();
+        manifest.repositories[2].depends_on = vec!["app".to_string()];
+        let error = manifest
+            .dependency_order(vec!["app", "lib", "proto"], |name| name)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "dependency cycle between app, lib, proto"
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Interceptors around the execution of operations.
+//!
+//! Cross-cutting behavior (logging, dry runs, policy checks, ...) is
+//! implemented once as a [`Middleware`] and added to the
+//! [`BatchExecutor`](crate::executor::BatchExecutor) instead of inside every
+//! operation. Middleware run in the order they were added, each deciding
+//! whether and how to continue the chain; the operation itself runs last.
+//!
+//! A middleware can skip an operation by returning [`Error::Skipped`], which
+//! reports the repository as skipped rather than failed.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::operations::GitOperation;
+use crate::precondition::Preconditions;
+use crate::repository::GitRepository;
+use crate::{Error, Result};
+
+pub trait Middleware: Send + Sync {
+    /// Handles `operation` for `repo`, usually by calling `next.run(repo)`
+    /// and looking at or changing what it returns.
+    fn call(
+        &self,
+        repo: &GitRepository,
+        operation: &dyn GitOperation,
+        next: Next<'_>,
+    ) -> Result<String>;
+}
+
+/// The rest of the chain, ending with the operation.
+pub struct Next<'a> {
+    operation: &'a dyn GitOperation,
+    chain: &'a [Arc<dyn Middleware>],
+    preconditions: &'a Preconditions,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(
+        operation: &'a dyn GitOperation,
+        chain: &'a [Arc<dyn Middleware>],
+        preconditions: &'a Preconditions,
+    ) -> Self {
+        Next {
+            operation,
+            chain,
+            preconditions,
+        }
+    }
+
+    /// Runs the rest of the chain. Mutating operations are skipped when the
+    /// repository does not meet the preconditions of the batch, checked
+    /// right before the operation runs.
+    pub fn run(self, repo: &GitRepository) -> Result<String> {
+        match self.chain.split_first() {
+            Some((middleware, rest)) => middleware.call(
+                repo,
+                self.operation,
+                Next::new(self.operation, rest, self.preconditions),
+            ),
+            None => {
+                if self.operation.is_mutating() {
+                    if let Some(failed) = self.preconditions.check(repo)? {
+                        return Err(Error::Skipped(format!("precondition failed: {}", failed)));
+                    }
+                }
+                self.operation.execute(repo)
+            }
+        }
+    }
+}
+
+/// Skips every mutating operation, reporting what would have run.
+pub struct DryRun;
+
+impl Middleware for DryRun {
+    fn call(
+        &self,
+        repo: &GitRepository,
+        operation: &dyn GitOperation,
+        next: Next<'_>,
+    ) -> Result<String> {
+        if operation.is_mutating() {
+            return Err(Error::Skipped(format!(
+                "dry run, would {}",
+                operation.name()
+            )));
+        }
+        next.run(repo)
+    }
+}
+
+/// Logs the start, outcome and duration of every operation to stderr.
+pub struct Logging;
+
+impl Middleware for Logging {
+    fn call(
+        &self,
+        repo: &GitRepository,
+        operation: &dyn GitOperation,
+        next: Next<'_>,
+    ) -> Result<String> {
+        eprintln!("{}: {} started", repo.name(), operation.name());
+        let start = Instant::now();
+        let result = next.run(repo);
+        let outcome = match &result {
+            Ok(_) => "done",
+            Err(Error::Skipped(_)) => "skipped",
+            Err(Error::Warning(_)) => "done with warnings",
+            Err(_) => "failed",
+        };
+        eprintln!(
+            "{}: {} {} in {:.2?}",
+            repo.name(),
+            operation.name(),
+            outcome,
+            start.elapsed()
+        );
+        result
+    }
+}
@@ -0,0 +1,1639 @@
+use std::fmt;
+use std::time::Duration;
+
+use git2::build::CheckoutBuilder;
+use git2::{
+    BranchType, DescribeFormatOptions, DescribeOptions, FetchOptions, FetchPrune, IndexAddOption,
+    PushOptions,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::conflicts;
+use crate::credentials::Credentials;
+use crate::interactive;
+use crate::pathspec::Pathspecs;
+use crate::process;
+use crate::repository::{
+    self, ChangeCounts, GitRepository, IgnoredFiles, RenameDetection, ScanOptions, Snapshot,
+};
+use crate::schedule;
+use crate::trailer;
+use crate::trash::Trash;
+use crate::{Error, Result};
+
+/// A unit of work executed against a single repository.
+///
+/// Operations are run concurrently by the
+/// [`BatchExecutor`](crate::executor::BatchExecutor), one invocation per
+/// repository, and report back a short human readable message.
+pub trait GitOperation: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Whether the operation changes the repository. Mutating operations are
+    /// never run against pinned repositories.
+    fn is_mutating(&self) -> bool {
+        false
+    }
+
+    /// Whether the operation pushes, refused during a freeze, see
+    /// [`crate::freeze`].
+    fn pushes(&self) -> bool {
+        false
+    }
+
+    /// Whether the operation marks its network and local phases, see
+    /// [`crate::schedule`].
+    fn is_pipelined(&self) -> bool {
+        false
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+    Success,
+    Warning,
+    Skipped,
+    Failed,
+}
+
+impl fmt::Display for OperationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            OperationStatus::Success => "ok",
+            OperationStatus::Warning => "warning",
+            OperationStatus::Skipped => "skipped",
+            OperationStatus::Failed => "failed",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Outcome of running an operation against one repository.
+#[derive(Debug, Clone)]
+pub struct OperationResult {
+    pub repo: String,
+    pub status: OperationStatus,
+    pub message: String,
+    pub duration: Duration,
+}
+
+impl OperationResult {
+    pub fn skipped(repo: impl Into<String>, reason: impl Into<String>) -> Self {
+        OperationResult {
+            repo: repo.into(),
+            status: OperationStatus::Skipped,
+            message: reason.into(),
+            duration: Duration::ZERO,
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status == OperationStatus::Success
+    }
+
+    pub fn is_failure(&self) -> bool {
+        self.status == OperationStatus::Failed
+    }
+}
+
+/// Branch, upstream distance and pending changes of a repository.
+#[derive(Default)]
+pub struct StatusOperation {
+    pathspecs: Pathspecs,
+    options: ScanOptions,
+}
+
+impl StatusOperation {
+    /// Status limited to the paths matching `pathspecs`.
+    pub fn matching(pathspecs: Vec<String>) -> Self {
+        StatusOperation::resolved(Pathspecs::everywhere(pathspecs))
+    }
+
+    /// Status limited to the paths matching the pathspecs of each
+    /// repository.
+    pub fn resolved(pathspecs: Pathspecs) -> Self {
+        StatusOperation {
+            pathspecs,
+            options: ScanOptions::default(),
+        }
+    }
+
+    /// How renamed files are detected, listed as `old -> new`.
+    pub fn renames(mut self, renames: RenameDetection) -> Self {
+        self.options.renames = renames;
+        self
+    }
+
+    /// Which ignored files are counted and listed.
+    pub fn ignored(mut self, ignored: IgnoredFiles) -> Self {
+        self.options.ignored = ignored;
+        self
+    }
+}
+
+impl GitOperation for StatusOperation {
+    fn name(&self) -> &str {
+        "status"
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        self.status(repo).map(|status| status.to_string())
+    }
+}
+
+impl StatusOperation {
+    /// The status of `repo`, reported by [`GitOperation::execute`] as its
+    /// message.
+    pub fn status(&self, repo: &GitRepository) -> Result<RepoStatus> {
+        let Some(pathspecs) = self.pathspecs.for_repo(repo.name()) else {
+            return Err(Error::Skipped("no matching path".to_string()));
+        };
+        let snapshot = Snapshot::capture_with(&repo.open()?, &pathspecs, self.options)?;
+        Ok(RepoStatus { snapshot })
+    }
+}
+
+/// The status of a repository, displayed like `main ↑1: 2 modified`.
+#[derive(Debug, Clone)]
+pub struct RepoStatus {
+    pub snapshot: Snapshot,
+}
+
+impl fmt::Display for RepoStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let snapshot = &self.snapshot;
+        write!(f, "{}", snapshot.head_description())?;
+        if let Some(upstream) = &snapshot.upstream {
+            if upstream.ahead > 0 {
+                write!(f, " ↑{}", upstream.ahead)?;
+            }
+            if upstream.behind > 0 {
+                write!(f, " ↓{}", upstream.behind)?;
+            }
+        }
+        write!(f, ": {}", snapshot.changes)?;
+        if !snapshot.renames.is_empty() {
+            let renames: Vec<_> = snapshot
+                .renames
+                .iter()
+                .map(|rename| rename.to_string())
+                .collect();
+            write!(f, " ({})", renames.join(", "))?;
+        }
+        if !snapshot.ignored.is_empty() {
+            write!(f, " (ignored: {})", snapshot.ignored.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Fetches every remote, then reports the status, in one pass per
+/// repository: a repository's status comes as soon as its own fetch is
+/// done, rather than after every fetch.
+pub struct RefreshOperation {
+    fetch: FetchOperation,
+    status: StatusOperation,
+}
+
+impl RefreshOperation {
+    pub fn new(prune: bool) -> Self {
+        RefreshOperation {
+            fetch: FetchOperation::new(true, prune),
+            status: StatusOperation::default(),
+        }
+    }
+}
+
+impl GitOperation for RefreshOperation {
+    fn name(&self) -> &str {
+        "refresh"
+    }
+
+    fn is_pipelined(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let fetched = schedule::network(|| self.fetch.execute(repo));
+        let status = schedule::local(|| self.status.execute(repo));
+        match fetched {
+            Ok(_) | Err(Error::Skipped(_)) => status,
+            // The status is still worth knowing, against the remote as last
+            // fetched.
+            Err(e) => Err(Error::Warning(format!("{} (fetch failed: {})", status?, e))),
+        }
+    }
+}
+
+/// Stages new, modified and deleted files matching the pathspecs, every file
+/// when there is none, like `git add --all`.
+pub struct AddOperation {
+    pathspecs: Pathspecs,
+}
+
+impl AddOperation {
+    pub fn new(pathspecs: Vec<String>) -> Self {
+        AddOperation::resolved(Pathspecs::everywhere(pathspecs))
+    }
+
+    /// Stages the changes matching the pathspecs of each repository.
+    pub fn resolved(pathspecs: Pathspecs) -> Self {
+        AddOperation { pathspecs }
+    }
+}
+
+impl GitOperation for AddOperation {
+    fn name(&self) -> &str {
+        "add"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let Some(matching) = self.pathspecs.for_repo(repo.name()) else {
+            return Err(Error::Skipped("no matching path".to_string()));
+        };
+        let git = repo.open()?;
+        let pathspecs = if matching.is_empty() {
+            vec![".".to_string()]
+        } else {
+            matching.clone()
+        };
+        let mut index = git.index()?;
+        index.add_all(pathspecs.iter(), IndexAddOption::DEFAULT, None)?;
+        index.update_all(pathspecs.iter(), None)?;
+        index.write()?;
+        // Without rename detection, renames count as staged like the rest.
+        let options = ScanOptions {
+            renames: RenameDetection::Off,
+            ..ScanOptions::default()
+        };
+        let staged = ChangeCounts::scan(&git, &matching, options)?.counts.staged;
+        Ok(format!("{} staged", staged))
+    }
+}
+
+/// Removes tracked files from the working tree and the index, like
+/// `git rm -r`, or moves them to a [`Trash`].
+pub struct RmOperation {
+    pathspecs: Pathspecs,
+    trash: Option<Trash>,
+}
+
+impl RmOperation {
+    /// Removes the files matching the pathspecs of each repository.
+    pub fn resolved(pathspecs: Pathspecs) -> Self {
+        RmOperation {
+            pathspecs,
+            trash: None,
+        }
+    }
+
+    /// Moves the files to `trash` instead of deleting them.
+    pub fn trash(mut self, trash: Trash) -> Self {
+        self.trash = Some(trash);
+        self
+    }
+}
+
+impl GitOperation for RmOperation {
+    fn name(&self) -> &str {
+        "rm"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let Some(matching) = self.pathspecs.for_repo(repo.name()) else {
+            return Err(Error::Skipped("no matching path".to_string()));
+        };
+        if matching.is_empty() {
+            return Err(Error::Operation(
+                "not removing every file of the repository".to_string(),
+            ));
+        }
+        let mut args = vec!["ls-files".to_string(), "-z".to_string(), "--".to_string()];
+        args.extend(matching.iter().cloned());
+        let listed = repo.git(&args)?;
+        let files: Vec<_> = listed.split('\0').filter(|file| !file.is_empty()).collect();
+        if files.is_empty() {
+            return Err(Error::Skipped("no tracked file matches".to_string()));
+        }
+        let mut args = vec!["rm", "--quiet", "-r"];
+        if let Some(trash) = &self.trash {
+            for file in &files {
+                if repo.path().join(file).exists() {
+                    trash.put(repo, file)?;
+                }
+            }
+            // The files are in the trash, whatever their changes.
+            args.extend(["--cached", "--force"]);
+        }
+        args.push("--");
+        args.extend(files.iter().copied());
+        repo.git(&args)?;
+        Ok(match &self.trash {
+            Some(trash) => format!("{} moved to trash {}", files.len(), trash.id()),
+            None => format!("{} removed", files.len()),
+        })
+    }
+}
+
+/// Commits the index, optionally staging every tracked change first.
+pub struct CommitOperation {
+    message: String,
+    all: bool,
+    trailers: Vec<(String, String)>,
+}
+
+impl CommitOperation {
+    pub fn new(message: impl Into<String>, all: bool) -> Self {
+        CommitOperation {
+            message: message.into(),
+            all,
+            trailers: Vec::new(),
+        }
+    }
+
+    /// Trailers appended to the message, see [`trailer`](crate::trailer).
+    pub fn trailers(mut self, trailers: Vec<(String, String)>) -> Self {
+        self.trailers = trailers;
+        self
+    }
+}
+
+impl GitOperation for CommitOperation {
+    fn name(&self) -> &str {
+        "commit"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let git = repo.open()?;
+        let mut index = git.index()?;
+        if self.all {
+            index.update_all(["*"].iter(), None)?;
+            index.write()?;
+        }
+        let tree = git.find_tree(index.write_tree()?)?;
+        let parent = match git.head() {
+            Ok(head) => Some(head.peel_to_commit()?),
+            Err(_) => None,
+        };
+        let unchanged = match &parent {
+            Some(parent) => parent.tree_id() == tree.id(),
+            None => index.is_empty(),
+        };
+        if unchanged {
+            return Ok("nothing to commit".to_string());
+        }
+
+        let signature = git.signature()?;
+        let parents: Vec<_> = parent.iter().collect();
+        let oid = git.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            &trailer::append(&self.message, &self.trailers),
+            &tree,
+            &parents,
+        )?;
+        Ok(format!(
+            "[{} {}] {}",
+            repository::head_description(&git)?,
+            repository::short_id(oid),
+            self.message.lines().next().unwrap_or_default()
+        ))
+    }
+}
+
+/// Runs an arbitrary command inside the repository directory.
+///
+/// `{repo}`, `{path}` and `{branch}` in the command are replaced with the
+/// name, path and branch of the repository, empty on a detached HEAD. The
+/// command gets them in `GIT_WS_REPO_NAME`, `GIT_WS_REPO_PATH` and
+/// `GIT_WS_BRANCH` too, safer in shell commands as they need no quoting.
+pub struct ExecOperation {
+    command: Vec<String>,
+    shell: bool,
+    limits: process::Limits,
+    exit_codes: ExitCodes,
+    output: Option<ExecSink>,
+}
+
+/// What the command run by exec wrote, and how it exited, in one
+/// repository.
+#[derive(Debug, Clone, Default)]
+pub struct ExecOutput {
+    /// `None` when the command was killed by a signal.
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Receives the output of the command of exec in each repository, by
+/// repository name.
+pub type ExecSink = std::sync::Arc<dyn Fn(&str, ExecOutput) + Send + Sync>;
+
+/// How exit codes other than 0 are classified.
+#[derive(Debug, Clone, Default)]
+pub struct ExitCodes {
+    /// Codes of flaky failures, worth running the command again.
+    pub retry: Vec<i32>,
+    /// Codes reported as warnings rather than failures.
+    pub warn: Vec<i32>,
+    /// How many times a command exiting with a retryable code runs again.
+    pub retries: u32,
+}
+
+impl ExecOperation {
+    pub fn new(command: Vec<String>) -> Self {
+        ExecOperation {
+            command,
+            shell: false,
+            limits: process::Limits::default(),
+            exit_codes: ExitCodes::default(),
+            output: None,
+        }
+    }
+
+    /// Hands the whole output of the command in each repository, of its
+    /// last attempt, to `sink`.
+    pub fn on_output(mut self, sink: ExecSink) -> Self {
+        self.output = Some(sink);
+        self
+    }
+
+    /// Runs the command through the platform shell so pipes, redirections
+    /// and variables work: its first word is the script, as is, and the
+    /// words after it are quoted arguments appended to it.
+    pub fn shell(mut self, shell: bool) -> Self {
+        self.shell = shell;
+        self
+    }
+
+    /// Time and resources the command may use in each repository.
+    pub fn limits(mut self, limits: process::Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Exit codes to retry or report as warnings. A command succeeding only
+    /// after a retry is reported as a warning too.
+    pub fn exit_codes(mut self, exit_codes: ExitCodes) -> Self {
+        self.exit_codes = exit_codes;
+        self
+    }
+
+    fn run(&self, repo: &GitRepository) -> Result<std::process::Output> {
+        let branch = repo.current_branch()?.unwrap_or_default();
+        let path = repo.path().to_string_lossy();
+        let values = [
+            ("{repo}", repo.name()),
+            ("{path}", path.as_ref()),
+            ("{branch}", branch.as_str()),
+        ];
+        let fill = |word: &str| fill_placeholders(word, &values, false);
+        // In a script, values are quoted so that a branch like `x;rm -rf ~`
+        // or a path with spaces stays one word.
+        let fill_script = |script: &str| fill_placeholders(script, &values, true);
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or_else(|| Error::Operation("no command given".to_string()))?;
+        let mut command = if self.shell {
+            let mut script = fill_script(program);
+            for arg in args {
+                script.push(' ');
+                script.push_str(&interactive::shell_quote(&fill(arg)));
+            }
+            interactive::shell(&script)
+        } else {
+            let mut command = interactive::command(fill(program));
+            command.args(args.iter().map(|arg| fill(arg)));
+            command
+        };
+        command
+            .env("GIT_WS_REPO_NAME", repo.name())
+            .env("GIT_WS_REPO_PATH", repo.path())
+            .env("GIT_WS_BRANCH", &branch);
+        match process::output(command.current_dir(repo.path()), &self.limits) {
+            Ok(output) => Ok(output),
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                Err(Error::Operation(e.to_string()))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Where a placeholder of a script stands.
+#[derive(Clone, Copy, PartialEq)]
+enum Quoting {
+    None,
+    Single,
+    Double,
+}
+
+/// `template` with the placeholders of `values` replaced in one pass, so a
+/// value holding a placeholder is left as it is. In a `script`, values are
+/// quoted for the quotes they stand in: a placeholder already in quotes
+/// only has the characters escaped the quotes would not protect.
+fn fill_placeholders(template: &str, values: &[(&str, &str)], script: bool) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut quoting = Quoting::None;
+    let mut rest = template;
+    while let Some(c) = rest.chars().next() {
+        if let Some((placeholder, value)) = values
+            .iter()
+            .find(|(placeholder, _)| rest.starts_with(placeholder))
+        {
+            filled.push_str(&match (script, quoting) {
+                (false, _) => value.to_string(),
+                (true, Quoting::None) => interactive::shell_quote(value),
+                (true, Quoting::Single) => value.replace('\'', r"'\''"),
+                (true, Quoting::Double) if cfg!(windows) => value.replace('"', "\"\""),
+                (true, Quoting::Double) => value.chars().fold(String::new(), |mut escaped, c| {
+                    if "\\\"$`".contains(c) {
+                        escaped.push('\\');
+                    }
+                    escaped.push(c);
+                    escaped
+                }),
+            });
+            rest = &rest[placeholder.len()..];
+            continue;
+        }
+        filled.push(c);
+        rest = &rest[c.len_utf8()..];
+        if !script {
+            continue;
+        }
+        match (c, quoting) {
+            // cmd knows no single quotes nor backslash escapes.
+            ('\\', Quoting::None | Quoting::Double) if !cfg!(windows) => {
+                if let Some(escaped) = rest.chars().next() {
+                    filled.push(escaped);
+                    rest = &rest[escaped.len_utf8()..];
+                }
+            }
+            ('\'', Quoting::None) if !cfg!(windows) => quoting = Quoting::Single,
+            ('\'', Quoting::Single) => quoting = Quoting::None,
+            ('"', Quoting::None) => quoting = Quoting::Double,
+            ('"', Quoting::Double) => quoting = Quoting::None,
+            _ => {}
+        }
+    }
+    filled
+}
+
+impl GitOperation for ExecOperation {
+    fn name(&self) -> &str {
+        "exec"
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let mut attempt = 1;
+        let output = loop {
+            let output = self.run(repo)?;
+            let retryable = output
+                .status
+                .code()
+                .is_some_and(|code| self.exit_codes.retry.contains(&code));
+            if !retryable || attempt > self.exit_codes.retries {
+                break output;
+            }
+            attempt += 1;
+            std::thread::sleep(std::time::Duration::from_secs(1));
+        };
+        if let Some(sink) = &self.output {
+            sink(
+                repo.name(),
+                ExecOutput {
+                    exit_code: output.status.code(),
+                    stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+                    stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                },
+            );
+        }
+        let attempts = match attempt {
+            1 => String::new(),
+            n => format!(", {} attempts", n),
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout)
+            .trim_end()
+            .to_string();
+        if output.status.success() {
+            if attempt > 1 {
+                return Err(Error::Warning(format!(
+                    "{} (succeeded on attempt {})",
+                    stdout, attempt
+                )));
+            }
+            return Ok(stdout);
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr)
+            .trim_end()
+            .to_string();
+        let message = format!("{} ({}{})", stderr, output.status, attempts);
+        let warning = output
+            .status
+            .code()
+            .is_some_and(|code| self.exit_codes.warn.contains(&code));
+        if warning {
+            Err(Error::Warning(message))
+        } else {
+            Err(Error::Operation(message))
+        }
+    }
+}
+
+/// Sets the upstream of local branches that have none to the branch of the
+/// same name on `remote`, optionally pushing branches the remote lacks.
+pub struct TrackOperation {
+    remote: String,
+    push: bool,
+}
+
+impl TrackOperation {
+    pub fn new(remote: impl Into<String>, push: bool) -> Self {
+        TrackOperation {
+            remote: remote.into(),
+            push,
+        }
+    }
+}
+
+impl GitOperation for TrackOperation {
+    fn name(&self) -> &str {
+        "track"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn pushes(&self) -> bool {
+        self.push
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let git = repo.open()?;
+        let mut remote = git.find_remote(&self.remote)?;
+        let mut tracked = Vec::new();
+        let mut missing = Vec::new();
+
+        for branch in git.branches(Some(BranchType::Local))? {
+            let (mut branch, _) = branch?;
+            if branch.upstream().is_ok() {
+                continue;
+            }
+            let name = match branch.name()? {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let remote_ref = format!("refs/remotes/{}/{}", self.remote, name);
+            if git.find_reference(&remote_ref).is_err() {
+                if !self.push {
+                    missing.push(name);
+                    continue;
+                }
+                let credentials = Credentials::new();
+                let mut options = PushOptions::new();
+                options.remote_callbacks(credentials.callbacks());
+                let refspec = format!("refs/heads/{0}:refs/heads/{0}", name);
+                remote.push(&[refspec.as_str()], Some(&mut options))?;
+                credentials.approve();
+            }
+            branch.set_upstream(Some(&format!("{}/{}", self.remote, name)))?;
+            tracked.push(name);
+        }
+
+        let mut message = if tracked.is_empty() {
+            "nothing to track".to_string()
+        } else {
+            format!("tracking {}", tracked.join(", "))
+        };
+        if !missing.is_empty() {
+            message.push_str(&format!(
+                "; not on {}: {} (use --push)",
+                self.remote,
+                missing.join(", ")
+            ));
+        }
+        Ok(message)
+    }
+}
+
+/// Puts a repository with a detached HEAD back on a branch: a branch created
+/// at the current commit, or the default branch.
+pub struct AttachOperation {
+    branch: Option<String>,
+}
+
+impl AttachOperation {
+    pub fn new(branch: Option<String>) -> Self {
+        AttachOperation { branch }
+    }
+}
+
+impl GitOperation for AttachOperation {
+    fn name(&self) -> &str {
+        "attach"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let git = repo.open()?;
+        if !git.head_detached()? {
+            return Ok("not detached".to_string());
+        }
+        let head = git.head()?.peel_to_commit()?;
+
+        if let Some(name) = &self.branch {
+            let branch = match git.find_branch(name, BranchType::Local) {
+                Ok(branch) => branch,
+                Err(_) => git.branch(name, &head, false)?,
+            };
+            if branch.get().target() != Some(head.id()) {
+                return Err(Error::Operation(format!(
+                    "branch {} exists and does not point to {}",
+                    name,
+                    repository::short_id(head.id())
+                )));
+            }
+            git.set_head(&format!("refs/heads/{}", name))?;
+            return Ok(format!("attached to {}", name));
+        }
+
+        let name = repository::default_branch(&git)?
+            .ok_or_else(|| Error::Operation("no default branch found".to_string()))?;
+        let target = git
+            .find_branch(&name, BranchType::Local)?
+            .get()
+            .peel_to_commit()?;
+        if target.id() != head.id() {
+            git.checkout_tree(target.as_object(), Some(CheckoutBuilder::new().safe()))?;
+        }
+        git.set_head(&format!("refs/heads/{}", name))?;
+        Ok(format!("returned to {}", name))
+    }
+}
+
+/// Fetches the remote of the checked out branch, `origin` when it has no
+/// upstream, or every remote. Branches and working trees are left alone,
+/// so fetching is not mutating.
+pub struct FetchOperation {
+    all: bool,
+    prune: bool,
+}
+
+impl FetchOperation {
+    pub fn new(all: bool, prune: bool) -> Self {
+        FetchOperation { all, prune }
+    }
+
+    fn remotes(&self, git: &git2::Repository) -> Result<Vec<String>> {
+        let remotes: Vec<String> = git
+            .remotes()?
+            .iter()
+            .flatten()
+            .map(str::to_string)
+            .collect();
+        if self.all {
+            return Ok(remotes);
+        }
+        let upstream = match repository::current_branch(git)? {
+            Some(branch) => git
+                .branch_upstream_remote(&format!("refs/heads/{}", branch))
+                .ok()
+                .and_then(|remote| remote.as_str().map(str::to_string)),
+            None => None,
+        };
+        let remote =
+            upstream.or_else(|| remotes.iter().find(|remote| *remote == "origin").cloned());
+        Ok(remote.into_iter().collect())
+    }
+}
+
+impl GitOperation for FetchOperation {
+    fn name(&self) -> &str {
+        "fetch"
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let git = repo.open()?;
+        let names = self.remotes(&git)?;
+        if names.is_empty() {
+            return Err(Error::Skipped("no remote".to_string()));
+        }
+        let mut updated = 0;
+        let mut pruned = 0;
+        for name in &names {
+            let mut remote = git.find_remote(name)?;
+            let credentials = Credentials::new();
+            {
+                let mut callbacks = credentials.callbacks();
+                callbacks.update_tips(|_, _, new| {
+                    if new.is_zero() {
+                        pruned += 1;
+                    } else {
+                        updated += 1;
+                    }
+                    true
+                });
+                let mut options = FetchOptions::new();
+                options.remote_callbacks(callbacks);
+                if self.prune {
+                    options.prune(FetchPrune::On);
+                }
+                remote.fetch::<&str>(&[], Some(&mut options), None)?;
+            }
+            credentials.approve();
+        }
+        let mut message = match updated {
+            0 => "up to date".to_string(),
+            updated => format!("{} ref(s) updated", updated),
+        };
+        if pruned > 0 {
+            message.push_str(&format!(", {} pruned", pruned));
+        }
+        message.push_str(&format!(" ({})", names.join(", ")));
+        Ok(message)
+    }
+}
+
+/// Pushes the checked out branch to its upstream. With `set_upstream`, a
+/// branch without upstream is pushed to the branch of the same name on
+/// `remote`, which becomes its upstream.
+pub struct PushOperation {
+    remote: String,
+    set_upstream: bool,
+}
+
+impl PushOperation {
+    pub fn new(remote: impl Into<String>, set_upstream: bool) -> Self {
+        PushOperation {
+            remote: remote.into(),
+            set_upstream,
+        }
+    }
+}
+
+impl GitOperation for PushOperation {
+    fn name(&self) -> &str {
+        "push"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn pushes(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let git = repo.open()?;
+        let Some(name) = repository::current_branch(&git)? else {
+            return Err(Error::Skipped("HEAD is detached".to_string()));
+        };
+        let local = format!("refs/heads/{}", name);
+        let tracked = git
+            .find_branch(&name, BranchType::Local)?
+            .upstream()
+            .is_ok();
+        let (remote_name, destination) = if tracked {
+            let remote = git.branch_upstream_remote(&local)?;
+            let merge = git
+                .config()?
+                .get_string(&format!("branch.{}.merge", name))?;
+            (remote.as_str().unwrap_or_default().to_string(), merge)
+        } else if self.set_upstream {
+            (self.remote.clone(), local.clone())
+        } else {
+            return Err(Error::Skipped(format!(
+                "no upstream for {} (use --set-upstream)",
+                name
+            )));
+        };
+        let ahead = repository::ahead_behind(&git)?.map(|(ahead, _)| ahead);
+        if ahead == Some(0) {
+            return Ok("up to date".to_string());
+        }
+
+        let mut remote = git.find_remote(&remote_name)?;
+        let credentials = Credentials::new();
+        let mut rejected = None;
+        {
+            let mut callbacks = credentials.callbacks();
+            callbacks.push_update_reference(|_, status| {
+                rejected = status.map(str::to_string);
+                Ok(())
+            });
+            let mut options = PushOptions::new();
+            options.remote_callbacks(callbacks);
+            let refspec = format!("{}:{}", local, destination);
+            remote.push(&[refspec.as_str()], Some(&mut options))?;
+        }
+        if let Some(reason) = rejected {
+            return Err(Error::Operation(format!("rejected: {}", reason)));
+        }
+        credentials.approve();
+
+        let destination = destination.trim_start_matches("refs/heads/");
+        if !tracked {
+            git.find_branch(&name, BranchType::Local)?
+                .set_upstream(Some(&format!("{}/{}", remote_name, destination)))?;
+        }
+        Ok(match ahead {
+            Some(ahead) => format!(
+                "{} -> {}/{}, {} commit(s)",
+                name, remote_name, destination, ahead
+            ),
+            None => format!("{} -> {}/{}, new branch", name, remote_name, destination),
+        })
+    }
+}
+
+/// How [`PullOperation`] integrates the upstream branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PullMode {
+    Merge,
+    Rebase,
+    FastForwardOnly,
+}
+
+impl PullMode {
+    fn flag(self) -> &'static str {
+        match self {
+            PullMode::Merge => "--no-rebase",
+            PullMode::Rebase => "--rebase",
+            PullMode::FastForwardOnly => "--ff-only",
+        }
+    }
+}
+
+/// Fetches the upstream of the checked out branch and integrates it.
+///
+/// A repository left conflicted fails with the conflicted files, and stays
+/// in the middle of the merge or rebase for the user to resolve.
+pub struct PullOperation {
+    mode: PullMode,
+}
+
+impl PullOperation {
+    pub fn new(mode: PullMode) -> Self {
+        PullOperation { mode }
+    }
+}
+
+impl GitOperation for PullOperation {
+    fn name(&self) -> &str {
+        "pull"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let snapshot = repo.snapshot()?;
+        if snapshot.branch.is_none() {
+            return Err(Error::Skipped("HEAD is detached".to_string()));
+        }
+        if snapshot.upstream.is_none() {
+            return Err(Error::Skipped("no upstream".to_string()));
+        }
+        let before = snapshot.head;
+        if let Err(e) = repo.git(["pull", "--no-edit", self.mode.flag()]) {
+            let conflicts = conflicts::list(repo, &[])?;
+            if conflicts.is_empty() {
+                return Err(e);
+            }
+            let paths: Vec<_> = conflicts
+                .iter()
+                .map(|conflict| conflict.path.as_str())
+                .collect();
+            return Err(Error::Operation(format!(
+                "conflict in {}, see `git ws conflicts`",
+                paths.join(", ")
+            )));
+        }
+        let after = repo.head_sha()?;
+        let Some(before) = before.filter(|before| *before != after) else {
+            return Ok("already up to date".to_string());
+        };
+        let range = format!("{}..{}", before, after);
+        let commits = repo.git(["rev-list", "--count", &range])?;
+        Ok(format!(
+            "{}..{}, {} new commit(s)",
+            &before[..7],
+            &after[..7],
+            commits
+        ))
+    }
+}
+
+/// Start of the message of the repositories a merge fast-forwarded.
+pub const FAST_FORWARD: &str = "fast-forward";
+/// Start of the message of the repositories a merge created a commit in.
+pub const MERGED: &str = "merged";
+
+/// Merges a branch, local or of `origin`, into the checked out branch.
+pub struct MergeOperation {
+    branch: String,
+    mode: Option<MergeMode>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeMode {
+    /// Always create a merge commit.
+    NoFastForward,
+    /// Only fast-forward, failing otherwise.
+    FastForwardOnly,
+}
+
+impl MergeOperation {
+    pub fn new(branch: impl Into<String>, mode: Option<MergeMode>) -> Self {
+        MergeOperation {
+            branch: branch.into(),
+            mode,
+        }
+    }
+
+    /// The ref of the branch to merge in `repo`, the local branch first.
+    fn target(&self, repo: &GitRepository) -> Option<String> {
+        [self.branch.clone(), format!("origin/{}", self.branch)]
+            .into_iter()
+            .find(|name| {
+                repo.git([
+                    "rev-parse",
+                    "--verify",
+                    "--quiet",
+                    &format!("{}^{{commit}}", name),
+                ])
+                .is_ok()
+            })
+    }
+}
+
+impl GitOperation for MergeOperation {
+    fn name(&self) -> &str {
+        "merge"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let Some(current) = repo.current_branch()? else {
+            return Err(Error::Skipped("HEAD is detached".to_string()));
+        };
+        if current == self.branch {
+            return Err(Error::Skipped(format!("on {}", self.branch)));
+        }
+        let Some(target) = self.target(repo) else {
+            return Err(Error::Skipped(format!("no branch {}", self.branch)));
+        };
+        let before = repo.head_sha()?;
+        let mut args = vec!["merge", "--no-edit"];
+        match self.mode {
+            Some(MergeMode::NoFastForward) => args.push("--no-ff"),
+            Some(MergeMode::FastForwardOnly) => args.push("--ff-only"),
+            None => {}
+        }
+        args.push(&target);
+        if let Err(e) = repo.git(args) {
+            let conflicts = conflicts::list(repo, &[])?;
+            if conflicts.is_empty() {
+                return Err(e);
+            }
+            let paths: Vec<_> = conflicts
+                .iter()
+                .map(|conflict| conflict.path.as_str())
+                .collect();
+            return Err(Error::Operation(format!(
+                "conflict in {}, see `git ws conflicts`",
+                paths.join(", ")
+            )));
+        }
+        let after = repo.head_sha()?;
+        if after == before {
+            return Ok("already up to date".to_string());
+        }
+        let parents = repo
+            .open()?
+            .find_commit(git2::Oid::from_str(&after)?)?
+            .parent_count();
+        if parents > 1 {
+            Ok(format!(
+                "{} {} into {}, {}",
+                MERGED,
+                target,
+                current,
+                &after[..7]
+            ))
+        } else {
+            Ok(format!("{} to {}", FAST_FORWARD, &after[..7]))
+        }
+    }
+}
+
+/// Rebases the checked out branch onto an upstream, like `origin/main`.
+pub struct RebaseBranchOperation {
+    upstream: String,
+}
+
+impl RebaseBranchOperation {
+    pub fn new(upstream: impl Into<String>) -> Self {
+        RebaseBranchOperation {
+            upstream: upstream.into(),
+        }
+    }
+}
+
+impl GitOperation for RebaseBranchOperation {
+    fn name(&self) -> &str {
+        "rebase"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let Some(current) = repo.current_branch()? else {
+            return Err(Error::Skipped("HEAD is detached".to_string()));
+        };
+        let upstream = format!("{}^{{commit}}", self.upstream);
+        if repo
+            .git(["rev-parse", "--verify", "--quiet", &upstream])
+            .is_err()
+        {
+            return Err(Error::Skipped(format!("no {}", self.upstream)));
+        }
+        if !ChangeCounts::collect(&repo.open()?)?.is_clean() {
+            return Err(Error::Skipped(
+                "local changes, commit or stash them first".to_string(),
+            ));
+        }
+        let before = repo.head_sha()?;
+        if let Err(e) = repo.git(["rebase", "--quiet", &self.upstream]) {
+            let conflicts = conflicts::list(repo, &[])?;
+            if conflicts.is_empty() {
+                return Err(e);
+            }
+            let paths: Vec<_> = conflicts
+                .iter()
+                .map(|conflict| conflict.path.as_str())
+                .collect();
+            return Err(Error::Operation(format!(
+                "conflict in {}, see `git ws conflicts`",
+                paths.join(", ")
+            )));
+        }
+        let after = repo.head_sha()?;
+        if after == before {
+            return Ok("already up to date".to_string());
+        }
+        let range = format!("{}..{}", self.upstream, after);
+        let commits = repo.git(["rev-list", "--count", &range])?;
+        Ok(format!(
+            "rebased {} onto {}, {} commit(s) on top",
+            current, self.upstream, commits
+        ))
+    }
+}
+
+/// How the commit a cherry-pick or a revert is about is found in each
+/// repository.
+#[derive(Debug, Clone)]
+pub enum CommitSource {
+    /// The same commit everywhere, for forks sharing the history.
+    Commit(String),
+    /// The commit whose message matches an extended regular expression, for
+    /// forks where it got its own id. Cherry-picks look on every branch.
+    Message(String),
+}
+
+/// Cherry-picks a commit onto the checked out branch, recording where it
+/// came from with `-x`.
+pub struct CherryPickOperation {
+    source: CommitSource,
+}
+
+impl CherryPickOperation {
+    pub fn new(source: CommitSource) -> Self {
+        CherryPickOperation { source }
+    }
+
+    /// The commit to pick in `repo`.
+    fn commit(&self, repo: &GitRepository) -> Result<String> {
+        match &self.source {
+            CommitSource::Commit(commit) => repo
+                .git([
+                    "rev-parse",
+                    "--verify",
+                    "--quiet",
+                    &format!("{}^{{commit}}", commit),
+                ])
+                .map_err(|_| Error::Skipped(format!("no commit {}", commit))),
+            CommitSource::Message(pattern) => {
+                let grep = format!("--grep={}", pattern);
+                let found =
+                    repo.git(["log", "--all", "--extended-regexp", &grep, "--format=%H"])?;
+                let found: Vec<_> = found.lines().collect();
+                // A match on HEAD is the fix applied already.
+                if let Some(applied) = found.iter().find(|commit| is_ancestor(repo, commit)) {
+                    return Err(Error::Skipped(format!(
+                        "already on the branch, as {}",
+                        &applied[..7]
+                    )));
+                }
+                match found.as_slice() {
+                    [] => Err(Error::Skipped(format!("no commit matching {}", pattern))),
+                    [commit] => Ok(commit.to_string()),
+                    several => {
+                        let ids: Vec<_> = several.iter().map(|commit| &commit[..7]).collect();
+                        Err(Error::Operation(format!(
+                            "{} commits match, {}",
+                            several.len(),
+                            ids.join(", ")
+                        )))
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Whether `commit` is reachable from HEAD.
+fn is_ancestor(repo: &GitRepository, commit: &str) -> bool {
+    repo.git(["merge-base", "--is-ancestor", commit, "HEAD"])
+        .is_ok()
+}
+
+impl GitOperation for CherryPickOperation {
+    fn name(&self) -> &str {
+        "cherry-pick"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        if repo.current_branch()?.is_none() {
+            return Err(Error::Skipped("HEAD is detached".to_string()));
+        }
+        let commit = self.commit(repo)?;
+        if is_ancestor(repo, &commit) {
+            return Err(Error::Skipped(format!(
+                "{} is on the branch already",
+                &commit[..7]
+            )));
+        }
+        // `git cherry` marks with `-` the commits whose change HEAD has.
+        let parent = format!("{}^", commit);
+        let cherry = repo
+            .git(["cherry", "HEAD", &commit, &parent])
+            .unwrap_or_default();
+        if cherry.starts_with('-') {
+            return Err(Error::Skipped(format!(
+                "{} is applied already",
+                &commit[..7]
+            )));
+        }
+        if !ChangeCounts::collect(&repo.open()?)?.is_clean() {
+            return Err(Error::Skipped(
+                "local changes, commit or stash them first".to_string(),
+            ));
+        }
+        if let Err(e) = repo.git(["cherry-pick", "-x", &commit]) {
+            let conflicts = conflicts::list(repo, &[])?;
+            if conflicts.is_empty() {
+                return Err(e);
+            }
+            let paths: Vec<_> = conflicts
+                .iter()
+                .map(|conflict| conflict.path.as_str())
+                .collect();
+            return Err(Error::Operation(format!(
+                "conflict in {}, see `git ws conflicts`",
+                paths.join(", ")
+            )));
+        }
+        Ok(format!(
+            "picked {} as {}",
+            &commit[..7],
+            &repo.head_sha()?[..7]
+        ))
+    }
+}
+
+/// Reverts a commit of the checked out branch, with a commit undoing it.
+///
+/// A merge is reverted against its first parent, undoing what the branch
+/// merged, as forges do.
+pub struct RevertOperation {
+    source: CommitSource,
+}
+
+impl RevertOperation {
+    pub fn new(source: CommitSource) -> Self {
+        RevertOperation { source }
+    }
+
+    /// The commit of the branch to revert in `repo`.
+    fn commit(&self, repo: &GitRepository) -> Result<String> {
+        match &self.source {
+            CommitSource::Commit(commit) => {
+                let found = repo
+                    .git([
+                        "rev-parse",
+                        "--verify",
+                        "--quiet",
+                        &format!("{}^{{commit}}", commit),
+                    ])
+                    .map_err(|_| Error::Skipped(format!("no commit {}", commit)))?;
+                if !is_ancestor(repo, &found) {
+                    return Err(Error::Skipped(format!(
+                        "{} is not on the branch",
+                        &found[..7]
+                    )));
+                }
+                Ok(found)
+            }
+            CommitSource::Message(pattern) => {
+                let grep = format!("--grep={}", pattern);
+                let found = repo.git(["log", "--extended-regexp", &grep, "--format=%H", "HEAD"])?;
+                let found: Vec<_> = found.lines().collect();
+                match found.as_slice() {
+                    [] => Err(Error::Skipped(format!("no commit matching {}", pattern))),
+                    [commit] => Ok(commit.to_string()),
+                    several => {
+                        let ids: Vec<_> = several.iter().map(|commit| &commit[..7]).collect();
+                        Err(Error::Operation(format!(
+                            "{} commits match, {}",
+                            several.len(),
+                            ids.join(", ")
+                        )))
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl GitOperation for RevertOperation {
+    fn name(&self) -> &str {
+        "revert"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        if repo.current_branch()?.is_none() {
+            return Err(Error::Skipped("HEAD is detached".to_string()));
+        }
+        let commit = self.commit(repo)?;
+        let reverted = format!("--grep=This reverts commit {}", commit);
+        let reverts = repo.git(["log", "--fixed-strings", &reverted, "--format=%h", "HEAD"])?;
+        if let Some(revert) = reverts.lines().next() {
+            return Err(Error::Skipped(format!(
+                "{} is reverted already, by {}",
+                &commit[..7],
+                revert
+            )));
+        }
+        if !ChangeCounts::collect(&repo.open()?)?.is_clean() {
+            return Err(Error::Skipped(
+                "local changes, commit or stash them first".to_string(),
+            ));
+        }
+        let parents = repo
+            .open()?
+            .find_commit(git2::Oid::from_str(&commit)?)?
+            .parent_count();
+        let mut args = vec!["revert", "--no-edit"];
+        if parents > 1 {
+            args.extend(["--mainline", "1"]);
+        }
+        args.push(&commit);
+        if let Err(e) = repo.git(args) {
+            let conflicts = conflicts::list(repo, &[])?;
+            if conflicts.is_empty() {
+                return Err(e);
+            }
+            let paths: Vec<_> = conflicts
+                .iter()
+                .map(|conflict| conflict.path.as_str())
+                .collect();
+            return Err(Error::Operation(format!(
+                "conflict in {}, see `git ws conflicts`",
+                paths.join(", ")
+            )));
+        }
+        Ok(format!(
+            "reverted {} by {}",
+            &commit[..7],
+            &repo.head_sha()?[..7]
+        ))
+    }
+}
+
+/// What `reset` resets besides HEAD, like the flags of `git reset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetMode {
+    /// HEAD only, the changes it drops left staged.
+    Soft,
+    /// HEAD and the index, the changes left in the working tree.
+    Mixed,
+    /// HEAD, the index and the working tree, dropping every change.
+    Hard,
+}
+
+impl ResetMode {
+    fn flag(self) -> &'static str {
+        match self {
+            ResetMode::Soft => "--soft",
+            ResetMode::Mixed => "--mixed",
+            ResetMode::Hard => "--hard",
+        }
+    }
+}
+
+/// Resets the checked out branch, or the detached HEAD, to a ref, like a
+/// release tag.
+///
+/// A hard reset refuses repositories with uncommitted changes, which it
+/// would lose, unless forced. Untracked files are kept in any case.
+pub struct ResetOperation {
+    target: String,
+    mode: ResetMode,
+    force: bool,
+}
+
+impl ResetOperation {
+    pub fn new(target: impl Into<String>, mode: ResetMode, force: bool) -> Self {
+        ResetOperation {
+            target: target.into(),
+            mode,
+            force,
+        }
+    }
+}
+
+impl GitOperation for ResetOperation {
+    fn name(&self) -> &str {
+        "reset"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let Ok(target) = repo.git([
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{}^{{commit}}", self.target),
+        ]) else {
+            return Err(Error::Skipped(format!("no {}", self.target)));
+        };
+        let mut discarded = 0;
+        if self.mode == ResetMode::Hard {
+            let counts = ChangeCounts::collect(&repo.open()?)?;
+            discarded = counts.staged + counts.modified + counts.renamed + counts.conflicted;
+            if discarded > 0 && !self.force {
+                return Err(Error::Skipped(format!(
+                    "{} uncommitted change(s), pass --force to discard them",
+                    discarded
+                )));
+            }
+        }
+        let before = repo.head_sha().ok();
+        repo.git(["reset", "--quiet", self.mode.flag(), &target])?;
+        let mut message = match before {
+            Some(before) if before == target => format!("at {} already", self.target),
+            Some(before) => format!(
+                "reset to {} ({}), was {}",
+                self.target,
+                &target[..7],
+                &before[..7]
+            ),
+            None => format!("reset to {} ({})", self.target, &target[..7]),
+        };
+        if discarded > 0 {
+            message.push_str(&format!(", {} change(s) discarded", discarded));
+        }
+        Ok(message)
+    }
+}
+
+/// Version string of the working tree, like `git describe --tags --dirty`.
+///
+/// Repositories without any tag are described by their abbreviated commit id.
+pub struct DescribeOperation;
+
+impl GitOperation for DescribeOperation {
+    fn name(&self) -> &str {
+        "describe"
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let git = repo.open()?;
+        let describe = git.describe(
+            DescribeOptions::new()
+                .describe_tags()
+                .show_commit_oid_as_fallback(true),
+        )?;
+        Ok(describe.format(Some(DescribeFormatOptions::new().dirty_suffix("-dirty")))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: [(&str, &str); 3] = [
+        ("{repo}", "api"),
+        ("{path}", "/ws/my api"),
+        ("{branch}", "x'{repo}\"$HOME"),
+    ];
+
+    #[test]
+    fn fills_every_placeholder_once() {
+        assert_eq!(
+            fill_placeholders("{repo}:{branch}", &VALUES, false),
+            "api:x'{repo}\"$HOME"
+        );
+        assert_eq!(
+            fill_placeholders("{unknown} {", &VALUES, false),
+            "{unknown} {"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn quotes_placeholders_for_where_they_stand_in_a_script() {
+        assert_eq!(
+            fill_placeholders("cd {path}", &VALUES, true),
+            "cd '/ws/my api'"
+        );
+        assert_eq!(
+            fill_placeholders(r#"cd "{path}""#, &VALUES, true),
+            r#"cd "/ws/my api""#
+        );
+        assert_eq!(
+            fill_placeholders("echo '{repo} {branch}'", &VALUES, true),
+            r#"echo 'api x'\''{repo}"$HOME'"#
+        );
+        assert_eq!(
+            fill_placeholders(r#"echo "{branch}" \"{repo}"#, &VALUES, true),
+            r#"echo "x'{repo}\"\$HOME" \"api"#
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn passes_values_as_they_are_through_the_shell() -> Result<()> {
+        let test = crate::testing::TestWorkspace::builder()
+            .repo("my api", crate::testing::RepoState::Clean)
+            .build()?;
+        let repo = test.repository("my api");
+        let path = repo.path().to_string_lossy().into_owned();
+        for script in [
+            "printf %s {path}",
+            r#"printf %s "{path}""#,
+            "printf %s '{path}'",
+        ] {
+            let operation = ExecOperation::new(vec![script.to_string()]).shell(true);
+            let output = operation.run(&repo)?;
+            assert_eq!(String::from_utf8_lossy(&output.stdout), path, "{}", script);
+        }
+        let operation =
+            ExecOperation::new(vec!["printf %s-%s {repo} '{branch}'".to_string()]).shell(true);
+        let output = operation.run(&repo)?;
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "my api-main");
+        Ok(())
+    }
+}
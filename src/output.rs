@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tabled::builder::Builder;
+use tabled::object::Segment;
+use tabled::{Alignment, Modify, Style, Table, Tabled};
+
+use crate::operations::{ExecOutput, OperationResult, OperationStatus};
+use crate::repository::{GitRepository, Rename, ScanOptions, Snapshot};
+use crate::Result;
+
+#[derive(Tabled)]
+struct ResultRow {
+    #[tabled(rename = "Repository")]
+    repo: String,
+    #[tabled(rename = "Result")]
+    status: String,
+    #[tabled(rename = "Message")]
+    message: String,
+}
+
+/// Renders rows with the table style shared by every command.
+pub fn render<T: Tabled>(rows: impl IntoIterator<Item = T>) -> String {
+    Table::new(rows)
+        .with(Style::rounded())
+        .with(Modify::new(Segment::all()).with(Alignment::left()))
+        .to_string()
+}
+
+/// Renders a table whose columns are only known at run time, with the
+/// style of [`render`].
+pub fn render_matrix(columns: Vec<String>, rows: Vec<Vec<String>>) -> String {
+    let mut builder = Builder::new().set_columns(columns);
+    for row in rows {
+        builder = builder.add_record(row);
+    }
+    builder
+        .build()
+        .with(Style::rounded())
+        .with(Modify::new(Segment::all()).with(Alignment::left()))
+        .to_string()
+}
+
+/// Whether results are written for the git-ws running this one over SSH,
+/// see [`crate::agent`].
+static AGENT_MODE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_agent_mode(enabled: bool) {
+    AGENT_MODE.store(enabled, Ordering::Relaxed);
+}
+
+/// A result as agent mode writes it, one JSON object per line.
+#[derive(Serialize, Deserialize)]
+struct ResultLine {
+    repo: String,
+    status: OperationStatus,
+    message: String,
+    duration_ms: u64,
+}
+
+/// The result written on `line` in agent mode, `None` for other output.
+pub fn parse_result_line(line: &str) -> Option<OperationResult> {
+    if !line.starts_with('{') {
+        return None;
+    }
+    let line: ResultLine = serde_json::from_str(line).ok()?;
+    Some(OperationResult {
+        repo: line.repo,
+        status: line.status,
+        message: line.message,
+        duration: Duration::from_millis(line.duration_ms),
+    })
+}
+
+/// Renders operation results, one row per repository, or one JSON line
+/// per repository in agent mode.
+pub fn results_table(results: &[OperationResult]) -> String {
+    if AGENT_MODE.load(Ordering::Relaxed) {
+        return results.iter().map(agent_line).collect();
+    }
+    render(results.iter().map(|result| ResultRow {
+        repo: result.repo.clone(),
+        status: result.status.to_string(),
+        message: result.message.clone(),
+    }))
+}
+
+fn agent_line(result: &OperationResult) -> String {
+    let line = ResultLine {
+        repo: result.repo.clone(),
+        status: result.status,
+        message: result.message.clone(),
+        duration_ms: result.duration.as_millis() as u64,
+    };
+    format!("{}\n", serde_json::to_string(&line).unwrap_or_default())
+}
+
+/// Whether results are printed as each repository is done rather than as
+/// a table at the end: on a terminal, and in agent mode.
+pub fn streams_results() -> bool {
+    AGENT_MODE.load(Ordering::Relaxed) || io::stdout().is_terminal()
+}
+
+/// Prints operation results one line each, as they come, aligned like the
+/// columns of a table.
+#[derive(Debug, Clone)]
+pub struct ResultStream {
+    width: usize,
+}
+
+impl ResultStream {
+    /// A stream for the results of the repositories named `names`.
+    pub fn new<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+        ResultStream {
+            width: names
+                .into_iter()
+                .map(|name| name.chars().count())
+                .max()
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn print(&self, result: &OperationResult) {
+        if AGENT_MODE.load(Ordering::Relaxed) {
+            print!("{}", agent_line(result));
+            return;
+        }
+        let prefix = format!(
+            "{:<7}  {:<width$}  ",
+            result.status.to_string(),
+            result.repo,
+            width = self.width
+        );
+        let indent = " ".repeat(prefix.chars().count());
+        let message = result.message.replace('\n', &format!("\n{}", indent));
+        println!("{}{}", prefix, message);
+    }
+}
+
+/// One repository as list and status report it, for templates and
+/// machine readable output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoRecord {
+    pub repo: String,
+    pub path: String,
+    /// Checked out branch, `None` when HEAD is detached.
+    pub branch: Option<String>,
+    pub head: Option<String>,
+    /// Short name of the upstream branch, like `origin/main`.
+    pub upstream: Option<String>,
+    pub ahead: usize,
+    pub behind: usize,
+    pub staged: usize,
+    pub modified: usize,
+    pub renamed: usize,
+    pub untracked: usize,
+    pub conflicted: usize,
+    pub ignored: usize,
+    pub dirty: bool,
+    pub pinned: bool,
+    pub renames: Vec<Rename>,
+    /// Ignored files and directories, when asked for.
+    pub ignored_paths: Vec<String>,
+}
+
+impl RepoRecord {
+    /// Captures `repo`, counting only the changes to paths matching
+    /// `pathspecs`, every path when there is none.
+    pub fn capture(
+        repo: &GitRepository,
+        pathspecs: &[String],
+        options: ScanOptions,
+    ) -> Result<Self> {
+        let snapshot = Snapshot::capture_with(&repo.open()?, pathspecs, options)?;
+        let changes = snapshot.changes;
+        let upstream = snapshot.upstream.as_ref();
+        Ok(RepoRecord {
+            repo: repo.name().to_string(),
+            path: repo.path().display().to_string(),
+            branch: snapshot.branch.clone(),
+            head: snapshot.head.clone(),
+            upstream: upstream.map(|upstream| upstream.name.clone()),
+            ahead: upstream.map_or(0, |upstream| upstream.ahead),
+            behind: upstream.map_or(0, |upstream| upstream.behind),
+            staged: changes.staged,
+            modified: changes.modified,
+            renamed: changes.renamed,
+            untracked: changes.untracked,
+            conflicted: changes.conflicted,
+            ignored: changes.ignored,
+            dirty: !changes.is_clean(),
+            pinned: false,
+            renames: snapshot.renames,
+            ignored_paths: snapshot.ignored,
+        })
+    }
+}
+
+/// The command exec ran in one repository, for `exec --json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecRecord {
+    pub repo: String,
+    /// `None` when the command did not run, or was killed by a signal.
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    /// What the command wrote on stderr, or why it did not run.
+    pub stderr: String,
+    pub duration_ms: u64,
+}
+
+/// A record of every result of exec, with the output `outputs` holds for
+/// its repository.
+pub fn exec_records(
+    results: &[OperationResult],
+    outputs: &mut BTreeMap<String, ExecOutput>,
+) -> Vec<ExecRecord> {
+    results
+        .iter()
+        .map(|result| {
+            let output = outputs.remove(&result.repo).unwrap_or_else(|| ExecOutput {
+                exit_code: None,
+                stdout: String::new(),
+                stderr: result.message.clone(),
+            });
+            ExecRecord {
+                repo: result.repo.clone(),
+                exit_code: output.exit_code,
+                stdout: output.stdout,
+                stderr: output.stderr,
+                duration_ms: result.duration.as_millis() as u64,
+            }
+        })
+        .collect()
+}
+
+/// Messages of the successful results keyed by repository, for the machine
+/// readable formats.
+pub fn results_map(results: &[OperationResult]) -> BTreeMap<String, String> {
+    results
+        .iter()
+        .filter(|result| result.is_success())
+        .map(|result| (result.repo.clone(), result.message.clone()))
+        .collect()
+}
+
+/// Environment variable name for a value of a repository, e.g.
+/// `REPO_TEAM_API_VERSION` for `team/api` and `VERSION`.
+pub fn env_var_name(repo: &str, suffix: &str) -> String {
+    let name: String = repo
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("REPO_{}_{}", name, suffix)
+}
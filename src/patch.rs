@@ -0,0 +1,232 @@
+//! Patch series spanning the workspace, to carry changes between
+//! workspaces without a shared remote.
+//!
+//! An export is a directory holding one directory per repository, named
+//! like the repository, with the patches `git format-patch` wrote:
+//!
+//! ```text
+//! patches/
+//!   api/0001-Add-the-endpoint.patch
+//!   team/client/0001-Call-the-endpoint.patch
+//!   team/client/0002-Document-the-call.patch
+//! ```
+//!
+//! With a cover letter, `0000-cover-letter.patch` at the top summarizes the
+//! series for mailing lists: the order to apply the repositories in and the
+//! commits and diffstat of each. Patch subjects then name their repository,
+//! as in `[PATCH api 1/2]`.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::mailmap::{self, Mailmap};
+use crate::manifest::Manifest;
+use crate::operations::GitOperation;
+use crate::repository::GitRepository;
+use crate::{Error, Result};
+
+/// File name of the cover letter, at the top of an export.
+pub const COVER_LETTER: &str = "0000-cover-letter.patch";
+
+/// The patches of every repository in the export at `dir`, by repository
+/// name, in the order they apply.
+pub fn series(dir: &Path) -> Result<BTreeMap<String, Vec<PathBuf>>> {
+    let mut series = BTreeMap::new();
+    collect(dir, dir, &mut series)?;
+    Ok(series)
+}
+
+fn collect(root: &Path, dir: &Path, series: &mut BTreeMap<String, Vec<PathBuf>>) -> Result<()> {
+    let mut patches = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect(root, &path, series)?;
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "patch")
+        {
+            patches.push(path);
+        }
+    }
+    // Patches at the top, like the cover letter, belong to no repository.
+    if !patches.is_empty() && dir != root {
+        patches.sort();
+        let name = dir
+            .strip_prefix(root)
+            .unwrap_or(dir)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        series.insert(name, patches);
+    }
+    Ok(())
+}
+
+/// Writes the commits made since a ref as patches below the export
+/// directory, which must be an absolute path.
+pub struct FormatPatchOperation {
+    since: String,
+    dir: PathBuf,
+    cover_letter: bool,
+}
+
+impl FormatPatchOperation {
+    pub fn new(since: impl Into<String>, dir: impl Into<PathBuf>) -> Self {
+        FormatPatchOperation {
+            since: since.into(),
+            dir: dir.into(),
+            cover_letter: false,
+        }
+    }
+
+    /// Numbers the patches and names the repository in their subject, to go
+    /// with a [`cover_letter`].
+    pub fn cover_letter(mut self, cover_letter: bool) -> Self {
+        self.cover_letter = cover_letter;
+        self
+    }
+}
+
+impl GitOperation for FormatPatchOperation {
+    fn name(&self) -> &str {
+        "format-patch"
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let range = format!("{}..HEAD", self.since);
+        let count = repo.git(["rev-list", "--count", &range])?;
+        if count == "0" {
+            return Err(Error::Skipped(format!("no commit since {}", self.since)));
+        }
+        let output = self.dir.join(repo.name());
+        let output = output.to_string_lossy();
+        let mut args = vec![
+            "format-patch".to_string(),
+            "--quiet".to_string(),
+            "-o".to_string(),
+            output.into_owned(),
+        ];
+        if self.cover_letter {
+            args.push("--numbered".to_string());
+            args.push(format!("--subject-prefix=PATCH {}", repo.name()));
+        }
+        args.push(range);
+        repo.git(&args)?;
+        Ok(format!("{} patch(es)", count))
+    }
+}
+
+/// A cover letter for the commits made since `since` in `repos`, as a
+/// `git format-patch` cover letter with the subject and blurb left to fill
+/// in. Repositories are listed dependencies first, following `manifest`,
+/// and authors mapped by the mailmap of the workspace too, when there is
+/// one.
+pub fn cover_letter(
+    repos: &[GitRepository],
+    since: &str,
+    manifest: &Manifest,
+    mailmap: Option<&Mailmap>,
+) -> Result<String> {
+    let repos = manifest.dependency_order(repos.to_vec(), |repo| repo.name())?;
+    let range = format!("{}..HEAD", since);
+    let mut total = 0;
+    let mut order = String::new();
+    let mut summaries = String::new();
+    for (i, repo) in repos.iter().enumerate() {
+        let count: usize = repo
+            .git(["rev-list", "--count", &range])?
+            .parse()
+            .unwrap_or_default();
+        total += count;
+        order.push_str(&format!(
+            "  {}. {} ({} patch(es))\n",
+            i + 1,
+            repo.name(),
+            count
+        ));
+        let mut shortlog = mailmap::git_args(repo, mailmap)?;
+        shortlog.extend(["shortlog".to_string(), range.clone()]);
+        let shortlog = repo.git(&shortlog)?;
+        let diffstat = repo.git(["diff", "--stat", "--summary", &format!("{}...HEAD", since)])?;
+        summaries.push_str(&format!(
+            "{}\n{}\n\n{}\n\n{}\n\n",
+            repo.name(),
+            "-".repeat(repo.name().len()),
+            shortlog.trim_end(),
+            diffstat
+        ));
+    }
+
+    let from = match repos.first() {
+        Some(repo) => author(repo)?,
+        None => String::new(),
+    };
+    Ok(format!(
+        "From git-ws Mon Sep 17 00:00:00 2001\n\
+         From: {}\n\
+         Subject: [PATCH 0/{}] *** SUBJECT HERE ***\n\
+         \n\
+         *** BLURB HERE ***\n\
+         \n\
+         This series spans {} repositories. Apply the patches of each\n\
+         repository in this order, or run `git-ws am <dir>` from the root of\n\
+         the receiving workspace:\n\
+         \n\
+         {}\n\
+         {}\
+         -- \n\
+         git-ws\n",
+        from,
+        total,
+        repos.len(),
+        order,
+        summaries
+    ))
+}
+
+/// The author of new commits in `repo`, as `Name <email>`.
+fn author(repo: &GitRepository) -> Result<String> {
+    let ident = repo.git(["var", "GIT_AUTHOR_IDENT"])?;
+    // The ident ends with the timestamp and the time zone.
+    let end = ident.rfind('>').map_or(ident.len(), |i| i + 1);
+    Ok(ident[..end].to_string())
+}
+
+/// Applies the patches exported for the repository with `git am`,
+/// aborting on the first one that does not apply.
+pub struct AmOperation {
+    series: BTreeMap<String, Vec<PathBuf>>,
+}
+
+impl AmOperation {
+    pub fn new(series: BTreeMap<String, Vec<PathBuf>>) -> Self {
+        AmOperation { series }
+    }
+}
+
+impl GitOperation for AmOperation {
+    fn name(&self) -> &str {
+        "am"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let Some(patches) = self.series.get(repo.name()) else {
+            return Err(Error::Skipped("no patches".to_string()));
+        };
+        let mut args: Vec<OsString> = vec!["am".into(), "--3way".into(), "--quiet".into()];
+        args.extend(patches.iter().map(|patch| patch.as_os_str().to_owned()));
+        if let Err(e) = repo.git(&args) {
+            let _ = repo.git(["am", "--abort"]);
+            return Err(Error::Operation(format!("{}, nothing applied", e)));
+        }
+        Ok(format!("applied {} patch(es)", patches.len()))
+    }
+}
@@ -0,0 +1,141 @@
+//! Pathspecs given to commands working on files, like `add`.
+//!
+//! A path inside a repository, relative to the workspace root or absolute,
+//! is translated to a pathspec of that repository only:
+//! `services/api/src/main.rs` is `src/main.rs` in `services/api`. Other
+//! pathspecs, like `src/` or `*.md`, apply to every repository as they are.
+//!
+//! Commands only run in the repositories the pathspecs touch, see
+//! [`Pathspecs::select`].
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path};
+
+use crate::repository::GitRepository;
+use crate::Result;
+
+/// Pathspecs resolved against the repositories of a workspace.
+#[derive(Debug, Clone, Default)]
+pub struct Pathspecs {
+    /// Pathspecs of every repository.
+    all: Vec<String>,
+    /// Pathspecs of one repository, relative to it.
+    repos: BTreeMap<String, Vec<String>>,
+    /// Repositories given as a whole.
+    whole: BTreeSet<String>,
+}
+
+impl Pathspecs {
+    /// `pathspecs` as they are, in every repository.
+    pub fn everywhere(pathspecs: Vec<String>) -> Self {
+        Pathspecs {
+            all: pathspecs,
+            ..Pathspecs::default()
+        }
+    }
+
+    /// Resolves `pathspecs` against `repos`, found below `root`.
+    pub fn resolve(pathspecs: Vec<String>, root: &Path, repos: &[GitRepository]) -> Self {
+        let mut resolved = Pathspecs::default();
+        for pathspec in pathspecs {
+            match owner(&pathspec, root, repos) {
+                Some((repo, None)) => {
+                    resolved.whole.insert(repo.to_string());
+                }
+                Some((repo, Some(relative))) => resolved
+                    .repos
+                    .entry(repo.to_string())
+                    .or_default()
+                    .push(relative),
+                None => resolved.all.push(pathspec),
+            }
+        }
+        resolved
+    }
+
+    /// The pathspecs of `repo`, an empty list meaning every path, or `None`
+    /// when every pathspec belongs to other repositories.
+    pub fn for_repo(&self, repo: &str) -> Option<Vec<String>> {
+        if self.whole.contains(repo) {
+            return Some(Vec::new());
+        }
+        let own = self.repos.get(repo);
+        let owned_elsewhere = !self.repos.is_empty() || !self.whole.is_empty();
+        if own.is_none() && self.all.is_empty() && owned_elsewhere {
+            return None;
+        }
+        let mut pathspecs = self.all.clone();
+        pathspecs.extend(own.into_iter().flatten().cloned());
+        Some(pathspecs)
+    }
+
+    /// The repositories of `repos` the pathspecs touch: owning a path, or
+    /// having a tracked or untracked file matching a pathspec given for
+    /// every repository. Without pathspecs, every repository.
+    pub fn select(&self, repos: Vec<GitRepository>) -> Result<Vec<GitRepository>> {
+        let mut selected = Vec::new();
+        for repo in repos {
+            let Some(pathspecs) = self.for_repo(repo.name()) else {
+                continue;
+            };
+            if self.whole.contains(repo.name())
+                || pathspecs.is_empty()
+                || matches(&repo, &pathspecs)?
+            {
+                selected.push(repo);
+            }
+        }
+        Ok(selected)
+    }
+}
+
+/// Whether a tracked or untracked file of `repo` matches `pathspecs`.
+fn matches(repo: &GitRepository, pathspecs: &[String]) -> Result<bool> {
+    let mut args = vec![
+        "ls-files".to_string(),
+        "--cached".to_string(),
+        "--others".to_string(),
+        "--exclude-standard".to_string(),
+        "--".to_string(),
+    ];
+    args.extend(pathspecs.iter().cloned());
+    Ok(!repo.git(&args)?.is_empty())
+}
+
+/// The repository `pathspec` is a path inside of, with the path relative
+/// to it, `None` for the repository itself. Magic pathspecs, like
+/// `:(glob)*.rs`, belong to no repository.
+fn owner<'a>(
+    pathspec: &str,
+    root: &Path,
+    repos: &'a [GitRepository],
+) -> Option<(&'a str, Option<String>)> {
+    if pathspec.starts_with(':') {
+        return None;
+    }
+    let path = Path::new(pathspec);
+    let path = if path.is_absolute() {
+        path.strip_prefix(root).ok()?
+    } else {
+        path
+    };
+    let components: Vec<_> = path
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect();
+    // The deepest repository first, in case one holds another.
+    repos
+        .iter()
+        .filter_map(|repo| {
+            let name: Vec<_> = repo.name().split('/').collect();
+            let inside =
+                components.len() >= name.len() && components.iter().zip(&name).all(|(a, b)| a == b);
+            inside.then(|| (repo.name(), name.len()))
+        })
+        .max_by_key(|(_, depth)| *depth)
+        .map(|(name, depth)| {
+            let relative = components[depth..].join("/");
+            (name, Some(relative).filter(|relative| !relative.is_empty()))
+        })
+}
@@ -0,0 +1,351 @@
+//! Plans: mutating batches reviewed before they run.
+//!
+//! With `--plan plan.json`, a mutating command changes nothing and writes
+//! the plan instead: the command and, for every repository it would change,
+//! the operation, the checked out branch, HEAD and a fingerprint of the
+//! working tree. `git-ws apply plan.json` runs the command again, only on
+//! those repositories, and refuses when any of them changed in between.
+//!
+//! Plans are signed with HMAC-SHA256 when a key is configured, and only
+//! signed plans are applied then:
+//!
+//! ```toml
+//! [plan]
+//! key = { env = "GIT_WS_PLAN_KEY" }
+//! # Refuse unsigned plans, and every plan when no key is configured.
+//! require_signature = true
+//! ```
+//!
+//! The key is what makes a plan trusted. Without `require_signature`, a
+//! machine with no key configured applies unsigned plans, trusting
+//! whoever wrote the file, and refuses signed ones, whose signature it
+//! cannot check. Review processes relying on the signature should set
+//! `require_signature` wherever plans are applied, so that a missing key
+//! refuses plans instead of accepting any of them.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::config::TokenSource;
+use crate::middleware::{Middleware, Next};
+use crate::operations::GitOperation;
+use crate::precondition::{self, Precondition, Preconditions};
+use crate::repository::GitRepository;
+use crate::workspace::Workspace;
+use crate::{Error, Result};
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PlanConfig {
+    /// Key signing and verifying plans.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<TokenSource>,
+    /// Only apply signed plans, failing when no key is configured.
+    #[serde(default)]
+    pub require_signature: bool,
+}
+
+impl PlanConfig {
+    /// The configured key, read from its source.
+    pub fn key(&self) -> Result<Option<Zeroizing<String>>> {
+        self.key.as_ref().map(TokenSource::resolve).transpose()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    /// Arguments following `git-ws`, as for a recorded
+    /// [`Step`](crate::session::Step).
+    pub args: Vec<String>,
+    /// Seconds since the epoch.
+    pub created_at: u64,
+    pub repositories: Vec<PlannedRepo>,
+    /// HMAC-SHA256 of the rest of the plan, hex encoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// A repository as it was when planned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedRepo {
+    pub repo: String,
+    pub operation: String,
+    pub branch: Option<String>,
+    pub head: String,
+    /// SHA-256 of the changes in the working tree and the index.
+    pub worktree: String,
+}
+
+impl PlannedRepo {
+    pub fn capture(repo: &GitRepository, operation: &str) -> Result<Self> {
+        Ok(PlannedRepo {
+            repo: repo.name().to_string(),
+            operation: operation.to_string(),
+            branch: repo.current_branch()?,
+            head: repo.head_sha()?,
+            worktree: precondition::fingerprint(repo)?,
+        })
+    }
+
+    /// The precondition of applying the plan to the repository: the same
+    /// branch, HEAD and working tree.
+    pub fn precondition(&self) -> Precondition {
+        Precondition {
+            branch: self.branch.clone(),
+            head: Some(self.head.clone()),
+            worktree: Some(self.worktree.clone()),
+            ..Precondition::default()
+        }
+    }
+}
+
+impl Plan {
+    pub fn new(args: Vec<String>) -> Self {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        Plan {
+            args,
+            created_at,
+            repositories: Vec::new(),
+            signature: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Signs the plan with `key`, when given, and writes it to `path`.
+    pub fn save(&mut self, path: &Path, key: Option<&str>) -> Result<()> {
+        self.signature = key.map(|key| self.sign(key)).transpose()?;
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Checks the signature against the key of `config`, in constant time.
+    /// Without a key, only unsigned plans are accepted, since a signature
+    /// could not be checked, and none when a signature is required.
+    pub fn verify(&self, config: &PlanConfig) -> Result<()> {
+        let key = config.key()?;
+        match (key, &self.signature) {
+            (Some(key), Some(signature)) => {
+                let matches = unhex(signature).is_some_and(|signature| {
+                    self.mac(&key)
+                        .is_ok_and(|mac| mac.verify_slice(&signature).is_ok())
+                });
+                if matches {
+                    return Ok(());
+                }
+                Err(Error::Operation(
+                    "the plan signature does not match, it was changed or signed with another key"
+                        .to_string(),
+                ))
+            }
+            (None, _) if config.require_signature => Err(Error::Operation(
+                "plans must be signed but no plan key is configured".to_string(),
+            )),
+            (Some(_), None) => Err(Error::Operation("the plan is not signed".to_string())),
+            (None, Some(_)) => Err(Error::Operation(
+                "the plan is signed but no plan key is configured".to_string(),
+            )),
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// Repositories of the plan that changed since, with how.
+    pub fn drift(&self, workspace: &Workspace) -> Result<Vec<(String, String)>> {
+        let mut drift = Vec::new();
+        for planned in &self.repositories {
+            let reason = match workspace.find_repository(&planned.repo) {
+                Ok(repo) => planned.precondition().check(&repo)?,
+                Err(_) => Some("not in the workspace".to_string()),
+            };
+            if let Some(reason) = reason {
+                drift.push((planned.repo.clone(), reason));
+            }
+        }
+        Ok(drift)
+    }
+
+    /// Preconditions the executor checks right before changing each
+    /// repository, as it may still change after [`Plan::drift`].
+    pub fn preconditions(&self) -> Preconditions {
+        Preconditions {
+            repos: self
+                .repositories
+                .iter()
+                .map(|planned| (planned.repo.clone(), planned.precondition()))
+                .collect(),
+            ..Preconditions::default()
+        }
+    }
+
+    fn sign(&self, key: &str) -> Result<String> {
+        Ok(hex(&self.mac(key)?.finalize().into_bytes()))
+    }
+
+    /// The HMAC of the plan without its signature.
+    fn mac(&self, key: &str) -> Result<Hmac<Sha256>> {
+        let unsigned = Plan {
+            signature: None,
+            ..self.clone()
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+            .map_err(|e| Error::Operation(format!("invalid plan key: {}", e)))?;
+        mac.update(serde_json::to_string(&unsigned)?.as_bytes());
+        Ok(mac)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Writes a plan of the mutating operations instead of running them.
+/// Operations reading the repositories still run.
+pub struct Planner {
+    path: PathBuf,
+    key: Option<Zeroizing<String>>,
+    plan: Mutex<Plan>,
+}
+
+impl Planner {
+    /// Starts the plan at `path`, for the command run with `args`.
+    pub fn new(
+        path: impl Into<PathBuf>,
+        args: Vec<String>,
+        key: Option<Zeroizing<String>>,
+    ) -> Result<Self> {
+        let path = path.into();
+        let mut plan = Plan::new(args);
+        plan.save(&path, key.as_ref().map(|key| key.as_str()))?;
+        Ok(Planner {
+            path,
+            key,
+            plan: Mutex::new(plan),
+        })
+    }
+}
+
+impl Middleware for Planner {
+    fn call(
+        &self,
+        repo: &GitRepository,
+        operation: &dyn GitOperation,
+        next: Next<'_>,
+    ) -> Result<String> {
+        if !operation.is_mutating() {
+            return next.run(repo);
+        }
+        let planned = PlannedRepo::capture(repo, operation.name())?;
+        let mut plan = self.plan.lock().unwrap_or_else(|e| e.into_inner());
+        plan.repositories.push(planned);
+        plan.repositories.sort_by(|a, b| a.repo.cmp(&b.repo));
+        plan.save(&self.path, self.key.as_ref().map(|key| key.as_str()))?;
+        Err(Error::Skipped(format!("planned {}", operation.name())))
+    }
+}
+
+/// Lets mutating operations run only on the repositories of a plan.
+pub struct PlanGuard {
+    planned: BTreeMap<String, PlannedRepo>,
+}
+
+impl PlanGuard {
+    pub fn new(plan: &Plan) -> Self {
+        PlanGuard {
+            planned: plan
+                .repositories
+                .iter()
+                .map(|planned| (planned.repo.clone(), planned.clone()))
+                .collect(),
+        }
+    }
+}
+
+impl Middleware for PlanGuard {
+    fn call(
+        &self,
+        repo: &GitRepository,
+        operation: &dyn GitOperation,
+        next: Next<'_>,
+    ) -> Result<String> {
+        if operation.is_mutating() && !self.planned.contains_key(repo.name()) {
+            return Err(Error::Skipped("not in the plan".to_string()));
+        }
+        next.run(repo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(key: Option<&str>, require_signature: bool) -> PlanConfig {
+        PlanConfig {
+            key: key.map(|key| {
+                let var = format!("GIT_WS_TEST_PLAN_KEY_{}", key.to_uppercase());
+                std::env::set_var(&var, key);
+                TokenSource::Env(var)
+            }),
+            require_signature,
+        }
+    }
+
+    fn plan(key: Option<&str>) -> Plan {
+        let mut plan = Plan::new(vec!["pull".to_string()]);
+        plan.signature = key.map(|key| plan.sign(key).unwrap());
+        plan
+    }
+
+    #[test]
+    fn applies_plans_signed_with_the_key() {
+        let keyed = config(Some("secret"), false);
+        assert!(plan(Some("secret")).verify(&keyed).is_ok());
+        assert!(plan(Some("other")).verify(&keyed).is_err());
+        assert!(plan(None).verify(&keyed).is_err());
+        let mut changed = plan(Some("secret"));
+        changed.args.push("--rebase".to_string());
+        assert!(changed.verify(&keyed).is_err());
+        let mut garbled = plan(None);
+        garbled.signature = Some("zz".to_string());
+        assert!(garbled.verify(&keyed).is_err());
+    }
+
+    #[test]
+    fn requires_a_key_when_asked_to() {
+        assert!(plan(None).verify(&config(None, false)).is_ok());
+        assert!(plan(Some("secret")).verify(&config(None, false)).is_err());
+        let error = plan(None).verify(&config(None, true)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "plans must be signed but no plan key is configured"
+        );
+    }
+
+    #[test]
+    fn reads_hex_back() {
+        assert_eq!(unhex(&hex(&[0, 15, 255])), Some(vec![0, 15, 255]));
+        assert_eq!(unhex("abc"), None);
+        assert_eq!(unhex("zz"), None);
+    }
+}
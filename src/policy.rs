@@ -0,0 +1,290 @@
+//! Approval of mutating batches by an external policy service, for
+//! change-management processes.
+//!
+//! Before a command changing repositories runs, git-ws posts the command
+//! and the branch, upstream, HEAD and ref named by the command of every
+//! repository it may change to the configured URL, and runs the command
+//! only when the service approves:
+//!
+//! ```toml
+//! [policy]
+//! url = "https://change.example.com/api/git-ws"
+//! token = { env = "GIT_WS_POLICY_TOKEN" }
+//! # Only ask when a repository is on one of these branches, tracks one or
+//! # the command names one.
+//! branches = ["release/*"]
+//! ```
+//!
+//! The request body is like
+//! `{"command": ["merge", "release/2.1"], "repositories": [{"repo": "api",
+//! "branch": "main", "upstream": "refs/remotes/origin/main", "head":
+//! "2f8a17b...", "target": "refs/heads/release/2.1"}]}`, and the service answers
+//! `{"approved": true}`, or `{"approved": false, "reason": "..."}`. Anything
+//! else, an unreachable service included, refuses the batch.
+
+use std::time::Duration;
+
+use git2::BranchType;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::config::TokenSource;
+use crate::repository::GitRepository;
+use crate::{Error, Result};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PolicyConfig {
+    /// URL the batches are posted to, none asked for when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Bearer token sent along.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<TokenSource>,
+    /// Branch patterns, `*` matching anything, the service is only asked
+    /// about when a repository is on, tracks or is told to change one of
+    /// them; always when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub branches: Vec<String>,
+}
+
+impl PolicyConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.url.is_some()
+    }
+
+    fn concerns(&self, repo: &PolicyRepo) -> bool {
+        if self.branches.is_empty() {
+            return true;
+        }
+        [&repo.branch, &repo.upstream, &repo.target]
+            .into_iter()
+            .flatten()
+            .map(|name| branch_name(name))
+            .any(|branch| {
+                self.branches.iter().any(|pattern| {
+                    let pattern = format!("^{}$", regex::escape(pattern).replace(r"\*", ".*"));
+                    Regex::new(&pattern).is_ok_and(|pattern| pattern.is_match(branch))
+                })
+            })
+    }
+}
+
+/// What the policy service is asked to approve.
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyRequest {
+    /// Arguments following `git-ws`.
+    pub command: Vec<String>,
+    pub repositories: Vec<PolicyRepo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyRepo {
+    pub repo: String,
+    /// Checked out branch, `None` when HEAD is detached.
+    pub branch: Option<String>,
+    /// Full name of the ref the branch tracks, like
+    /// `refs/remotes/origin/main`.
+    pub upstream: Option<String>,
+    pub head: Option<String>,
+    /// Full name of the ref the command names, like the branch merged or
+    /// the commit reset to, as given when it does not resolve to a ref.
+    pub target: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PolicyResponse {
+    approved: bool,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// Asks the policy service whether the command `args`, naming the ref
+/// `target`, may change `repos`, failing unless it approves. Nothing is
+/// asked when no policy is configured, or no repository concerns it.
+pub fn check(
+    config: &PolicyConfig,
+    args: Vec<String>,
+    repos: &[GitRepository],
+    target: Option<&str>,
+) -> Result<()> {
+    let Some(url) = &config.url else {
+        return Ok(());
+    };
+    let mut repositories = Vec::new();
+    for repo in repos {
+        let branch = repo.current_branch()?;
+        let git = repo.open()?;
+        let upstream = branch
+            .as_deref()
+            .and_then(|branch| git.find_branch(branch, BranchType::Local).ok())
+            .and_then(|branch| branch.upstream().ok())
+            .and_then(|upstream| upstream.get().name().map(str::to_string));
+        let target = target.map(|target| match git.revparse_ext(target) {
+            Ok((_, Some(reference))) => reference.name().unwrap_or(target).to_string(),
+            _ => target.to_string(),
+        });
+        repositories.push(PolicyRepo {
+            repo: repo.name().to_string(),
+            branch,
+            upstream,
+            head: repo.head_sha().ok(),
+            target,
+        });
+    }
+    if !repositories.iter().any(|repo| config.concerns(repo)) {
+        return Ok(());
+    }
+    let request = PolicyRequest {
+        command: args,
+        repositories,
+    };
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+    let mut call = agent.post(url).set("User-Agent", "git-ws");
+    if let Some(token) = &config.token {
+        call = call.set(
+            "Authorization",
+            &format!("Bearer {}", token.resolve()?.trim()),
+        );
+    }
+    let response = call.send_json(&request).map_err(|e| match e {
+        ureq::Error::Status(code, _) => {
+            Error::Operation(format!("the policy service answered {}, refusing", code))
+        }
+        ureq::Error::Transport(transport) => Error::Operation(format!(
+            "the policy service is unreachable, refusing: {}",
+            transport
+        )),
+    })?;
+    let response: PolicyResponse = response.into_json().map_err(|_| {
+        Error::Operation("the policy service gave no decision, refusing".to_string())
+    })?;
+    if response.approved {
+        return Ok(());
+    }
+    Err(Error::Operation(format!(
+        "refused by the policy service: {}",
+        response.reason.as_deref().unwrap_or("no reason given")
+    )))
+}
+
+/// The branch `name` is or tracks, `name` itself when it is no branch.
+fn branch_name(name: &str) -> &str {
+    if let Some(branch) = name.strip_prefix("refs/heads/") {
+        return branch;
+    }
+    match name.strip_prefix("refs/remotes/") {
+        Some(remote) => remote.split_once('/').map_or(remote, |(_, branch)| branch),
+        None => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{RepoState, TestWorkspace};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    /// A policy service answering `answer` to one request, handing back the
+    /// body it was posted.
+    fn stub(answer: &'static str) -> (PolicyConfig, JoinHandle<serde_json::Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = PolicyConfig {
+            url: Some(format!("http://{}/", listener.local_addr().unwrap())),
+            ..Default::default()
+        };
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let mut stream = stream;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                answer.len(),
+                answer
+            )
+            .unwrap();
+            serde_json::from_slice(&body).unwrap()
+        });
+        (config, server)
+    }
+
+    #[test]
+    fn posts_the_refs_of_every_repository() -> Result<()> {
+        let test = TestWorkspace::builder()
+            .repo("api", RepoState::Clean)
+            .build()?;
+        let (config, server) = stub(r#"{"approved": true}"#);
+        let args = vec!["merge".to_string(), "main".to_string()];
+        check(&config, args, &[test.repository("api")], Some("main"))?;
+        let body = server.join().unwrap();
+        assert_eq!(body["command"], serde_json::json!(["merge", "main"]));
+        let repo = &body["repositories"][0];
+        assert_eq!(repo["repo"], "api");
+        assert_eq!(repo["branch"], "main");
+        assert_eq!(repo["upstream"], "refs/remotes/origin/main");
+        assert_eq!(repo["target"], "refs/heads/main");
+        assert_eq!(repo["head"].as_str().map(str::len), Some(40));
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_with_the_reason_of_the_service() -> Result<()> {
+        let test = TestWorkspace::builder()
+            .repo("api", RepoState::Clean)
+            .build()?;
+        let (config, server) = stub(r#"{"approved": false, "reason": "frozen"}"#);
+        let err = check(
+            &config,
+            vec!["push".to_string()],
+            &[test.repository("api")],
+            None,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("frozen"), "{}", err);
+        server.join().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn asks_about_the_branches_named_by_the_command() -> Result<()> {
+        let test = TestWorkspace::builder()
+            .repo("api", RepoState::Clean)
+            .build()?;
+        let repos = [test.repository("api")];
+        // Nothing listens there: asking would fail.
+        let unasked = PolicyConfig {
+            url: Some("http://127.0.0.1:1/".to_string()),
+            branches: vec!["release/*".to_string()],
+            ..Default::default()
+        };
+        check(&unasked, vec!["push".to_string()], &repos, None)?;
+        let err = check(
+            &unasked,
+            vec!["merge".to_string()],
+            &repos,
+            Some("release/2.1"),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("unreachable"), "{}", err);
+        Ok(())
+    }
+}
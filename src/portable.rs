@@ -0,0 +1,347 @@
+//! Moving a workspace to another machine as one file.
+//!
+//! `state export` writes a zstd compressed tar holding the manifest, the
+//! lockfile, the configuration and the journal of the workspace, the branch
+//! and commit every repository is on, and, when asked for, their local
+//! changes as patches. A workspace without a manifest gets one made from
+//! the `origin` remotes. `state import` writes those files in another
+//! workspace, clones the repositories, see [`crate::sync`], and puts them
+//! back on their branch and commit with [`RestoreOperation`].
+//!
+//! The configuration only names where tokens are read from, so the file
+//! holds no secret.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::interactive;
+use crate::lockfile::LOCK_FILE;
+use crate::manifest::{Manifest, ManifestRepository, MANIFEST_FILE};
+use crate::operations::GitOperation;
+use crate::recover::JOURNAL_FILE;
+use crate::repository::{ChangeCounts, GitRepository};
+use crate::workspace::{Workspace, STATE_DIR};
+use crate::{Error, Result};
+
+const CONFIG_FILE: &str = "config.toml";
+const HEADS_FILE: &str = "heads.json";
+const CHANGES_DIR: &str = "changes";
+
+/// Where a repository was when the workspace was exported.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Head {
+    /// `None` on a detached HEAD.
+    pub branch: Option<String>,
+    pub sha: String,
+}
+
+/// What an export holds.
+#[derive(Debug, Default)]
+pub struct Exported {
+    pub repos: usize,
+    /// Repositories whose local changes were exported.
+    pub changes: Vec<String>,
+    /// Repositories left out of a made up manifest, having no origin.
+    pub left_out: Vec<String>,
+}
+
+/// Exports the workspace and its `repos` to `path`, with their local
+/// changes when `changes` is set.
+pub fn export(
+    workspace: &Workspace,
+    repos: &[GitRepository],
+    path: &Path,
+    changes: bool,
+) -> Result<Exported> {
+    let mut exported = Exported::default();
+    let mut files: Vec<(String, Vec<u8>)> = Vec::new();
+    let manifest_path = workspace.root().join(MANIFEST_FILE);
+    if manifest_path.is_file() {
+        files.push((MANIFEST_FILE.to_string(), fs::read(manifest_path)?));
+    } else {
+        let mut manifest = Manifest::default();
+        for repo in repos {
+            let git = repo.open()?;
+            let url = match git.find_remote("origin") {
+                Ok(remote) => remote.url().map(str::to_string),
+                Err(_) => None,
+            };
+            let Some(url) = url else {
+                exported.left_out.push(repo.name().to_string());
+                continue;
+            };
+            manifest.repositories.push(ManifestRepository {
+                path: repo.name().to_string(),
+                url,
+                branch: None,
+                depends_on: Vec::new(),
+            });
+        }
+        let manifest = toml::to_string_pretty(&manifest)?;
+        files.push((MANIFEST_FILE.to_string(), manifest.into_bytes()));
+    }
+    let lock_path = workspace.root().join(LOCK_FILE);
+    if lock_path.is_file() {
+        files.push((LOCK_FILE.to_string(), fs::read(lock_path)?));
+    }
+    for name in [CONFIG_FILE, JOURNAL_FILE] {
+        let path = workspace.state_dir().join(name);
+        if path.is_file() {
+            files.push((format!("{}/{}", STATE_DIR, name), fs::read(path)?));
+        }
+    }
+    let mut heads = BTreeMap::new();
+    for repo in repos {
+        let Ok(sha) = repo.head_sha() else {
+            continue;
+        };
+        let head = Head {
+            branch: repo.current_branch()?,
+            sha,
+        };
+        heads.insert(repo.name().to_string(), head);
+        if changes {
+            if let Some(patch) = local_changes(repo)? {
+                let name = format!("{}/{}.patch", CHANGES_DIR, repo.name());
+                files.push((name, patch.into_bytes()));
+                exported.changes.push(repo.name().to_string());
+            }
+        }
+    }
+    exported.repos = heads.len();
+    files.push((HEADS_FILE.to_string(), serde_json::to_vec_pretty(&heads)?));
+
+    let encoder = zstd::Encoder::new(File::create(path)?, 0)?;
+    let mut archive = tar::Builder::new(encoder);
+    for (name, contents) in &files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        archive.append_data(&mut header, name, contents.as_slice())?;
+    }
+    archive.into_inner()?.finish()?.flush()?;
+    Ok(exported)
+}
+
+/// The changes of `repo` since HEAD, untracked files included, as a binary
+/// patch. The index of the repository is left alone.
+fn local_changes(repo: &GitRepository) -> Result<Option<String>> {
+    if ChangeCounts::collect(&repo.open()?)?.is_clean() {
+        return Ok(None);
+    }
+    let index = repo.open()?.path().join("git-ws-export-index");
+    let result = (|| {
+        git_with_index(repo, &index, &["read-tree", "HEAD"])?;
+        git_with_index(repo, &index, &["add", "--all"])?;
+        git_with_index(repo, &index, &["diff", "--cached", "--binary", "HEAD"])
+    })();
+    let _ = fs::remove_file(&index);
+    let patch = result?;
+    Ok(if patch.is_empty() { None } else { Some(patch) })
+}
+
+fn git_with_index(repo: &GitRepository, index: &Path, args: &[&str]) -> Result<String> {
+    let output = interactive::command("git")
+        .args(args)
+        .current_dir(repo.path())
+        .env("GIT_INDEX_FILE", index)
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::Operation(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// What an import brought in.
+#[derive(Debug, Default)]
+pub struct Imported {
+    pub heads: BTreeMap<String, Head>,
+    /// Local changes, by repository.
+    pub patches: BTreeMap<String, String>,
+    /// Files written in the workspace.
+    pub written: Vec<String>,
+}
+
+/// Writes the files of the export at `path` in the workspace at `root`.
+/// Files the workspace has already are only replaced when `force`d, but
+/// for the journal, whose entries are added to the existing ones.
+pub fn import(root: &Path, path: &Path, force: bool) -> Result<Imported> {
+    let mut archive = tar::Archive::new(zstd::Decoder::new(File::open(path)?)?);
+    let mut imported = Imported::default();
+    let mut files = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        files.push((name, contents));
+    }
+    let journal = format!("{}/{}", STATE_DIR, JOURNAL_FILE);
+    // Only these are written, wherever else the archive wants files.
+    let written = [
+        MANIFEST_FILE.to_string(),
+        LOCK_FILE.to_string(),
+        format!("{}/{}", STATE_DIR, CONFIG_FILE),
+        journal.clone(),
+    ];
+    // Nothing is written until every file is known to fit.
+    for (name, contents) in &files {
+        let target = root.join(name);
+        let replaced = written.contains(name) && *name != journal;
+        if replaced && !force && target.is_file() && fs::read(&target)? != *contents {
+            return Err(Error::Operation(format!(
+                "{} exists and differs, use --force to replace it",
+                name
+            )));
+        }
+    }
+    for (name, contents) in files {
+        if name == HEADS_FILE {
+            imported.heads = serde_json::from_slice(&contents)?;
+            continue;
+        }
+        if let Some(patch) = name.strip_prefix(&format!("{}/", CHANGES_DIR)) {
+            let repo = patch.trim_end_matches(".patch").to_string();
+            let patch = String::from_utf8_lossy(&contents).into_owned();
+            imported.patches.insert(repo, patch);
+            continue;
+        }
+        if !written.contains(&name) {
+            continue;
+        }
+        let target = root.join(&name);
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        if name == journal && target.is_file() {
+            let existing = fs::read_to_string(&target)?;
+            let contents = String::from_utf8_lossy(&contents);
+            let added: Vec<_> = contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .filter(|line| !existing.lines().any(|known| known == *line))
+                .collect();
+            if added.is_empty() {
+                continue;
+            }
+            let mut file = fs::OpenOptions::new().append(true).open(&target)?;
+            for line in added {
+                writeln!(file, "{}", line)?;
+            }
+        } else if fs::read(&target).ok().as_ref() != Some(&contents) {
+            fs::write(&target, contents)?;
+        } else {
+            continue;
+        }
+        imported.written.push(name);
+    }
+    Ok(imported)
+}
+
+/// Puts a repository back on the branch and commit it was exported on, and
+/// applies its exported local changes.
+pub struct RestoreOperation {
+    heads: BTreeMap<String, Head>,
+    patches: BTreeMap<String, String>,
+}
+
+impl RestoreOperation {
+    pub fn new(imported: &Imported) -> Self {
+        RestoreOperation {
+            heads: imported.heads.clone(),
+            patches: imported.patches.clone(),
+        }
+    }
+
+    fn has_commit(repo: &GitRepository, sha: &str) -> bool {
+        repo.git(["cat-file", "-e", &format!("{}^{{commit}}", sha)])
+            .is_ok()
+    }
+}
+
+impl GitOperation for RestoreOperation {
+    fn name(&self) -> &str {
+        "import"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let Some(head) = self.heads.get(repo.name()) else {
+            return Err(Error::Skipped("not in the exported state".to_string()));
+        };
+        let git = repo.open()?;
+        if !ChangeCounts::collect(&git)?.is_clean() {
+            return Err(Error::Warning(
+                "local changes, left where it is".to_string(),
+            ));
+        }
+        if !Self::has_commit(repo, &head.sha) {
+            let _ = repo.git(["fetch", "--quiet", "origin"]);
+        }
+        let have = Self::has_commit(repo, &head.sha);
+        let short = &head.sha[..7];
+        let on = match &head.branch {
+            Some(branch) => {
+                let local = git.find_branch(branch, git2::BranchType::Local).is_ok();
+                let remote = format!("origin/{}", branch);
+                if local {
+                    repo.git(["checkout", "--quiet", branch])?;
+                } else if have {
+                    repo.git(["checkout", "--quiet", "-b", branch, &head.sha])?;
+                    let _ = repo.git(["branch", "--quiet", "--set-upstream-to", &remote]);
+                } else if repo
+                    .git(["rev-parse", "--verify", "--quiet", &remote])
+                    .is_ok()
+                {
+                    repo.git(["checkout", "--quiet", "-b", branch, "--track", &remote])?;
+                } else {
+                    return Err(Error::Operation(format!(
+                        "neither {} nor commit {} is on the remote, push them from the \
+                         other machine",
+                        branch, short
+                    )));
+                }
+                if have && repo.head_sha()? != head.sha {
+                    repo.git(["reset", "--quiet", "--keep", &head.sha])?;
+                }
+                format!("on {}@{}", branch, short)
+            }
+            None if have => {
+                repo.git(["checkout", "--quiet", "--detach", &head.sha])?;
+                format!("detached at {}", short)
+            }
+            None => {
+                return Err(Error::Operation(format!(
+                    "commit {} is not on the remote",
+                    short
+                )))
+            }
+        };
+        let mut message = on;
+        if let Some(patch) = self.patches.get(repo.name()) {
+            let path = git.path().join("git-ws-import.patch");
+            fs::write(&path, patch)?;
+            let applied = repo.git(["apply", "--whitespace=nowarn", &path.to_string_lossy()]);
+            let _ = fs::remove_file(&path);
+            applied?;
+            message.push_str(", local changes restored");
+        }
+        if !have {
+            return Err(Error::Warning(format!(
+                "{}; commit {} is not on the remote, push it from the other machine",
+                message, short
+            )));
+        }
+        Ok(message)
+    }
+}
@@ -0,0 +1,190 @@
+//! What a repository must look like for a mutating operation to change it.
+//!
+//! Plans and scripts are written against the workspace as it was. The
+//! executor checks their preconditions right before each mutating
+//! operation, and skips repositories that no longer match instead of
+//! changing them:
+//!
+//! ```text
+//! git-ws --expect api=main@4f2a9c1 --expect-clean commit -m "Release"
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::repository::{ChangeCounts, GitRepository};
+use crate::{Error, Result};
+
+/// Expected state of one repository. Unset fields are not checked.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Precondition {
+    /// Branch that must be checked out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Commit HEAD must point to, possibly abbreviated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub head: Option<String>,
+    /// The working tree and the index must have no change.
+    #[serde(default)]
+    pub clean: bool,
+    /// [`fingerprint`] the working tree must have.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worktree: Option<String>,
+}
+
+impl Precondition {
+    /// Parses `REPO=BRANCH@SHA`, where either the branch or the `@SHA` may
+    /// be left out.
+    pub fn parse(spec: &str) -> Result<(String, Self)> {
+        let invalid = || {
+            Error::Operation(format!(
+                "invalid precondition '{}', expected REPO=BRANCH@SHA",
+                spec
+            ))
+        };
+        let (repo, expected) = spec.split_once('=').ok_or_else(invalid)?;
+        let (branch, head) = match expected.split_once('@') {
+            Some((branch, head)) => (branch, Some(head)),
+            None => (expected, None),
+        };
+        if repo.is_empty() || (branch.is_empty() && head.is_none_or(str::is_empty)) {
+            return Err(invalid());
+        }
+        let precondition = Precondition {
+            branch: Some(branch)
+                .filter(|branch| !branch.is_empty())
+                .map(str::to_string),
+            head: head
+                .filter(|head| !head.is_empty())
+                .map(str::to_ascii_lowercase),
+            ..Precondition::default()
+        };
+        Ok((repo.to_string(), precondition))
+    }
+
+    /// How `repo` does not meet the precondition, `None` when it does.
+    pub fn check(&self, repo: &GitRepository) -> Result<Option<String>> {
+        if let Some(expected) = &self.branch {
+            let branch = repo.current_branch()?;
+            if branch.as_ref() != Some(expected) {
+                return Ok(Some(format!(
+                    "branch is {}, expected {}",
+                    branch.as_deref().unwrap_or("(detached)"),
+                    expected
+                )));
+            }
+        }
+        if let Some(expected) = &self.head {
+            let head = repo.head_sha()?;
+            if !head.starts_with(expected.as_str()) {
+                return Ok(Some(format!("HEAD is {}, expected {}", head, expected)));
+            }
+        }
+        if self.clean {
+            let changes = ChangeCounts::collect(&repo.open()?)?;
+            if !changes.is_clean() {
+                return Ok(Some(format!("working tree is not clean ({})", changes)));
+            }
+        }
+        if let Some(expected) = &self.worktree {
+            if fingerprint(repo)? != *expected {
+                return Ok(Some("working tree changed".to_string()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Preconditions of a batch: some for every repository, others for one.
+#[derive(Debug, Clone, Default)]
+pub struct Preconditions {
+    pub all: Precondition,
+    pub repos: BTreeMap<String, Precondition>,
+}
+
+impl Preconditions {
+    /// How `repo` does not meet its preconditions, `None` when it does.
+    pub fn check(&self, repo: &GitRepository) -> Result<Option<String>> {
+        if let Some(failed) = self.all.check(repo)? {
+            return Ok(Some(failed));
+        }
+        match self.repos.get(repo.name()) {
+            Some(precondition) => precondition.check(repo),
+            None => Ok(None),
+        }
+    }
+}
+
+/// SHA-256 of the staged and unstaged changes and of the untracked files.
+pub fn fingerprint(repo: &GitRepository) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(repo.git(["diff", "--binary", "HEAD"])?);
+    hasher.update(repo.git(["diff", "--binary", "--cached"])?);
+    let untracked = repo.git(["ls-files", "--others", "--exclude-standard", "-z"])?;
+    for file in untracked.split('\0').filter(|file| !file.is_empty()) {
+        hasher.update(file);
+        // Contents too, a file may change without its name doing so.
+        if let Ok(contents) = fs::read(repo.path().join(file)) {
+            hasher.update(contents);
+        }
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{RepoState, TestWorkspace};
+
+    #[test]
+    fn parses_branch_and_head() {
+        let (repo, precondition) = Precondition::parse("api=main@4F2A9C1").unwrap();
+        assert_eq!(repo, "api");
+        assert_eq!(precondition.branch.as_deref(), Some("main"));
+        assert_eq!(precondition.head.as_deref(), Some("4f2a9c1"));
+
+        let (_, precondition) = Precondition::parse("api=main").unwrap();
+        assert_eq!(precondition.head, None);
+        let (_, precondition) = Precondition::parse("api=@4f2a9c1").unwrap();
+        assert_eq!(precondition.branch, None);
+    }
+
+    #[test]
+    fn rejects_incomplete_preconditions() {
+        for spec in ["api", "=main", "api=", "api=@"] {
+            let error = Precondition::parse(spec).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("invalid precondition '{}', expected REPO=BRANCH@SHA", spec)
+            );
+        }
+    }
+
+    #[test]
+    fn checks_a_repository() {
+        let test = TestWorkspace::builder()
+            .repo("clean", RepoState::Clean)
+            .repo("dirty", RepoState::Dirty)
+            .build()
+            .unwrap();
+        let (_, main) = Precondition::parse("clean=main").unwrap();
+        assert_eq!(main.check(&test.repository("clean")).unwrap(), None);
+        let (_, other) = Precondition::parse("clean=release").unwrap();
+        assert_eq!(
+            other.check(&test.repository("clean")).unwrap().as_deref(),
+            Some("branch is main, expected release")
+        );
+        let clean = Precondition {
+            clean: true,
+            ..Precondition::default()
+        };
+        assert!(clean.check(&test.repository("dirty")).unwrap().is_some());
+    }
+}
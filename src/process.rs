@@ -0,0 +1,332 @@
+//! Child processes that must not outlive an interrupted git-ws.
+//!
+//! Commands run through [`output`] are tracked until they exit. When git-ws
+//! is interrupted, [`shutdown`] passes the signal on to them and kills
+//! whatever is still running after a grace period, grandchildren such as
+//! compilers included.
+//!
+//! On Unix every child leads its own process group, which receives the
+//! signal. Children do not read the terminal there, their stdin is closed.
+//! On Windows children share the console and get Ctrl+C directly; each is
+//! put in a Job Object, terminated as a whole once the grace period is over.
+//!
+//! [`Limits`] bound what a single child may use. The memory and CPU limits
+//! are rlimits on Unix, applying to each process of the tree on its own, and
+//! limits of the whole Job Object on Windows.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Children still running, by process id.
+static CHILDREN: Mutex<BTreeMap<u32, sys::Group>> = Mutex::new(BTreeMap::new());
+
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Interrupt,
+    Terminate,
+}
+
+impl Signal {
+    /// Exit status of a process stopped by the signal, as shells report it.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Signal::Interrupt => 130,
+            Signal::Terminate => 143,
+        }
+    }
+}
+
+/// What a child may use before being stopped.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    /// Wall-clock time after which the child is killed.
+    pub timeout: Option<Duration>,
+    /// Memory, in bytes.
+    pub memory: Option<u64>,
+    /// CPU time.
+    pub cpu: Option<Duration>,
+}
+
+/// Like [`Command::output`], with the child tracked for [`shutdown`] and
+/// held to `limits`. Fails once a shutdown has started, and with
+/// [`io::ErrorKind::TimedOut`] when the child ran past its timeout.
+pub fn output(command: &mut Command, limits: &Limits) -> io::Result<Output> {
+    if STOPPING.load(Ordering::SeqCst) {
+        return Err(io::Error::new(io::ErrorKind::Interrupted, "interrupted"));
+    }
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let (child, group) = sys::spawn(command, limits)?;
+    let id = child.id();
+    children().insert(id, group);
+
+    let timed_out = Arc::new(AtomicBool::new(false));
+    let (done, finished) = mpsc::channel::<()>();
+    if let Some(timeout) = limits.timeout {
+        let timed_out = Arc::clone(&timed_out);
+        thread::spawn(move || {
+            if finished.recv_timeout(timeout) == Err(mpsc::RecvTimeoutError::Timeout) {
+                if let Some(group) = children().get(&id) {
+                    timed_out.store(true, Ordering::SeqCst);
+                    sys::kill(group);
+                }
+            }
+        });
+    }
+    let output = child.wait_with_output();
+    children().remove(&id);
+    drop(done);
+
+    if timed_out.load(Ordering::SeqCst) {
+        return Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!(
+                "timed out after {}s",
+                limits.timeout.unwrap_or_default().as_secs()
+            ),
+        ));
+    }
+    output
+}
+
+/// Parses a size such as `512M` or `2G`, in bytes with an optional `K`, `M`
+/// or `G` suffix.
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let (digits, unit) = match size.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => size.split_at(i),
+        None => (size, ""),
+    };
+    let shift = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        _ => return Err(format!("unknown size unit `{}`", unit)),
+    };
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid size `{}`", size))?;
+    value
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("size `{}` is too large", size))
+}
+
+/// Waits for SIGINT or SIGTERM, Ctrl+C on Windows.
+pub async fn interrupted() -> io::Result<Signal> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = interrupt.recv() => Ok(Signal::Interrupt),
+            _ = terminate.recv() => Ok(Signal::Terminate),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await?;
+        Ok(Signal::Interrupt)
+    }
+}
+
+/// Forwards `signal` to the running children, waits up to `grace` for them
+/// to exit and kills those left. No child is started afterwards.
+pub fn shutdown(signal: Signal, grace: Duration) {
+    STOPPING.store(true, Ordering::SeqCst);
+    stop_children(signal, grace);
+}
+
+/// Like [`shutdown`], with children still started afterwards.
+pub fn stop_children(signal: Signal, grace: Duration) {
+    for group in children().values() {
+        sys::signal(group, signal);
+    }
+    let deadline = Instant::now() + grace;
+    while Instant::now() < deadline {
+        if children().is_empty() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    for group in children().values() {
+        sys::kill(group);
+    }
+}
+
+fn children() -> MutexGuard<'static, BTreeMap<u32, sys::Group>> {
+    CHILDREN.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::io;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, Command, Stdio};
+
+    use super::{Limits, Signal};
+
+    pub struct Group(libc::pid_t);
+
+    pub fn spawn(command: &mut Command, limits: &Limits) -> io::Result<(Child, Group)> {
+        let memory = limits.memory.map(|bytes| bytes as libc::rlim_t);
+        let cpu = limits.cpu.map(|cpu| cpu.as_secs().max(1) as libc::rlim_t);
+        if memory.is_some() || cpu.is_some() {
+            // SAFETY: setrlimit is async-signal-safe and nothing is allocated.
+            unsafe {
+                command.pre_exec(move || {
+                    if let Some(memory) = memory {
+                        let limit = libc::rlimit {
+                            rlim_cur: memory,
+                            rlim_max: memory,
+                        };
+                        if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0 {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+                    if let Some(cpu) = cpu {
+                        let limit = libc::rlimit {
+                            rlim_cur: cpu,
+                            rlim_max: cpu,
+                        };
+                        if libc::setrlimit(libc::RLIMIT_CPU, &limit) != 0 {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+                    Ok(())
+                });
+            }
+        }
+        // Outside the foreground process group, reading the terminal would
+        // stop the child.
+        let child = command.stdin(Stdio::null()).process_group(0).spawn()?;
+        let group = Group(child.id() as libc::pid_t);
+        Ok((child, group))
+    }
+
+    pub fn signal(group: &Group, signal: Signal) {
+        let signal = match signal {
+            Signal::Interrupt => libc::SIGINT,
+            Signal::Terminate => libc::SIGTERM,
+        };
+        // SAFETY: kill has no memory safety requirements.
+        unsafe {
+            libc::kill(-group.0, signal);
+        }
+    }
+
+    pub fn kill(group: &Group) {
+        // SAFETY: kill has no memory safety requirements.
+        unsafe {
+            libc::kill(-group.0, libc::SIGKILL);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::io;
+    use std::os::windows::io::AsRawHandle;
+    use std::process::{Child, Command};
+    use std::{mem, ptr};
+
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, TerminateJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_JOB_MEMORY, JOB_OBJECT_LIMIT_JOB_TIME,
+    };
+
+    use super::{Limits, Signal};
+
+    pub struct Group(HANDLE);
+
+    pub fn spawn(command: &mut Command, limits: &Limits) -> io::Result<(Child, Group)> {
+        // SAFETY: both arguments may be null.
+        let job = unsafe { CreateJobObjectW(ptr::null(), ptr::null()) };
+        if job == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let group = Group(job);
+        if limits.memory.is_some() || limits.cpu.is_some() {
+            // SAFETY: the structure is plain data, valid when zeroed.
+            let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { mem::zeroed() };
+            if let Some(memory) = limits.memory {
+                info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_JOB_MEMORY;
+                info.JobMemoryLimit = memory as usize;
+            }
+            if let Some(cpu) = limits.cpu {
+                // In units of 100 nanoseconds.
+                info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_JOB_TIME;
+                info.BasicLimitInformation.PerJobUserTimeLimit = (cpu.as_nanos() / 100) as i64;
+            }
+            // SAFETY: `info` outlives the call and its size is passed along.
+            let set = unsafe {
+                SetInformationJobObject(
+                    job,
+                    JobObjectExtendedLimitInformation,
+                    &info as *const _ as *const _,
+                    mem::size_of_val(&info) as u32,
+                )
+            };
+            if set == 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        let child = command.spawn()?;
+        // Failing leaves the child outside the job, running as usual.
+        // SAFETY: both handles are valid while `group` and `child` live.
+        unsafe {
+            AssignProcessToJobObject(job, child.as_raw_handle() as HANDLE);
+        }
+        Ok((child, group))
+    }
+
+    pub fn signal(_group: &Group, _signal: Signal) {
+        // Ctrl+C reaches every process attached to the console.
+    }
+
+    pub fn kill(group: &Group) {
+        // SAFETY: the handle is valid while `group` lives.
+        unsafe {
+            TerminateJobObject(group.0, 1);
+        }
+    }
+
+    impl Drop for Group {
+        fn drop(&mut self) {
+            // SAFETY: the handle is owned by `self`.
+            unsafe {
+                CloseHandle(self.0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("4K"), Ok(4096));
+        assert_eq!(parse_size("512M"), Ok(512 << 20));
+        assert_eq!(parse_size("2gb"), Ok(2 << 30));
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        assert_eq!(parse_size("2T"), Err("unknown size unit `T`".to_string()));
+        assert_eq!(parse_size("M"), Err("invalid size `M`".to_string()));
+        assert!(parse_size("99999999999G").is_err());
+    }
+}
@@ -0,0 +1,129 @@
+//! Progress bars for long batches, drawn on stderr.
+//!
+//! While a batch runs, every repository in flight has a spinner telling how
+//! long it has been running, below a bar counting the repositories done.
+//! Nothing is drawn unless stderr is a terminal, and the bars are cleared
+//! when the batch ends, leaving the results alone. They are hidden while
+//! git-ws asks the user something, like credentials, see [`suspend`].
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+const TICK: Duration = Duration::from_millis(100);
+
+/// The bars of the process, shared by every [`ProgressBars`] for
+/// [`suspend`] to find them.
+static DRAWN: OnceLock<MultiProgress> = OnceLock::new();
+
+/// Runs `f`, talking to the user on the terminal, with the bars hidden
+/// meanwhile, when any are drawn.
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    match DRAWN.get() {
+        Some(multi) => multi.suspend(f),
+        None => f(),
+    }
+}
+
+/// The bars of the batches of an executor, see
+/// [`BatchExecutor::with_progress_bars`](crate::executor::BatchExecutor::with_progress_bars).
+#[derive(Debug, Clone)]
+pub struct ProgressBars {
+    multi: MultiProgress,
+}
+
+impl ProgressBars {
+    pub fn new() -> Self {
+        ProgressBars {
+            multi: DRAWN.get_or_init(MultiProgress::new).clone(),
+        }
+    }
+
+    /// Bars for a batch of `total` repositories, `None` for a single one.
+    pub(crate) fn batch(&self, total: usize) -> Option<BatchBars> {
+        if total < 2 {
+            return None;
+        }
+        let overall = ProgressBar::new(total as u64).with_style(
+            ProgressStyle::with_template("{bar:30} {pos}/{len} repositories {msg}")
+                .expect("valid template"),
+        );
+        Some(BatchBars {
+            multi: self.multi.clone(),
+            overall: self.multi.add(overall),
+            running: Mutex::new(HashMap::new()),
+            failed: Mutex::new(0),
+        })
+    }
+}
+
+/// The bars of one batch, cleared when dropped.
+pub(crate) struct BatchBars {
+    multi: MultiProgress,
+    overall: ProgressBar,
+    running: Mutex<HashMap<String, ProgressBar>>,
+    failed: Mutex<usize>,
+}
+
+impl BatchBars {
+    pub(crate) fn started(&self, repo: &str) {
+        let spinner = ProgressBar::new_spinner()
+            .with_style(
+                ProgressStyle::with_template("{spinner} {msg} {elapsed}").expect("valid template"),
+            )
+            .with_message(repo.to_string());
+        let spinner = self.multi.add(spinner);
+        spinner.enable_steady_tick(TICK);
+        self.running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(repo.to_string(), spinner);
+    }
+
+    /// Counts `repo` as done, whether it was started or not.
+    pub(crate) fn finished(&self, repo: &str, failed: bool) {
+        let spinner = self
+            .running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(repo);
+        if let Some(spinner) = spinner {
+            spinner.finish_and_clear();
+            self.multi.remove(&spinner);
+        }
+        if failed {
+            let mut count = self.failed.lock().unwrap_or_else(|e| e.into_inner());
+            *count += 1;
+            self.overall.set_message(format!("({} failed)", count));
+        }
+        self.overall.inc(1);
+    }
+
+    /// Runs `f` with the bars hidden, for it to print.
+    pub(crate) fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.multi.suspend(f)
+    }
+}
+
+impl Default for ProgressBars {
+    fn default() -> Self {
+        ProgressBars::new()
+    }
+}
+
+impl Drop for BatchBars {
+    fn drop(&mut self) {
+        for spinner in self
+            .running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+        {
+            spinner.finish_and_clear();
+        }
+        self.overall.finish_and_clear();
+        let _ = self.multi.clear();
+    }
+}
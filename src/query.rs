@@ -0,0 +1,805 @@
+//! A small jq, to filter JSON output without an external tool:
+//!
+//! ```text
+//! git-ws status --query '.[] | select(.dirty) | .repo'
+//! ```
+//!
+//! Supported are paths (`.`, `.name`, `.[]`, `.[0]`, `.["name"]`), pipes,
+//! commas, array construction (`[...]`), literals, parentheses, the
+//! comparisons `==`, `!=`, `<`, `<=`, `>`, `>=`, `and`, `or`, and the
+//! functions `select`, `map`, `not`, `length`, `keys`, `has`, `empty`,
+//! `startswith`, `endswith` and `test`. As in jq, a filter produces any
+//! number of values for each input, and `false` and `null` are false.
+
+use std::cmp::Ordering;
+
+use regex::Regex;
+use serde_json::Value;
+
+use crate::{Error, Result};
+
+/// A parsed query.
+#[derive(Debug, Clone)]
+pub struct Query {
+    filter: Filter,
+}
+
+#[derive(Debug, Clone)]
+enum Filter {
+    Identity,
+    Field(String),
+    Index(Box<Filter>),
+    Iterate,
+    Literal(Value),
+    Collect(Box<Filter>),
+    Pipe(Box<Filter>, Box<Filter>),
+    Comma(Box<Filter>, Box<Filter>),
+    Compare(Comparison, Box<Filter>, Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Call(String, Vec<Filter>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Query {
+    pub fn parse(source: &str) -> Result<Self> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let filter = parser.pipe()?;
+        match parser.peek() {
+            None => Ok(Query { filter }),
+            Some(token) => Err(invalid(&format!("unexpected {}", token))),
+        }
+    }
+
+    /// Values the query produces for `input`.
+    pub fn run(&self, input: &Value) -> Result<Vec<Value>> {
+        eval(&self.filter, input)
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::Operation(format!("invalid query: {}", reason))
+}
+
+fn failed(reason: String) -> Error {
+    Error::Operation(format!("query failed: {}", reason))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Dot,
+    /// `.name` or `."name"`.
+    Field(String),
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Punct(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Dot => write!(f, "'.'"),
+            Token::Field(name) => write!(f, "'.{}'", name),
+            Token::Ident(ident) => write!(f, "'{}'", ident),
+            Token::Str(string) => write!(f, "{:?}", string),
+            Token::Num(number) => write!(f, "{}", number),
+            Token::Punct(punct) => write!(f, "'{}'", punct),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    const PUNCTS: [&str; 13] = [
+        "==", "!=", "<=", ">=", "<", ">", "|", ",", "[", "]", "(", ")", ";",
+    ];
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c == '.' {
+            rest = &rest[1..];
+            if rest.starts_with('"') {
+                let (name, len) = string_literal(rest)?;
+                tokens.push(Token::Field(name));
+                rest = &rest[len..];
+            } else if rest.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+                let len = identifier_len(rest);
+                tokens.push(Token::Field(rest[..len].to_string()));
+                rest = &rest[len..];
+            } else {
+                tokens.push(Token::Dot);
+            }
+        } else if c == '"' {
+            let (string, len) = string_literal(rest)?;
+            tokens.push(Token::Str(string));
+            rest = &rest[len..];
+        } else if c.is_ascii_digit()
+            || (c == '-' && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            let len = rest[1..]
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .map_or(rest.len(), |i| i + 1);
+            let number = rest[..len]
+                .parse()
+                .map_err(|_| invalid(&format!("bad number '{}'", &rest[..len])))?;
+            tokens.push(Token::Num(number));
+            rest = &rest[len..];
+        } else if c.is_alphabetic() || c == '_' {
+            let len = identifier_len(rest);
+            tokens.push(Token::Ident(rest[..len].to_string()));
+            rest = &rest[len..];
+        } else if let Some(punct) = PUNCTS.iter().find(|punct| rest.starts_with(**punct)) {
+            tokens.push(Token::Punct(punct));
+            rest = &rest[punct.len()..];
+        } else {
+            return Err(invalid(&format!("unexpected '{}'", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+fn identifier_len(source: &str) -> usize {
+    source
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(source.len())
+}
+
+/// The string literal `source` starts with, and its length in `source`.
+fn string_literal(source: &str) -> Result<(String, usize)> {
+    let mut string = String::new();
+    let mut chars = source.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((string, i + 1)),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => string.push('\n'),
+                Some((_, 't')) => string.push('\t'),
+                Some((_, c)) => string.push(c),
+                None => break,
+            },
+            c => string.push(c),
+        }
+    }
+    Err(invalid("unterminated string"))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &'static str) -> Result<()> {
+        if self.eat(&Token::Punct(punct)) {
+            Ok(())
+        } else {
+            Err(invalid(&format!("expected '{}'", punct)))
+        }
+    }
+
+    fn pipe(&mut self) -> Result<Filter> {
+        let mut filter = self.comma()?;
+        while self.eat(&Token::Punct("|")) {
+            filter = Filter::Pipe(Box::new(filter), Box::new(self.comma()?));
+        }
+        Ok(filter)
+    }
+
+    fn comma(&mut self) -> Result<Filter> {
+        let mut filter = self.or()?;
+        while self.eat(&Token::Punct(",")) {
+            filter = Filter::Comma(Box::new(filter), Box::new(self.or()?));
+        }
+        Ok(filter)
+    }
+
+    fn or(&mut self) -> Result<Filter> {
+        let mut filter = self.and()?;
+        while self.eat(&Token::Ident("or".to_string())) {
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter> {
+        let mut filter = self.comparison()?;
+        while self.eat(&Token::Ident("and".to_string())) {
+            filter = Filter::And(Box::new(filter), Box::new(self.comparison()?));
+        }
+        Ok(filter)
+    }
+
+    fn comparison(&mut self) -> Result<Filter> {
+        let left = self.postfix()?;
+        let comparison = match self.peek() {
+            Some(Token::Punct("==")) => Comparison::Eq,
+            Some(Token::Punct("!=")) => Comparison::Ne,
+            Some(Token::Punct("<")) => Comparison::Lt,
+            Some(Token::Punct("<=")) => Comparison::Le,
+            Some(Token::Punct(">")) => Comparison::Gt,
+            Some(Token::Punct(">=")) => Comparison::Ge,
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let right = self.postfix()?;
+        Ok(Filter::Compare(comparison, Box::new(left), Box::new(right)))
+    }
+
+    /// A term followed by paths, like `.a.b[0][]`.
+    fn postfix(&mut self) -> Result<Filter> {
+        let mut filter = self.term()?;
+        loop {
+            let path = if let Some(Token::Field(name)) = self.peek() {
+                let name = name.clone();
+                self.pos += 1;
+                Filter::Field(name)
+            } else if self.eat(&Token::Dot) {
+                self.expect("[")?;
+                self.brackets()?
+            } else if self.eat(&Token::Punct("[")) {
+                self.brackets()?
+            } else {
+                return Ok(filter);
+            };
+            filter = Filter::Pipe(Box::new(filter), Box::new(path));
+        }
+    }
+
+    /// The inside of `[...]` following a path, after the `[`.
+    fn brackets(&mut self) -> Result<Filter> {
+        if self.eat(&Token::Punct("]")) {
+            return Ok(Filter::Iterate);
+        }
+        let index = self.pipe()?;
+        self.expect("]")?;
+        Ok(Filter::Index(Box::new(index)))
+    }
+
+    fn term(&mut self) -> Result<Filter> {
+        match self.next() {
+            Some(Token::Dot) => {
+                if self.eat(&Token::Punct("[")) {
+                    self.brackets()
+                } else {
+                    Ok(Filter::Identity)
+                }
+            }
+            Some(Token::Field(name)) => Ok(Filter::Field(name)),
+            Some(Token::Str(string)) => Ok(Filter::Literal(Value::String(string))),
+            Some(Token::Num(number)) => Ok(Filter::Literal(number_value(number))),
+            Some(Token::Punct("(")) => {
+                let filter = self.pipe()?;
+                self.expect(")")?;
+                Ok(filter)
+            }
+            Some(Token::Punct("[")) => {
+                if self.eat(&Token::Punct("]")) {
+                    return Ok(Filter::Literal(Value::Array(Vec::new())));
+                }
+                let filter = self.pipe()?;
+                self.expect("]")?;
+                Ok(Filter::Collect(Box::new(filter)))
+            }
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "true" => Ok(Filter::Literal(Value::Bool(true))),
+                "false" => Ok(Filter::Literal(Value::Bool(false))),
+                "null" => Ok(Filter::Literal(Value::Null)),
+                _ => {
+                    let mut args = Vec::new();
+                    if self.eat(&Token::Punct("(")) {
+                        args.push(self.pipe()?);
+                        while self.eat(&Token::Punct(";")) {
+                            args.push(self.pipe()?);
+                        }
+                        self.expect(")")?;
+                    }
+                    check_arity(&ident, args.len())?;
+                    Ok(Filter::Call(ident, args))
+                }
+            },
+            Some(token) => Err(invalid(&format!("unexpected {}", token))),
+            None => Err(invalid("unexpected end")),
+        }
+    }
+}
+
+fn check_arity(name: &str, count: usize) -> Result<()> {
+    let expected = match name {
+        "not" | "length" | "keys" | "empty" => 0,
+        "select" | "map" | "has" | "startswith" | "endswith" | "test" => 1,
+        _ => return Err(invalid(&format!("unknown function '{}'", name))),
+    };
+    if count != expected {
+        return Err(invalid(&format!(
+            "{} takes {} argument(s), not {}",
+            name, expected, count
+        )));
+    }
+    Ok(())
+}
+
+fn number_value(number: f64) -> Value {
+    if number.fract() == 0.0 && number.abs() < i64::MAX as f64 {
+        Value::from(number as i64)
+    } else {
+        Value::from(number)
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    !matches!(value, Value::Null | Value::Bool(false))
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Orders values like jq: null, false, true, numbers, strings, arrays,
+/// objects.
+fn compare(a: &Value, b: &Value) -> Ordering {
+    fn rank(value: &Value) -> u8 {
+        match value {
+            Value::Null => 0,
+            Value::Bool(false) => 1,
+            Value::Bool(true) => 2,
+            Value::Number(_) => 3,
+            Value::String(_) => 4,
+            Value::Array(_) => 5,
+            Value::Object(_) => 6,
+        }
+    }
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            let (a, b) = (a.as_f64().unwrap_or(0.0), b.as_f64().unwrap_or(0.0));
+            a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+        }
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (Value::Object(_), Value::Object(_)) if a == b => Ordering::Equal,
+        (Value::Object(a), Value::Object(b)) => serde_json::to_string(a)
+            .unwrap_or_default()
+            .cmp(&serde_json::to_string(b).unwrap_or_default()),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+/// The values of `filter` for `input`, one per value of `right` and of
+/// `left`, combined with `f`.
+fn binary(
+    left: &Filter,
+    right: &Filter,
+    input: &Value,
+    f: impl Fn(&Value, &Value) -> Value,
+) -> Result<Vec<Value>> {
+    let mut values = Vec::new();
+    for right in eval(right, input)? {
+        for left in eval(left, input)? {
+            values.push(f(&left, &right));
+        }
+    }
+    Ok(values)
+}
+
+/// The values of `left and right`, or of `left or right` when `or`. As in
+/// jq, `right` is only evaluated for the values of `left` not deciding the
+/// outcome alone.
+fn logical(left: &Filter, right: &Filter, input: &Value, or: bool) -> Result<Vec<Value>> {
+    let mut values = Vec::new();
+    for left in eval(left, input)? {
+        if truthy(&left) == or {
+            values.push(Value::Bool(or));
+            continue;
+        }
+        for right in eval(right, input)? {
+            values.push(Value::Bool(truthy(&right)));
+        }
+    }
+    Ok(values)
+}
+
+/// The single string argument of a call.
+fn string_argument(name: &str, arg: &Filter, input: &Value) -> Result<Vec<String>> {
+    eval(arg, input)?
+        .into_iter()
+        .map(|value| match value {
+            Value::String(string) => Ok(string),
+            value => Err(failed(format!(
+                "{} needs a string, not {}",
+                name,
+                type_name(&value)
+            ))),
+        })
+        .collect()
+}
+
+fn eval(filter: &Filter, input: &Value) -> Result<Vec<Value>> {
+    Ok(match filter {
+        Filter::Identity => vec![input.clone()],
+        Filter::Field(name) => match input {
+            Value::Object(object) => vec![object.get(name).cloned().unwrap_or(Value::Null)],
+            Value::Null => vec![Value::Null],
+            value => {
+                return Err(failed(format!(
+                    "cannot get .{} of {}",
+                    name,
+                    type_name(value)
+                )))
+            }
+        },
+        Filter::Index(index) => {
+            let mut values = Vec::new();
+            for index in eval(index, input)? {
+                values.push(match (input, &index) {
+                    (Value::Null, _) => Value::Null,
+                    (Value::Object(object), Value::String(key)) => {
+                        object.get(key).cloned().unwrap_or(Value::Null)
+                    }
+                    (Value::Array(array), Value::Number(n)) => {
+                        let n = n.as_f64().unwrap_or(0.0) as i64;
+                        let n = if n < 0 { array.len() as i64 + n } else { n };
+                        usize::try_from(n)
+                            .ok()
+                            .and_then(|n| array.get(n))
+                            .cloned()
+                            .unwrap_or(Value::Null)
+                    }
+                    (input, index) => {
+                        return Err(failed(format!(
+                            "cannot index {} with {}",
+                            type_name(input),
+                            type_name(index)
+                        )))
+                    }
+                });
+            }
+            values
+        }
+        Filter::Iterate => match input {
+            Value::Array(array) => array.clone(),
+            Value::Object(object) => object.values().cloned().collect(),
+            value => return Err(failed(format!("cannot iterate over {}", type_name(value)))),
+        },
+        Filter::Literal(value) => vec![value.clone()],
+        Filter::Collect(filter) => vec![Value::Array(eval(filter, input)?)],
+        Filter::Pipe(left, right) => {
+            let mut values = Vec::new();
+            for value in eval(left, input)? {
+                values.extend(eval(right, &value)?);
+            }
+            values
+        }
+        Filter::Comma(left, right) => {
+            let mut values = eval(left, input)?;
+            values.extend(eval(right, input)?);
+            values
+        }
+        Filter::Compare(comparison, left, right) => binary(left, right, input, |a, b| {
+            let ordering = compare(a, b);
+            Value::Bool(match comparison {
+                Comparison::Eq => ordering.is_eq(),
+                Comparison::Ne => ordering.is_ne(),
+                Comparison::Lt => ordering.is_lt(),
+                Comparison::Le => ordering.is_le(),
+                Comparison::Gt => ordering.is_gt(),
+                Comparison::Ge => ordering.is_ge(),
+            })
+        })?,
+        Filter::And(left, right) => logical(left, right, input, false)?,
+        Filter::Or(left, right) => logical(left, right, input, true)?,
+        Filter::Call(name, args) => call(name, args, input)?,
+    })
+}
+
+fn call(name: &str, args: &[Filter], input: &Value) -> Result<Vec<Value>> {
+    Ok(match name {
+        "empty" => Vec::new(),
+        "not" => vec![Value::Bool(!truthy(input))],
+        "length" => vec![match input {
+            Value::Null => Value::from(0),
+            Value::Bool(_) => return Err(failed("boolean has no length".to_string())),
+            Value::Number(n) => Value::from(n.as_f64().unwrap_or(0.0).abs()),
+            Value::String(string) => Value::from(string.chars().count()),
+            Value::Array(array) => Value::from(array.len()),
+            Value::Object(object) => Value::from(object.len()),
+        }],
+        "keys" => vec![match input {
+            Value::Object(object) => {
+                let mut keys: Vec<_> = object.keys().cloned().map(Value::String).collect();
+                keys.sort_by(compare);
+                Value::Array(keys)
+            }
+            Value::Array(array) => Value::Array((0..array.len()).map(Value::from).collect()),
+            value => return Err(failed(format!("{} has no keys", type_name(value)))),
+        }],
+        "select" => {
+            let mut values = Vec::new();
+            for condition in eval(&args[0], input)? {
+                if truthy(&condition) {
+                    values.push(input.clone());
+                }
+            }
+            values
+        }
+        "map" => {
+            let mut values = Vec::new();
+            for value in eval(&Filter::Iterate, input)? {
+                values.extend(eval(&args[0], &value)?);
+            }
+            vec![Value::Array(values)]
+        }
+        "has" => {
+            let mut values = Vec::new();
+            for key in eval(&args[0], input)? {
+                values.push(Value::Bool(match (input, &key) {
+                    (Value::Object(object), Value::String(key)) => object.contains_key(key),
+                    (Value::Array(array), Value::Number(n)) => n
+                        .as_f64()
+                        .is_some_and(|n| n >= 0.0 && (n as usize) < array.len()),
+                    (input, key) => {
+                        return Err(failed(format!(
+                            "cannot check whether {} has a {} key",
+                            type_name(input),
+                            type_name(key)
+                        )))
+                    }
+                }));
+            }
+            values
+        }
+        "startswith" | "endswith" | "test" => {
+            let Value::String(string) = input else {
+                return Err(failed(format!(
+                    "{} needs a string input, not {}",
+                    name,
+                    type_name(input)
+                )));
+            };
+            let mut values = Vec::new();
+            for pattern in string_argument(name, &args[0], input)? {
+                values.push(Value::Bool(match name {
+                    "startswith" => string.starts_with(&pattern),
+                    "endswith" => string.ends_with(&pattern),
+                    _ => Regex::new(&pattern)
+                        .map_err(|e| failed(format!("invalid regex: {}", e)))?
+                        .is_match(string),
+                }));
+            }
+            values
+        }
+        _ => unreachable!("checked when parsing"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn run(query: &str, input: Value) -> Vec<Value> {
+        Query::parse(query).unwrap().run(&input).unwrap()
+    }
+
+    fn parse_error(query: &str) -> String {
+        Query::parse(query).unwrap_err().to_string()
+    }
+
+    fn run_error(query: &str, input: Value) -> String {
+        Query::parse(query)
+            .unwrap()
+            .run(&input)
+            .unwrap_err()
+            .to_string()
+    }
+
+    fn repos() -> Value {
+        json!([
+            {"repo": "api", "dirty": true, "ahead": 2, "branch": "main"},
+            {"repo": "web", "dirty": false, "ahead": 0, "branch": "feature/x"},
+            {"repo": "docs", "dirty": null, "ahead": 5, "branch": null}
+        ])
+    }
+
+    #[test]
+    fn follows_paths() {
+        let input = json!({"a": {"b": [10, 20, 30]}, "odd key": 1});
+        assert_eq!(run(".", json!(1)), [json!(1)]);
+        assert_eq!(run(".a.b", input.clone()), [json!([10, 20, 30])]);
+        assert_eq!(run(".a.b[0]", input.clone()), [json!(10)]);
+        assert_eq!(run(".a.b[-1]", input.clone()), [json!(30)]);
+        assert_eq!(run(".a.b[5]", input.clone()), [Value::Null]);
+        assert_eq!(
+            run(".a.b[]", input.clone()),
+            [json!(10), json!(20), json!(30)]
+        );
+        assert_eq!(run(r#".["odd key"]"#, input.clone()), [json!(1)]);
+        assert_eq!(run(r#"."odd key""#, input.clone()), [json!(1)]);
+        assert_eq!(run(".missing.deeper", input), [Value::Null]);
+    }
+
+    #[test]
+    fn pipes_and_collects() {
+        assert_eq!(
+            run(".[] | .repo", repos()),
+            [json!("api"), json!("web"), json!("docs")]
+        );
+        assert_eq!(run("[.[] | .ahead]", repos()), [json!([2, 0, 5])]);
+        assert_eq!(run("map(.repo) | length", repos()), [json!(3)]);
+        assert_eq!(
+            run(".[0] | .repo, .ahead", repos()),
+            [json!("api"), json!(2)]
+        );
+        assert_eq!(run("[]", json!(null)), [json!([])]);
+        assert_eq!(
+            run("(1, 2) | [., .]", json!(null)),
+            [json!([1, 1]), json!([2, 2])]
+        );
+    }
+
+    #[test]
+    fn filters_with_select() {
+        assert_eq!(run(".[] | select(.dirty) | .repo", repos()), [json!("api")]);
+        assert_eq!(
+            run(".[] | select(.dirty | not) | .repo", repos()),
+            [json!("web"), json!("docs")]
+        );
+        assert_eq!(
+            run(
+                ".[] | select(.ahead > 0 and .branch != null) | .repo",
+                repos()
+            ),
+            [json!("api")]
+        );
+        assert_eq!(
+            run(".[] | select(.ahead >= 5 or .dirty) | .repo", repos()),
+            [json!("api"), json!("docs")]
+        );
+        assert_eq!(
+            run(r#".[] | select(.branch == "main") | .repo"#, repos()),
+            [json!("api")]
+        );
+        assert_eq!(
+            run(".[] | select(.ahead < -1)", repos()),
+            Vec::<Value>::new()
+        );
+    }
+
+    #[test]
+    fn calls_functions() {
+        assert_eq!(run("keys", json!({"b": 1, "a": 2})), [json!(["a", "b"])]);
+        assert_eq!(run("keys", json!([7, 8])), [json!([0, 1])]);
+        assert_eq!(
+            run(r#"has("a"), has("z")"#, json!({"a": 1})),
+            [json!(true), json!(false)]
+        );
+        assert_eq!(run("has(1)", json!([1])), [json!(false)]);
+        assert_eq!(run("length", json!("héllo")), [json!(5)]);
+        assert_eq!(run("length", json!(null)), [json!(0)]);
+        assert_eq!(run("empty", json!(1)), Vec::<Value>::new());
+        assert_eq!(
+            run(
+                r#".[] | select(.branch | . != null and startswith("feature/")) | .repo"#,
+                repos()
+            ),
+            [json!("web")]
+        );
+        assert_eq!(run(r#"endswith("md")"#, json!("README.md")), [json!(true)]);
+        assert_eq!(run(r#"test("^v[0-9]+")"#, json!("v12")), [json!(true)]);
+    }
+
+    #[test]
+    fn compares_like_jq() {
+        assert_eq!(run("null < false", json!(null)), [json!(true)]);
+        assert_eq!(run("true < 0", json!(null)), [json!(true)]);
+        assert_eq!(run(r#"10 < "1""#, json!(null)), [json!(true)]);
+        assert_eq!(run("[1, 2] < [1, 3]", json!(null)), [json!(true)]);
+        assert_eq!(run("1.5 == 1.5", json!(null)), [json!(true)]);
+        assert_eq!(run("2 == 2.0", json!(null)), [json!(true)]);
+    }
+
+    #[test]
+    fn short_circuits_and_and_or() {
+        assert_eq!(run("false and .x", json!(1)), [json!(false)]);
+        assert_eq!(run("true or .x", json!(1)), [json!(true)]);
+        assert_eq!(
+            run("(true, false) and (true, false)", json!(null)),
+            [json!(true), json!(false), json!(false)]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_queries() {
+        assert_eq!(parse_error(".a |"), "invalid query: unexpected end");
+        assert_eq!(parse_error(".a)"), "invalid query: unexpected ')'");
+        assert_eq!(parse_error("[.a"), "invalid query: expected ']'");
+        assert_eq!(parse_error(r#"."a"#), "invalid query: unterminated string");
+        assert_eq!(parse_error(".a & .b"), "invalid query: unexpected '&'");
+        assert_eq!(
+            parse_error("sort"),
+            "invalid query: unknown function 'sort'"
+        );
+        assert_eq!(
+            parse_error("select"),
+            "invalid query: select takes 1 argument(s), not 0"
+        );
+        assert_eq!(
+            parse_error("has(1; 2)"),
+            "invalid query: has takes 1 argument(s), not 2"
+        );
+    }
+
+    #[test]
+    fn reports_failures() {
+        assert_eq!(
+            run_error(".a", json!([1])),
+            "query failed: cannot get .a of array"
+        );
+        assert_eq!(
+            run_error(".[]", json!(3)),
+            "query failed: cannot iterate over number"
+        );
+        assert_eq!(
+            run_error(".[0]", json!({"a": 1})),
+            "query failed: cannot index object with number"
+        );
+        assert_eq!(
+            run_error("length", json!(true)),
+            "query failed: boolean has no length"
+        );
+        assert_eq!(
+            run_error("keys", json!(1)),
+            "query failed: number has no keys"
+        );
+        assert_eq!(
+            run_error(r#"startswith("a")"#, json!(1)),
+            "query failed: startswith needs a string input, not number"
+        );
+        assert_eq!(
+            run_error("startswith(1)", json!("a")),
+            "query failed: startswith needs a string, not number"
+        );
+        assert!(run_error(r#"test("(")"#, json!("a")).starts_with("query failed: invalid regex"));
+    }
+}
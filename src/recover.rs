@@ -0,0 +1,509 @@
+//! Undoing batch mistakes.
+//!
+//! Every branch a mutating operation moves is written to a journal in the
+//! state directory, with the batch, the run of git-ws, that moved it.
+//! [`reflog`] lines the reflogs of the repositories up with the journal to
+//! tell the movements of git-ws from the user's own, and a batch is undone
+//! by resetting the branches it moved, see [`RecoverOperation`].
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use git2::{BranchType, Repository};
+use serde::{Deserialize, Serialize};
+
+use crate::freeze::FreezeGuard;
+use crate::middleware::{Middleware, Next};
+use crate::operations::GitOperation;
+use crate::repository::{self, GitRepository};
+use crate::{Error, Result};
+
+pub const JOURNAL_FILE: &str = "journal.jsonl";
+
+/// A branch moved by an operation of git-ws.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Id of the run of git-ws.
+    pub batch: String,
+    /// When the operation ended, in seconds since the epoch.
+    pub time: i64,
+    /// Name of the operation, like `pull`.
+    pub operation: String,
+    pub repo: String,
+    pub branch: String,
+    /// Commit the branch pointed to before, `None` when it was created.
+    pub before: Option<String>,
+    /// Commit the branch points to after, `None` when it was deleted.
+    pub after: Option<String>,
+    /// Why the batch pushed during a freeze, see [`crate::freeze`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub freeze_override: Option<String>,
+}
+
+/// The journal of the workspace whose state directory is `dir`, oldest
+/// entry first.
+pub fn load(dir: &Path) -> Result<Vec<JournalEntry>> {
+    let path = dir.join(JOURNAL_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for line in fs::read_to_string(path)?.lines() {
+        if !line.trim().is_empty() {
+            entries.push(serde_json::from_str(line)?);
+        }
+    }
+    Ok(entries)
+}
+
+/// Writes the branches mutating operations move to the journal.
+pub struct Journal {
+    path: PathBuf,
+    batch: String,
+    freeze: Option<Arc<FreezeGuard>>,
+    lock: Mutex<()>,
+}
+
+impl Journal {
+    /// A journal in the state directory `dir`, for the batch `batch`.
+    pub fn new(dir: &Path, batch: impl Into<String>) -> Self {
+        Journal {
+            path: dir.join(JOURNAL_FILE),
+            batch: batch.into(),
+            freeze: None,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Records the reason `freeze` is overridden with along with every
+    /// operation of the batch that pushes, the branches it leaves in place
+    /// included.
+    pub fn with_freeze(mut self, freeze: Arc<FreezeGuard>) -> Self {
+        self.freeze = Some(freeze);
+        self
+    }
+
+    /// Records the reason the freeze is overridden with when `operation`
+    /// changed the remote of `repo` outside the executor, like merging a
+    /// pull request, `branch` standing for the repository. Nothing is
+    /// recorded when no freeze is overridden.
+    pub fn record_override(
+        &self,
+        repo: &GitRepository,
+        operation: &str,
+        branch: &str,
+    ) -> Result<()> {
+        let Some(reason) = self.freeze.as_ref().and_then(|freeze| freeze.overridden()) else {
+            return Ok(());
+        };
+        let tip = branch_tips(&repo.open()?)?.remove(branch);
+        self.append(&[JournalEntry {
+            batch: self.batch.clone(),
+            time: now(),
+            operation: operation.to_string(),
+            repo: repo.name().to_string(),
+            branch: branch.to_string(),
+            before: tip.clone(),
+            after: tip,
+            freeze_override: Some(reason.to_string()),
+        }])
+    }
+
+    fn append(&self, entries: &[JournalEntry]) -> Result<()> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        for entry in entries {
+            writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        }
+        Ok(())
+    }
+}
+
+impl Middleware for Journal {
+    fn call(
+        &self,
+        repo: &GitRepository,
+        operation: &dyn GitOperation,
+        next: Next<'_>,
+    ) -> Result<String> {
+        if !operation.is_mutating() {
+            return next.run(repo);
+        }
+        // Operations like ci-checkout create the repository.
+        let tips = || match repo.open() {
+            Ok(git) => branch_tips(&git),
+            Err(_) => Ok(BTreeMap::new()),
+        };
+        let before = tips()?;
+        let result = next.run(repo);
+        // The operation has run, failing to journal it must not hide how
+        // it went.
+        let after = match tips() {
+            Ok(after) => after,
+            Err(e) => {
+                eprintln!("warning: {}: not journaled: {}", repo.name(), e);
+                return result;
+            }
+        };
+        let time = now();
+        let freeze_override = match &self.freeze {
+            Some(freeze) if operation.pushes() => freeze.overridden().map(str::to_string),
+            _ => None,
+        };
+        let mut branches: Vec<_> = before.keys().chain(after.keys()).collect();
+        branches.sort();
+        branches.dedup();
+        let entry = |branch: &String| JournalEntry {
+            batch: self.batch.clone(),
+            time,
+            operation: operation.name().to_string(),
+            repo: repo.name().to_string(),
+            branch: branch.clone(),
+            before: before.get(branch).cloned(),
+            after: after.get(branch).cloned(),
+            freeze_override: freeze_override.clone(),
+        };
+        let mut entries: Vec<_> = branches
+            .into_iter()
+            .filter(|branch| before.get(*branch) != after.get(*branch))
+            .map(entry)
+            .collect();
+        if entries.is_empty() && freeze_override.is_some() {
+            // Pushes move no local branch, the checked out one stands for
+            // the repository.
+            if let Ok(Some(branch)) = repo.current_branch() {
+                entries.push(entry(&branch));
+            }
+        }
+        if !entries.is_empty() {
+            if let Err(e) = self.append(&entries) {
+                eprintln!("warning: {}: not journaled: {}", repo.name(), e);
+            }
+        }
+        result
+    }
+}
+
+fn branch_tips(git: &Repository) -> Result<BTreeMap<String, String>> {
+    let mut tips = BTreeMap::new();
+    for branch in git.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        if let (Some(name), Some(target)) = (branch.name()?, branch.get().target()) {
+            tips.insert(name.to_string(), target.to_string());
+        }
+    }
+    Ok(tips)
+}
+
+/// Seconds since the epoch.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// The time `since`, like `1 hour ago` or `yesterday`, understood the way
+/// git does, in seconds since the epoch.
+pub fn parse_since(repo: &GitRepository, since: &str) -> Result<i64> {
+    let parsed = repo.git(["rev-parse", &format!("--since={}", since)])?;
+    parsed
+        .strip_prefix("--max-age=")
+        .and_then(|seconds| seconds.parse().ok())
+        .ok_or_else(|| Error::Operation(format!("invalid date: {}", since)))
+}
+
+/// A reflog entry of a branch.
+#[derive(Debug, Clone)]
+pub struct ReflogEntry {
+    pub branch: String,
+    pub time: i64,
+    pub old: String,
+    pub new: String,
+    pub message: String,
+    /// The journal entry of the git-ws operation that moved the branch.
+    pub batch: Option<JournalEntry>,
+}
+
+/// The reflog entries of the local branches of `repo` since `since`, newest
+/// first, matched with the `journal`.
+pub fn reflog(
+    repo: &GitRepository,
+    since: i64,
+    journal: &[JournalEntry],
+) -> Result<Vec<ReflogEntry>> {
+    let git = repo.open()?;
+    let mut entries = Vec::new();
+    for branch in branch_tips(&git)?.into_keys() {
+        let reflog = git.reflog(&format!("refs/heads/{}", branch))?;
+        for entry in reflog.iter() {
+            let time = entry.committer().when().seconds();
+            if time < since {
+                continue;
+            }
+            let old = entry.id_old().to_string();
+            let new = entry.id_new().to_string();
+            let batch = journal
+                .iter()
+                .rev()
+                .find(|journaled| {
+                    journaled.repo == repo.name()
+                        && journaled.branch == branch
+                        && journaled.after.as_deref() == Some(new.as_str())
+                        && journaled.before.as_deref().unwrap_or(ZERO) == old
+                        && (time - journaled.time).abs() < 3600
+                })
+                .cloned();
+            entries.push(ReflogEntry {
+                branch: branch.clone(),
+                time,
+                old,
+                new,
+                message: entry.message().unwrap_or_default().to_string(),
+                batch,
+            });
+        }
+    }
+    entries.sort_by_key(|entry| Reverse(entry.time));
+    Ok(entries)
+}
+
+const ZERO: &str = "0000000000000000000000000000000000000000";
+
+/// How long ago `time`, in seconds since the epoch, was.
+pub fn ago(time: i64) -> String {
+    let elapsed = (now() - time).max(0);
+    match elapsed {
+        0..=59 => format!("{} seconds ago", elapsed),
+        60..=3599 => format!("{} minutes ago", elapsed / 60),
+        3600..=86399 => format!("{} hours ago", elapsed / 3600),
+        _ => format!("{} days ago", elapsed / 86400),
+    }
+}
+
+/// A batch of the journal.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    pub id: String,
+    /// When the last branch moved.
+    pub time: i64,
+    pub operations: Vec<String>,
+    pub entries: Vec<JournalEntry>,
+}
+
+/// The batches of the journal, the latest first.
+pub fn batches(journal: Vec<JournalEntry>) -> Vec<Batch> {
+    let mut batches: Vec<Batch> = Vec::new();
+    // Entries recording a freeze override may have moved nothing.
+    for entry in journal
+        .into_iter()
+        .filter(|entry| entry.before != entry.after)
+    {
+        let batch = match batches.iter_mut().find(|batch| batch.id == entry.batch) {
+            Some(batch) => batch,
+            None => {
+                batches.push(Batch {
+                    id: entry.batch.clone(),
+                    time: entry.time,
+                    operations: Vec::new(),
+                    entries: Vec::new(),
+                });
+                batches.last_mut().expect("just pushed")
+            }
+        };
+        batch.time = batch.time.max(entry.time);
+        if !batch.operations.contains(&entry.operation) {
+            batch.operations.push(entry.operation.clone());
+        }
+        batch.entries.push(entry);
+    }
+    batches.sort_by_key(|batch| Reverse(batch.time));
+    batches
+}
+
+/// Moves a branch back to where it was before a batch.
+#[derive(Debug, Clone)]
+pub struct Reset {
+    pub branch: String,
+    /// Where the batch left the branch.
+    pub from: Option<String>,
+    /// Where the batch found it.
+    pub to: Option<String>,
+}
+
+/// The resets undoing `batch`, by repository.
+pub fn resets(batch: &Batch) -> BTreeMap<String, Vec<Reset>> {
+    let mut resets: BTreeMap<String, Vec<Reset>> = BTreeMap::new();
+    for entry in &batch.entries {
+        let repo = resets.entry(entry.repo.clone()).or_default();
+        match repo.iter_mut().find(|reset| reset.branch == entry.branch) {
+            // The branch moved more than once, it goes back to where the
+            // first move found it.
+            Some(reset) => reset.from = entry.after.clone(),
+            None => repo.push(Reset {
+                branch: entry.branch.clone(),
+                from: entry.after.clone(),
+                to: entry.before.clone(),
+            }),
+        }
+    }
+    resets
+}
+
+/// Resets the branches a batch moved back to where the batch found them.
+/// A branch moved again since is left alone, unless `force`d.
+pub struct RecoverOperation {
+    resets: BTreeMap<String, Vec<Reset>>,
+    force: bool,
+}
+
+impl RecoverOperation {
+    pub fn new(resets: BTreeMap<String, Vec<Reset>>, force: bool) -> Self {
+        RecoverOperation { resets, force }
+    }
+}
+
+impl GitOperation for RecoverOperation {
+    fn name(&self) -> &str {
+        "recover"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let Some(resets) = self.resets.get(repo.name()) else {
+            return Err(Error::Skipped("not moved by the batch".to_string()));
+        };
+        let git = repo.open()?;
+        let tips = branch_tips(&git)?;
+        let current = repository::current_branch(&git)?;
+        let mut done = Vec::new();
+        let mut moved = Vec::new();
+        for reset in resets {
+            let tip = tips.get(&reset.branch);
+            if tip != reset.from.as_ref() && !self.force {
+                moved.push(reset.branch.clone());
+                continue;
+            }
+            let refname = format!("refs/heads/{}", reset.branch);
+            match &reset.to {
+                Some(to) if current.as_ref() == Some(&reset.branch) => {
+                    repo.git(["reset", "--quiet", "--keep", to])?;
+                }
+                Some(to) => {
+                    repo.git(["update-ref", "-m", "git-ws recover", &refname, to])?;
+                }
+                None if current.as_ref() == Some(&reset.branch) => {
+                    return Err(Error::Operation(format!(
+                        "{} was created by the batch and is checked out",
+                        reset.branch
+                    )));
+                }
+                None => {
+                    repo.git(["update-ref", "-d", &refname])?;
+                }
+            }
+            done.push(reset.branch.clone());
+        }
+        if !moved.is_empty() {
+            return Err(Error::Warning(format!(
+                "moved since the batch, left alone: {}{}",
+                moved.join(", "),
+                if done.is_empty() {
+                    String::new()
+                } else {
+                    format!("; reset {}", done.join(", "))
+                }
+            )));
+        }
+        Ok(format!("reset {}", done.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::precondition::Preconditions;
+    use crate::testing::{RepoState, TestWorkspace};
+
+    struct Branch;
+
+    impl GitOperation for Branch {
+        fn name(&self) -> &str {
+            "branch"
+        }
+
+        fn is_mutating(&self) -> bool {
+            true
+        }
+
+        fn execute(&self, repo: &GitRepository) -> Result<String> {
+            repo.git(["branch", "moved"])?;
+            Ok("created".to_string())
+        }
+    }
+
+    #[test]
+    fn failing_to_journal_keeps_the_outcome() {
+        let test = TestWorkspace::builder()
+            .repo("api", RepoState::Clean)
+            .build()
+            .unwrap();
+        let repo = test.repository("api");
+        // A file where the state directory should be.
+        let dir = repo.path().join("README");
+        let journal = Journal::new(&dir, "batch");
+        let preconditions = Preconditions::default();
+        let result = journal.call(&repo, &Branch, Next::new(&Branch, &[], &preconditions));
+        assert_eq!(result.unwrap(), "created");
+    }
+
+    #[test]
+    fn records_the_freeze_overridden_outside_the_executor() -> Result<()> {
+        let test = TestWorkspace::builder()
+            .repo("api", RepoState::Clean)
+            .build()?;
+        let repo = test.repository("api");
+        let dir = test.root().join("state");
+        fs::write(test.root().join("calendar.txt"), "1970-01-01 epoch\n")?;
+        let config = crate::freeze::FreezeConfig {
+            calendar: Some(PathBuf::from("calendar.txt")),
+            utc_offset: Some("+00:00".to_string()),
+            ..Default::default()
+        };
+
+        let thawed = FreezeGuard::new(&config, test.root(), 86400, Some("hotfix"));
+        Journal::new(&dir, "thawed")
+            .with_freeze(Arc::new(thawed))
+            .record_override(&repo, "pr merge", "main")?;
+        assert!(load(&dir)?.is_empty());
+
+        let frozen = FreezeGuard::new(&config, test.root(), 3600, Some("hotfix"));
+        Journal::new(&dir, "frozen")
+            .with_freeze(Arc::new(frozen))
+            .record_override(&repo, "pr merge", "main")?;
+        let entries = load(&dir)?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].batch, "frozen");
+        assert_eq!(entries[0].operation, "pr merge");
+        assert_eq!(entries[0].branch, "main");
+        assert_eq!(entries[0].freeze_override.as_deref(), Some("hotfix"));
+        // Nothing moved locally, recovering the batch leaves the branch.
+        assert_eq!(entries[0].before, Some(repo.head_sha()?));
+        assert_eq!(entries[0].before, entries[0].after);
+        Ok(())
+    }
+}
@@ -0,0 +1,300 @@
+//! The remotes of the repositories, and parsing of their URLs.
+
+use std::fmt;
+
+use crate::operations::GitOperation;
+use crate::repository::GitRepository;
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Scheme {
+    Ssh,
+    Https,
+    Http,
+    Git,
+}
+
+impl Scheme {
+    pub fn default_port(self) -> u16 {
+        match self {
+            Scheme::Ssh => 22,
+            Scheme::Https => 443,
+            Scheme::Http => 80,
+            Scheme::Git => 9418,
+        }
+    }
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Scheme::Ssh => "ssh",
+            Scheme::Https => "https",
+            Scheme::Http => "http",
+            Scheme::Git => "git",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A remote reached over the network. Local paths and `file://` URLs have
+/// no counterpart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteUrl {
+    pub scheme: Scheme,
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+    pub path: String,
+}
+
+impl RemoteUrl {
+    /// Parses `scheme://[user@]host[:port]/path` URLs as well as the scp-like
+    /// `[user@]host:path` syntax used for ssh.
+    pub fn parse(url: &str) -> Option<Self> {
+        if let Some((scheme, rest)) = url.split_once("://") {
+            let scheme = match scheme {
+                "ssh" | "git+ssh" | "ssh+git" => Scheme::Ssh,
+                "https" => Scheme::Https,
+                "http" => Scheme::Http,
+                "git" => Scheme::Git,
+                _ => return None,
+            };
+            let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+            let (user, host_port) = match authority.rsplit_once('@') {
+                Some((user, host)) => (Some(user), host),
+                None => (None, authority),
+            };
+            let (host, port) = match host_port.rsplit_once(':') {
+                Some((host, port)) if !host.is_empty() => (host, port.parse().ok()),
+                _ => (host_port, None),
+            };
+            if host.is_empty() {
+                return None;
+            }
+            return Some(RemoteUrl {
+                scheme,
+                user: user.map(str::to_string),
+                host: host.to_string(),
+                port,
+                path: path.to_string(),
+            });
+        }
+
+        // scp-like syntax; a colon after the first slash means a local path.
+        let (authority, path) = url.split_once(':')?;
+        if authority.is_empty() || authority.contains('/') || authority.len() == 1 {
+            return None;
+        }
+        let (user, host) = match authority.split_once('@') {
+            Some((user, host)) => (Some(user.to_string()), host),
+            None => (None, authority),
+        };
+        Some(RemoteUrl {
+            scheme: Scheme::Ssh,
+            user,
+            host: host.to_string(),
+            port: None,
+            path: path.to_string(),
+        })
+    }
+
+    pub fn port_or_default(&self) -> u16 {
+        self.port.unwrap_or_else(|| self.scheme.default_port())
+    }
+}
+
+/// A remote of a repository.
+#[derive(Debug, Clone)]
+pub struct RemoteEntry {
+    pub name: String,
+    pub url: Option<String>,
+    /// URL pushed to, when it differs from `url`.
+    pub push_url: Option<String>,
+}
+
+/// The remotes of `repo`, sorted by name.
+pub fn list(repo: &GitRepository) -> Result<Vec<RemoteEntry>> {
+    let git = repo.open()?;
+    let mut remotes = Vec::new();
+    for name in git.remotes()?.iter().flatten() {
+        let remote = git.find_remote(name)?;
+        remotes.push(RemoteEntry {
+            name: name.to_string(),
+            url: remote.url().map(str::to_string),
+            push_url: remote.pushurl().map(str::to_string),
+        });
+    }
+    remotes.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(remotes)
+}
+
+/// `url` for `repo`, `{repo}` replaced with its name in the workspace and
+/// `{name}` with the last component of it, like `api` for `payments/api`.
+fn expand(url: &str, repo: &GitRepository) -> String {
+    let name = repo.name().rsplit('/').next().unwrap_or(repo.name());
+    url.replace("{repo}", repo.name()).replace("{name}", name)
+}
+
+fn url_of(repo: &GitRepository, name: &str, push: bool) -> Option<String> {
+    let mut args = vec!["remote", "get-url"];
+    if push {
+        args.push("--push");
+    }
+    args.push(name);
+    repo.git(args).ok()
+}
+
+/// Adds a remote to every repository, skipping those having it already.
+pub struct AddRemoteOperation {
+    name: String,
+    url: String,
+}
+
+impl AddRemoteOperation {
+    pub fn new(name: impl Into<String>, url: impl Into<String>) -> Self {
+        AddRemoteOperation {
+            name: name.into(),
+            url: url.into(),
+        }
+    }
+}
+
+impl GitOperation for AddRemoteOperation {
+    fn name(&self) -> &str {
+        "remote add"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let url = expand(&self.url, repo);
+        if let Some(existing) = url_of(repo, &self.name, false) {
+            if existing == url {
+                return Err(Error::Skipped(format!("{} exists already", self.name)));
+            }
+            return Err(Error::Operation(format!(
+                "{} exists already, with {}, use remote set-url",
+                self.name, existing
+            )));
+        }
+        repo.git(["remote", "add", &self.name, &url])?;
+        Ok(format!("added {}", url))
+    }
+}
+
+/// Removes a remote, and its remote-tracking branches, from every
+/// repository having it.
+pub struct RemoveRemoteOperation {
+    name: String,
+}
+
+impl RemoveRemoteOperation {
+    pub fn new(name: impl Into<String>) -> Self {
+        RemoveRemoteOperation { name: name.into() }
+    }
+}
+
+impl GitOperation for RemoveRemoteOperation {
+    fn name(&self) -> &str {
+        "remote remove"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let Some(url) = url_of(repo, &self.name, false) else {
+            return Err(Error::Skipped(format!("no remote {}", self.name)));
+        };
+        repo.git(["remote", "remove", &self.name])?;
+        Ok(format!("removed, was {}", url))
+    }
+}
+
+/// Changes the URL of a remote in every repository having it, or only the
+/// URL pushed to.
+pub struct SetRemoteUrlOperation {
+    name: String,
+    url: String,
+    push: bool,
+}
+
+impl SetRemoteUrlOperation {
+    pub fn new(name: impl Into<String>, url: impl Into<String>, push: bool) -> Self {
+        SetRemoteUrlOperation {
+            name: name.into(),
+            url: url.into(),
+            push,
+        }
+    }
+}
+
+impl GitOperation for SetRemoteUrlOperation {
+    fn name(&self) -> &str {
+        "remote set-url"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let Some(previous) = url_of(repo, &self.name, self.push) else {
+            return Err(Error::Skipped(format!("no remote {}", self.name)));
+        };
+        let url = expand(&self.url, repo);
+        if previous == url {
+            return Err(Error::Skipped(format!("{} already", url)));
+        }
+        let mut args = vec!["remote", "set-url"];
+        if self.push {
+            args.push("--push");
+        }
+        args.extend([self.name.as_str(), url.as_str()]);
+        repo.git(args)?;
+        Ok(format!("{}, was {}", url, previous))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_urls_with_a_scheme() {
+        let url = RemoteUrl::parse("ssh://git@example.com:2222/team/api.git").unwrap();
+        assert_eq!(url.scheme, Scheme::Ssh);
+        assert_eq!(url.user.as_deref(), Some("git"));
+        assert_eq!(url.host, "example.com");
+        assert_eq!(url.port, Some(2222));
+        assert_eq!(url.path, "team/api.git");
+
+        let url = RemoteUrl::parse("https://example.com/team/api").unwrap();
+        assert_eq!(url.scheme, Scheme::Https);
+        assert_eq!(url.user, None);
+        assert_eq!(url.port_or_default(), 443);
+    }
+
+    #[test]
+    fn parses_scp_like_urls() {
+        let url = RemoteUrl::parse("git@example.com:team/api.git").unwrap();
+        assert_eq!(url.scheme, Scheme::Ssh);
+        assert_eq!(url.user.as_deref(), Some("git"));
+        assert_eq!(url.host, "example.com");
+        assert_eq!(url.path, "team/api.git");
+        assert_eq!(url.port_or_default(), 22);
+    }
+
+    #[test]
+    fn rejects_local_paths() {
+        assert_eq!(RemoteUrl::parse("/srv/git/api.git"), None);
+        assert_eq!(RemoteUrl::parse("./api:old"), None);
+        assert_eq!(RemoteUrl::parse("C:\\repos\\api"), None);
+        assert_eq!(RemoteUrl::parse("file:///srv/git/api.git"), None);
+        assert_eq!(RemoteUrl::parse("https:///api"), None);
+    }
+}
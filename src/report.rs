@@ -0,0 +1,406 @@
+//! A static HTML dashboard of the workspace, for a glanceable page without
+//! a server.
+//!
+//! `report html --output DIR` writes `DIR/index.html`, one self-contained
+//! file: the status of every repository, workspace statistics, the
+//! repositories whose last commit is older than the stale threshold, and
+//! their compliance with the manifest and with tracking an upstream.
+//!
+//! The page is made from the state cached in `report.json` of the state
+//! directory, which `refresh` updates after fetching, so writing it scans
+//! no repository. Repositories missing from the cache are collected when
+//! the page is written, and `report html --refresh` collects them all.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::mailmap::{self, Identity, Mailmap};
+use crate::manifest::Manifest;
+use crate::output::RepoRecord;
+use crate::recover;
+use crate::repository::{GitRepository, ScanOptions};
+use crate::Result;
+
+const INDEX_FILE: &str = "index.html";
+const CACHE_FILE: &str = "report.json";
+/// Days the commits of the activity statistics are counted over.
+const ACTIVITY_DAYS: u32 = 30;
+
+/// A rule a repository follows or breaks: those of the manifest, and
+/// tracking an upstream.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Compliance {
+    pub rule: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// What the dashboard shows of a repository.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoReport {
+    pub record: RepoRecord,
+    /// When it was collected, in seconds since the epoch.
+    pub collected: i64,
+    /// Time of the last commit on HEAD, in seconds since the epoch.
+    pub last_commit: Option<i64>,
+    /// Commits on HEAD over the last [`ACTIVITY_DAYS`] days.
+    pub recent_commits: usize,
+    /// Authors of those commits, as the mailmaps map them.
+    #[serde(default)]
+    pub recent_authors: Vec<Identity>,
+    pub compliance: Vec<Compliance>,
+}
+
+impl RepoReport {
+    /// Whether the last commit is older than `stale_days`, or there is none.
+    pub fn is_stale(&self, stale_days: u64) -> bool {
+        self.last_commit
+            .is_none_or(|time| recover::now() - time > stale_days as i64 * 86400)
+    }
+}
+
+/// Gathers what the dashboard shows of `repo`, checking it against
+/// `manifest` when the workspace has one, and mapping authors by `mailmap`.
+pub fn collect(
+    repo: &GitRepository,
+    manifest: &Manifest,
+    mailmap: Option<&Mailmap>,
+    pinned: bool,
+) -> Result<RepoReport> {
+    let mut record = RepoRecord::capture(repo, &[], ScanOptions::default())?;
+    record.pinned = pinned;
+    let git = repo.open()?;
+    let last_commit = git
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .map(|commit| commit.time().seconds())
+        .ok();
+    let since = format!("{} days ago", ACTIVITY_DAYS);
+    let identities = mailmap::identities(repo, mailmap, Some(&since))?;
+    let recent_commits = identities.values().sum();
+    let mut recent_authors: Vec<_> = identities.into_keys().map(|(_, mapped)| mapped).collect();
+    recent_authors.sort();
+    recent_authors.dedup();
+    let mut compliance = Vec::new();
+    if !manifest.repositories.is_empty() {
+        let declared = manifest
+            .repositories
+            .iter()
+            .find(|declared| declared.path.trim_end_matches('/') == repo.name());
+        compliance.push(Compliance {
+            rule: "in manifest".to_string(),
+            ok: declared.is_some(),
+            detail: String::new(),
+        });
+        if let Some(declared) = declared {
+            let url = git
+                .find_remote("origin")
+                .ok()
+                .and_then(|remote| remote.url().map(str::to_string));
+            compliance.push(Compliance {
+                rule: "origin as declared".to_string(),
+                ok: url.as_deref() == Some(declared.url.as_str()),
+                detail: url.unwrap_or_else(|| "no origin".to_string()),
+            });
+            if let Some(branch) = &declared.branch {
+                compliance.push(Compliance {
+                    rule: "on declared branch".to_string(),
+                    ok: record.branch.as_ref() == Some(branch),
+                    detail: record
+                        .branch
+                        .clone()
+                        .unwrap_or_else(|| "detached".to_string()),
+                });
+            }
+        }
+    }
+    compliance.push(Compliance {
+        rule: "tracks upstream".to_string(),
+        ok: record.upstream.is_some(),
+        detail: record.upstream.clone().unwrap_or_default(),
+    });
+    Ok(RepoReport {
+        record,
+        collected: recover::now(),
+        last_commit,
+        recent_commits,
+        recent_authors,
+        compliance,
+    })
+}
+
+/// The reports cached in the state directory `dir`, by repository.
+pub fn load_cache(dir: &Path) -> Result<BTreeMap<String, RepoReport>> {
+    let path = dir.join(CACHE_FILE);
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Caches `reports` in the state directory `dir`, along with those of the
+/// other repositories cached already.
+pub fn save_cache(dir: &Path, reports: &[RepoReport]) -> Result<()> {
+    let mut cache = load_cache(dir).unwrap_or_default();
+    for report in reports {
+        cache.insert(report.record.repo.clone(), report.clone());
+    }
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(CACHE_FILE), serde_json::to_string(&cache)?)?;
+    Ok(())
+}
+
+/// Writes the dashboard of `reports` in `dir`, returning the path of the
+/// page.
+pub fn write_html(
+    dir: &Path,
+    title: &str,
+    reports: &[RepoReport],
+    stale_days: u64,
+) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(INDEX_FILE);
+    fs::write(&path, html(title, reports, stale_days))?;
+    Ok(path)
+}
+
+/// The dashboard of `reports`, one HTML page needing nothing else.
+pub fn html(title: &str, reports: &[RepoReport], stale_days: u64) -> String {
+    let count = |keep: &dyn Fn(&RepoReport) -> bool| reports.iter().filter(|r| keep(r)).count();
+    let dirty = count(&|r| r.record.dirty);
+    let ahead = count(&|r| r.record.ahead > 0);
+    let behind = count(&|r| r.record.behind > 0);
+    let no_upstream = count(&|r| r.record.upstream.is_none());
+    let detached = count(&|r| r.record.branch.is_none());
+    let stale: Vec<_> = reports.iter().filter(|r| r.is_stale(stale_days)).collect();
+    let breaking = count(&|r| r.compliance.iter().any(|c| !c.ok));
+    let recent: usize = reports.iter().map(|r| r.recent_commits).sum();
+    // People committing in several repositories count once.
+    let authors = reports
+        .iter()
+        .flat_map(|r| &r.recent_authors)
+        .map(|author| author.email.to_lowercase())
+        .collect::<BTreeSet<_>>()
+        .len();
+
+    let mut page = String::new();
+    let title = escape(title);
+    let _ = write!(
+        page,
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; margin: 2em; color: #24292f; }}
+h1 {{ margin-bottom: 0; }}
+.generated {{ color: #6e7781; margin-top: .2em; }}
+.stats {{ display: flex; flex-wrap: wrap; gap: 1em; margin: 1.5em 0; }}
+.stat {{ border: 1px solid #d0d7de; border-radius: 6px; padding: .6em 1em; min-width: 7em; }}
+.stat b {{ display: block; font-size: 1.6em; }}
+table {{ border-collapse: collapse; width: 100%; margin-bottom: 2em; }}
+th, td {{ border-bottom: 1px solid #d0d7de; padding: .4em .6em; text-align: left; }}
+th {{ background: #f6f8fa; }}
+.ok {{ color: #1a7f37; }}
+.warn {{ color: #9a6700; }}
+.bad {{ color: #cf222e; }}
+code {{ font-size: .9em; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p class="generated">Generated {generated} UTC, from the state collected {collected}.</p>
+<div class="stats">
+"#,
+        generated = utc(recover::now()),
+        collected = reports
+            .iter()
+            .map(|report| report.collected)
+            .min()
+            .map_or_else(
+                || "now".to_string(),
+                |time| format!("since {} UTC", utc(time))
+            ),
+    );
+    for (label, value) in [
+        ("repositories", reports.len()),
+        ("dirty", dirty),
+        ("ahead", ahead),
+        ("behind", behind),
+        ("without upstream", no_upstream),
+        ("detached", detached),
+        ("stale", stale.len()),
+        ("not compliant", breaking),
+        (&format!("commits in {} days", ACTIVITY_DAYS), recent),
+        (&format!("authors in {} days", ACTIVITY_DAYS), authors),
+    ] {
+        let _ = writeln!(
+            page,
+            r#"<div class="stat"><b>{}</b>{}</div>"#,
+            value,
+            escape(label)
+        );
+    }
+    page.push_str("</div>\n");
+
+    page.push_str("<h2>Status</h2>\n<table>\n<tr><th>Repository</th><th>Branch</th><th>Commit</th><th>Changes</th><th>Upstream</th><th>Last commit</th></tr>\n");
+    for report in reports {
+        let record = &report.record;
+        let changes = if record.dirty {
+            let parts: Vec<String> = [
+                (record.staged, "staged"),
+                (record.modified, "modified"),
+                (record.renamed, "renamed"),
+                (record.untracked, "untracked"),
+                (record.conflicted, "conflicted"),
+            ]
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, label)| format!("{} {}", count, label))
+            .collect();
+            format!(r#"<span class="warn">{}</span>"#, parts.join(", "))
+        } else {
+            r#"<span class="ok">clean</span>"#.to_string()
+        };
+        let upstream = match &record.upstream {
+            None => r#"<span class="warn">none</span>"#.to_string(),
+            Some(upstream) if record.ahead == 0 && record.behind == 0 => {
+                format!(r#"<span class="ok">{}</span>"#, escape(upstream))
+            }
+            Some(upstream) => format!(
+                r#"<span class="warn">{} ↑{} ↓{}</span>"#,
+                escape(upstream),
+                record.ahead,
+                record.behind
+            ),
+        };
+        let _ = writeln!(
+            page,
+            "<tr><td>{}{}</td><td>{}</td><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&record.repo),
+            if record.pinned { " 📌" } else { "" },
+            escape(record.branch.as_deref().unwrap_or("(detached)")),
+            record.head.as_deref().map_or("", |head| &head[..head.len().min(7)]),
+            changes,
+            upstream,
+            report.last_commit.map(recover::ago).unwrap_or_default(),
+        );
+    }
+    page.push_str("</table>\n");
+
+    let _ = writeln!(
+        page,
+        "<h2>Stale repositories</h2>\n<p>No commit for more than {} days.</p>",
+        stale_days
+    );
+    if stale.is_empty() {
+        page.push_str("<p class=\"ok\">None.</p>\n");
+    } else {
+        page.push_str("<table>\n<tr><th>Repository</th><th>Last commit</th></tr>\n");
+        for report in &stale {
+            let _ = writeln!(
+                page,
+                "<tr><td>{}</td><td>{}</td></tr>",
+                escape(&report.record.repo),
+                report
+                    .last_commit
+                    .map(recover::ago)
+                    .unwrap_or_else(|| "never".to_string()),
+            );
+        }
+        page.push_str("</table>\n");
+    }
+
+    page.push_str("<h2>Compliance</h2>\n");
+    let broken: Vec<_> = reports
+        .iter()
+        .flat_map(|report| {
+            report
+                .compliance
+                .iter()
+                .filter(|c| !c.ok)
+                .map(move |c| (&report.record.repo, c))
+        })
+        .collect();
+    if broken.is_empty() {
+        page.push_str("<p class=\"ok\">Every repository complies.</p>\n");
+    } else {
+        page.push_str("<table>\n<tr><th>Repository</th><th>Rule</th><th>Found</th></tr>\n");
+        for (repo, compliance) in broken {
+            let _ = writeln!(
+                page,
+                "<tr><td>{}</td><td class=\"bad\">{}</td><td>{}</td></tr>",
+                escape(repo),
+                compliance.rule,
+                escape(&compliance.detail),
+            );
+        }
+        page.push_str("</table>\n");
+    }
+    page.push_str("</body>\n</html>\n");
+    page
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Like `2024-03-18 14:05`, for `time` in seconds since the epoch.
+fn utc(time: i64) -> String {
+    let days = time.div_euclid(86400);
+    let seconds = time.rem_euclid(86400);
+    // Civil date from days since the epoch, after Howard Hinnant.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{RepoState, TestWorkspace};
+
+    #[test]
+    fn caches_the_reports() {
+        let test = TestWorkspace::builder()
+            .repo("api", RepoState::Clean)
+            .repo("web", RepoState::Dirty)
+            .build()
+            .unwrap();
+        let dir = test.workspace().state_dir();
+        let manifest = Manifest::default();
+        let api = collect(&test.repository("api"), &manifest, None, false).unwrap();
+        let web = collect(&test.repository("web"), &manifest, None, false).unwrap();
+        assert!(load_cache(&dir).unwrap().is_empty());
+
+        save_cache(&dir, &[api]).unwrap();
+        save_cache(&dir, &[web]).unwrap();
+        let cache = load_cache(&dir).unwrap();
+        assert_eq!(cache.keys().collect::<Vec<_>>(), ["api", "web"]);
+        assert!(!cache["api"].record.dirty);
+        assert!(cache["web"].record.dirty);
+        assert_eq!(cache["web"].compliance[0].rule, "tracks upstream");
+    }
+}
@@ -0,0 +1,481 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use git2::{BranchType, Delta, Repository, Status, StatusOptions};
+use serde::{Deserialize, Serialize};
+
+use crate::interactive;
+use crate::{Error, Result};
+
+/// A repository that belongs to the workspace.
+///
+/// The name is the path of the repository relative to the workspace root,
+/// always using `/` as separator so it is stable across platforms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitRepository {
+    name: String,
+    path: PathBuf,
+}
+
+impl GitRepository {
+    pub fn new(name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        GitRepository {
+            name: name.into(),
+            path: path.into(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn open(&self) -> Result<Repository> {
+        Ok(Repository::open(&self.path)?)
+    }
+
+    /// Name of the checked out branch, `None` when HEAD is detached.
+    pub fn current_branch(&self) -> Result<Option<String>> {
+        current_branch(&self.open()?)
+    }
+
+    /// Everything commands usually want to know about the repository,
+    /// collected at once.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        Snapshot::capture(&self.open()?, &[])
+    }
+
+    /// Full id of the commit HEAD points to.
+    pub fn head_sha(&self) -> Result<String> {
+        Ok(self.open()?.head()?.peel_to_commit()?.id().to_string())
+    }
+
+    /// Runs the git command line inside the repository and returns its
+    /// trimmed standard output. Used for the few features libgit2 lacks.
+    pub fn git<I, S>(&self, args: I) -> Result<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let output = interactive::command("git")
+            .args(args)
+            .current_dir(&self.path)
+            .output()?;
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout)
+                .trim_end()
+                .to_string())
+        } else {
+            Err(Error::Operation(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ))
+        }
+    }
+}
+
+/// State of a repository at one point in time.
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    /// Checked out branch, `None` when HEAD is detached.
+    pub branch: Option<String>,
+    /// Full id of the commit HEAD points to, `None` before the first commit.
+    pub head: Option<String>,
+    pub upstream: Option<Upstream>,
+    pub changes: ChangeCounts,
+    /// Renamed files, counted in `changes.renamed`.
+    pub renames: Vec<Rename>,
+    /// Ignored files and directories, when asked for, counted in
+    /// `changes.ignored`.
+    pub ignored: Vec<String>,
+    pub remotes: Vec<RemoteInfo>,
+    /// Tags pointing at HEAD.
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Upstream {
+    /// Short name, like `origin/main`.
+    pub name: String,
+    pub ahead: usize,
+    pub behind: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteInfo {
+    pub name: String,
+    pub url: Option<String>,
+}
+
+impl Snapshot {
+    /// Captures `repo`, counting only the changes to paths matching
+    /// `pathspecs`, every path when there is none.
+    pub fn capture(repo: &Repository, pathspecs: &[String]) -> Result<Self> {
+        Snapshot::capture_with(repo, pathspecs, ScanOptions::default())
+    }
+
+    /// Like [`Snapshot::capture`], scanning the changes with `options`.
+    pub fn capture_with(
+        repo: &Repository,
+        pathspecs: &[String],
+        options: ScanOptions,
+    ) -> Result<Self> {
+        let branch = current_branch(repo)?;
+        let head = repo
+            .head()
+            .ok()
+            .and_then(|head| head.peel_to_commit().ok())
+            .map(|commit| commit.id());
+
+        let mut remotes = Vec::new();
+        for name in repo.remotes()?.iter().flatten() {
+            remotes.push(RemoteInfo {
+                name: name.to_string(),
+                url: repo.find_remote(name)?.url().map(str::to_string),
+            });
+        }
+
+        let mut tags = Vec::new();
+        if let Some(head) = head {
+            repo.tag_foreach(|oid, name| {
+                let target = repo
+                    .find_object(oid, None)
+                    .and_then(|object| object.peel_to_commit());
+                if matches!(target, Ok(commit) if commit.id() == head) {
+                    let name = String::from_utf8_lossy(name);
+                    tags.push(name.trim_start_matches("refs/tags/").to_string());
+                }
+                true
+            })?;
+            tags.sort();
+        }
+
+        let scan = ChangeCounts::scan(repo, pathspecs, options)?;
+        Ok(Snapshot {
+            upstream: upstream(repo, branch.as_deref())?,
+            branch,
+            head: head.map(|oid| oid.to_string()),
+            changes: scan.counts,
+            renames: scan.renames,
+            ignored: scan.ignored,
+            remotes,
+            tags,
+        })
+    }
+
+    /// The branch name, or the abbreviated commit id when HEAD is detached.
+    pub fn head_description(&self) -> String {
+        match (&self.branch, &self.head) {
+            (Some(branch), _) => branch.clone(),
+            (None, Some(head)) => format!("(detached at {})", &head[..7]),
+            (None, None) => "(no commits)".to_string(),
+        }
+    }
+}
+
+fn upstream(repo: &Repository, branch: Option<&str>) -> Result<Option<Upstream>> {
+    let local = match branch.and_then(|branch| repo.find_branch(branch, BranchType::Local).ok()) {
+        Some(local) => local,
+        None => return Ok(None),
+    };
+    let upstream = match local.upstream() {
+        Ok(upstream) => upstream,
+        Err(_) => return Ok(None),
+    };
+    let name = upstream.name()?.unwrap_or_default().to_string();
+    let (ahead, behind) = match (local.get().target(), upstream.get().target()) {
+        (Some(local), Some(upstream)) => repo.graph_ahead_behind(local, upstream)?,
+        _ => (0, 0),
+    };
+    Ok(Some(Upstream {
+        name,
+        ahead,
+        behind,
+    }))
+}
+
+/// Name of the checked out branch, `None` when HEAD is detached.
+///
+/// An unborn branch (a fresh repository without commits) still reports its
+/// name.
+pub fn current_branch(repo: &Repository) -> Result<Option<String>> {
+    match repo.head() {
+        Ok(head) if head.is_branch() => Ok(head.shorthand().map(str::to_string)),
+        Ok(_) => Ok(None),
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => {
+            let head = repo.find_reference("HEAD")?;
+            Ok(head
+                .symbolic_target()
+                .map(|target| target.trim_start_matches("refs/heads/").to_string()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Human readable description of HEAD: the branch name, or the abbreviated
+/// commit id when HEAD is detached.
+pub fn head_description(repo: &Repository) -> Result<String> {
+    if let Some(branch) = current_branch(repo)? {
+        return Ok(branch);
+    }
+    let oid = repo.head()?.peel_to_commit()?.id();
+    Ok(format!("(detached at {})", short_id(oid)))
+}
+
+/// Commits ahead of and behind the upstream of the current branch, `None`
+/// when there is no upstream configured.
+pub fn ahead_behind(repo: &Repository) -> Result<Option<(usize, usize)>> {
+    let branch = current_branch(repo)?;
+    Ok(upstream(repo, branch.as_deref())?.map(|upstream| (upstream.ahead, upstream.behind)))
+}
+
+/// The branch the remote `origin` considers its default, falling back to a
+/// local `main` or `master` branch.
+pub fn default_branch(repo: &Repository) -> Result<Option<String>> {
+    if let Ok(head) = repo.find_reference("refs/remotes/origin/HEAD") {
+        if let Some(target) = head.symbolic_target() {
+            return Ok(Some(
+                target
+                    .trim_start_matches("refs/remotes/origin/")
+                    .to_string(),
+            ));
+        }
+    }
+    for candidate in ["main", "master"] {
+        if repo.find_branch(candidate, BranchType::Local).is_ok() {
+            return Ok(Some(candidate.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+pub fn short_id(oid: git2::Oid) -> String {
+    oid.to_string()[..7].to_string()
+}
+
+/// How renamed files are told from a deleted file and an added one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenameDetection {
+    Off,
+    /// Files at least this similar, in percent, are renames.
+    Similarity(u16),
+}
+
+impl Default for RenameDetection {
+    /// 50%, like git.
+    fn default() -> Self {
+        RenameDetection::Similarity(50)
+    }
+}
+
+/// Which ignored files a scan reports.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IgnoredFiles {
+    #[default]
+    Hidden,
+    /// Directories holding only ignored files as one entry, like
+    /// `git status --ignored`.
+    Collapsed,
+    /// Every ignored file, like `git status --ignored=matching`.
+    Matching,
+}
+
+/// What a scan of the changes looks for besides the counts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ScanOptions {
+    pub renames: RenameDetection,
+    pub ignored: IgnoredFiles,
+}
+
+/// Changes found by [`ChangeCounts::scan`].
+#[derive(Debug, Clone, Default)]
+pub struct Scan {
+    pub counts: ChangeCounts,
+    pub renames: Vec<Rename>,
+    /// Paths of the ignored files, directories ending with `/`.
+    pub ignored: Vec<String>,
+}
+
+/// A file renamed in the index or in the working tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rename {
+    pub from: String,
+    pub to: String,
+    /// Whether the rename is staged.
+    pub staged: bool,
+}
+
+impl fmt::Display for Rename {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}", self.from, self.to)
+    }
+}
+
+/// Number of changed files in the index and the working tree.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ChangeCounts {
+    pub staged: usize,
+    pub modified: usize,
+    /// Renamed files, staged or not, which count as nothing else.
+    pub renamed: usize,
+    pub untracked: usize,
+    pub conflicted: usize,
+    /// Ignored files, only counted when asked for. They do not make the
+    /// repository unclean.
+    pub ignored: usize,
+}
+
+impl ChangeCounts {
+    pub fn collect(repo: &Repository) -> Result<Self> {
+        ChangeCounts::collect_matching(repo, &[])
+    }
+
+    /// Counts only the paths matching one of `pathspecs`, every path when
+    /// there is none.
+    pub fn collect_matching(repo: &Repository, pathspecs: &[String]) -> Result<Self> {
+        Ok(ChangeCounts::scan(repo, pathspecs, ScanOptions::default())?.counts)
+    }
+
+    /// Counts the paths matching one of `pathspecs`, every path when there
+    /// is none, and lists the renamed and ignored ones as `options` ask.
+    pub fn scan(repo: &Repository, pathspecs: &[String], options: ScanOptions) -> Result<Scan> {
+        let mut status_options = StatusOptions::new();
+        status_options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true);
+        if let RenameDetection::Similarity(similarity) = options.renames {
+            status_options
+                .renames_head_to_index(true)
+                .renames_index_to_workdir(true)
+                .rename_threshold(similarity);
+        }
+        if options.ignored != IgnoredFiles::Hidden {
+            status_options
+                .include_ignored(true)
+                .recurse_ignored_dirs(options.ignored == IgnoredFiles::Matching);
+        }
+        for pathspec in pathspecs {
+            status_options.pathspec(pathspec);
+        }
+        let mut scan = Scan::default();
+        for entry in repo.statuses(Some(&mut status_options))?.iter() {
+            if entry.status().is_ignored() {
+                scan.counts.ignored += 1;
+                scan.ignored.extend(entry.path().map(str::to_string));
+                continue;
+            }
+            scan.counts.add(entry.status());
+            let deltas = [
+                (entry.head_to_index(), true),
+                (entry.index_to_workdir(), false),
+            ];
+            for (delta, staged) in deltas {
+                let Some(delta) = delta.filter(|delta| delta.status() == Delta::Renamed) else {
+                    continue;
+                };
+                let path = |file: git2::DiffFile<'_>| {
+                    file.path()
+                        .map(|path| path.to_string_lossy().replace('\\', "/"))
+                        .unwrap_or_default()
+                };
+                scan.renames.push(Rename {
+                    from: path(delta.old_file()),
+                    to: path(delta.new_file()),
+                    staged,
+                });
+            }
+        }
+        Ok(scan)
+    }
+
+    fn add(&mut self, status: Status) {
+        if status.is_conflicted() {
+            self.conflicted += 1;
+            return;
+        }
+        if status.intersects(Status::INDEX_RENAMED | Status::WT_RENAMED) {
+            self.renamed += 1;
+            return;
+        }
+        if status.intersects(
+            Status::INDEX_NEW
+                | Status::INDEX_MODIFIED
+                | Status::INDEX_DELETED
+                | Status::INDEX_RENAMED
+                | Status::INDEX_TYPECHANGE,
+        ) {
+            self.staged += 1;
+        }
+        if status.intersects(
+            Status::WT_MODIFIED | Status::WT_DELETED | Status::WT_RENAMED | Status::WT_TYPECHANGE,
+        ) {
+            self.modified += 1;
+        }
+        if status.contains(Status::WT_NEW) {
+            self.untracked += 1;
+        }
+    }
+
+    pub fn is_clean(&self) -> bool {
+        ChangeCounts {
+            ignored: 0,
+            ..*self
+        } == ChangeCounts::default()
+    }
+}
+
+impl fmt::Display for ChangeCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<String> = [
+            (self.staged, "staged"),
+            (self.modified, "modified"),
+            (self.renamed, "renamed"),
+            (self.untracked, "untracked"),
+            (self.conflicted, "conflicted"),
+            (self.ignored, "ignored"),
+        ]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{} {}", count, label))
+        .collect();
+        if self.is_clean() {
+            parts.insert(0, "clean".to_string());
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{RepoState, TestWorkspace};
+
+    #[test]
+    fn snapshots_the_state_of_repositories() {
+        let test = TestWorkspace::builder()
+            .repo("clean", RepoState::Clean)
+            .repo("dirty", RepoState::Dirty)
+            .repo("ahead", RepoState::Ahead(2))
+            .repo("behind", RepoState::Behind(3))
+            .repo("conflicted", RepoState::Conflicted)
+            .build()
+            .unwrap();
+        let snapshot = |name: &str| test.repository(name).snapshot().unwrap();
+
+        let clean = snapshot("clean");
+        assert_eq!(clean.head_description(), "main");
+        assert!(clean.changes.is_clean());
+        let dirty = snapshot("dirty").changes;
+        assert_eq!((dirty.modified, dirty.untracked), (1, 1));
+        let ahead = snapshot("ahead").upstream.unwrap();
+        assert_eq!(
+            (ahead.name.as_str(), ahead.ahead, ahead.behind),
+            ("origin/main", 2, 0)
+        );
+        let behind = snapshot("behind").upstream.unwrap();
+        assert_eq!((behind.ahead, behind.behind), (0, 3));
+        assert_eq!(snapshot("conflicted").changes.conflicted, 1);
+    }
+}
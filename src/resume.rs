@@ -0,0 +1,234 @@
+//! Resuming a batch cut short by a crash or a reboot, `git-ws resume`.
+//!
+//! The first mutating operation of a batch writes the batch, with the
+//! arguments git-ws ran with, to `batches/<id>.json` in the state directory,
+//! and every operation completing on a repository is appended to
+//! `batches/<id>.progress`. Both are removed once the batch ends, failed or
+//! not, so they are only left behind by a batch that never got to its end.
+//! Being named after the batch, batches run at the same time in a workspace
+//! keep their own.
+//!
+//! The journal is not enough to tell what was done: it only records the
+//! mutating operations that moved a branch, while a stash, a push or a
+//! commit on a repository with nothing to commit must not run again either.
+//!
+//! Resuming runs the same command again in the same batch, so `recover` sees
+//! one batch, skipping the operations already done on each repository.
+
+use std::collections::BTreeSet;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::middleware::{Middleware, Next};
+use crate::operations::GitOperation;
+use crate::recover;
+use crate::repository::GitRepository;
+use crate::{Error, Result};
+
+pub const BATCHES_DIR: &str = "batches";
+
+/// A batch that has not ended.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingBatch {
+    pub id: String,
+    /// Arguments following `git-ws`, without the workspace option.
+    pub args: Vec<String>,
+    /// When it started, in seconds since the epoch.
+    pub time: i64,
+}
+
+fn batch_file(dir: &Path, id: &str) -> PathBuf {
+    dir.join(BATCHES_DIR).join(format!("{}.json", id))
+}
+
+fn progress_file(dir: &Path, id: &str) -> PathBuf {
+    dir.join(BATCHES_DIR).join(format!("{}.progress", id))
+}
+
+/// The batches of the workspace whose state directory is `dir` that did not
+/// end, the latest first, with the operations done, as `operation repo`.
+pub fn pending(dir: &Path) -> Result<Vec<(PendingBatch, BTreeSet<String>)>> {
+    let entries = match fs::read_dir(dir.join(BATCHES_DIR)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut batches = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        let batch: PendingBatch = serde_json::from_str(&fs::read_to_string(&path)?)?;
+        let done = match fs::read_to_string(progress_file(dir, &batch.id)) {
+            Ok(done) => done.lines().map(str::to_string).collect(),
+            Err(e) if e.kind() == ErrorKind::NotFound => BTreeSet::new(),
+            Err(e) => return Err(e.into()),
+        };
+        batches.push((batch, done));
+    }
+    batches.sort_by(|(a, _), (b, _)| b.time.cmp(&a.time).then_with(|| a.id.cmp(&b.id)));
+    Ok(batches)
+}
+
+/// Records the progress of the batch, forgetting it when dropped at its
+/// end.
+pub struct Progress {
+    dir: PathBuf,
+    batch: PendingBatch,
+    /// Operations done before the batch was interrupted.
+    done: BTreeSet<String>,
+    /// Whether the batch was written.
+    started: Mutex<bool>,
+}
+
+impl Progress {
+    /// The progress of a new batch `id` run with `args`, in the state
+    /// directory `dir`.
+    pub fn new(dir: &Path, id: impl Into<String>, args: Vec<String>) -> Self {
+        Progress {
+            dir: dir.to_path_buf(),
+            batch: PendingBatch {
+                id: id.into(),
+                args,
+                time: recover::now(),
+            },
+            done: BTreeSet::new(),
+            started: Mutex::new(false),
+        }
+    }
+
+    /// The progress of `batch`, resumed, `done` being skipped.
+    pub fn resuming(dir: &Path, batch: PendingBatch, done: BTreeSet<String>) -> Self {
+        Progress {
+            dir: dir.to_path_buf(),
+            batch,
+            done,
+            started: Mutex::new(true),
+        }
+    }
+
+    /// Writes the batch on its first mutating operation, warning of the
+    /// batches interrupted before, which would otherwise go unnoticed until
+    /// `git-ws resume`.
+    fn start(&self) -> Result<()> {
+        let mut started = self.started.lock().unwrap_or_else(|e| e.into_inner());
+        if !*started {
+            for (batch, _) in pending(&self.dir)? {
+                eprintln!(
+                    "warning: batch {} of `git-ws {}` did not end, `git-ws resume --batch {}` runs it again",
+                    &batch.id[..8.min(batch.id.len())],
+                    batch.args.join(" "),
+                    &batch.id[..8.min(batch.id.len())],
+                );
+            }
+            fs::create_dir_all(self.dir.join(BATCHES_DIR))?;
+            fs::write(progress_file(&self.dir, &self.batch.id), "")?;
+            fs::write(
+                batch_file(&self.dir, &self.batch.id),
+                serde_json::to_string_pretty(&self.batch)?,
+            )?;
+            *started = true;
+        }
+        Ok(())
+    }
+
+    fn record(&self, key: &str) -> Result<()> {
+        let _started = self.started.lock().unwrap_or_else(|e| e.into_inner());
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(progress_file(&self.dir, &self.batch.id))?;
+        writeln!(file, "{}", key)?;
+        Ok(())
+    }
+}
+
+impl Middleware for Progress {
+    fn call(
+        &self,
+        repo: &GitRepository,
+        operation: &dyn GitOperation,
+        next: Next<'_>,
+    ) -> Result<String> {
+        if !operation.is_mutating() {
+            return next.run(repo);
+        }
+        let key = format!("{} {}", operation.name(), repo.name());
+        if self.done.contains(&key) {
+            return Err(Error::Skipped(format!(
+                "{} done before the interruption",
+                operation.name()
+            )));
+        }
+        self.start()?;
+        let result = next.run(repo);
+        if matches!(result, Ok(_) | Err(Error::Warning(_))) {
+            // At worst the operation runs again on resuming.
+            let _ = self.record(&key);
+        }
+        result
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        let started = self.started.lock().unwrap_or_else(|e| e.into_inner());
+        if *started {
+            let _ = fs::remove_file(batch_file(&self.dir, &self.batch.id));
+            let _ = fs::remove_file(progress_file(&self.dir, &self.batch.id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Commit;
+
+    impl GitOperation for Commit {
+        fn name(&self) -> &str {
+            "commit"
+        }
+
+        fn is_mutating(&self) -> bool {
+            true
+        }
+
+        fn execute(&self, _repo: &GitRepository) -> Result<String> {
+            Ok("committed".to_string())
+        }
+    }
+
+    #[test]
+    fn keeps_the_progress_of_each_batch() {
+        let dir = std::env::temp_dir().join(format!("git-ws-resume-{}", std::process::id()));
+        let api = GitRepository::new("api", &dir);
+        let ledger = GitRepository::new("ledger", &dir);
+        let preconditions = crate::precondition::Preconditions::default();
+        let run = |progress: &Progress, repo: &GitRepository| {
+            progress.call(repo, &Commit, Next::new(&Commit, &[], &preconditions))
+        };
+
+        let first = Progress::new(&dir, "batch-1", vec!["commit".to_string()]);
+        let second = Progress::new(&dir, "batch-2", vec!["commit".to_string()]);
+        run(&first, &api).unwrap();
+        run(&second, &ledger).unwrap();
+        drop(second);
+        let (batch, done) = pending(&dir).unwrap().remove(0);
+        assert_eq!(batch.id, "batch-1");
+        assert_eq!(done, BTreeSet::from(["commit api".to_string()]));
+        std::mem::forget(first);
+
+        let resumed = Progress::resuming(&dir, batch, done);
+        assert!(matches!(run(&resumed, &api), Err(Error::Skipped(_))));
+        assert_eq!(run(&resumed, &ledger).unwrap(), "committed");
+        drop(resumed);
+        assert!(pending(&dir).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
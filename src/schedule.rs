@@ -0,0 +1,109 @@
+//! Pipelining of the composite operations, like `sync` and `refresh`.
+//!
+//! An operation fetching then working on the repository spends the first
+//! phase waiting on the network and the second on the disk and CPU. Run as
+//! one unit, at most `concurrency` repositories at a time, the CPU idles
+//! while every slot fetches, and the network while every slot merges.
+//!
+//! A [pipelined](crate::operations::GitOperation::is_pipelined) operation
+//! instead marks its phases with [`network`] and [`local`], each under its
+//! own limit, so the local phase of a repository overlaps the network phase
+//! of the next ones. Outside of the executor, the phases run right away.
+
+use std::cell::RefCell;
+use std::sync::{Arc, Condvar, Mutex};
+
+thread_local! {
+    static LIMITS: RefCell<Option<Arc<Limits>>> = const { RefCell::new(None) };
+}
+
+/// How many network and local phases run at a time.
+#[derive(Debug)]
+pub struct Limits {
+    network: Gate,
+    local: Gate,
+}
+
+impl Limits {
+    pub fn new(network: usize, local: usize) -> Self {
+        Limits {
+            network: Gate::new(network),
+            local: Gate::new(local),
+        }
+    }
+
+    /// Phases running at a time at most, the repositories worth starting.
+    pub fn total(&self) -> usize {
+        self.network.size + self.local.size
+    }
+}
+
+/// Runs `f`, the phase waiting on the network, under the network limit.
+pub fn network<T>(f: impl FnOnce() -> T) -> T {
+    phase(|limits| &limits.network, f)
+}
+
+/// Runs `f`, the phase working on the repository, under the local limit.
+pub fn local<T>(f: impl FnOnce() -> T) -> T {
+    phase(|limits| &limits.local, f)
+}
+
+fn phase<T>(gate: impl FnOnce(&Limits) -> &Gate, f: impl FnOnce() -> T) -> T {
+    let limits = LIMITS.with(|limits| limits.borrow().clone());
+    match limits {
+        Some(limits) => {
+            let _permit = gate(&limits).acquire();
+            f()
+        }
+        None => f(),
+    }
+}
+
+/// Runs `f` with its phases under `limits`, on the current thread.
+pub(crate) fn with_limits<T>(limits: Arc<Limits>, f: impl FnOnce() -> T) -> T {
+    let previous = LIMITS.with(|current| current.borrow_mut().replace(limits));
+    let result = f();
+    LIMITS.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// A blocking semaphore, the phases running on the blocking threads.
+#[derive(Debug)]
+struct Gate {
+    size: usize,
+    available: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl Gate {
+    fn new(size: usize) -> Self {
+        let size = size.max(1);
+        Gate {
+            size,
+            available: Mutex::new(size),
+            freed: Condvar::new(),
+        }
+    }
+
+    fn acquire(&self) -> Permit<'_> {
+        let mut available = self.available.lock().unwrap_or_else(|e| e.into_inner());
+        while *available == 0 {
+            available = self
+                .freed
+                .wait(available)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        *available -= 1;
+        Permit(self)
+    }
+}
+
+struct Permit<'a>(&'a Gate);
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut available = self.0.available.lock().unwrap_or_else(|e| e.into_inner());
+        *available += 1;
+        self.0.freed.notify_one();
+    }
+}
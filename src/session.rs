@@ -0,0 +1,119 @@
+//! Recorded sessions: the git-ws commands run in a workspace, to replay them
+//! against another one.
+//!
+//! `git-ws record start` remembers the session file in the workspace state,
+//! and every later git-ws command run in the workspace is appended to it
+//! until `git-ws record stop`.
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Result;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub steps: Vec<Step>,
+}
+
+/// One git-ws command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Step {
+    /// Arguments following `git-ws`, without the workspace option, so the
+    /// step runs in whatever workspace it is replayed against.
+    pub args: Vec<String>,
+}
+
+impl Session {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Appends a step running `args` to the session at `path`.
+    pub fn append(path: &Path, args: Vec<String>) -> Result<()> {
+        let mut session = if path.exists() {
+            Session::load(path)?
+        } else {
+            Session::default()
+        };
+        session.steps.push(Step {
+            args: without_options(args, &["-C", "--workspace"], true),
+        });
+        session.save(path)
+    }
+}
+
+/// `args` without the given value-taking `options`. With `up_to_command`,
+/// only options before the command are removed, and arguments of the
+/// command, which may look the same, are kept as they are.
+pub fn without_options(args: Vec<String>, options: &[&str], up_to_command: bool) -> Vec<String> {
+    let attached = |arg: &str| {
+        options.iter().any(|option| {
+            if option.starts_with("--") {
+                arg.starts_with(&format!("{}=", option))
+            } else {
+                arg.starts_with(option) && arg.len() > option.len()
+            }
+        })
+    };
+    let mut kept = Vec::with_capacity(args.len());
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if options.contains(&arg.as_str()) {
+            args.next();
+            continue;
+        }
+        if attached(&arg) {
+            continue;
+        }
+        let command = !arg.starts_with('-');
+        kept.push(arg);
+        if command && up_to_command {
+            // Everything after the command belongs to it.
+            kept.extend(args);
+            break;
+        }
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn removes_options_before_the_command() {
+        let kept = without_options(
+            args(&["-C", "/ws", "--verbose", "exec", "-C", "x"]),
+            &["-C", "--workspace"],
+            true,
+        );
+        assert_eq!(kept, args(&["--verbose", "exec", "-C", "x"]));
+        let kept = without_options(
+            args(&["--workspace=/ws", "-C/ws", "status"]),
+            &["-C", "--workspace"],
+            true,
+        );
+        assert_eq!(kept, args(&["status"]));
+    }
+
+    #[test]
+    fn removes_options_everywhere() {
+        let kept = without_options(
+            args(&["status", "--plan", "p.json", "--plan=q.json", "-v"]),
+            &["--plan"],
+            false,
+        );
+        assert_eq!(kept, args(&["status", "-v"]));
+    }
+}
@@ -0,0 +1,312 @@
+//! The first-run setup of a workspace, `git-ws setup`.
+//!
+//! Walks through the workspace root, the repositories left out, the groups,
+//! the concurrency and the credentials of the hosts the remotes point to,
+//! checking every answer as it comes, and writes `config.toml`. Running it
+//! again starts from the configuration written before.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::config::{HostConfig, TokenSource};
+use crate::interactive;
+use crate::remote::{self, RemoteUrl, Scheme};
+use crate::repository::GitRepository;
+use crate::workspace::{self, Workspace};
+use crate::{Error, Result};
+
+/// Repositories named when telling what was found.
+const SHOWN: usize = 8;
+const MAX_CONCURRENCY: usize = 256;
+
+/// Asks the questions of the setup, starting from the workspace at `start`,
+/// and returns the path of the configuration written.
+pub fn run(start: &Path) -> Result<PathBuf> {
+    interactive::require_input("setup")?;
+    let root = ask_until(
+        &format!("Workspace root [{}]:", start.display()),
+        |answer| {
+            let root = if answer.is_empty() {
+                start.to_path_buf()
+            } else {
+                PathBuf::from(answer)
+            };
+            if !root.is_dir() {
+                return Err(Error::Operation(format!(
+                    "{} is not a directory",
+                    root.display()
+                )));
+            }
+            Ok(root.canonicalize()?)
+        },
+    )?;
+    let workspace = Workspace::new(&root);
+    let mut config = workspace.load_config()?;
+    let repos = workspace.discover_repositories()?;
+    let names: Vec<&str> = repos.iter().map(GitRepository::name).collect();
+    if names.is_empty() {
+        eprintln!("  no repository below {} yet", root.display());
+    } else {
+        eprintln!("  found {} repositories: {}", names.len(), sample(&names));
+    }
+
+    config.exclude = ask_until(
+        &format!(
+            "Repositories to leave out, comma-separated patterns like legacy-*{}:",
+            current(&config.exclude.join(", "))
+        ),
+        |answer| patterns(answer, &config.exclude, &names),
+    )?;
+    let kept: Vec<&str> = names
+        .iter()
+        .copied()
+        .filter(|name| {
+            !config
+                .exclude
+                .iter()
+                .any(|pattern| workspace::name_matches(pattern, name))
+        })
+        .collect();
+
+    for (group, patterns) in &config.groups {
+        eprintln!("  group {}: {}", group, patterns.join(", "));
+    }
+    loop {
+        let group = ask_until("Group to add or change, empty when done:", |answer| {
+            if answer.contains(|c: char| c.is_whitespace() || c == ',') {
+                return Err(Error::Operation(
+                    "a group name has no spaces or commas".to_string(),
+                ));
+            }
+            Ok(answer.to_string())
+        })?;
+        if group.is_empty() {
+            break;
+        }
+        let existing = config.groups.get(&group).cloned().unwrap_or_default();
+        let members = ask_until(
+            &format!(
+                "Repositories of {}, comma-separated patterns like services/*{}:",
+                group,
+                current(&existing.join(", "))
+            ),
+            |answer| patterns(answer, &existing, &kept),
+        )?;
+        if members.is_empty() {
+            config.groups.remove(&group);
+        } else {
+            config.groups.insert(group, members);
+        }
+    }
+
+    config.concurrency = ask_until(
+        &format!(
+            "Repositories to work on at a time [{}]:",
+            config.concurrency()
+        ),
+        |answer| {
+            if answer.is_empty() {
+                return Ok(config.concurrency);
+            }
+            match answer.parse::<usize>() {
+                Ok(count) if (1..=MAX_CONCURRENCY).contains(&count) => Ok(Some(count)),
+                _ => Err(Error::Operation(format!(
+                    "expected a number from 1 to {}",
+                    MAX_CONCURRENCY
+                ))),
+            }
+        },
+    )?;
+
+    for (host, (parsed, url, repo)) in hosts(&repos)? {
+        match parsed.scheme {
+            Scheme::Ssh => check_ssh(&parsed, &url, repo)?,
+            Scheme::Https | Scheme::Http => {
+                let existing = config.hosts.get(&host).cloned().unwrap_or_default();
+                ask_credentials(&host, existing, &mut config.hosts)?;
+            }
+            Scheme::Git => {}
+        }
+    }
+
+    workspace.save_config(&config)
+}
+
+/// Asks `question` until `parse` accepts the answer.
+fn ask_until<T>(question: &str, parse: impl Fn(&str) -> Result<T>) -> Result<T> {
+    loop {
+        let answer = interactive::ask(question)?;
+        match parse(&answer) {
+            Ok(value) => return Ok(value),
+            Err(e) => eprintln!("  {}", e),
+        }
+    }
+}
+
+/// ` [current]`, for questions whose empty answer keeps `value`.
+fn current(value: &str) -> String {
+    if value.is_empty() {
+        String::new()
+    } else {
+        format!(" [{}]", value)
+    }
+}
+
+fn sample(names: &[&str]) -> String {
+    let mut sample = names
+        .iter()
+        .take(SHOWN)
+        .copied()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if names.len() > SHOWN {
+        sample.push_str(&format!(" and {} more", names.len() - SHOWN));
+    }
+    sample
+}
+
+/// The comma-separated patterns of `answer`, `existing` when empty and none
+/// for `-`, each having to match one of `names`.
+fn patterns(answer: &str, existing: &[String], names: &[&str]) -> Result<Vec<String>> {
+    if answer.is_empty() {
+        return Ok(existing.to_vec());
+    }
+    if answer == "-" {
+        return Ok(Vec::new());
+    }
+    let patterns: Vec<String> = answer
+        .split(',')
+        .map(str::trim)
+        .filter(|pattern| !pattern.is_empty())
+        .map(str::to_string)
+        .collect();
+    for pattern in &patterns {
+        if !names
+            .iter()
+            .any(|name| workspace::name_matches(pattern, name))
+        {
+            return Err(Error::Operation(format!(
+                "{} matches no repository, - for none",
+                pattern
+            )));
+        }
+    }
+    Ok(patterns)
+}
+
+/// The hosts the remotes of `repos` point to, with one of the remotes,
+/// parsed and as configured, and its repository.
+fn hosts(repos: &[GitRepository]) -> Result<BTreeMap<String, (RemoteUrl, String, &GitRepository)>> {
+    let mut hosts = BTreeMap::new();
+    for repo in repos {
+        for url in remote::list(repo)?
+            .into_iter()
+            .filter_map(|entry| entry.url)
+        {
+            if let Some(parsed) = RemoteUrl::parse(&url) {
+                hosts
+                    .entry(parsed.host.clone())
+                    .or_insert((parsed, url, repo));
+            }
+        }
+    }
+    Ok(hosts)
+}
+
+/// Lists the branches of `url`, a remote of `repo`, over ssh, without
+/// prompting.
+fn check_ssh(parsed: &RemoteUrl, url: &str, repo: &GitRepository) -> Result<()> {
+    let host = &parsed.host;
+    eprintln!("  checking ssh access to {}...", host);
+    let output = interactive::command("git")
+        .args(["ls-remote", "--heads", url])
+        .current_dir(repo.path())
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env(
+            "GIT_SSH_COMMAND",
+            "ssh -o BatchMode=yes -o ConnectTimeout=10",
+        )
+        .output()?;
+    if output.status.success() {
+        eprintln!("  ssh access to {} works", host);
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let login = match &parsed.user {
+            Some(user) => format!("{}@{}", user, host),
+            None => host.to_string(),
+        };
+        eprintln!(
+            "  warning: no ssh access to {}: {}\n  check your key with `ssh -T {}`",
+            host,
+            stderr.lines().next().unwrap_or_default().trim(),
+            login
+        );
+    }
+    Ok(())
+}
+
+/// Asks where the token of `host` is read from, checking it can be read.
+fn ask_credentials(
+    host: &str,
+    existing: HostConfig,
+    hosts: &mut BTreeMap<String, HostConfig>,
+) -> Result<()> {
+    let shown = existing.token.as_ref().map(describe).unwrap_or_default();
+    let token = ask_until(
+        &format!(
+            "Token for {}: env:VAR, file:PATH or command:CMD, - for git's credential helpers{}:",
+            host,
+            current(&shown)
+        ),
+        |answer| {
+            let source = match answer {
+                "" => return Ok(existing.token.clone()),
+                "-" => return Ok(None),
+                answer => parse_source(answer)?,
+            };
+            source.resolve()?;
+            Ok(Some(source))
+        },
+    )?;
+    let Some(token) = token else {
+        hosts.remove(host);
+        return Ok(());
+    };
+    let username = interactive::ask(&format!(
+        "User name sent with the token, like oauth2, empty for none{}:",
+        current(existing.username.as_deref().unwrap_or_default())
+    ))?;
+    let username = match username.as_str() {
+        "" => existing.username.clone(),
+        "-" => None,
+        username => Some(username.to_string()),
+    };
+    hosts.insert(
+        host.to_string(),
+        HostConfig {
+            username,
+            token: Some(token),
+            ..existing
+        },
+    );
+    Ok(())
+}
+
+fn parse_source(answer: &str) -> Result<TokenSource> {
+    match answer.split_once(':') {
+        Some(("env", name)) => Ok(TokenSource::Env(name.trim().to_string())),
+        Some(("file", path)) => Ok(TokenSource::File(PathBuf::from(path.trim()))),
+        Some(("command", command)) => Ok(TokenSource::Command(command.trim().to_string())),
+        _ => Err(Error::Operation(
+            "expected env:VAR, file:PATH or command:CMD".to_string(),
+        )),
+    }
+}
+
+fn describe(source: &TokenSource) -> String {
+    match source {
+        TokenSource::Env(name) => format!("env:{}", name),
+        TokenSource::File(path) => format!("file:{}", path.display()),
+        TokenSource::Command(command) => format!("command:{}", command),
+    }
+}
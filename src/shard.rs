@@ -0,0 +1,175 @@
+//! Splitting a workspace across parallel jobs, like the jobs of a CI matrix.
+//!
+//! `--shard 2/5` keeps the repositories of the second of five shards. A
+//! repository goes to the shard scoring highest for its name (rendezvous
+//! hashing), so every job agrees on the split without talking to the
+//! others, and changing the number of shards only moves the repositories
+//! the new shards take. The JSON reports of the jobs are put back together
+//! with [`merge`].
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{Error, Result};
+
+/// One shard of the workspace, `index` counting from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: u32,
+    pub count: u32,
+}
+
+impl Shard {
+    /// Whether the repository `name` belongs to the shard.
+    pub fn contains(&self, name: &str) -> bool {
+        let owner = (1..=self.count)
+            .max_by_key(|index| score(*index, name))
+            .unwrap_or(1);
+        owner == self.index
+    }
+}
+
+fn score(index: u32, name: &str) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(index.to_be_bytes());
+    hasher.update(name.as_bytes());
+    let digest = hasher.finalize();
+    u64::from_be_bytes(digest[..8].try_into().expect("8 bytes"))
+}
+
+impl FromStr for Shard {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let invalid = || Error::Operation(format!("invalid shard `{}`, expected like 2/5", text));
+        let (index, count) = text.split_once('/').ok_or_else(invalid)?;
+        let index: u32 = index.trim().parse().map_err(|_| invalid())?;
+        let count: u32 = count.trim().parse().map_err(|_| invalid())?;
+        if index == 0 || index > count {
+            return Err(invalid());
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// The JSON reports of the shards, as one. Arrays are concatenated, and
+/// sorted by repository when their elements name one; objects are merged.
+/// A repository reported by two shards is an error: the shards were not
+/// cut the same way.
+pub fn merge(reports: Vec<Value>) -> Result<Value> {
+    let mut reports = reports.into_iter();
+    let Some(first) = reports.next() else {
+        return Ok(Value::Array(Vec::new()));
+    };
+    let mut merged = first;
+    for report in reports {
+        merged = merge_two(merged, report)?;
+    }
+    if let Value::Array(elements) = &mut merged {
+        elements.sort_by(|a, b| repo(a).cmp(&repo(b)));
+        for pair in elements.windows(2) {
+            if let (Some(a), Some(b)) = (repo(&pair[0]), repo(&pair[1])) {
+                if a == b {
+                    return Err(Error::Operation(format!("{} is in several reports", a)));
+                }
+            }
+        }
+    }
+    Ok(merged)
+}
+
+fn merge_two(merged: Value, report: Value) -> Result<Value> {
+    match (merged, report) {
+        (Value::Array(mut merged), Value::Array(report)) => {
+            merged.extend(report);
+            Ok(Value::Array(merged))
+        }
+        (Value::Object(mut merged), Value::Object(report)) => {
+            merge_objects(&mut merged, report)?;
+            Ok(Value::Object(merged))
+        }
+        _ => Err(Error::Operation(
+            "reports must all be arrays, or all objects".to_string(),
+        )),
+    }
+}
+
+fn merge_objects(merged: &mut Map<String, Value>, report: Map<String, Value>) -> Result<()> {
+    for (key, value) in report {
+        match merged.get(&key) {
+            Some(existing) if *existing != value => {
+                return Err(Error::Operation(format!("{} is in several reports", key)));
+            }
+            _ => {
+                merged.insert(key, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn repo(element: &Value) -> Option<&str> {
+    element.get("repo").and_then(Value::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_shards() {
+        assert_eq!(
+            "2/5".parse::<Shard>().unwrap(),
+            Shard { index: 2, count: 5 }
+        );
+        assert_eq!("2/5".parse::<Shard>().unwrap().to_string(), "2/5");
+        for text in ["0/5", "6/5", "2", "a/5"] {
+            let error = text.parse::<Shard>().unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("invalid shard `{}`, expected like 2/5", text)
+            );
+        }
+    }
+
+    #[test]
+    fn puts_every_repository_in_one_shard() {
+        for i in 0..50 {
+            let name = format!("repo-{}", i);
+            let owners = (1..=4)
+                .filter(|index| {
+                    Shard {
+                        index: *index,
+                        count: 4,
+                    }
+                    .contains(&name)
+                })
+                .count();
+            assert_eq!(owners, 1, "{}", name);
+        }
+    }
+
+    #[test]
+    fn merges_reports_by_repository() {
+        let merged = merge(vec![
+            serde_json::json!([{"repo": "b"}]),
+            serde_json::json!([{"repo": "a"}]),
+        ])
+        .unwrap();
+        assert_eq!(merged, serde_json::json!([{"repo": "a"}, {"repo": "b"}]));
+        let twice = merge(vec![
+            serde_json::json!([{"repo": "a"}]),
+            serde_json::json!([{"repo": "a"}]),
+        ]);
+        assert!(twice.is_err());
+    }
+}
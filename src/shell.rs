@@ -0,0 +1,119 @@
+//! Shell integration: a `gws` function wrapping git-ws, whose `gws cd`
+//! jumps to a repository of the workspace by a fuzzy name, with completion.
+//!
+//! ```text
+//! eval "$(git-ws shell-init bash)"   # in ~/.bashrc
+//! gws cd gam                         # cd to team/gamma
+//! ```
+
+use std::cmp::Reverse;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// The script defining the function `name` for `shell`.
+pub fn init(shell: Shell, name: &str) -> String {
+    let script = match shell {
+        Shell::Bash => BASH,
+        Shell::Zsh => ZSH,
+        Shell::Fish => FISH,
+    };
+    script.replace("__NAME__", name)
+}
+
+const BASH: &str = r#"# git-ws shell integration, from `git-ws shell-init bash`
+__NAME__() {
+    if [ "$1" = cd ]; then
+        shift
+        local dir
+        dir="$(command git-ws cd -- "$@")" && builtin cd -- "$dir"
+    else
+        command git-ws "$@"
+    fi
+}
+___NAME___complete() {
+    if [ "$COMP_CWORD" -eq 2 ] && [ "${COMP_WORDS[1]}" = cd ]; then
+        local IFS=$'\n'
+        COMPREPLY=($(command git-ws cd --list -- "${COMP_WORDS[2]}" 2>/dev/null))
+    fi
+}
+complete -o default -F ___NAME___complete __NAME__
+"#;
+
+const ZSH: &str = r#"# git-ws shell integration, from `git-ws shell-init zsh`
+__NAME__() {
+    if [[ "$1" == cd ]]; then
+        shift
+        local dir
+        dir="$(command git-ws cd -- "$@")" && builtin cd -- "$dir"
+    else
+        command git-ws "$@"
+    fi
+}
+___NAME__() {
+    if (( CURRENT == 3 )) && [[ "${words[2]}" == cd ]]; then
+        local -a repos
+        repos=(${(f)"$(command git-ws cd --list -- "${words[3]}" 2>/dev/null)"})
+        compadd -U -- $repos
+    else
+        _files
+    fi
+}
+(( $+functions[compdef] )) && compdef ___NAME__ __NAME__
+"#;
+
+const FISH: &str = r#"# git-ws shell integration, from `git-ws shell-init fish`
+function __NAME__
+    if test "$argv[1]" = cd
+        set -l dir (command git-ws cd -- $argv[2..-1]); and builtin cd -- $dir
+    else
+        command git-ws $argv
+    end
+end
+complete -c __NAME__ -n '__fish_seen_subcommand_from cd' -f -a '(command git-ws cd --list -- (commandline -ct) 2>/dev/null)'
+"#;
+
+/// The names matching `query`, best first. A name matches when the
+/// characters of the query appear in it in order; exact names, then last
+/// path segments, prefixes and substrings come first, and shorter names
+/// before longer ones.
+pub fn fuzzy_matches<'a>(names: &'a [String], query: &str) -> Vec<&'a str> {
+    let query = query.to_lowercase();
+    let mut matches: Vec<_> = names
+        .iter()
+        .filter_map(|name| score(name, &query).map(|score| (score, name.as_str())))
+        .collect();
+    matches.sort_by_key(|(score, name)| (Reverse(*score), name.len(), *name));
+    matches.into_iter().map(|(_, name)| name).collect()
+}
+
+/// How well `name` matches the lowercase `query`, higher is better.
+fn score(name: &str, query: &str) -> Option<u8> {
+    let name = name.to_lowercase();
+    let last = name.rsplit('/').next().unwrap_or(&name);
+    let score = if name == query {
+        6
+    } else if last == query {
+        5
+    } else if last.starts_with(query) {
+        4
+    } else if name.starts_with(query) {
+        3
+    } else if name.contains(query) {
+        2
+    } else if is_subsequence(query, &name) {
+        1
+    } else {
+        return None;
+    };
+    Some(score)
+}
+
+fn is_subsequence(query: &str, name: &str) -> bool {
+    let mut name = name.chars();
+    query.chars().all(|c| name.any(|n| n == c))
+}
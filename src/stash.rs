@@ -0,0 +1,297 @@
+//! Stashes across the repositories of a workspace.
+//!
+//! Every repository keeps its own stack, and a repository without local
+//! changes when `stash push` ran gets no entry. The entries are tagged with
+//! the batch pushing them, `git-ws <batch id>`, for `stash pop` to take
+//! those of the latest push only, leaving alone the repositories it did not
+//! stash and their older entries.
+
+use crate::operations::GitOperation;
+use crate::repository::{ChangeCounts, GitRepository};
+use crate::{Error, Result};
+
+/// An entry of the stash of a repository.
+#[derive(Debug, Clone)]
+pub struct StashEntry {
+    /// Like `stash@{0}`.
+    pub name: String,
+    /// When it was stashed, in seconds since the epoch.
+    pub time: i64,
+    /// Like `On main: message`, or `WIP on main: 2f8a17b subject`.
+    pub message: String,
+}
+
+/// Prefix of the messages of the entries git-ws stashes.
+const TAG: &str = "git-ws ";
+
+impl StashEntry {
+    /// The batch that stashed the entry, for those stashed by git-ws.
+    pub fn batch(&self) -> Option<&str> {
+        let (_, message) = self.message.split_once(": ")?;
+        let rest = message.strip_prefix(TAG)?;
+        let batch = rest.split(':').next().unwrap_or_default();
+        (!batch.is_empty()).then_some(batch)
+    }
+}
+
+/// The message of the entries stashed by `batch`.
+fn tagged(batch: &str, message: Option<&str>) -> String {
+    match message {
+        Some(message) => format!("{}{}: {}", TAG, batch, message),
+        None => format!("{}{}", TAG, batch),
+    }
+}
+
+/// The batch of the latest entry git-ws stashed in any of `repos`.
+pub fn latest_batch(repos: &[GitRepository]) -> Result<Option<String>> {
+    let mut latest: Option<(i64, String)> = None;
+    for repo in repos {
+        for entry in list(repo)? {
+            if let Some(batch) = entry.batch() {
+                if latest.as_ref().is_none_or(|(time, _)| entry.time > *time) {
+                    latest = Some((entry.time, batch.to_string()));
+                }
+            }
+        }
+    }
+    Ok(latest.map(|(_, batch)| batch))
+}
+
+/// Which entry of the stash of every repository to take.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+    /// The entry at this index, like `stash@{1}`.
+    Index(usize),
+    /// The entry stashed by this batch, see [`latest_batch`].
+    Batch(String),
+}
+
+/// The stash of `repo`, the latest entry first.
+pub fn list(repo: &GitRepository) -> Result<Vec<StashEntry>> {
+    let output = repo.git(["stash", "list", "--format=%gd%x1f%ct%x1f%gs"])?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\x1f');
+            let name = fields.next()?.to_string();
+            let time = fields.next()?.parse().ok()?;
+            let message = fields.next().unwrap_or_default().to_string();
+            Some(StashEntry {
+                name,
+                time,
+                message,
+            })
+        })
+        .collect())
+}
+
+/// The index of a stash entry given like `stash@{2}`, or just `2`.
+pub fn parse_index(text: &str) -> Result<usize> {
+    let index = text
+        .strip_prefix("stash@{")
+        .and_then(|rest| rest.strip_suffix('}'))
+        .unwrap_or(text);
+    index
+        .parse()
+        .map_err(|_| Error::Operation(format!("invalid stash entry `{}`", text)))
+}
+
+/// The entry of the stash of `repo` selected, skipping repositories not
+/// having it.
+fn entry(repo: &GitRepository, selector: &Selector) -> Result<StashEntry> {
+    let entries = list(repo)?;
+    if entries.is_empty() {
+        return Err(Error::Skipped("nothing stashed".to_string()));
+    }
+    match selector {
+        Selector::Index(index) => entries
+            .into_iter()
+            .nth(*index)
+            .ok_or_else(|| Error::Skipped(format!("no stash@{{{}}}", index))),
+        Selector::Batch(batch) => entries
+            .into_iter()
+            .find(|entry| entry.batch() == Some(batch.as_str()))
+            .ok_or_else(|| Error::Skipped("nothing stashed by this batch".to_string())),
+    }
+}
+
+/// Stashes the local changes, with the untracked files when
+/// `include_untracked` is set, tagging the entries with `batch`.
+pub struct StashPushOperation {
+    batch: String,
+    message: Option<String>,
+    include_untracked: bool,
+}
+
+impl StashPushOperation {
+    pub fn new(batch: impl Into<String>, message: Option<String>, include_untracked: bool) -> Self {
+        StashPushOperation {
+            batch: batch.into(),
+            message,
+            include_untracked,
+        }
+    }
+}
+
+impl GitOperation for StashPushOperation {
+    fn name(&self) -> &str {
+        "stash push"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let counts = ChangeCounts::collect(&repo.open()?)?;
+        let untracked = if self.include_untracked {
+            counts.untracked
+        } else {
+            0
+        };
+        if counts.staged + counts.modified + counts.renamed + counts.conflicted + untracked == 0 {
+            return Err(Error::Skipped("no local changes".to_string()));
+        }
+        let message = tagged(&self.batch, self.message.as_deref());
+        let mut args = vec!["stash", "push", "--quiet", "--message", &message];
+        if self.include_untracked {
+            args.push("--include-untracked");
+        }
+        repo.git(args)?;
+        Ok("stashed as stash@{0}".to_string())
+    }
+}
+
+/// Applies an entry of the stash, dropping it afterwards when `pop`ping.
+pub struct StashApplyOperation {
+    selector: Selector,
+    pop: bool,
+}
+
+impl StashApplyOperation {
+    pub fn new(selector: Selector, pop: bool) -> Self {
+        StashApplyOperation { selector, pop }
+    }
+}
+
+impl GitOperation for StashApplyOperation {
+    fn name(&self) -> &str {
+        if self.pop {
+            "stash pop"
+        } else {
+            "stash apply"
+        }
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let entry = entry(repo, &self.selector)?;
+        let action = if self.pop { "pop" } else { "apply" };
+        match repo.git(["stash", action, "--quiet", &entry.name]) {
+            // git keeps the entry when applying it conflicts.
+            Err(Error::Operation(message)) if message.contains("CONFLICT") => Err(
+                Error::Operation(format!("{} conflicts, kept in the stash", entry.name)),
+            ),
+            Err(Error::Operation(message)) if message.contains("would be overwritten") => {
+                Err(Error::Operation(format!(
+                    "local changes would be overwritten by {}",
+                    entry.name
+                )))
+            }
+            result => result.map(|_| {
+                if self.pop {
+                    format!("popped {}", entry.message)
+                } else {
+                    format!("applied {}", entry.message)
+                }
+            }),
+        }
+    }
+}
+
+/// Drops an entry of the stash.
+pub struct StashDropOperation {
+    selector: Selector,
+}
+
+impl StashDropOperation {
+    pub fn new(selector: Selector) -> Self {
+        StashDropOperation { selector }
+    }
+}
+
+impl GitOperation for StashDropOperation {
+    fn name(&self) -> &str {
+        "stash drop"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let entry = entry(repo, &self.selector)?;
+        repo.git(["stash", "drop", "--quiet", &entry.name])?;
+        Ok(format!("dropped {}", entry.message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{RepoState, TestWorkspace};
+
+    fn with_author(repo: &GitRepository) {
+        let mut config = repo.open().unwrap().config().unwrap();
+        config.set_str("user.name", "Test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+    }
+
+    #[test]
+    fn pops_the_entries_of_the_batch_only() {
+        let test = TestWorkspace::builder()
+            .repo("api", RepoState::Dirty)
+            .repo("web", RepoState::Dirty)
+            .build()
+            .unwrap();
+        let api = test.repository("api");
+        let web = test.repository("web");
+        with_author(&api);
+        with_author(&web);
+        // Stashed by hand before, and clean when the batch stashes.
+        web.git(["stash", "push", "--quiet", "--message", "older"])
+            .unwrap();
+        assert_eq!(stash_batch(&[api.clone(), web.clone()]), None);
+
+        let push = StashPushOperation::new("b1", Some("wip".to_string()), false);
+        push.execute(&api).unwrap();
+        assert!(matches!(push.execute(&web), Err(Error::Skipped(_))));
+        let entries = list(&api).unwrap();
+        assert!(entries[0].message.ends_with(": git-ws b1: wip"));
+        assert_eq!(entries[0].batch(), Some("b1"));
+        assert_eq!(list(&web).unwrap()[0].batch(), None);
+
+        let repos = [api.clone(), web.clone()];
+        let selector = Selector::Batch(stash_batch(&repos).unwrap());
+        let pop = StashApplyOperation::new(selector, true);
+        pop.execute(&api).unwrap();
+        assert!(matches!(pop.execute(&web), Err(Error::Skipped(_))));
+        assert!(list(&api).unwrap().is_empty());
+        assert_eq!(list(&web).unwrap().len(), 1);
+    }
+
+    fn stash_batch(repos: &[GitRepository]) -> Option<String> {
+        latest_batch(repos).unwrap()
+    }
+
+    #[test]
+    fn parses_stash_indexes() {
+        assert_eq!(parse_index("2").unwrap(), 2);
+        assert_eq!(parse_index("stash@{0}").unwrap(), 0);
+        let error = parse_index("stash@{x}").unwrap_err();
+        assert_eq!(error.to_string(), "invalid stash entry `stash@{x}`");
+    }
+}
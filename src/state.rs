@@ -0,0 +1,55 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::batch::BatchMerge;
+use crate::bisect::BisectSession;
+use crate::Result;
+
+const STATE_FILE: &str = "state.json";
+
+/// Persistent workspace state, stored as `state.json` in the state directory.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct WorkspaceState {
+    /// Repositories excluded from every mutating operation.
+    #[serde(default)]
+    pub pinned: BTreeSet<String>,
+
+    /// The bisect in progress, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bisect: Option<BisectSession>,
+
+    /// The batch merge or rebase left unfinished, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<BatchMerge>,
+
+    /// Session file commands are recorded to, see [`crate::session`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recording: Option<PathBuf>,
+}
+
+impl WorkspaceState {
+    /// Loads the state from `dir`, returning the default state when nothing
+    /// has been saved yet.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(STATE_FILE);
+        if !path.exists() {
+            return Ok(WorkspaceState::default());
+        }
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(dir.join(STATE_FILE), content)?;
+        Ok(())
+    }
+
+    pub fn is_pinned(&self, repo: &str) -> bool {
+        self.pinned.contains(repo)
+    }
+}
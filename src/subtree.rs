@@ -0,0 +1,131 @@
+//! Moving directories between workspace repositories with their history.
+//!
+//! Built on `git subtree`: the history of a directory is split into its own
+//! line of commits in the source repository, fetched into the target and
+//! either checked out as the target's history (for a new repository) or added
+//! or merged below a prefix of an existing one.
+
+use std::fs;
+
+use crate::repository::GitRepository;
+use crate::workspace::Workspace;
+use crate::{Error, Result};
+
+/// Temporary ref history is fetched from.
+const TRANSFER_REF: &str = "refs/git-ws/transfer";
+
+/// Extracts the history of `path` in `source` into the repository `target`.
+///
+/// A target that does not exist yet is created with the directory as its
+/// root. An existing target gets the directory added below `prefix`, `path`
+/// by default. With `remove`, the directory is then removed from `source`.
+pub fn split(
+    workspace: &Workspace,
+    source: &GitRepository,
+    path: &str,
+    target: &str,
+    prefix: Option<&str>,
+    remove: bool,
+) -> Result<String> {
+    if remove {
+        ensure_not_pinned(workspace, source)?;
+    }
+    let target = target.trim_end_matches('/');
+    let message = match workspace.find_repository(target) {
+        Ok(existing) => {
+            let prefix = prefix.unwrap_or(path);
+            merge(workspace, &existing, prefix, source, Some(path))?
+        }
+        Err(Error::RepositoryNotFound(_)) => {
+            let dir = workspace.root().join(target);
+            if dir.exists() {
+                return Err(Error::Operation(format!(
+                    "{} exists and is not a repository",
+                    target
+                )));
+            }
+            fs::create_dir_all(&dir)?;
+            let created = GitRepository::new(target, &dir);
+            let populated = created.git(["init", "--quiet"]).and_then(|_| {
+                let sha = fetch(source, Some(path), &created)?;
+                created.git(["reset", "--quiet", "--hard", &sha])
+            });
+            if let Err(e) = populated {
+                let _ = fs::remove_dir_all(&dir);
+                return Err(e);
+            }
+            format!("created {} from {}/{}", target, source.name(), path)
+        }
+        Err(e) => return Err(e),
+    };
+    if remove {
+        source.git(["rm", "-r", "--quiet", "--", path])?;
+        source.git([
+            "commit",
+            "--quiet",
+            "-m",
+            &format!("Move {} to {}", path, target),
+        ])?;
+    }
+    Ok(message)
+}
+
+/// Brings `source`, or only the history of its directory `path`, into the
+/// `prefix` directory of `target`: added when the prefix does not exist yet,
+/// merged with what an earlier split or merge brought in otherwise.
+pub fn merge(
+    workspace: &Workspace,
+    target: &GitRepository,
+    prefix: &str,
+    source: &GitRepository,
+    path: Option<&str>,
+) -> Result<String> {
+    ensure_not_pinned(workspace, target)?;
+    let prefix = prefix.trim_matches('/');
+    let sha = fetch(source, path, target)?;
+    let action = if target.path().join(prefix).exists() {
+        "merge"
+    } else {
+        "add"
+    };
+    target.git(["subtree", action, "--prefix", prefix, &sha])?;
+    let origin = match path {
+        Some(path) => format!("{}/{}", source.name(), path),
+        None => source.name().to_string(),
+    };
+    Ok(format!(
+        "{} {} into {}/{}",
+        action,
+        origin,
+        target.name(),
+        prefix
+    ))
+}
+
+/// Fetches the history of `path` in `source`, or its whole HEAD, into
+/// `target` and returns the tip commit.
+fn fetch(source: &GitRepository, path: Option<&str>, target: &GitRepository) -> Result<String> {
+    let sha = match path {
+        Some(path) => source.git(["subtree", "split", "-q", "--prefix", path])?,
+        None => source.git(["rev-parse", "HEAD"])?,
+    };
+    transfer(source, &sha, target)?;
+    Ok(sha)
+}
+
+/// Makes the commit `sha` of `source`, with its history, available in
+/// `target`.
+pub(crate) fn transfer(source: &GitRepository, sha: &str, target: &GitRepository) -> Result<()> {
+    source.git(["update-ref", TRANSFER_REF, sha])?;
+    let source_path = source.path().to_string_lossy().into_owned();
+    let fetched = target.git(["fetch", "--quiet", "--no-tags", &source_path, TRANSFER_REF]);
+    source.git(["update-ref", "-d", TRANSFER_REF])?;
+    fetched.map(|_| ())
+}
+
+pub(crate) fn ensure_not_pinned(workspace: &Workspace, repo: &GitRepository) -> Result<()> {
+    if workspace.load_state()?.is_pinned(repo.name()) {
+        return Err(Error::Operation(format!("{} is pinned", repo.name())));
+    }
+    Ok(())
+}
@@ -0,0 +1,187 @@
+//! Bringing a workspace in line with its manifest.
+//!
+//! Repositories of the manifest missing on disk are cloned, the others
+//! fetched, and every repository is put on its default branch, fast-forwarded
+//! to the remote one. Repositories with local changes, or whose branch
+//! diverged, are left alone and reported.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::bootstrap::Bootstrap;
+use crate::interactive;
+use crate::manifest::Manifest;
+use crate::operations::GitOperation;
+use crate::repository::{self, ChangeCounts, GitRepository};
+use crate::schedule;
+use crate::workspace::Workspace;
+use crate::{Error, Result};
+
+struct Source {
+    url: String,
+    branch: Option<String>,
+}
+
+/// Clones, or fetches, a repository of the manifest, and checks out its
+/// default branch.
+pub struct SyncOperation {
+    sources: BTreeMap<String, Source>,
+    bootstrap: Option<(Bootstrap, PathBuf)>,
+}
+
+impl SyncOperation {
+    pub fn new(manifest: &Manifest) -> Self {
+        let sources = manifest
+            .repositories
+            .iter()
+            .map(|repo| {
+                let source = Source {
+                    url: repo.url.clone(),
+                    branch: repo.branch.clone(),
+                };
+                (repo.path.trim_end_matches('/').to_string(), source)
+            })
+            .collect();
+        SyncOperation {
+            sources,
+            bootstrap: None,
+        }
+    }
+
+    /// Bootstrap steps applied to the repositories cloned, with the
+    /// workspace root relative paths are resolved against.
+    pub fn bootstrap(mut self, bootstrap: Bootstrap, root: impl Into<PathBuf>) -> Self {
+        if !bootstrap.is_empty() {
+            self.bootstrap = Some((bootstrap, root.into()));
+        }
+        self
+    }
+
+    /// Every repository of the manifest, cloned or not, located in
+    /// `workspace`, or those `workspace` is limited to, see
+    /// [`Workspace::selects`].
+    pub fn repositories(&self, workspace: &Workspace) -> Result<Vec<GitRepository>> {
+        let mut repos = Vec::new();
+        for path in self.sources.keys() {
+            if workspace.selects(path)? {
+                repos.push(GitRepository::new(
+                    path.as_str(),
+                    workspace.root().join(path),
+                ));
+            }
+        }
+        Ok(repos)
+    }
+
+    fn clone(&self, repo: &GitRepository, source: &Source) -> Result<String> {
+        let parent = repo.path().parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(parent)?;
+        let mut clone = interactive::command("git");
+        clone.args(["clone", "--quiet"]);
+        if let Some(branch) = &source.branch {
+            clone.args(["--branch", branch]);
+        }
+        let output = schedule::network(|| {
+            clone
+                .arg(&source.url)
+                .arg(repo.path())
+                .current_dir(parent)
+                .output()
+        })?;
+        if !output.status.success() {
+            return Err(Error::Operation(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+        let branch = repo.current_branch()?.unwrap_or_default();
+        if let Some((bootstrap, root)) = &self.bootstrap {
+            schedule::local(|| bootstrap.apply(root, repo))?;
+        }
+        Ok(format!("cloned, on {}", branch))
+    }
+
+    fn update(&self, repo: &GitRepository, source: &Source) -> Result<String> {
+        schedule::network(|| repo.git(["fetch", "--quiet", "origin"]))?;
+        schedule::local(|| self.check_out(repo, source))
+    }
+
+    /// Puts `repo`, just fetched, on its default branch, fast-forwarded.
+    fn check_out(&self, repo: &GitRepository, source: &Source) -> Result<String> {
+        let git = repo.open()?;
+        let branch = match &source.branch {
+            Some(branch) => branch.clone(),
+            None => {
+                if repository::default_branch(&git)?.is_none() {
+                    // Clones made by other means may lack origin/HEAD.
+                    let _ = repo.git(["remote", "set-head", "origin", "--auto"]);
+                }
+                repository::default_branch(&git)?
+                    .ok_or_else(|| Error::Operation("no default branch found".to_string()))?
+            }
+        };
+        let current = repository::current_branch(&git)?;
+        if current.as_deref() != Some(branch.as_str()) {
+            if !ChangeCounts::collect(&git)?.is_clean() {
+                return Err(Error::Warning(format!(
+                    "fetched, not switching to {}: local changes on {}",
+                    branch,
+                    current.as_deref().unwrap_or("detached HEAD")
+                )));
+            }
+            // Creates the branch from origin when there is no local one.
+            repo.git(["checkout", "--quiet", &branch])?;
+        }
+        let remote = format!("origin/{}", branch);
+        if repo
+            .git(["rev-parse", "--verify", "--quiet", &remote])
+            .is_err()
+        {
+            return Ok(format!("fetched, on {}", branch));
+        }
+        let counts = repo.git([
+            "rev-list",
+            "--left-right",
+            "--count",
+            &format!("{}...{}", branch, remote),
+        ])?;
+        let (ahead, behind) = counts.split_once('\t').unwrap_or(("0", "0"));
+        match (ahead, behind) {
+            (_, "0") => Ok(format!("fetched, on {}", branch)),
+            ("0", behind) => {
+                repo.git(["merge", "--quiet", "--ff-only", &remote])?;
+                Ok(format!("fetched, on {}, {} new commit(s)", branch, behind))
+            }
+            (ahead, behind) => Err(Error::Warning(format!(
+                "fetched, {} diverged from {}: {} ahead, {} behind",
+                branch, remote, ahead, behind
+            ))),
+        }
+    }
+}
+
+impl GitOperation for SyncOperation {
+    fn name(&self) -> &str {
+        "sync"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn is_pipelined(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let source = self
+            .sources
+            .get(repo.name())
+            .ok_or_else(|| Error::RepositoryNotFound(repo.name().to_string()))?;
+        if repo.path().join(".git").exists() {
+            self.update(repo, source)
+        } else {
+            self.clone(repo, source)
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Tags across the repositories of a workspace, for releases cut together.
+
+use crate::operations::GitOperation;
+use crate::repository::GitRepository;
+use crate::{Error, Result};
+
+/// A tag of a repository.
+#[derive(Debug, Clone)]
+pub struct TagEntry {
+    pub name: String,
+    /// Whether it is an annotated tag, rather than a lightweight one.
+    pub annotated: bool,
+}
+
+/// The tags of `repo`, sorted.
+pub fn list(repo: &GitRepository) -> Result<Vec<TagEntry>> {
+    let output = repo.git(["tag", "--list", "--format=%(refname:strip=2) %(objecttype)"])?;
+    let mut tags: Vec<_> = output
+        .lines()
+        .filter_map(|line| {
+            let (name, kind) = line.rsplit_once(' ')?;
+            Some(TagEntry {
+                name: name.to_string(),
+                annotated: kind == "tag",
+            })
+        })
+        .collect();
+    tags.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(tags)
+}
+
+/// The commit `tag` points to in `repo`, `None` when it has no such tag.
+fn target(repo: &GitRepository, tag: &str) -> Option<String> {
+    repo.git([
+        "rev-parse",
+        "--verify",
+        "--quiet",
+        &format!("refs/tags/{}^{{commit}}", tag),
+    ])
+    .ok()
+}
+
+/// Tags HEAD, with an annotated tag when there is a `message`, a
+/// lightweight one otherwise.
+pub struct CreateTagOperation {
+    name: String,
+    message: Option<String>,
+}
+
+impl CreateTagOperation {
+    pub fn new(name: impl Into<String>, message: Option<String>) -> Self {
+        CreateTagOperation {
+            name: name.into(),
+            message,
+        }
+    }
+}
+
+impl GitOperation for CreateTagOperation {
+    fn name(&self) -> &str {
+        "tag"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let head = repo
+            .head_sha()
+            .map_err(|_| Error::Operation("no commit to tag".to_string()))?;
+        if let Some(tagged) = target(repo, &self.name) {
+            if tagged == head {
+                return Err(Error::Skipped(format!("{} is on HEAD already", self.name)));
+            }
+            return Err(Error::Operation(format!(
+                "{} exists already, on {}",
+                self.name,
+                &tagged[..7]
+            )));
+        }
+        match &self.message {
+            Some(message) => repo.git(["tag", "--annotate", "--message", message, &self.name])?,
+            None => repo.git(["tag", &self.name])?,
+        };
+        Ok(format!("tagged {}", &head[..7]))
+    }
+}
+
+/// Deletes a tag from every repository having it.
+pub struct DeleteTagOperation {
+    name: String,
+}
+
+impl DeleteTagOperation {
+    pub fn new(name: impl Into<String>) -> Self {
+        DeleteTagOperation { name: name.into() }
+    }
+}
+
+impl GitOperation for DeleteTagOperation {
+    fn name(&self) -> &str {
+        "tag delete"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let Some(tagged) = target(repo, &self.name) else {
+            return Err(Error::Skipped(format!("no tag {}", self.name)));
+        };
+        repo.git(["tag", "--delete", &self.name])?;
+        Ok(format!("deleted, was on {}", &tagged[..7]))
+    }
+}
@@ -0,0 +1,144 @@
+//! Output templates, for scripts wanting some fields in a given shape:
+//!
+//! ```text
+//! git-ws status --template '{repo}\t{branch}\t{ahead}/{behind}'
+//! ```
+//!
+//! `{field}` is replaced with a field of the record, `{field.key}` with
+//! a nested one. Missing values render as nothing. `{{` and `}}` write
+//! braces, and `\t`, `\n` and `\\` a tab, a newline and a backslash, as
+//! shells pass them through quotes as is.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{Error, Result};
+
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    Field(String),
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self> {
+        let invalid = |reason: &str| Error::Operation(format!("invalid template: {}", reason));
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => field.push(c),
+                            None => return Err(invalid("unclosed '{'")),
+                        }
+                    }
+                    let field = field.trim();
+                    if field.is_empty() {
+                        return Err(invalid("empty field name"));
+                    }
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(field.to_string()));
+                }
+                '}' => return Err(invalid("unmatched '}', write '}}' for a brace")),
+                '\\' => match chars.next() {
+                    Some('t') => text.push('\t'),
+                    Some('n') => text.push('\n'),
+                    Some('\\') => text.push('\\'),
+                    Some(c) => {
+                        text.push('\\');
+                        text.push(c);
+                    }
+                    None => text.push('\\'),
+                },
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Template { parts })
+    }
+
+    /// Renders the template for `record`, failing on fields it does not
+    /// have.
+    pub fn render(&self, record: &impl Serialize) -> Result<String> {
+        let record = serde_json::to_value(record)?;
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Field(field) => {
+                    let mut value = &record;
+                    for key in field.split('.') {
+                        if value.is_null() {
+                            break;
+                        }
+                        value = value.get(key).ok_or_else(|| {
+                            Error::Operation(format!("unknown template field '{}'", field))
+                        })?;
+                    }
+                    match value {
+                        Value::Null => {}
+                        Value::String(value) => rendered.push_str(value),
+                        value => rendered.push_str(&value.to_string()),
+                    }
+                }
+            }
+        }
+        Ok(rendered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn renders_fields_and_escapes() {
+        let template =
+            Template::parse("{repo}\\t{upstream.ahead}/{upstream.behind} {{x}}").unwrap();
+        let record = json!({"repo": "api", "upstream": {"ahead": 2, "behind": 0}});
+        assert_eq!(template.render(&record).unwrap(), "api\t2/0 {x}");
+    }
+
+    #[test]
+    fn renders_missing_values_as_nothing() {
+        let template = Template::parse("[{upstream.ahead}]").unwrap();
+        assert_eq!(template.render(&json!({"upstream": null})).unwrap(), "[]");
+        let error = template.render(&json!({})).unwrap_err();
+        assert_eq!(error.to_string(), "unknown template field 'upstream.ahead'");
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        for (source, reason) in [
+            ("{repo", "unclosed '{'"),
+            ("{ }", "empty field name"),
+            ("a}b", "unmatched '}', write '}}' for a brace"),
+        ] {
+            let error = Template::parse(source).unwrap_err();
+            assert_eq!(error.to_string(), format!("invalid template: {}", reason));
+        }
+    }
+}
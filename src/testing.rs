@@ -0,0 +1,421 @@
+//! Throwaway workspaces for tests, ours and those of tools built on git-ws.
+//!
+//! ```no_run
+//! use git_ws::testing::{RepoState, TestWorkspace};
+//!
+//! # fn main() -> git_ws::Result<()> {
+//! let ws = TestWorkspace::builder()
+//!     .repos(3, RepoState::Clean)
+//!     .repo("libs/dirty", RepoState::Dirty)
+//!     .repo("app", RepoState::Ahead(2))
+//!     .build()?;
+//! assert_eq!(ws.workspace().discover_repositories()?.len(), 5);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Every repository has a `main` branch tracking `origin/main`, where
+//! `origin` is a bare repository outside the workspace. Commits use a fixed
+//! author and fixed dates, so ids are the same on every run. The directory
+//! is removed when the [`TestWorkspace`] is dropped.
+//!
+//! With the `proptest` feature, [`strategies`] generates repository states
+//! and whole workspaces, for properties that must hold whatever the
+//! workspace looks like:
+//!
+//! ```ignore
+//! proptest! {
+//!     #[test]
+//!     fn finds_every_repository(shape in any::<WorkspaceShape>()) {
+//!         let ws = shape.build().unwrap();
+//!         let found = ws.workspace().discover_repositories().unwrap();
+//!         prop_assert_eq!(found.len(), shape.repos.len());
+//!     }
+//! }
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use git2::{
+    build::CheckoutBuilder, BranchType, Oid, Repository, RepositoryInitOptions, Signature, Time,
+};
+
+use crate::repository::GitRepository;
+use crate::workspace::{Workspace, STATE_DIR};
+use crate::Result;
+
+const BRANCH: &str = "main";
+const FILE: &str = "README";
+
+/// Seconds since the epoch of the first commit of every repository.
+const EPOCH: i64 = 1_600_000_000;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// State a repository is put in after its first commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepoState {
+    Clean,
+    /// A modified tracked file and an untracked file.
+    Dirty,
+    /// Commits not pushed to `origin/main`.
+    Ahead(usize),
+    /// Commits on `origin/main` not merged locally.
+    Behind(usize),
+    /// A merge stopped on a conflict in `README`.
+    Conflicted,
+}
+
+/// The repositories of a workspace and their states, to build as many test
+/// workspaces from as needed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkspaceShape {
+    pub repos: Vec<(String, RepoState)>,
+}
+
+impl WorkspaceShape {
+    pub fn build(&self) -> Result<TestWorkspace> {
+        self.repos
+            .iter()
+            .fold(TestWorkspace::builder(), |builder, (name, state)| {
+                builder.repo(name.clone(), *state)
+            })
+            .build()
+    }
+}
+
+#[derive(Default)]
+pub struct TestWorkspaceBuilder {
+    repos: Vec<(String, RepoState)>,
+}
+
+impl TestWorkspaceBuilder {
+    pub fn repo(mut self, name: impl Into<String>, state: RepoState) -> Self {
+        self.repos.push((name.into(), state));
+        self
+    }
+
+    /// Adds `count` repositories named `repo-<n>`, `n` being their position
+    /// among the repositories added so far.
+    pub fn repos(mut self, count: usize, state: RepoState) -> Self {
+        let start = self.repos.len();
+        for i in start..start + count {
+            self.repos.push((format!("repo-{}", i), state));
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<TestWorkspace> {
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!("git-ws-test-{}-{}", std::process::id(), id));
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        let root = dir.join("workspace");
+        fs::create_dir_all(root.join(STATE_DIR))?;
+        let test = TestWorkspace {
+            workspace: Workspace::new(&root),
+            dir,
+        };
+        for (name, state) in &self.repos {
+            test.create_repo(name, *state)?;
+        }
+        Ok(test)
+    }
+}
+
+/// A workspace in a temporary directory, removed on drop.
+pub struct TestWorkspace {
+    dir: PathBuf,
+    workspace: Workspace,
+}
+
+impl TestWorkspace {
+    pub fn builder() -> TestWorkspaceBuilder {
+        TestWorkspaceBuilder::default()
+    }
+
+    pub fn workspace(&self) -> &Workspace {
+        &self.workspace
+    }
+
+    pub fn root(&self) -> &Path {
+        self.workspace.root()
+    }
+
+    /// The bare repository serving as `origin` of the repository `name`.
+    pub fn origin(&self, name: &str) -> PathBuf {
+        self.dir.join("origins").join(format!("{}.git", name))
+    }
+
+    pub fn repository(&self, name: &str) -> GitRepository {
+        GitRepository::new(name, self.root().join(name))
+    }
+
+    /// Adds a repository in `state` to the workspace.
+    pub fn create_repo(&self, name: &str, state: RepoState) -> Result<GitRepository> {
+        let path = self.root().join(name);
+        let mut options = RepositoryInitOptions::new();
+        options.initial_head(BRANCH);
+        let repo = Repository::init_opts(&path, &options)?;
+        let mut writer = Writer {
+            repo: &repo,
+            tick: 0,
+        };
+
+        let base = writer.commit("Initial commit", "initial\n")?;
+        let origin = Repository::init_bare(self.origin(name))?;
+        let origin_url = self.origin(name).to_string_lossy().into_owned();
+        repo.remote("origin", &origin_url)?;
+        copy_objects(&repo, &origin, base)?;
+        origin.reference(&format!("refs/heads/{}", BRANCH), base, true, "test setup")?;
+        let tracking = format!("refs/remotes/origin/{}", BRANCH);
+        repo.reference(&tracking, base, true, "test setup")?;
+        let mut branch = repo.find_branch(BRANCH, BranchType::Local)?;
+        branch.set_upstream(Some(&format!("origin/{}", BRANCH)))?;
+
+        match state {
+            RepoState::Clean => {}
+            RepoState::Dirty => {
+                fs::write(path.join(FILE), "changed\n")?;
+                fs::write(path.join("untracked"), "new\n")?;
+            }
+            RepoState::Ahead(count) => {
+                for i in 0..count {
+                    writer.commit(
+                        &format!("Local change {}", i + 1),
+                        &format!("local {}\n", i),
+                    )?;
+                }
+            }
+            RepoState::Behind(count) => {
+                let mut tip = base;
+                for i in 0..count {
+                    tip = writer.commit_on(
+                        tip,
+                        &format!("Upstream change {}", i + 1),
+                        &format!("upstream {}\n", i),
+                    )?;
+                }
+                copy_objects(&repo, &origin, tip)?;
+                origin.reference(&format!("refs/heads/{}", BRANCH), tip, true, "test setup")?;
+                repo.reference(&tracking, tip, true, "test setup")?;
+            }
+            RepoState::Conflicted => {
+                let theirs = writer.commit_on(base, "Their change", "theirs\n")?;
+                writer.commit("Our change", "ours\n")?;
+                let theirs = repo.find_annotated_commit(theirs)?;
+                repo.merge(
+                    &[&theirs],
+                    None,
+                    Some(CheckoutBuilder::new().allow_conflicts(true)),
+                )?;
+            }
+        }
+        Ok(GitRepository::new(name, path))
+    }
+}
+
+impl Drop for TestWorkspace {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Writes commits with a fixed author and increasing dates.
+struct Writer<'a> {
+    repo: &'a Repository,
+    tick: i64,
+}
+
+impl Writer<'_> {
+    fn signature(&mut self) -> Result<Signature<'static>> {
+        self.tick += 1;
+        Ok(Signature::new(
+            "git-ws test",
+            "test@git-ws.invalid",
+            &Time::new(EPOCH + self.tick * 60, 0),
+        )?)
+    }
+
+    /// Commits `README` with `content` on the checked out branch and updates
+    /// the working tree.
+    fn commit(&mut self, message: &str, content: &str) -> Result<Oid> {
+        let parent = match self.repo.head() {
+            Ok(head) => Some(head.peel_to_commit()?),
+            Err(_) => None,
+        };
+        let tree = self.tree(content)?;
+        let signature = self.signature()?;
+        let parents: Vec<_> = parent.iter().collect();
+        let oid = self.repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &self.repo.find_tree(tree)?,
+            &parents,
+        )?;
+        self.repo
+            .checkout_head(Some(CheckoutBuilder::new().force()))?;
+        Ok(oid)
+    }
+
+    /// Commits on top of `parent` without moving any branch.
+    fn commit_on(&mut self, parent: Oid, message: &str, content: &str) -> Result<Oid> {
+        let tree = self.tree(content)?;
+        let signature = self.signature()?;
+        let parent = self.repo.find_commit(parent)?;
+        Ok(self.repo.commit(
+            None,
+            &signature,
+            &signature,
+            message,
+            &self.repo.find_tree(tree)?,
+            &[&parent],
+        )?)
+    }
+
+    fn tree(&self, content: &str) -> Result<Oid> {
+        let blob = self.repo.blob(content.as_bytes())?;
+        let mut builder = self.repo.treebuilder(None)?;
+        builder.insert(FILE, blob, 0o100644)?;
+        Ok(builder.write()?)
+    }
+}
+
+/// Strategies generating [`RepoState`]s and [`WorkspaceShape`]s, also
+/// behind `any::<RepoState>()` and `any::<WorkspaceShape>()`.
+#[cfg(any(test, feature = "proptest"))]
+pub mod strategies {
+    use proptest::arbitrary::Arbitrary;
+    use proptest::prelude::*;
+    use proptest::strategy::BoxedStrategy;
+
+    use super::{RepoState, WorkspaceShape};
+
+    /// Any state, with up to `max_commits` commits ahead or behind.
+    pub fn repo_state(max_commits: usize) -> impl Strategy<Value = RepoState> {
+        prop_oneof![
+            Just(RepoState::Clean),
+            Just(RepoState::Dirty),
+            (1..=max_commits).prop_map(RepoState::Ahead),
+            (1..=max_commits).prop_map(RepoState::Behind),
+            Just(RepoState::Conflicted),
+        ]
+    }
+
+    /// Relative paths of repositories, some nested in directories like
+    /// `libs/core`.
+    pub fn repo_name() -> impl Strategy<Value = String> {
+        "[a-z][a-z0-9-]{0,7}(/[a-z][a-z0-9-]{0,7})?"
+    }
+
+    /// Between one and `max_repos` repositories, none inside another.
+    pub fn workspace_shape(max_repos: usize) -> impl Strategy<Value = WorkspaceShape> {
+        prop::collection::btree_map(repo_name(), repo_state(3), 1..=max_repos).prop_map(|repos| {
+            let names: Vec<_> = repos.keys().cloned().collect();
+            let nested = |name: &str| {
+                names
+                    .iter()
+                    .any(|other| name.starts_with(&format!("{}/", other)))
+            };
+            WorkspaceShape {
+                repos: repos
+                    .into_iter()
+                    .filter(|(name, _)| !nested(name))
+                    .collect(),
+            }
+        })
+    }
+
+    impl Arbitrary for RepoState {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<RepoState>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            repo_state(3).boxed()
+        }
+    }
+
+    impl Arbitrary for WorkspaceShape {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<WorkspaceShape>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            workspace_shape(5).boxed()
+        }
+    }
+}
+
+/// Copies the history of `tip` from `from` into `to`.
+fn copy_objects(from: &Repository, to: &Repository, tip: Oid) -> Result<()> {
+    let source = from.odb()?;
+    let target = to.odb()?;
+    let mut walk = from.revwalk()?;
+    walk.push(tip)?;
+    for oid in walk {
+        let commit = from.find_commit(oid?)?;
+        copy_object(&source, &target, commit.id())?;
+        commit
+            .tree()?
+            .walk(git2::TreeWalkMode::PreOrder, |_, entry| {
+                let _ = copy_object(&source, &target, entry.id());
+                git2::TreeWalkResult::Ok
+            })?;
+        copy_object(&source, &target, commit.tree_id())?;
+    }
+    Ok(())
+}
+
+fn copy_object(source: &git2::Odb, target: &git2::Odb, oid: Oid) -> Result<()> {
+    let object = source.read(oid)?;
+    target.write(object.kind(), object.data())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn builds_every_repository_in_its_state(shape in any::<WorkspaceShape>()) {
+            let test = shape.build().unwrap();
+            let mut found: Vec<_> = test
+                .workspace()
+                .discover_repositories()
+                .unwrap()
+                .iter()
+                .map(|repo| repo.name().to_string())
+                .collect();
+            found.sort();
+            let names: Vec<_> = shape.repos.iter().map(|(name, _)| name.clone()).collect();
+            prop_assert_eq!(found, names);
+            for (name, state) in &shape.repos {
+                let snapshot = test.repository(name).snapshot().unwrap();
+                let upstream = snapshot.upstream.as_ref().unwrap();
+                prop_assert_eq!(snapshot.branch.as_deref(), Some(BRANCH));
+                let (ahead, behind) = match state {
+                    RepoState::Ahead(count) => (*count, 0),
+                    RepoState::Behind(count) => (0, *count),
+                    RepoState::Conflicted => (1, 0),
+                    RepoState::Clean | RepoState::Dirty => (0, 0),
+                };
+                prop_assert_eq!((upstream.ahead, upstream.behind), (ahead, behind), "{}", name);
+                prop_assert_eq!(snapshot.changes.conflicted, usize::from(*state == RepoState::Conflicted));
+                prop_assert_eq!(
+                    snapshot.changes.is_clean(),
+                    matches!(state, RepoState::Clean | RepoState::Ahead(_) | RepoState::Behind(_)),
+                    "{}",
+                    name
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Ticket references in commit messages, for release notes.
+//!
+//! `tickets --since v1.0` gathers the ticket ids the commits since `v1.0`
+//! mention in every repository, once each, with the repositories they
+//! touch. With `--titles`, the titles are asked of the tracker:
+//!
+//! ```toml
+//! [tickets]
+//! # Ids of Jira-like projects by default.
+//! pattern = 'PAY-\d+'
+//! # `{id}` is replaced by the ticket id.
+//! url = "https://jira.example.com/rest/api/2/issue/{id}"
+//! # Dotted path of the title in the JSON answer.
+//! title = "fields.summary"
+//! token = { env = "JIRA_TOKEN" }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::thread;
+use std::time::Duration;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::TokenSource;
+use crate::repository::GitRepository;
+use crate::{Error, Result};
+
+const DEFAULT_PATTERN: &str = r"\b[A-Z][A-Z0-9]+-[0-9]+\b";
+const DEFAULT_TITLE: &str = "fields.summary";
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// Titles asked for at once.
+const CONCURRENT_REQUESTS: usize = 8;
+
+/// The `[tickets]` section of the configuration.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TicketConfig {
+    /// Regular expression matching a ticket id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// URL of a ticket in the API of the tracker, `{id}` standing for its id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Dotted path of the title in the answer of the tracker.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Bearer token sent along.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<TokenSource>,
+}
+
+impl TicketConfig {
+    pub fn pattern(&self) -> Result<Regex> {
+        let pattern = self.pattern.as_deref().unwrap_or(DEFAULT_PATTERN);
+        Regex::new(pattern)
+            .map_err(|e| Error::Operation(format!("invalid ticket pattern {}: {}", pattern, e)))
+    }
+}
+
+/// A ticket the commits mention.
+#[derive(Debug, Clone)]
+pub struct Ticket {
+    pub id: String,
+    /// Repositories with a commit mentioning it.
+    pub repos: BTreeSet<String>,
+    pub commits: usize,
+    pub title: Option<String>,
+}
+
+/// The ticket ids the commits of `repo` after `since` mention, with the
+/// number of commits mentioning each. Skipped when `repo` has no `since`.
+pub fn scan(repo: &GitRepository, since: &str, pattern: &Regex) -> Result<BTreeMap<String, usize>> {
+    let since_commit = format!("{}^{{commit}}", since);
+    if repo
+        .git(["rev-parse", "--verify", "--quiet", &since_commit])
+        .is_err()
+    {
+        return Err(Error::Skipped(format!("no {}", since)));
+    }
+    let range = format!("{}..HEAD", since);
+    let log = repo.git(["log", "--format=%B%x1e", &range])?;
+    let mut found = BTreeMap::new();
+    for message in log.split('\x1e') {
+        let ids: BTreeSet<_> = pattern.find_iter(message).map(|m| m.as_str()).collect();
+        for id in ids {
+            *found.entry(id.to_string()).or_insert(0) += 1;
+        }
+    }
+    Ok(found)
+}
+
+/// The tickets of every repository, each once, ordered by project and
+/// number.
+pub fn merge(found: Vec<(String, BTreeMap<String, usize>)>) -> Vec<Ticket> {
+    let mut tickets: BTreeMap<String, Ticket> = BTreeMap::new();
+    for (repo, ids) in found {
+        for (id, commits) in ids {
+            let ticket = tickets.entry(id.clone()).or_insert_with(|| Ticket {
+                id,
+                repos: BTreeSet::new(),
+                commits: 0,
+                title: None,
+            });
+            ticket.repos.insert(repo.clone());
+            ticket.commits += commits;
+        }
+    }
+    let mut tickets: Vec<_> = tickets.into_values().collect();
+    tickets.sort_by(|a, b| order(&a.id).cmp(&order(&b.id)));
+    tickets
+}
+
+/// `PAY-12` before `PAY-100`: the project, then the number.
+fn order(id: &str) -> (&str, u64, &str) {
+    match id.rsplit_once('-') {
+        Some((project, number)) => (project, number.parse().unwrap_or(u64::MAX), id),
+        None => (id, 0, id),
+    }
+}
+
+/// Asks the tracker for the title of every ticket, returning the errors by
+/// ticket id. Fails when no tracker is configured.
+pub fn fetch_titles(config: &TicketConfig, tickets: &mut [Ticket]) -> Result<Vec<(String, Error)>> {
+    let Some(url) = &config.url else {
+        return Err(Error::Operation(
+            "no tracker configured, set tickets.url".to_string(),
+        ));
+    };
+    let token = config
+        .token
+        .as_ref()
+        .map(TokenSource::resolve)
+        .transpose()?;
+    let path = config.title.as_deref().unwrap_or(DEFAULT_TITLE);
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+    let fetch = |id: &str| -> Result<Option<String>> {
+        let mut request = agent
+            .get(&url.replace("{id}", id))
+            .set("User-Agent", "git-ws")
+            .set("Accept", "application/json");
+        if let Some(token) = &token {
+            request = request.set("Authorization", &format!("Bearer {}", token.trim()));
+        }
+        let answer: Value = match request.call() {
+            Ok(response) => response.into_json()?,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(ureq::Error::Status(code, _)) => {
+                return Err(Error::Operation(format!("the tracker answered {}", code)))
+            }
+            Err(ureq::Error::Transport(transport)) => {
+                return Err(Error::Operation(format!(
+                    "the tracker is unreachable: {}",
+                    transport
+                )))
+            }
+        };
+        let title = path
+            .split('.')
+            .try_fold(&answer, |value, key| value.get(key))
+            .and_then(Value::as_str);
+        Ok(title.map(str::to_string))
+    };
+    let mut errors = Vec::new();
+    for chunk in tickets.chunks_mut(CONCURRENT_REQUESTS) {
+        let titles: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = chunk
+                .iter()
+                .map(|ticket| scope.spawn(|| fetch(&ticket.id)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("fetching a title panicked"))
+                .collect()
+        });
+        for (ticket, title) in chunk.iter_mut().zip(titles) {
+            match title {
+                Ok(title) => ticket.title = title,
+                Err(e) => errors.push((ticket.id.clone(), e)),
+            }
+        }
+    }
+    Ok(errors)
+}
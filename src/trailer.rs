@@ -0,0 +1,68 @@
+//! Trailers git-ws adds to the commits it creates, to find the commits of
+//! one batch, or one changeset, across repositories later on.
+
+use crate::repository::GitRepository;
+use crate::Result;
+
+/// Id shared by the commits one `commit` created in every repository.
+pub const BATCH: &str = "Git-Ws-Batch";
+/// Changeset the commit belongs to, like PAY-123.
+pub const CHANGESET: &str = "Git-Ws-Changeset";
+
+/// A new batch id.
+pub fn new_batch_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// `message` with the `trailers` appended, in their own paragraph unless the
+/// message already ends with trailers.
+pub fn append(message: &str, trailers: &[(String, String)]) -> String {
+    if trailers.is_empty() {
+        return message.to_string();
+    }
+    let message = message.trim_end();
+    let last = message.rsplit("\n\n").next().unwrap_or_default();
+    let mut result = message.to_string();
+    let has_trailers = message.contains("\n\n") && last.lines().all(is_trailer);
+    result.push_str(if has_trailers { "\n" } else { "\n\n" });
+    for (key, value) in trailers {
+        result.push_str(&format!("{}: {}\n", key, value));
+    }
+    result
+}
+
+fn is_trailer(line: &str) -> bool {
+    line.split_once(": ").is_some_and(|(key, _)| {
+        !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+/// A commit found by its trailer.
+#[derive(Debug, Clone)]
+pub struct TrailedCommit {
+    pub id: String,
+    pub subject: String,
+}
+
+/// The commits of `repo`, reachable from any ref, carrying the trailer
+/// `key: value`.
+pub fn find(repo: &GitRepository, key: &str, value: &str) -> Result<Vec<TrailedCommit>> {
+    let format = format!("--format=%h%x00%s%x00%(trailers:key={},valueonly)%x01", key);
+    let log = repo.git(["log", "--all", &format])?;
+    Ok(log
+        .split('\x01')
+        .filter_map(|entry| {
+            let mut fields = entry.trim_start_matches('\n').splitn(3, '\0');
+            let id = fields.next()?;
+            let subject = fields.next()?;
+            let values = fields.next()?;
+            values
+                .lines()
+                .any(|found| found.trim() == value)
+                .then(|| TrailedCommit {
+                    id: id.to_string(),
+                    subject: subject.to_string(),
+                })
+        })
+        .collect())
+}
@@ -0,0 +1,264 @@
+//! A trash for the files workspace-wide commands delete, like `rm --trash`.
+//!
+//! Every run of such a command moves its files to its own directory,
+//! `.git-ws/trash/<time>/<repo>/<path>`, named after the time it ran, in
+//! seconds since the epoch. `trash restore` moves them back. Runs older
+//! than the expiry of the configuration are deleted for good whenever
+//! files are trashed.
+//!
+//! ```toml
+//! [trash]
+//! expire_days = 14
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::repository::GitRepository;
+use crate::workspace::Workspace;
+use crate::{Error, Result};
+
+const TRASH_DIR: &str = "trash";
+const DEFAULT_EXPIRE_DAYS: u64 = 14;
+
+/// The `[trash]` section of the configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashConfig {
+    /// Days trashed files are kept.
+    #[serde(default = "default_expire_days")]
+    pub expire_days: u64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig {
+            expire_days: DEFAULT_EXPIRE_DAYS,
+        }
+    }
+}
+
+fn default_expire_days() -> u64 {
+    DEFAULT_EXPIRE_DAYS
+}
+
+impl TrashConfig {
+    pub fn max_age(&self) -> Duration {
+        Duration::from_secs(self.expire_days * 86400)
+    }
+}
+
+/// Where one run puts the files it deletes.
+#[derive(Debug, Clone)]
+pub struct Trash {
+    dir: PathBuf,
+}
+
+impl Trash {
+    /// The trash of a run starting now, in the state directory `state_dir`.
+    pub fn new(state_dir: &Path) -> Self {
+        let trash = state_dir.join(TRASH_DIR);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        // Runs in the same second get the next free name.
+        let mut id = now;
+        while trash.join(id.to_string()).exists() {
+            id += 1;
+        }
+        Trash {
+            dir: trash.join(id.to_string()),
+        }
+    }
+
+    pub fn id(&self) -> String {
+        self.dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Moves `path`, relative to `repo`, to the trash, and removes the
+    /// directories it leaves empty, as git does.
+    pub fn put(&self, repo: &GitRepository, path: &str) -> Result<()> {
+        let source = repo.path().join(path);
+        move_path(&source, &self.dir.join(repo.name()).join(path))?;
+        for dir in source.ancestors().skip(1) {
+            if dir == repo.path() || fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Moves `from` to `to`, copying it when they are on different
+/// filesystems.
+fn move_path(from: &Path, to: &Path) -> Result<()> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    copy_path(from, to)?;
+    if from.is_dir() {
+        fs::remove_dir_all(from)?;
+    } else {
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+fn copy_path(from: &Path, to: &Path) -> io::Result<()> {
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_path(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+/// A run whose files are in the trash.
+#[derive(Debug, Clone)]
+pub struct TrashedRun {
+    pub id: String,
+    /// When the run started, in seconds since the epoch.
+    pub time: i64,
+    /// Trashed files, relative to their repository, by repository.
+    pub files: BTreeMap<String, Vec<String>>,
+}
+
+/// The runs in the trash of `workspace`, the latest first.
+pub fn list(workspace: &Workspace) -> Result<Vec<TrashedRun>> {
+    let trash = workspace.state_dir().join(TRASH_DIR);
+    if !trash.is_dir() {
+        return Ok(Vec::new());
+    }
+    let names = workspace.cached_repository_names()?;
+    let mut runs = Vec::new();
+    for entry in fs::read_dir(&trash)? {
+        let entry = entry?;
+        let id = entry.file_name().to_string_lossy().into_owned();
+        let Ok(time) = id.parse() else {
+            continue;
+        };
+        let mut files = BTreeMap::new();
+        collect_files(&names, &entry.path(), &entry.path(), &mut files)?;
+        runs.push(TrashedRun { id, time, files });
+    }
+    runs.sort_by_key(|run| std::cmp::Reverse(run.time));
+    Ok(runs)
+}
+
+/// Collects the files below `dir`, in the trash of a run at `run`, by the
+/// repository of `names` they belong to.
+fn collect_files(
+    names: &[String],
+    run: &Path,
+    dir: &Path,
+    files: &mut BTreeMap<String, Vec<String>>,
+) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            collect_files(names, run, &path, files)?;
+            continue;
+        }
+        let relative = path.strip_prefix(run).unwrap_or(&path);
+        let relative = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        // Repository names hold slashes too, the longest one matching wins.
+        let repo = names
+            .iter()
+            .filter(|name| relative.starts_with(&format!("{}/", name)))
+            .max_by_key(|name| name.len())
+            .cloned();
+        let (repo, file) = match repo {
+            Some(repo) => {
+                let file = relative[repo.len() + 1..].to_string();
+                (repo, file)
+            }
+            None => match relative.split_once('/') {
+                Some((repo, file)) => (repo.to_string(), file.to_string()),
+                None => continue,
+            },
+        };
+        files.entry(repo).or_default().push(file);
+    }
+    Ok(())
+}
+
+/// What restoring a run did in a repository.
+#[derive(Debug, Clone, Default)]
+pub struct Restored {
+    pub restored: usize,
+    /// Files left in the trash, as the repository has them again.
+    pub existing: Vec<String>,
+}
+
+/// Moves the files of the run `id` back to their repositories, leaving in
+/// the trash those the repositories have again, unless `force`d. The run
+/// is removed from the trash once it is empty.
+pub fn restore(workspace: &Workspace, id: &str, force: bool) -> Result<BTreeMap<String, Restored>> {
+    let run = list(workspace)?
+        .into_iter()
+        .find(|run| run.id == id)
+        .ok_or_else(|| Error::Operation(format!("no run {} in the trash", id)))?;
+    let dir = workspace.state_dir().join(TRASH_DIR).join(&run.id);
+    let mut restored: BTreeMap<String, Restored> = BTreeMap::new();
+    for (repo, files) in &run.files {
+        let outcome = restored.entry(repo.clone()).or_default();
+        for file in files {
+            let target = workspace.root().join(repo).join(file);
+            if target.exists() && !force {
+                outcome.existing.push(file.clone());
+                continue;
+            }
+            move_path(&dir.join(repo).join(file), &target)?;
+            outcome.restored += 1;
+        }
+    }
+    if restored.values().all(|outcome| outcome.existing.is_empty()) {
+        fs::remove_dir_all(&dir)?;
+    }
+    Ok(restored)
+}
+
+/// Deletes for good the runs older than `max_age`, returning how many.
+pub fn expire(workspace: &Workspace, max_age: Duration) -> Result<usize> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default();
+    let mut expired = 0;
+    for run in list(workspace)? {
+        if now - run.time > max_age.as_secs() as i64 {
+            fs::remove_dir_all(workspace.state_dir().join(TRASH_DIR).join(&run.id))?;
+            expired += 1;
+        }
+    }
+    Ok(expired)
+}
+
+/// Deletes every run in the trash for good.
+pub fn empty(workspace: &Workspace) -> Result<()> {
+    let trash = workspace.state_dir().join(TRASH_DIR);
+    if trash.is_dir() {
+        fs::remove_dir_all(trash)?;
+    }
+    Ok(())
+}
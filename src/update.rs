@@ -0,0 +1,354 @@
+//! Updating git-ws itself from its releases, for machines without cargo.
+//!
+//! `self-update` looks for the latest release of a channel, `stable` or
+//! `nightly` for the prereleases, and downloads the binary of the platform,
+//! like `git-ws-x86_64-linux`. The binary is checked against the SHA-256
+//! checksum published along with it, `.sha256`, and against its minisign
+//! signature, `.minisig`, whose trusted comment must name the release and
+//! the binary, like `tag:v1.2.0 file:git-ws-x86_64-linux`: the feed is not
+//! signed, and could offer an older binary as a newer release otherwise.
+//! Only then is the running binary replaced, in one rename.
+//!
+//! A release is newer when its tag is a greater version than the one of
+//! the running binary, prereleases ordered as in semver, like
+//! `v1.3.0-nightly.20261014`. The public key of the signatures and the tag
+//! are set at build time, in `GIT_WS_UPDATE_KEY` and `GIT_WS_RELEASE_TAG`,
+//! by the release builds. Other builds can look for updates but not install
+//! them.
+
+use std::cmp::Ordering;
+use std::env::consts::{ARCH, EXE_SUFFIX, OS};
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{Error, Result};
+
+/// The releases of git-ws, newest first.
+pub const FEED: &str = "https://api.github.com/repos/locez/git-ws/releases";
+const PUBLIC_KEY: Option<&str> = option_env!("GIT_WS_UPDATE_KEY");
+/// Tag of the release the binary was built for, the version of the crate
+/// for other builds.
+const RELEASE_TAG: Option<&str> = option_env!("GIT_WS_RELEASE_TAG");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const TIMEOUT: Duration = Duration::from_secs(60);
+/// Largest download accepted.
+const MAX_SIZE: u64 = 256 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Stable,
+    Nightly,
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Stable => write!(f, "stable"),
+            Channel::Nightly => write!(f, "nightly"),
+        }
+    }
+}
+
+/// What [`run`] found or did.
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    /// The running binary is the release, or newer.
+    UpToDate { tag: String },
+    /// The release is newer, and was not installed.
+    Available { tag: String },
+    /// The binary at `path` was replaced by the release.
+    Updated { tag: String, path: PathBuf },
+}
+
+#[derive(Deserialize)]
+struct FeedRelease {
+    tag_name: String,
+    #[serde(default)]
+    prerelease: bool,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// The name of the binary of this platform in a release.
+pub fn asset_name() -> String {
+    format!("git-ws-{}-{}{}", ARCH, OS, EXE_SUFFIX)
+}
+
+/// Looks for the latest release of `channel` and, unless `check_only`,
+/// replaces the running binary with it when it is newer.
+pub fn run(channel: Channel, check_only: bool) -> Result<Outcome> {
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+    let releases: Vec<FeedRelease> = get(&agent, FEED)?.into_json()?;
+    let release = releases
+        .into_iter()
+        .filter(|release| !release.draft)
+        .find(|release| release.prerelease == (channel == Channel::Nightly))
+        .ok_or_else(|| Error::Operation(format!("no {} release", channel)))?;
+    let tag = release.tag_name;
+    let name = asset_name();
+    let url = |suffix: &str| {
+        let name = format!("{}{}", name, suffix);
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .map(|asset| asset.browser_download_url.as_str())
+            .ok_or_else(|| Error::Operation(format!("release {} has no {}", tag, name)))
+    };
+
+    let checksum = String::from_utf8_lossy(&download(&agent, url(".sha256")?)?)
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    if checksum.len() != 64 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Error::Operation(format!(
+            "invalid checksum of {} in release {}",
+            name, tag
+        )));
+    }
+    let path = std::env::current_exe()?.canonicalize()?;
+    let Some(order) = compare(&tag, RELEASE_TAG.unwrap_or(VERSION)) else {
+        return Err(Error::Operation(format!(
+            "the tag of release {} is not a version",
+            tag
+        )));
+    };
+    let newer = order.is_gt();
+    if !newer || sha256(&fs::read(&path)?) == checksum {
+        return Ok(Outcome::UpToDate { tag });
+    }
+    if check_only {
+        return Ok(Outcome::Available { tag });
+    }
+
+    let Some(key) = PUBLIC_KEY else {
+        return Err(Error::Operation(
+            "this build has no key to check the releases with, install the release by hand"
+                .to_string(),
+        ));
+    };
+    let binary = download(&agent, url("")?)?;
+    if sha256(&binary) != checksum {
+        return Err(Error::Operation(format!(
+            "the {} downloaded does not match its checksum",
+            name
+        )));
+    }
+    let signature = String::from_utf8_lossy(&download(&agent, url(".minisig")?)?).into_owned();
+    let signature = PublicKey::from_base64(key).and_then(|key| {
+        let signature = Signature::decode(&signature)?;
+        key.verify(&binary, &signature, false)?;
+        Ok(signature)
+    });
+    let signature = match signature {
+        Ok(signature) => signature,
+        Err(e) => {
+            return Err(Error::Operation(format!(
+                "the signature of the {} downloaded is invalid: {}",
+                name, e
+            )))
+        }
+    };
+    if !signs(signature.trusted_comment(), &tag, &name) {
+        return Err(Error::Operation(format!(
+            "the {} downloaded is signed for another release: {}",
+            name,
+            signature.trusted_comment()
+        )));
+    }
+    replace(&path, &binary)?;
+    Ok(Outcome::Updated { tag, path })
+}
+
+fn get(agent: &ureq::Agent, url: &str) -> Result<ureq::Response> {
+    match agent.get(url).set("User-Agent", "git-ws").call() {
+        Ok(response) => Ok(response),
+        Err(ureq::Error::Status(code, _)) => {
+            Err(Error::Operation(format!("{} answered {}", url, code)))
+        }
+        Err(ureq::Error::Transport(transport)) => Err(Error::Operation(format!(
+            "the releases are unreachable: {}",
+            transport
+        ))),
+    }
+}
+
+fn download(agent: &ureq::Agent, url: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    get(agent, url)?
+        .into_reader()
+        .take(MAX_SIZE)
+        .read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Whether the trusted comment of a signature names the release `tag` and
+/// the binary `asset`.
+fn signs(trusted_comment: &str, tag: &str, asset: &str) -> bool {
+    let has = |key: &str, value: &str| {
+        trusted_comment
+            .split_whitespace()
+            .any(|field| field.strip_prefix(key) == Some(value))
+    };
+    has("tag:", tag) && has("file:", asset)
+}
+
+/// Compares the versions `v1.2.3` and `1.2.0`, `None` when either is not
+/// one. Prereleases, like `1.2.0-rc.1`, come before their version and are
+/// ordered field by field, numbers before names.
+fn compare(a: &str, b: &str) -> Option<Ordering> {
+    let parse = |version: &str| -> Option<(Vec<u64>, Option<String>)> {
+        let version = version.trim_start_matches('v');
+        let version = version.split('+').next()?;
+        let (core, prerelease) = match version.split_once('-') {
+            Some((core, prerelease)) => (core, Some(prerelease.to_string())),
+            None => (version, None),
+        };
+        let core: Option<Vec<u64>> = core.split('.').map(|part| part.parse().ok()).collect();
+        Some((core?, prerelease))
+    };
+    let (a_core, a_pre) = parse(a)?;
+    let (b_core, b_pre) = parse(b)?;
+    Some(a_core.cmp(&b_core).then_with(|| match (a_pre, b_pre) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => compare_prereleases(&a, &b),
+    }))
+}
+
+fn compare_prereleases(a: &str, b: &str) -> Ordering {
+    let mut a = a.split('.');
+    let mut b = b.split('.');
+    loop {
+        let order = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => a.cmp(b),
+            },
+        };
+        if order.is_ne() {
+            return order;
+        }
+    }
+}
+
+/// Writes `binary` next to `path`, with the permissions of `path`, and
+/// renames it over `path`.
+fn replace(path: &Path, binary: &[u8]) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| Error::Operation(format!("{} is not a file", path.display())))?;
+    let new = path.with_file_name(format!(".{}.new", file_name.to_string_lossy()));
+    fs::write(&new, binary)?;
+    if let Err(e) =
+        fs::set_permissions(&new, fs::metadata(path)?.permissions()).and_then(|()| swap(&new, path))
+    {
+        let _ = fs::remove_file(&new);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn swap(new: &Path, path: &Path) -> std::io::Result<()> {
+    fs::rename(new, path)
+}
+
+/// Windows cannot replace a running binary, but can rename it out of the
+/// way first.
+#[cfg(windows)]
+fn swap(new: &Path, path: &Path) -> std::io::Result<()> {
+    let old = path.with_extension("old");
+    let _ = fs::remove_file(&old);
+    fs::rename(path, &old)?;
+    fs::rename(new, path).inspect_err(|_| {
+        let _ = fs::rename(&old, path);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_versions() {
+        assert_eq!(compare("v1.2.3", "1.2.0"), Some(Ordering::Greater));
+        assert_eq!(compare("1.10.0", "1.9.9"), Some(Ordering::Greater));
+        assert_eq!(compare("v0.3.0-rc.1", "0.3.0"), Some(Ordering::Less));
+        assert_eq!(compare("v1.0", "1.0.1"), Some(Ordering::Less));
+        assert_eq!(compare("nightly", "1.0.0"), None);
+    }
+
+    #[test]
+    fn orders_prereleases() {
+        let nightly = "v1.3.0-nightly.20261014";
+        assert_eq!(
+            compare(nightly, "v1.3.0-nightly.20261013"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(compare(nightly, nightly), Some(Ordering::Equal));
+        assert_eq!(compare(nightly, "1.2.9"), Some(Ordering::Greater));
+        assert_eq!(compare("1.0.0-rc.2", "1.0.0-rc.10"), Some(Ordering::Less));
+        assert_eq!(compare("1.0.0-rc.1", "1.0.0-1"), Some(Ordering::Greater));
+        assert_eq!(compare("1.0.0-rc", "1.0.0-rc.1"), Some(Ordering::Less));
+    }
+
+    #[test]
+    fn checks_the_release_signed() {
+        let asset = "git-ws-x86_64-linux";
+        assert!(signs(
+            "tag:v1.2.0 file:git-ws-x86_64-linux",
+            "v1.2.0",
+            asset
+        ));
+        assert!(signs(
+            "timestamp:1760000000 file:git-ws-x86_64-linux tag:v1.2.0",
+            "v1.2.0",
+            asset
+        ));
+        assert!(!signs(
+            "tag:v1.1.0 file:git-ws-x86_64-linux",
+            "v1.2.0",
+            asset
+        ));
+        assert!(!signs(
+            "tag:v1.2.0 file:git-ws-aarch64-linux",
+            "v1.2.0",
+            asset
+        ));
+        assert!(!signs(
+            "timestamp:1760000000 file:git-ws-x86_64-linux",
+            "v1.2.0",
+            asset
+        ));
+    }
+}
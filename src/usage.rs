@@ -0,0 +1,301 @@
+//! Local statistics on how git-ws is used, for `git-ws usage`.
+//!
+//! Every operation git-ws runs on a repository is written to `usage.jsonl`
+//! in the state directory, when the workspace has one, with its batch, how
+//! long it took and how it ended. Nothing leaves the machine: `usage` reads
+//! the file back to tell the operations run most, how long their batches
+//! take and how many repositories run at once, and the repositories failing
+//! most.
+//!
+//! The journal, see [`crate::recover`], cannot tell those: it only records
+//! the branches mutating operations move, without durations or outcomes.
+//! Once the file reaches [`MAX_FILE_SIZE`] it is moved to `usage.jsonl.1`,
+//! replacing the previous one, so the statistics cover the latest runs
+//! only.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::middleware::{Middleware, Next};
+use crate::operations::GitOperation;
+use crate::repository::GitRepository;
+use crate::{Error, Result};
+
+pub const USAGE_FILE: &str = "usage.jsonl";
+/// Size past which the file is rotated.
+pub const MAX_FILE_SIZE: u64 = 4 << 20;
+
+/// An operation run on a repository.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageEntry {
+    /// Id of the run of git-ws.
+    pub batch: String,
+    /// Name of the operation, like `pull`.
+    pub operation: String,
+    pub repo: String,
+    /// When the operation started, in milliseconds since the epoch.
+    pub started: u64,
+    /// How long it took, in milliseconds.
+    pub duration: u64,
+    pub result: RunResult,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunResult {
+    Ok,
+    Skipped,
+    Warning,
+    Failed,
+}
+
+/// The usage of the workspace whose state directory is `dir`, oldest entry
+/// first. Lines that do not parse, like one a crash cut short, are
+/// skipped.
+pub fn load(dir: &Path) -> Result<Vec<UsageEntry>> {
+    let path = dir.join(USAGE_FILE);
+    let mut entries = Vec::new();
+    for path in [rotated(&path), path] {
+        if !path.exists() {
+            continue;
+        }
+        entries.extend(
+            fs::read_to_string(path)?
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok()),
+        );
+    }
+    Ok(entries)
+}
+
+fn rotated(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+/// Writes every operation run to the usage of the workspace.
+pub struct Usage {
+    path: PathBuf,
+    batch: String,
+    lock: Mutex<()>,
+}
+
+impl Usage {
+    /// The usage in the state directory `dir`, for the batch `batch`.
+    pub fn new(dir: &Path, batch: impl Into<String>) -> Self {
+        Usage {
+            path: dir.join(USAGE_FILE),
+            batch: batch.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn append(&self, entry: &UsageEntry) -> Result<()> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if !self.path.parent().is_some_and(Path::is_dir) {
+            return Ok(());
+        }
+        if fs::metadata(&self.path).is_ok_and(|metadata| metadata.len() >= MAX_FILE_SIZE) {
+            fs::rename(&self.path, rotated(&self.path))?;
+        }
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        // One write, for the lines of processes running at once not to mix.
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)?;
+        Ok(())
+    }
+}
+
+impl Middleware for Usage {
+    fn call(
+        &self,
+        repo: &GitRepository,
+        operation: &dyn GitOperation,
+        next: Next<'_>,
+    ) -> Result<String> {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        let start = Instant::now();
+        let result = next.run(repo);
+        let entry = UsageEntry {
+            batch: self.batch.clone(),
+            operation: operation.name().to_string(),
+            repo: repo.name().to_string(),
+            started,
+            duration: start.elapsed().as_millis() as u64,
+            result: match &result {
+                Ok(_) => RunResult::Ok,
+                Err(Error::Skipped(_)) => RunResult::Skipped,
+                Err(Error::Warning(_)) => RunResult::Warning,
+                Err(_) => RunResult::Failed,
+            },
+        };
+        // Statistics are not worth failing the operation for.
+        let _ = self.append(&entry);
+        result
+    }
+}
+
+/// How an operation was used.
+#[derive(Debug, Clone)]
+pub struct OperationUsage {
+    pub name: String,
+    pub batches: usize,
+    /// Repositories it ran on, counting each batch.
+    pub runs: usize,
+    pub failed: usize,
+    /// From the first repository starting to the last one ending, on
+    /// average.
+    pub average_batch: Duration,
+    /// Repositories running at once, on average.
+    pub parallelism: f64,
+}
+
+/// How the operations went on a repository.
+#[derive(Debug, Clone)]
+pub struct RepoUsage {
+    pub name: String,
+    pub runs: usize,
+    pub failed: usize,
+    pub warnings: usize,
+    pub average: Duration,
+    /// The operations that failed on it, each once.
+    pub failing: BTreeSet<String>,
+}
+
+/// The operations, the most batches first, and the repositories, the most
+/// failures first, of `entries`.
+pub fn summarize(entries: &[UsageEntry]) -> (Vec<OperationUsage>, Vec<RepoUsage>) {
+    let mut batches: BTreeMap<(&str, &str), Vec<&UsageEntry>> = BTreeMap::new();
+    for entry in entries {
+        batches
+            .entry((entry.operation.as_str(), entry.batch.as_str()))
+            .or_default()
+            .push(entry);
+    }
+    let mut operations: BTreeMap<&str, OperationUsage> = BTreeMap::new();
+    let mut busy: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+    for ((name, _), runs) in &batches {
+        let first = runs.iter().map(|run| run.started).min().unwrap_or_default();
+        let last = runs
+            .iter()
+            .map(|run| run.started + run.duration)
+            .max()
+            .unwrap_or_default();
+        let usage = operations.entry(name).or_insert_with(|| OperationUsage {
+            name: name.to_string(),
+            batches: 0,
+            runs: 0,
+            failed: 0,
+            average_batch: Duration::ZERO,
+            parallelism: 0.0,
+        });
+        usage.batches += 1;
+        usage.runs += runs.len();
+        usage.failed += runs
+            .iter()
+            .filter(|run| run.result == RunResult::Failed)
+            .count();
+        let (working, elapsed) = busy.entry(name).or_default();
+        *working += runs.iter().map(|run| run.duration).sum::<u64>();
+        *elapsed += last - first;
+    }
+    for (name, usage) in &mut operations {
+        let (working, elapsed) = busy[name];
+        usage.average_batch = Duration::from_millis(elapsed / usage.batches as u64);
+        usage.parallelism = if elapsed == 0 {
+            1.0
+        } else {
+            working as f64 / elapsed as f64
+        };
+    }
+    let mut operations: Vec<_> = operations.into_values().collect();
+    operations.sort_by_key(|usage| Reverse(usage.batches));
+
+    let mut repos: BTreeMap<&str, (RepoUsage, u64)> = BTreeMap::new();
+    for entry in entries {
+        let (usage, total) = repos.entry(&entry.repo).or_insert_with(|| {
+            let usage = RepoUsage {
+                name: entry.repo.clone(),
+                runs: 0,
+                failed: 0,
+                warnings: 0,
+                average: Duration::ZERO,
+                failing: BTreeSet::new(),
+            };
+            (usage, 0)
+        });
+        usage.runs += 1;
+        *total += entry.duration;
+        match entry.result {
+            RunResult::Failed => {
+                usage.failed += 1;
+                usage.failing.insert(entry.operation.clone());
+            }
+            RunResult::Warning => usage.warnings += 1,
+            RunResult::Ok | RunResult::Skipped => {}
+        }
+    }
+    let mut repos: Vec<_> = repos
+        .into_values()
+        .map(|(mut usage, total)| {
+            usage.average = Duration::from_millis(total / usage.runs as u64);
+            usage
+        })
+        .collect();
+    repos.sort_by_key(|usage| (Reverse(usage.failed), Reverse(usage.warnings)));
+    (operations, repos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(operation: &str) -> UsageEntry {
+        UsageEntry {
+            batch: "b1".to_string(),
+            operation: operation.to_string(),
+            repo: "api".to_string(),
+            started: 0,
+            duration: 10,
+            result: RunResult::Ok,
+        }
+    }
+
+    #[test]
+    fn rotates_and_skips_broken_lines() {
+        let dir = std::env::temp_dir().join(format!("git-ws-usage-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let usage = Usage::new(&dir, "b1");
+        usage.append(&entry("status")).unwrap();
+        let path = dir.join(USAGE_FILE);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"batch\":\"b1\",\"oper").unwrap();
+        file.write_all(b"\n").unwrap();
+        file.set_len(MAX_FILE_SIZE).unwrap();
+        usage.append(&entry("pull")).unwrap();
+
+        assert!(rotated(&path).exists());
+        let operations: Vec<_> = load(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.operation)
+            .collect();
+        assert_eq!(operations, ["status", "pull"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
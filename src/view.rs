@@ -0,0 +1,312 @@
+//! A monorepo-like view over parts of the workspace repositories.
+//!
+//! The configuration maps directories of repositories to paths in a view
+//! directory:
+//!
+//! ```toml
+//! [view]
+//! dir = "view"
+//!
+//! [[view.mapping]]
+//! from = "repoA/src/lib"
+//! to = "libs/a"
+//! ```
+//!
+//! Materializing the view creates a symlink per mapping. The view commands
+//! translate mappings back to repositories and repository relative
+//! pathspecs.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::operations::{AddOperation, CommitOperation, GitOperation};
+use crate::repository::{ChangeCounts, GitRepository};
+use crate::workspace::Workspace;
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ViewConfig {
+    /// Directory of the view, relative to the workspace root.
+    #[serde(default = "default_dir")]
+    pub dir: PathBuf,
+    #[serde(default, rename = "mapping")]
+    pub mappings: Vec<Mapping>,
+}
+
+impl Default for ViewConfig {
+    fn default() -> Self {
+        ViewConfig {
+            dir: default_dir(),
+            mappings: Vec::new(),
+        }
+    }
+}
+
+fn default_dir() -> PathBuf {
+    PathBuf::from("view")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mapping {
+    /// Workspace relative path of a directory inside a repository.
+    pub from: String,
+    /// Path of the directory in the view.
+    pub to: String,
+}
+
+/// A mapping with the repository owning its source.
+#[derive(Debug, Clone)]
+pub struct ResolvedMapping {
+    pub repo: GitRepository,
+    /// Source path relative to the repository, empty for the whole
+    /// repository.
+    pub subpath: String,
+    pub to: String,
+}
+
+impl ResolvedMapping {
+    fn pathspec(&self) -> String {
+        if self.subpath.is_empty() {
+            ".".to_string()
+        } else {
+            self.subpath.clone()
+        }
+    }
+}
+
+/// Finds the repository owning the source of every mapping.
+pub fn resolve(config: &ViewConfig, repos: &[GitRepository]) -> Result<Vec<ResolvedMapping>> {
+    config
+        .mappings
+        .iter()
+        .map(|mapping| {
+            let from = mapping.from.trim_matches('/');
+            let repo = repos
+                .iter()
+                .filter(|repo| {
+                    from == repo.name() || from.starts_with(&format!("{}/", repo.name()))
+                })
+                .max_by_key(|repo| repo.name().len())
+                .ok_or_else(|| {
+                    Error::Operation(format!("no repository contains {}", mapping.from))
+                })?;
+            Ok(ResolvedMapping {
+                repo: repo.clone(),
+                subpath: from[repo.name().len()..]
+                    .trim_start_matches('/')
+                    .to_string(),
+                to: mapping.to.trim_matches('/').to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Creates the view directory with a symlink per mapping, replacing links
+/// left over from previous mappings. Returns the view paths created.
+pub fn materialize(
+    workspace: &Workspace,
+    config: &ViewConfig,
+    mappings: &[ResolvedMapping],
+) -> Result<Vec<String>> {
+    let dir = workspace.root().join(&config.dir);
+    fs::create_dir_all(&dir)?;
+    remove_links(&dir)?;
+
+    let mut created = Vec::new();
+    for mapping in mappings {
+        let source = match mapping.subpath.as_str() {
+            "" => mapping.repo.path().to_path_buf(),
+            subpath => mapping.repo.path().join(subpath),
+        };
+        if !source.is_dir() {
+            return Err(Error::Operation(format!(
+                "{} is not a directory",
+                source.display()
+            )));
+        }
+        let link = dir.join(&mapping.to);
+        if let Some(parent) = link.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        symlink_dir(&source, &link)?;
+        created.push(mapping.to.clone());
+    }
+    Ok(created)
+}
+
+fn remove_links(dir: &Path) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.file_type().is_symlink() {
+            remove_link(&path)?;
+        } else if metadata.is_dir() {
+            remove_links(&path)?;
+            // Only succeeds for directories that held nothing but links.
+            let _ = fs::remove_dir(&path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink_dir(source: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(source, link)
+}
+
+#[cfg(windows)]
+fn symlink_dir(source: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_dir(source, link)
+}
+
+#[cfg(unix)]
+fn remove_link(link: &Path) -> std::io::Result<()> {
+    fs::remove_file(link)
+}
+
+#[cfg(windows)]
+fn remove_link(link: &Path) -> std::io::Result<()> {
+    fs::remove_dir(link)
+}
+
+/// Pending changes below the source of `mapping`.
+pub fn status(mapping: &ResolvedMapping) -> Result<ChangeCounts> {
+    let repo = mapping.repo.open()?;
+    if mapping.subpath.is_empty() {
+        return ChangeCounts::collect(&repo);
+    }
+    ChangeCounts::collect_matching(&repo, std::slice::from_ref(&mapping.subpath))
+}
+
+/// The repositories owning at least one mapping, in mapping order.
+pub fn repositories(mappings: &[ResolvedMapping]) -> Vec<GitRepository> {
+    let mut repos: Vec<GitRepository> = Vec::new();
+    for mapping in mappings {
+        if !repos.contains(&mapping.repo) {
+            repos.push(mapping.repo.clone());
+        }
+    }
+    repos
+}
+
+/// Stages and commits the changes below the mapped directories of every
+/// repository owning a mapping.
+pub struct ViewCommitOperation {
+    pathspecs: BTreeMap<String, Vec<String>>,
+    message: String,
+}
+
+impl ViewCommitOperation {
+    pub fn new(mappings: &[ResolvedMapping], message: impl Into<String>) -> Self {
+        let mut pathspecs: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for mapping in mappings {
+            pathspecs
+                .entry(mapping.repo.name().to_string())
+                .or_default()
+                .push(mapping.pathspec());
+        }
+        ViewCommitOperation {
+            pathspecs,
+            message: message.into(),
+        }
+    }
+}
+
+impl GitOperation for ViewCommitOperation {
+    fn name(&self) -> &str {
+        "view commit"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn execute(&self, repo: &GitRepository) -> Result<String> {
+        let pathspecs = self.pathspecs.get(repo.name()).cloned().unwrap_or_default();
+        if pathspecs.is_empty() {
+            return Ok("not part of the view".to_string());
+        }
+        let outside = staged_outside(repo, &pathspecs)?;
+        if !outside.is_empty() {
+            return Err(Error::Operation(format!(
+                "changes outside the view are staged, unstage them first: {}",
+                outside.join(", ")
+            )));
+        }
+        AddOperation::new(pathspecs).execute(repo)?;
+        CommitOperation::new(self.message.clone(), false).execute(repo)
+    }
+}
+
+/// Paths staged in `repo` that none of `pathspecs` matches, which a commit
+/// of the view would take along.
+fn staged_outside(repo: &GitRepository, pathspecs: &[String]) -> Result<Vec<String>> {
+    let git = repo.open()?;
+    let head = git.head().ok().and_then(|head| head.peel_to_tree().ok());
+    let diff = git.diff_tree_to_index(head.as_ref(), None, None)?;
+    let spec = git2::Pathspec::new(pathspecs.iter())?;
+    Ok(diff
+        .deltas()
+        .filter_map(|delta| delta.new_file().path().or(delta.old_file().path()))
+        .filter(|path| !spec.matches_path(path, git2::PathspecFlags::DEFAULT))
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A repository with `docs/guide` and `src/lib.rs` committed, both
+    /// changed and staged since.
+    fn staged_repo(dir: &Path) -> Result<GitRepository> {
+        let _ = fs::remove_dir_all(dir);
+        let git = git2::Repository::init(dir)?;
+        let mut config = git.config()?;
+        config.set_str("user.name", "git-ws test")?;
+        config.set_str("user.email", "test@git-ws.invalid")?;
+        fs::create_dir_all(dir.join("docs"))?;
+        fs::create_dir_all(dir.join("src"))?;
+        let repo = GitRepository::new("api", dir);
+        for content in ["first\n", "second\n"] {
+            fs::write(dir.join("docs/guide"), content)?;
+            fs::write(dir.join("src/lib.rs"), content)?;
+            AddOperation::new(vec![".".to_string()]).execute(&repo)?;
+            if content == "first\n" {
+                CommitOperation::new("Initial commit".to_string(), false).execute(&repo)?;
+            }
+        }
+        Ok(repo)
+    }
+
+    #[test]
+    fn commits_nothing_staged_outside_the_view() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("git-ws-view-{}", std::process::id()));
+        let repo = staged_repo(&dir)?;
+        let head = repo.head_sha()?;
+        let operation = ViewCommitOperation {
+            pathspecs: BTreeMap::from([("api".to_string(), vec!["docs".to_string()])]),
+            message: "Update the guide".to_string(),
+        };
+        let error = operation.execute(&repo).unwrap_err();
+        assert!(error.to_string().contains("src/lib.rs"), "{}", error);
+        assert_eq!(repo.head_sha()?, head);
+
+        repo.git(["reset", "--quiet", "--", "src"])?;
+        operation.execute(&repo)?;
+        let git = repo.open()?;
+        let commit = git.head()?.peel_to_commit()?;
+        let parent = commit.parent(0)?;
+        let diff = git.diff_tree_to_tree(Some(&parent.tree()?), Some(&commit.tree()?), None)?;
+        let paths: Vec<_> = diff
+            .deltas()
+            .filter_map(|delta| delta.new_file().path().map(Path::to_path_buf))
+            .collect();
+        assert_eq!(paths, vec![PathBuf::from("docs/guide")]);
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
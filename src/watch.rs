@@ -0,0 +1,602 @@
+//! Watching the repositories of a workspace for changes.
+//!
+//! Repositories are watched with the notifications of the platform
+//! (inotify, FSEvents, ReadDirectoryChangesW) where they work, and polled
+//! on network filesystems, NFS and SMB, whose changes made by other
+//! machines are never notified. Polling skips the objects and logs of the
+//! git directory. Changes are debounced per repository: a repository is
+//! reported once it stayed quiet for its debounce delay.
+//!
+//! ```toml
+//! [watch]
+//! debounce_ms = 300
+//! poll_interval_ms = 2000
+//!
+//! [watch.repos."services/api"]
+//! backend = "poll"
+//! debounce_ms = 2000
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use notify::event::EventKind;
+use notify::{Event, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+
+use crate::events::{EventBus, WorkspaceEvent};
+use crate::repository::{ChangeCounts, GitRepository};
+use crate::{Error, Result};
+
+const DEFAULT_DEBOUNCE_MS: u64 = 300;
+const DEFAULT_POLL_INTERVAL_MS: u64 = 2000;
+
+/// How repositories are watched.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Notifications, or polling on network filesystems.
+    #[default]
+    Auto,
+    /// Notifications of the platform.
+    Native,
+    /// Polling, the only way to see changes made by other machines on a
+    /// network filesystem.
+    Poll,
+}
+
+/// The `[watch]` section of the configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchConfig {
+    #[serde(default)]
+    pub backend: Backend,
+    /// How long a repository must stay quiet before its changes are
+    /// reported.
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+    /// How often polled repositories are scanned.
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+    /// Settings of one repository, by name.
+    #[serde(default)]
+    pub repos: BTreeMap<String, RepoWatchConfig>,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        WatchConfig {
+            backend: Backend::default(),
+            debounce_ms: DEFAULT_DEBOUNCE_MS,
+            poll_interval_ms: DEFAULT_POLL_INTERVAL_MS,
+            repos: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RepoWatchConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<Backend>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debounce_ms: Option<u64>,
+}
+
+fn default_debounce_ms() -> u64 {
+    DEFAULT_DEBOUNCE_MS
+}
+
+fn default_poll_interval_ms() -> u64 {
+    DEFAULT_POLL_INTERVAL_MS
+}
+
+impl WatchConfig {
+    fn backend(&self, repo: &str) -> Backend {
+        self.repos
+            .get(repo)
+            .and_then(|config| config.backend)
+            .unwrap_or(self.backend)
+    }
+
+    fn debounce(&self, repo: &str) -> Duration {
+        let ms = self
+            .repos
+            .get(repo)
+            .and_then(|config| config.debounce_ms)
+            .unwrap_or(self.debounce_ms);
+        Duration::from_millis(ms)
+    }
+}
+
+/// A watched repository.
+struct Watched {
+    repo: GitRepository,
+    name: String,
+    debounce: Duration,
+    polled: bool,
+    /// When the repository last changed, while its changes are not
+    /// reported yet.
+    changed: Option<Instant>,
+    /// Changes last seen, for [`WorkspaceWatcher::changes`].
+    changes: Option<ChangeCounts>,
+}
+
+/// Watches the repositories of a workspace.
+pub struct WorkspaceWatcher {
+    repos: Vec<Watched>,
+    /// The directories of the repositories, working trees and git
+    /// directories, with the index of their repository, to tell which one
+    /// an event is about.
+    dirs: Vec<(PathBuf, usize)>,
+    events: Receiver<notify::Result<Event>>,
+    bus: Option<EventBus>,
+    // Watching stops when they are dropped: one watcher for all the
+    // repositories watched natively, inotify allowing only 128 per user by
+    // default, and one polling the others.
+    _watchers: Vec<Box<dyn Watcher + Send>>,
+}
+
+impl WorkspaceWatcher {
+    pub fn new(repos: &[GitRepository], config: &WatchConfig) -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let handler = move |event| {
+            let _ = sender.send(event);
+        };
+        let mut native = None;
+        let mut native_failed = false;
+        let mut polled = None;
+        let mut watched = Vec::new();
+        let mut dirs = Vec::new();
+        for (i, repo) in repos.iter().enumerate() {
+            let mut polls = match config.backend(repo.name()) {
+                Backend::Auto => is_network_fs(repo.path()),
+                Backend::Native => false,
+                Backend::Poll => true,
+            };
+            if !polls && native.is_none() && !native_failed {
+                match RecommendedWatcher::new(handler.clone(), notify::Config::default()) {
+                    Ok(watcher) => native = Some(watcher),
+                    Err(e) if is_limit(&e) => {
+                        eprintln!("warning: cannot watch natively, polling: {}", e);
+                        native_failed = true;
+                    }
+                    Err(e) => return Err(watch_error(e)),
+                }
+            }
+            if !polls {
+                match &mut native {
+                    Some(native) => match native.watch(repo.path(), RecursiveMode::Recursive) {
+                        Ok(()) => {}
+                        Err(e) if is_limit(&e) => {
+                            eprintln!(
+                                "warning: {}: cannot watch natively, polling: {}",
+                                repo.name(),
+                                e
+                            );
+                            let _ = native.unwatch(repo.path());
+                            polls = true;
+                        }
+                        Err(e) => return Err(watch_error(e)),
+                    },
+                    None => polls = true,
+                }
+            }
+            dirs.push((repo.path().to_path_buf(), i));
+            if polls {
+                let polled = match &mut polled {
+                    Some(polled) => polled,
+                    None => {
+                        let interval = Duration::from_millis(config.poll_interval_ms);
+                        let config = notify::Config::default().with_poll_interval(interval);
+                        polled
+                            .insert(PollWatcher::new(handler.clone(), config).map_err(watch_error)?)
+                    }
+                };
+                for (path, mode) in polled_paths(repo)? {
+                    polled.watch(&path, mode).map_err(watch_error)?;
+                }
+                // The git directory of a worktree lives elsewhere.
+                let git = repo.open()?.path().to_path_buf();
+                if !git.starts_with(repo.path()) {
+                    dirs.push((git, i));
+                }
+            }
+            watched.push(Watched {
+                repo: repo.clone(),
+                name: repo.name().to_string(),
+                debounce: config.debounce(repo.name()),
+                polled: polls,
+                changed: None,
+                changes: None,
+            });
+        }
+        // Nested repositories: the deepest directory holding a path is its
+        // repository.
+        dirs.sort_by_key(|(dir, _)| std::cmp::Reverse(dir.components().count()));
+        let mut watchers: Vec<Box<dyn Watcher + Send>> = Vec::new();
+        watchers.extend(native.map(|watcher| Box::new(watcher) as Box<dyn Watcher + Send>));
+        watchers.extend(polled.map(|watcher| Box::new(watcher) as Box<dyn Watcher + Send>));
+        Ok(WorkspaceWatcher {
+            repos: watched,
+            dirs,
+            events,
+            bus: None,
+            _watchers: watchers,
+        })
+    }
+
+    /// The repository `path` belongs to.
+    fn owner(&self, path: &Path) -> Option<usize> {
+        self.dirs
+            .iter()
+            .find(|(dir, _)| path.starts_with(dir))
+            .map(|(_, i)| *i)
+    }
+
+    /// Reports the repositories getting new changes to `bus`, see
+    /// [`WorkspaceWatcher::changes`].
+    pub fn with_events(mut self, bus: EventBus) -> Self {
+        self.bus = Some(bus);
+        self
+    }
+
+    /// The watched repositories, and whether each is polled.
+    pub fn repositories(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.repos
+            .iter()
+            .map(|repo| (repo.name.as_str(), repo.polled))
+    }
+
+    /// Waits until repositories changed and stayed quiet for their debounce
+    /// delay, and returns their names.
+    pub fn wait(&mut self) -> Result<Vec<String>> {
+        loop {
+            let now = Instant::now();
+            let ready: Vec<String> = self
+                .repos
+                .iter_mut()
+                .filter(|repo| matches!(repo.changed, Some(at) if now >= at + repo.debounce))
+                .map(|repo| {
+                    repo.changed = None;
+                    repo.name.clone()
+                })
+                .collect();
+            if !ready.is_empty() {
+                return Ok(ready);
+            }
+            let deadline = self
+                .repos
+                .iter()
+                .filter_map(|repo| repo.changed.map(|at| at + repo.debounce))
+                .min();
+            let event = match deadline {
+                Some(deadline) => {
+                    match self
+                        .events
+                        .recv_timeout(deadline.saturating_duration_since(now))
+                    {
+                        Ok(event) => event,
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+                None => match self.events.recv() {
+                    Ok(event) => event,
+                    Err(_) => break,
+                },
+            };
+            let event = event.map_err(watch_error)?;
+            if !matches!(event.kind, EventKind::Access(_)) {
+                for path in &event.paths {
+                    if let Some(i) = self.owner(path).filter(|_| relevant(path)) {
+                        self.repos[i].changed = Some(Instant::now());
+                    }
+                }
+            }
+        }
+        Err(Error::Operation("watching stopped".to_string()))
+    }
+
+    /// Waits until repositories changed, see [`WorkspaceWatcher::wait`], and
+    /// returns those whose changes are not the ones last seen, with their
+    /// changes. The changes of every repository are read first on the first
+    /// call.
+    pub fn changes(&mut self) -> Result<Vec<(String, ChangeCounts)>> {
+        for watched in &mut self.repos {
+            if watched.changes.is_none() {
+                watched.changes = Some(watched.repo.snapshot()?.changes);
+            }
+        }
+        loop {
+            let mut dirty = Vec::new();
+            for name in self.wait()? {
+                let Some(watched) = self.repos.iter_mut().find(|repo| repo.name == name) else {
+                    continue;
+                };
+                let changes = watched.repo.snapshot()?.changes;
+                if watched.changes.replace(changes) == Some(changes) {
+                    continue;
+                }
+                if let Some(bus) = &self.bus {
+                    bus.emit(WorkspaceEvent::RepositoryDirty {
+                        repo: name.clone(),
+                        changes,
+                    });
+                }
+                dirty.push((name, changes));
+            }
+            if !dirty.is_empty() {
+                return Ok(dirty);
+            }
+        }
+    }
+}
+
+/// The paths polled for `repo`: its working tree and, of its git
+/// directory, only what [`relevant`] looks at. Polling scans every file
+/// under a path, the objects and logs would make scans of large
+/// repositories as slow as they are useless. Directories created at the
+/// top of the working tree are seen, not what they get until the watch
+/// restarts.
+fn polled_paths(repo: &GitRepository) -> Result<Vec<(PathBuf, RecursiveMode)>> {
+    let mut paths = vec![(repo.path().to_path_buf(), RecursiveMode::NonRecursive)];
+    for entry in fs::read_dir(repo.path())? {
+        let entry = entry?;
+        if entry.file_name() != ".git" && entry.file_type()?.is_dir() {
+            paths.push((entry.path(), RecursiveMode::Recursive));
+        }
+    }
+    let git = repo.open()?;
+    let candidates = [
+        (git.path().join("HEAD"), RecursiveMode::NonRecursive),
+        (git.path().join("index"), RecursiveMode::NonRecursive),
+        (git.path().join("refs"), RecursiveMode::Recursive),
+        (git.path().join("packed-refs"), RecursiveMode::NonRecursive),
+    ];
+    paths.extend(candidates.into_iter().filter(|(path, _)| path.exists()));
+    Ok(paths)
+}
+
+fn watch_error(e: notify::Error) -> Error {
+    match e.kind {
+        notify::ErrorKind::Io(e) => Error::Io(e),
+        kind => Error::Operation(format!("cannot watch: {:?}", kind)),
+    }
+}
+
+/// Whether a change to `path` may change what git reports: a change to the
+/// working tree, or to the index, HEAD or refs. Objects, logs and lock files
+/// change along with those.
+fn relevant(path: &Path) -> bool {
+    let mut components = path
+        .components()
+        .skip_while(|c| *c != Component::Normal(".git".as_ref()));
+    if components.next().is_none() {
+        return true;
+    }
+    match components.next() {
+        Some(Component::Normal(name)) => {
+            name == "index" || name == "HEAD" || name == "refs" || name == "packed-refs"
+        }
+        _ => false,
+    }
+}
+
+/// Whether `e` comes from a limit of the platform on what can be watched,
+/// like the 128 inotify instances or the inotify watches of a user.
+fn is_limit(e: &notify::Error) -> bool {
+    match &e.kind {
+        notify::ErrorKind::MaxFilesWatch => true,
+        #[cfg(unix)]
+        notify::ErrorKind::Io(e) => matches!(
+            e.raw_os_error(),
+            Some(libc::EMFILE | libc::ENFILE | libc::ENOSPC)
+        ),
+        _ => false,
+    }
+}
+
+/// Whether `path` lives on a network filesystem, whose changes made on
+/// other machines are never notified.
+#[cfg(target_os = "linux")]
+pub fn is_network_fs(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    const NFS: u32 = 0x6969;
+    const SMB: u32 = 0x517b;
+    const CIFS: u32 = 0xff53_4d42;
+    const SMB2: u32 = 0xfe53_4d42;
+    const AFS: u32 = 0x5346_414f;
+    const CODA: u32 = 0x7375_7245;
+    // WSL 2 mounts Windows drives over 9P.
+    const V9FS: u32 = 0x0102_1997;
+
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return false;
+    }
+    matches!(
+        stat.f_type as u32,
+        NFS | SMB | CIFS | SMB2 | AFS | CODA | V9FS
+    )
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+pub fn is_network_fs(path: &Path) -> bool {
+    use std::ffi::CStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return false;
+    }
+    let name = unsafe { CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+    matches!(
+        name.to_bytes(),
+        b"nfs" | b"smbfs" | b"afpfs" | b"webdav" | b"cifs"
+    )
+}
+
+#[cfg(windows)]
+pub fn is_network_fs(path: &Path) -> bool {
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Prefix;
+    use windows_sys::Win32::Storage::FileSystem::GetDriveTypeW;
+
+    const DRIVE_REMOTE: u32 = 4;
+
+    let root = match path.components().next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::UNC(..) | Prefix::VerbatimUNC(..) => return true,
+            _ => format!("{}\\", prefix.as_os_str().to_string_lossy()),
+        },
+        _ => return false,
+    };
+    let wide: Vec<u16> = std::ffi::OsStr::new(&root)
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    unsafe { GetDriveTypeW(wide.as_ptr()) == DRIVE_REMOTE }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "freebsd",
+    windows
+)))]
+pub fn is_network_fs(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{RepoState, TestWorkspace};
+
+    #[test]
+    fn polls_no_objects() {
+        let test = TestWorkspace::builder()
+            .repo("api", RepoState::Clean)
+            .build()
+            .unwrap();
+        let repo = test.repository("api");
+        fs::create_dir(repo.path().join("src")).unwrap();
+        let git = repo.path().join(".git");
+        let paths = polled_paths(&repo).unwrap();
+        let polled = |path: &Path| paths.iter().any(|(polled, _)| polled == path);
+        assert!(polled(repo.path()));
+        assert!(polled(&repo.path().join("src")));
+        assert!(polled(&git.join("HEAD")));
+        assert!(polled(&git.join("index")));
+        assert!(polled(&git.join("refs")));
+        assert!(paths.iter().all(|(path, mode)| path != &git
+            && !path.starts_with(git.join("objects"))
+            && (*mode == RecursiveMode::NonRecursive || !git.starts_with(path))));
+    }
+
+    #[test]
+    fn shares_one_watcher_per_backend() -> Result<()> {
+        let test = TestWorkspace::builder()
+            .repos(6, RepoState::Clean)
+            .repo("libs/core", RepoState::Clean)
+            .build()?;
+        let repos = test.workspace().discover_repositories()?;
+        let mut config = WatchConfig {
+            backend: Backend::Native,
+            ..WatchConfig::default()
+        };
+        let watcher = WorkspaceWatcher::new(&repos, &config)?;
+        assert_eq!(watcher._watchers.len(), 1);
+        assert!(watcher.repositories().all(|(_, polled)| !polled));
+
+        config.repos.insert(
+            "repo-0".to_string(),
+            RepoWatchConfig {
+                backend: Some(Backend::Poll),
+                ..RepoWatchConfig::default()
+            },
+        );
+        let watcher = WorkspaceWatcher::new(&repos, &config)?;
+        assert_eq!(watcher._watchers.len(), 2);
+        let core = test.root().join("libs/core/src/lib.rs");
+        let owner = watcher.owner(&core).map(|i| watcher.repos[i].name.as_str());
+        assert_eq!(owner, Some("libs/core"));
+        assert_eq!(watcher.owner(Path::new("/elsewhere")), None);
+        Ok(())
+    }
+
+    #[test]
+    fn reports_the_repository_an_event_is_about() -> Result<()> {
+        let test = TestWorkspace::builder()
+            .repos(3, RepoState::Clean)
+            .build()?;
+        let repos = test.workspace().discover_repositories()?;
+        let config = WatchConfig {
+            backend: Backend::Native,
+            debounce_ms: 0,
+            ..WatchConfig::default()
+        };
+        let mut watcher = WorkspaceWatcher::new(&repos, &config)?;
+        fs::write(test.repository("repo-1").path().join("new"), "new\n")?;
+        assert_eq!(watcher.wait()?, vec!["repo-1".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn falls_back_to_polling_at_the_limits_of_the_platform() {
+        assert!(is_limit(&notify::Error::new(
+            notify::ErrorKind::MaxFilesWatch
+        )));
+        #[cfg(unix)]
+        for code in [libc::EMFILE, libc::ENOSPC] {
+            let e = notify::Error::io(std::io::Error::from_raw_os_error(code));
+            assert!(is_limit(&e));
+        }
+        let e = notify::Error::io(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert!(!is_limit(&e));
+    }
+
+    #[test]
+    fn reports_repositories_getting_dirty_to_the_bus() -> Result<()> {
+        let test = TestWorkspace::builder()
+            .repo("api", RepoState::Clean)
+            .repo("web", RepoState::Clean)
+            .build()?;
+        let repos = test.workspace().discover_repositories()?;
+        let config = WatchConfig {
+            backend: Backend::Native,
+            debounce_ms: 0,
+            ..WatchConfig::default()
+        };
+        let bus = EventBus::new(8);
+        let mut events = bus.subscribe();
+        let mut watcher = WorkspaceWatcher::new(&repos, &config)?.with_events(bus);
+        // Once the watcher read the changes it starts from.
+        let new = test.repository("web").path().join("new");
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            fs::write(new, "new\n")
+        });
+        let changes = watcher.changes()?;
+        writer.join().unwrap()?;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].0, "web");
+        match events.try_recv() {
+            Ok(WorkspaceEvent::RepositoryDirty { repo, changes }) => {
+                assert_eq!(repo, "web");
+                assert!(!changes.is_clean());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::config::Config;
+use crate::events::{EventBus, WorkspaceEvent};
+use crate::executor::{BatchExecutor, RepoHandle, RepoOutcome};
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::repository::GitRepository;
+use crate::shard::Shard;
+use crate::state::WorkspaceState;
+use crate::{Error, Result};
+
+/// Name of the directory, relative to the workspace root, holding git-ws
+/// state.
+pub const STATE_DIR: &str = ".git-ws";
+
+/// File in the state directory listing the repositories found by the last
+/// discovery, one name per line, for shell completion.
+const DISCOVERY_CACHE: &str = "repositories";
+
+/// Whether the repository `name` matches `pattern`, `*` matching anything,
+/// slashes included, and `?` any one character. A trailing slash is
+/// ignored.
+pub fn name_matches(pattern: &str, name: &str) -> bool {
+    let pattern = regex::escape(pattern.trim_end_matches('/'))
+        .replace(r"\*", ".*")
+        .replace(r"\?", ".");
+    Regex::new(&format!("^{}$", pattern)).is_ok_and(|pattern| pattern.is_match(name))
+}
+
+/// How deep below the workspace root repositories are searched for.
+const MAX_DEPTH: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    root: PathBuf,
+    events: Option<EventBus>,
+    shard: Option<Shard>,
+    group: Option<String>,
+    /// Patterns of the repositories to keep, all of them when empty.
+    only: Vec<String>,
+    /// Patterns of the repositories to leave out.
+    excluded: Vec<String>,
+}
+
+impl Workspace {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Workspace {
+            root: root.into(),
+            events: None,
+            shard: None,
+            group: None,
+            only: Vec::new(),
+            excluded: Vec::new(),
+        }
+    }
+
+    /// Reports the repositories found by discovery to `events`.
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Keeps the repositories of `shard` only, see [`crate::shard`].
+    pub fn with_shard(mut self, shard: Shard) -> Self {
+        self.shard = Some(shard);
+        self
+    }
+
+    pub fn shard(&self) -> Option<Shard> {
+        self.shard
+    }
+
+    /// Keeps the repositories of the group `group` of the manifest only.
+    pub fn with_group(mut self, group: impl Into<String>) -> Self {
+        self.group = Some(group.into());
+        self
+    }
+
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    /// Keeps the repositories matching one of `patterns` only, like
+    /// `service-*`, see [`name_matches`].
+    pub fn with_repos(mut self, patterns: Vec<String>) -> Self {
+        self.only = patterns;
+        self
+    }
+
+    /// Leaves the repositories matching one of `patterns` out, like
+    /// `legacy-*`, see [`name_matches`].
+    pub fn without_repos(mut self, patterns: Vec<String>) -> Self {
+        self.excluded.extend(patterns);
+        self
+    }
+
+    /// The groups of the configuration and of the manifest, with the
+    /// repository patterns of each; those of the manifest win.
+    pub fn groups(&self) -> Result<BTreeMap<String, Vec<String>>> {
+        let mut groups = self.load_config()?.groups;
+        groups.extend(Manifest::for_workspace(&self.root)?.groups);
+        Ok(groups)
+    }
+
+    /// Finds the workspace containing `start`: the closest ancestor holding
+    /// a state directory or a manifest, or `start` itself when there is
+    /// none.
+    pub fn discover(start: &Path) -> Self {
+        let root = start
+            .ancestors()
+            .find(|dir| dir.join(STATE_DIR).is_dir() || dir.join(MANIFEST_FILE).is_file())
+            .unwrap_or(start);
+        Workspace::new(root)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn state_dir(&self) -> PathBuf {
+        self.root.join(STATE_DIR)
+    }
+
+    pub fn load_config(&self) -> Result<Config> {
+        Config::load(&self.state_dir())
+    }
+
+    /// Writes `config` to the state directory, returning the path written.
+    pub fn save_config(&self, config: &Config) -> Result<PathBuf> {
+        config.save(&self.state_dir())
+    }
+
+    pub fn load_state(&self) -> Result<WorkspaceState> {
+        WorkspaceState::load(&self.state_dir())
+    }
+
+    pub fn save_state(&self, state: &WorkspaceState) -> Result<()> {
+        state.save(&self.state_dir())
+    }
+
+    /// Returns every repository of the workspace, sorted by name: the
+    /// repositories of the manifest cloned already, when there is a
+    /// manifest, or else the repositories found walking the workspace.
+    /// Nested repositories are not searched for. With a shard, a group or
+    /// repository patterns, only the repositories of the shard, of the
+    /// group and matching the patterns are returned, less those left out.
+    pub fn discover_repositories(&self) -> Result<Vec<GitRepository>> {
+        let mut repos = Vec::new();
+        if self.has_manifest() {
+            for declared in Manifest::for_workspace(&self.root)?.repositories {
+                let path = self.root.join(declared.path.trim_end_matches('/'));
+                if path.join(".git").exists() {
+                    repos.push(GitRepository::new(self.relative_name(&path), path));
+                }
+            }
+        } else {
+            self.walk(&self.root, 0, &mut repos)?;
+        }
+        repos.sort_by(|a, b| a.name().cmp(b.name()));
+        self.save_discovery_cache(&repos);
+        let selected = self.selection()?;
+        repos.retain(|repo| selected(repo.name()));
+        if let Some(events) = &self.events {
+            for repo in &repos {
+                events.emit(WorkspaceEvent::RepositoryDiscovered {
+                    repo: repo.name().to_string(),
+                });
+            }
+        }
+        Ok(repos)
+    }
+
+    /// Whether the repository `name` is in the shard, the group and the
+    /// patterns the workspace is limited to, and not left out, for
+    /// repositories not cloned
+    /// yet; [`Workspace::discover_repositories`] applies it already.
+    pub fn selects(&self, name: &str) -> Result<bool> {
+        Ok(self.selection()?(name))
+    }
+
+    fn selection(&self) -> Result<impl Fn(&str) -> bool + '_> {
+        let group = match &self.group {
+            Some(group) => Some(self.groups()?.remove(group).ok_or_else(|| {
+                Error::Operation(format!(
+                    "no group {} in the manifest or the configuration",
+                    group
+                ))
+            })?),
+            None => None,
+        };
+        let matches_any = |patterns: &[String], name: &str| {
+            patterns.iter().any(|pattern| name_matches(pattern, name))
+        };
+        Ok(move |name: &str| {
+            self.shard.is_none_or(|shard| shard.contains(name))
+                && group.as_ref().is_none_or(|group| matches_any(group, name))
+                && (self.only.is_empty() || matches_any(&self.only, name))
+                && !matches_any(&self.excluded, name)
+        })
+    }
+
+    /// Whether the repositories are declared by a manifest at the root.
+    pub fn has_manifest(&self) -> bool {
+        self.root.join(MANIFEST_FILE).is_file()
+    }
+
+    /// Names of the repositories found by the last discovery, discovering
+    /// them now when there is no cache.
+    pub fn cached_repository_names(&self) -> Result<Vec<String>> {
+        match fs::read_to_string(self.state_dir().join(DISCOVERY_CACHE)) {
+            Ok(cache) => Ok(cache.lines().map(str::to_string).collect()),
+            Err(_) => Ok(self
+                .discover_repositories()?
+                .iter()
+                .map(|repo| repo.name().to_string())
+                .collect()),
+        }
+    }
+
+    /// Remembers the names of `repos`, in workspaces having a state
+    /// directory already. The cache is only a hint, so failing to write it
+    /// is not an error.
+    fn save_discovery_cache(&self, repos: &[GitRepository]) {
+        let dir = self.state_dir();
+        if !dir.is_dir() {
+            return;
+        }
+        let names: String = repos
+            .iter()
+            .map(|repo| format!("{}\n", repo.name()))
+            .collect();
+        let path = dir.join(DISCOVERY_CACHE);
+        if fs::read_to_string(&path).ok().as_deref() != Some(names.as_str()) {
+            let _ = fs::write(path, names);
+        }
+    }
+
+    /// Runs `f` against every repository of the workspace through
+    /// `executor`, see [`BatchExecutor::for_each`].
+    pub async fn for_each_repo<F, Fut, T>(
+        &self,
+        executor: &BatchExecutor,
+        f: F,
+    ) -> Result<Vec<RepoOutcome<T>>>
+    where
+        F: Fn(RepoHandle) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let repos = self.discover_repositories()?;
+        Ok(executor.for_each(&repos, f).await)
+    }
+
+    pub fn find_repository(&self, name: &str) -> Result<GitRepository> {
+        let name = name.trim_end_matches('/');
+        self.discover_repositories()?
+            .into_iter()
+            .find(|repo| repo.name() == name)
+            .ok_or_else(|| Error::RepositoryNotFound(name.to_string()))
+    }
+
+    fn walk(&self, dir: &Path, depth: usize, repos: &mut Vec<GitRepository>) -> Result<()> {
+        if depth > MAX_DEPTH {
+            return Ok(());
+        }
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if path.join(".git").exists() {
+                repos.push(GitRepository::new(self.relative_name(&path), path));
+            } else {
+                self.walk(&path, depth + 1, repos)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn relative_name(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{RepoState, TestWorkspace};
+
+    #[test]
+    fn matches_names() {
+        assert!(name_matches("service-*", "service-api"));
+        assert!(name_matches("libs/*", "libs/a/b"));
+        assert!(name_matches("app?", "app2"));
+        assert!(name_matches("libs/", "libs"));
+        assert!(!name_matches("service-*", "legacy-service-api"));
+        assert!(!name_matches("app?", "app"));
+        assert!(!name_matches("a.c", "abc"));
+    }
+
+    #[test]
+    fn discovers_the_repositories_selected() {
+        let test = TestWorkspace::builder()
+            .repo("service-api", RepoState::Clean)
+            .repo("service-web", RepoState::Dirty)
+            .repo("libs/core", RepoState::Ahead(1))
+            .build()
+            .unwrap();
+        let names = |workspace: Workspace| -> Vec<String> {
+            workspace
+                .discover_repositories()
+                .unwrap()
+                .iter()
+                .map(|repo| repo.name().to_string())
+                .collect()
+        };
+        let workspace = test.workspace().clone();
+        assert_eq!(
+            names(workspace.clone()),
+            ["libs/core", "service-api", "service-web"]
+        );
+        assert_eq!(
+            names(workspace.clone().with_repos(vec!["service-*".to_string()])),
+            ["service-api", "service-web"]
+        );
+        assert_eq!(
+            names(
+                workspace
+                    .with_repos(vec!["service-*".to_string()])
+                    .without_repos(vec!["*-web".to_string()])
+            ),
+            ["service-api"]
+        );
+    }
+}